@@ -0,0 +1,177 @@
+//! Times the hot `check_results` reads through the API on a large history:
+//! `cargo bench --bench check_results`. `BENCH_RESULTS` sets how many rows each of the
+//! `BENCH_CHECKS` checks gets (100000 and 10 by default).
+
+use axum::body::{to_bytes, Body};
+use axum::extract::connect_info::MockConnectInfo;
+use axum::http::{Method, Request, StatusCode};
+use axum::Router;
+use chrono::{Duration, Utc};
+use serde_json::{json, Value};
+use std::env;
+use std::net::SocketAddr;
+use std::time::Instant;
+use tower::ServiceExt;
+use uptime_saas::scheduler::Clock;
+use uptime_saas::store;
+
+const ITERATIONS: usize = 50;
+
+fn env_or(name: &str, default: i64) -> i64 {
+    env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+async fn send(router: &Router, method: Method, path: &str, body: Option<Value>) -> Value {
+    let request = Request::builder().method(method).uri(path);
+    let request = match body {
+        Some(body) => request
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string())),
+        None => request.body(Body::empty()),
+    }
+    .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert!(
+        status == StatusCode::OK || status == StatusCode::CREATED,
+        "{path}: {status} {}",
+        String::from_utf8_lossy(&bytes)
+    );
+    serde_json::from_slice(&bytes).unwrap_or(Value::Null)
+}
+
+async fn bench(router: &Router, name: &str, method: Method, path: &str, body: Option<Value>) {
+    send(router, method.clone(), path, body.clone()).await;
+    let mut times = Vec::with_capacity(ITERATIONS);
+    for _ in 0..ITERATIONS {
+        let started = Instant::now();
+        send(router, method.clone(), path, body.clone()).await;
+        times.push(started.elapsed());
+    }
+    times.sort();
+    println!(
+        "{name:<28} p50 {:>9.3?}  p95 {:>9.3?}  max {:>9.3?}",
+        times[ITERATIONS / 2],
+        times[ITERATIONS * 95 / 100],
+        times[ITERATIONS - 1]
+    );
+}
+
+#[tokio::main]
+async fn main() {
+    let per_check = env_or("BENCH_RESULTS", 100_000);
+    let checks = env_or("BENCH_CHECKS", 10);
+    let dir = env::temp_dir().join(format!("uptime-bench-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let db = store::connect(&format!("sqlite://{}", dir.join("uptime.db").display()))
+        .await
+        .unwrap();
+    let now = Utc::now();
+    // Every timed request comes from the same address
+    env::set_var("RATE_LIMIT_PER_MINUTE", "0");
+    let router = uptime_saas::start(db.clone(), Clock::manual(now))
+        .await
+        .unwrap()
+        .layer(MockConnectInfo(SocketAddr::from(([127, 0, 0, 1], 0))));
+
+    let started = Instant::now();
+    let mut ids = Vec::new();
+    for n in 0..checks {
+        let check = send(
+            &router,
+            Method::POST,
+            "/checks",
+            Some(json!({
+                "name": format!("bench {n}"),
+                "url": "https://example.com",
+                "interval_seconds": 60,
+                "is_active": false,
+            })),
+        )
+        .await;
+        let id = check["id"].as_str().unwrap().to_string();
+        // One result a minute up to now, with every 50th one down
+        sqlx::query(
+            r#"
+            WITH RECURSIVE n(i) AS (SELECT 0 UNION ALL SELECT i + 1 FROM n WHERE i + 1 < ?2)
+            INSERT INTO check_results (check_id, checked_at, status, http_status, latency_ms, error_kind)
+            SELECT ?1, strftime('%Y-%m-%dT%H:%M:%fZ', ?3, '-' || i || ' minutes'),
+                   CASE WHEN i % 50 = 0 THEN 'DOWN' ELSE 'UP' END,
+                   CASE WHEN i % 50 = 0 THEN 503 ELSE 200 END,
+                   50 + i % 200,
+                   CASE WHEN i % 50 = 0 THEN 'http_status' END
+            FROM n
+            "#,
+        )
+        .bind(&id)
+        .bind(per_check)
+        .bind(now.format("%Y-%m-%dT%H:%M:%SZ").to_string())
+        .execute(&db)
+        .await
+        .unwrap();
+        ids.push(id);
+    }
+    println!(
+        "seeded {} results for {checks} checks in {:.1?}",
+        per_check * checks,
+        started.elapsed()
+    );
+
+    let id = &ids[0];
+    let day = json!({
+        "from": (now - Duration::days(1)).to_rfc3339(),
+        "to": now.to_rfc3339(),
+    });
+    let latest = json!({ "query": format!("{{ check(id: \"{id}\") {{ results(limit: 50) {{ status }} }} }}") });
+    bench(
+        &router,
+        "latest 50 (graphql)",
+        Method::POST,
+        "/graphql",
+        Some(latest),
+    )
+    .await;
+    bench(
+        &router,
+        "errors by kind",
+        Method::GET,
+        &format!("/checks/{id}/results?error_kind=http_status"),
+        None,
+    )
+    .await;
+    bench(
+        &router,
+        "stats 7d",
+        Method::GET,
+        &format!("/checks/{id}/stats?period=7d"),
+        None,
+    )
+    .await;
+    bench(
+        &router,
+        "latency by region 24h",
+        Method::GET,
+        &format!("/checks/{id}/latency-by-region?period=24h"),
+        None,
+    )
+    .await;
+    bench(
+        &router,
+        "grafana 24h by minute",
+        Method::POST,
+        "/grafana/query",
+        Some(json!({
+            "range": day,
+            "intervalMs": 60000,
+            "targets": [{ "target": format!("{id}:latency_ms") }],
+        })),
+    )
+    .await;
+
+    db.close().await;
+    std::fs::remove_dir_all(&dir).unwrap();
+}
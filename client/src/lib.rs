@@ -0,0 +1,735 @@
+//! Async client for the uptime monitoring API.
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), uptime_client::Error> {
+//! use uptime_client::{Client, CreateCheck};
+//!
+//! let client = Client::new("http://localhost:8080").api_key("<api key>");
+//! let created = client
+//!     .create_check(
+//!         &CreateCheck {
+//!             name: "Homepage".into(),
+//!             url: "https://example.com".into(),
+//!             interval_seconds: 60,
+//!             ..Default::default()
+//!         },
+//!         None,
+//!     )
+//!     .await?;
+//! let check = client.get_check(&created.id).await?;
+//! client.delete_check(&check.id, check.version).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+mod types;
+
+pub use types::*;
+
+use reqwest::{Method, RequestBuilder, Response};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The request could not be sent or the response could not be read.
+    Http(reqwest::Error),
+    /// The API answered with an error status; `message` is its body.
+    Api { status: u16, message: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Http(e) => write!(f, "request failed: {e}"),
+            Error::Api { status, message } => write!(f, "API returned {status}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Http(e) => Some(e),
+            Error::Api { .. } => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Http(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    base_url: String,
+    api_key: Option<String>,
+    org_id: Option<String>,
+}
+
+impl Client {
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Client::with_http(reqwest::Client::new(), base_url)
+    }
+
+    pub fn with_http(http: reqwest::Client, base_url: impl Into<String>) -> Self {
+        Client {
+            http,
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            api_key: None,
+            org_id: None,
+        }
+    }
+
+    /// Sent as `Authorization: Bearer`; agent tokens work for the agent endpoints.
+    pub fn api_key(mut self, api_key: impl Into<String>) -> Self {
+        self.api_key = Some(api_key.into());
+        self
+    }
+
+    /// Needed by users that belong to several organizations (`X-Org-Id`).
+    pub fn org(mut self, org_id: impl Into<String>) -> Self {
+        self.org_id = Some(org_id.into());
+        self
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let mut request = self
+            .http
+            .request(method, format!("{}{path}", self.base_url));
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        if let Some(org_id) = &self.org_id {
+            request = request.header("X-Org-Id", org_id);
+        }
+        request
+    }
+
+    async fn send(request: RequestBuilder) -> Result<Response> {
+        let response = request.send().await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        Err(Error::Api {
+            status: status.as_u16(),
+            message: response.text().await.unwrap_or_default(),
+        })
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        Ok(Self::send(self.request(Method::GET, path))
+            .await?
+            .json()
+            .await?)
+    }
+
+    async fn post<B: Serialize + ?Sized, T: DeserializeOwned>(
+        &self,
+        path: &str,
+        body: &B,
+    ) -> Result<T> {
+        Ok(Self::send(self.request(Method::POST, path).json(body))
+            .await?
+            .json()
+            .await?)
+    }
+
+    async fn delete(&self, path: &str) -> Result<()> {
+        Self::send(self.request(Method::DELETE, path)).await?;
+        Ok(())
+    }
+
+    pub async fn health(&self) -> Result<()> {
+        Self::send(self.request(Method::GET, "/health")).await?;
+        Ok(())
+    }
+
+    /// Returned whether or not the instance is ready; check `status`.
+    pub async fn readiness(&self) -> Result<Readiness> {
+        Ok(self
+            .request(Method::GET, "/readyz")
+            .send()
+            .await?
+            .json()
+            .await?)
+    }
+
+    /// Prometheus text format. Needs an instance operator's key.
+    pub async fn metrics(&self) -> Result<String> {
+        Ok(Self::send(self.request(Method::GET, "/metrics"))
+            .await?
+            .text()
+            .await?)
+    }
+
+    pub async fn list_checks(&self) -> Result<Vec<Check>> {
+        self.get("/checks").await
+    }
+
+    pub async fn get_check(&self, id: &str) -> Result<Check> {
+        self.get(&format!("/checks/{id}")).await
+    }
+
+    /// With an `idempotency_key`, retrying the same request returns the check it created
+    /// the first time instead of creating another.
+    pub async fn create_check(
+        &self,
+        check: &CreateCheck,
+        idempotency_key: Option<&str>,
+    ) -> Result<Created> {
+        let mut request = self.request(Method::POST, "/checks").json(check);
+        if let Some(key) = idempotency_key {
+            request = request.header("Idempotency-Key", key);
+        }
+        Ok(Self::send(request).await?.json().await?)
+    }
+
+    /// Fails with 412 when the check is no longer at `version`.
+    pub async fn update_check(
+        &self,
+        id: &str,
+        version: i64,
+        changes: &UpdateCheck,
+    ) -> Result<Check> {
+        let request = self
+            .request(Method::PATCH, &format!("/checks/{id}"))
+            .header("If-Match", format!("\"{version}\""))
+            .json(changes);
+        Ok(Self::send(request).await?.json().await?)
+    }
+
+    pub async fn delete_check(&self, id: &str, version: i64) -> Result<()> {
+        let request = self
+            .request(Method::DELETE, &format!("/checks/{id}"))
+            .header("If-Match", format!("\"{version}\""));
+        Self::send(request).await?;
+        Ok(())
+    }
+
+    pub async fn list_results(&self, check_id: &str) -> Result<Vec<CheckResult>> {
+        self.get(&format!("/checks/{check_id}/results")).await
+    }
+
+    /// Failed results of one error kind, e.g. `dns` or `http_5xx`.
+    pub async fn list_results_by_kind(
+        &self,
+        check_id: &str,
+        error_kind: &str,
+    ) -> Result<Vec<CheckResult>> {
+        let request = self
+            .request(Method::GET, &format!("/checks/{check_id}/results"))
+            .query(&[("error_kind", error_kind)]);
+        Ok(Self::send(request).await?.json().await?)
+    }
+
+    /// Every result of the check, oldest first, as newline-delimited JSON. Read it with
+    /// [`Response::chunk`] to process long histories without buffering them.
+    pub async fn export_results(&self, check_id: &str) -> Result<Response> {
+        Self::send(self.request(Method::GET, &format!("/checks/{check_id}/results.ndjson"))).await
+    }
+
+    pub async fn list_incidents(&self, check_id: &str) -> Result<Vec<Incident>> {
+        self.get(&format!("/checks/{check_id}/incidents")).await
+    }
+
+    pub async fn list_incident_updates(&self, incident_id: &str) -> Result<Vec<IncidentUpdate>> {
+        self.get(&format!("/incidents/{incident_id}/updates")).await
+    }
+
+    /// Shown on the timeline of the status pages that list the incident's check.
+    pub async fn create_incident_update(
+        &self,
+        incident_id: &str,
+        update: &CreateIncidentUpdate,
+    ) -> Result<IncidentUpdate> {
+        self.post(&format!("/incidents/{incident_id}/updates"), update)
+            .await
+    }
+
+    /// Newest first.
+    pub async fn list_annotations(&self, check_id: &str) -> Result<Vec<Annotation>> {
+        self.get(&format!("/checks/{check_id}/annotations")).await
+    }
+
+    pub async fn create_annotation(
+        &self,
+        check_id: &str,
+        annotation: &CreateAnnotation,
+    ) -> Result<Annotation> {
+        self.post(&format!("/checks/{check_id}/annotations"), annotation)
+            .await
+    }
+
+    pub async fn delete_annotation(&self, id: &str) -> Result<()> {
+        self.delete(&format!("/annotations/{id}")).await
+    }
+
+    /// `period` like `24h` or `7d`; the server defaults to `24h`. `timezone` is an IANA zone
+    /// such as `Europe/Madrid` for the daily breakdown, UTC by default.
+    pub async fn check_stats(
+        &self,
+        check_id: &str,
+        period: Option<&str>,
+        timezone: Option<&str>,
+    ) -> Result<CheckStats> {
+        let mut request = self.request(Method::GET, &format!("/checks/{check_id}/stats"));
+        if let Some(period) = period {
+            request = request.query(&[("period", period)]);
+        }
+        if let Some(timezone) = timezone {
+            request = request.query(&[("timezone", timezone)]);
+        }
+        Ok(Self::send(request).await?.json().await?)
+    }
+
+    /// `period` is e.g. `24h` or `7d`; 24 hours by default.
+    pub async fn latency_by_region(
+        &self,
+        check_id: &str,
+        period: Option<&str>,
+    ) -> Result<LatencyByRegion> {
+        let mut request = self.request(
+            Method::GET,
+            &format!("/checks/{check_id}/latency-by-region"),
+        );
+        if let Some(period) = period {
+            request = request.query(&[("period", period)]);
+        }
+        Ok(Self::send(request).await?.json().await?)
+    }
+
+    pub async fn get_dependencies(&self, check_id: &str) -> Result<Dependencies> {
+        self.get(&format!("/checks/{check_id}/dependencies")).await
+    }
+
+    pub async fn set_dependencies(
+        &self,
+        check_id: &str,
+        dependencies: &Dependencies,
+    ) -> Result<Dependencies> {
+        let request = self
+            .request(Method::PUT, &format!("/checks/{check_id}/dependencies"))
+            .json(dependencies);
+        Ok(Self::send(request).await?.json().await?)
+    }
+
+    pub async fn get_check_notifications(&self, check_id: &str) -> Result<CheckNotifications> {
+        self.get(&format!("/checks/{check_id}/notifications")).await
+    }
+
+    pub async fn set_check_notifications(
+        &self,
+        check_id: &str,
+        notifications: &CheckNotifications,
+    ) -> Result<CheckNotifications> {
+        let request = self
+            .request(Method::PUT, &format!("/checks/{check_id}/notifications"))
+            .json(notifications);
+        Ok(Self::send(request).await?.json().await?)
+    }
+
+    pub async fn list_channels(&self) -> Result<Vec<Channel>> {
+        self.get("/channels").await
+    }
+
+    pub async fn create_channel(&self, channel: &CreateChannel) -> Result<Channel> {
+        self.post("/channels", channel).await
+    }
+
+    pub async fn delete_channel(&self, id: &str) -> Result<()> {
+        self.delete(&format!("/channels/{id}")).await
+    }
+
+    /// Sends a test message to the channel right away.
+    pub async fn test_channel(&self, id: &str) -> Result<()> {
+        Self::send(self.request(Method::POST, &format!("/channels/{id}/test"))).await?;
+        Ok(())
+    }
+
+    pub async fn list_maintenance(&self) -> Result<Vec<MaintenanceWindow>> {
+        self.get("/maintenance").await
+    }
+
+    pub async fn create_maintenance(
+        &self,
+        window: &CreateMaintenanceWindow,
+    ) -> Result<MaintenanceWindow> {
+        self.post("/maintenance", window).await
+    }
+
+    /// Adds the events of an iCalendar file. Floating times are read in `timezone`, UTC when
+    /// `None`; the windows cover every check unless `check_id` is set.
+    pub async fn import_maintenance(
+        &self,
+        ics: &str,
+        check_id: Option<&str>,
+        timezone: Option<&str>,
+    ) -> Result<ImportedMaintenance> {
+        let mut request = self
+            .request(Method::POST, "/maintenance/import")
+            .header("Content-Type", "text/calendar")
+            .body(ics.to_string());
+        if let Some(check_id) = check_id {
+            request = request.query(&[("check_id", check_id)]);
+        }
+        if let Some(timezone) = timezone {
+            request = request.query(&[("timezone", timezone)]);
+        }
+        Ok(Self::send(request).await?.json().await?)
+    }
+
+    pub async fn delete_maintenance(&self, id: &str) -> Result<()> {
+        self.delete(&format!("/maintenance/{id}")).await
+    }
+
+    /// The maintenance windows as an iCalendar feed.
+    pub async fn maintenance_ics(&self) -> Result<String> {
+        Ok(Self::send(self.request(Method::GET, "/maintenance.ics"))
+            .await?
+            .text()
+            .await?)
+    }
+
+    pub async fn list_status_pages(&self) -> Result<Vec<StatusPage>> {
+        self.get("/status-pages").await
+    }
+
+    pub async fn create_status_page(&self, page: &CreateStatusPage) -> Result<StatusPage> {
+        self.post("/status-pages", page).await
+    }
+
+    pub async fn delete_status_page(&self, id: &str) -> Result<()> {
+        self.delete(&format!("/status-pages/{id}")).await
+    }
+
+    /// The Atom feed of the page's incidents. It is public, so no API key is needed.
+    pub async fn status_page_feed(&self, slug: &str) -> Result<String> {
+        Ok(
+            Self::send(self.request(Method::GET, &format!("/status-pages/{slug}/feed.atom")))
+                .await?
+                .text()
+                .await?,
+        )
+    }
+
+    pub async fn list_groups(&self) -> Result<Vec<CheckGroup>> {
+        self.get("/groups").await
+    }
+
+    pub async fn create_group(&self, group: &CreateCheckGroup) -> Result<CheckGroup> {
+        self.post("/groups", group).await
+    }
+
+    pub async fn delete_group(&self, id: &str) -> Result<()> {
+        self.delete(&format!("/groups/{id}")).await
+    }
+
+    /// `period` is e.g. `24h` or `7d`; 24 hours by default.
+    pub async fn group_status(&self, id: &str, period: Option<&str>) -> Result<GroupStatus> {
+        let mut request = self.request(Method::GET, &format!("/groups/{id}/status"));
+        if let Some(period) = period {
+            request = request.query(&[("period", period)]);
+        }
+        Ok(Self::send(request).await?.json().await?)
+    }
+
+    pub async fn list_secrets(&self) -> Result<Vec<Secret>> {
+        self.get("/secrets").await
+    }
+
+    pub async fn create_secret(&self, secret: &CreateSecret) -> Result<Created> {
+        self.post("/secrets", secret).await
+    }
+
+    pub async fn delete_secret(&self, id: &str) -> Result<()> {
+        self.delete(&format!("/secrets/{id}")).await
+    }
+
+    pub async fn list_users(&self) -> Result<Vec<User>> {
+        self.get("/users").await
+    }
+
+    pub async fn create_user(&self, user: &CreateUser) -> Result<AddedUser> {
+        let response = Self::send(self.request(Method::POST, "/users").json(user)).await?;
+        Ok(match response.status() {
+            reqwest::StatusCode::ACCEPTED => AddedUser::Invited(response.json().await?),
+            _ => AddedUser::Created(response.json().await?),
+        })
+    }
+
+    pub async fn update_user(&self, id: &str, changes: &UpdateUser) -> Result<User> {
+        let request = self
+            .request(Method::PATCH, &format!("/users/{id}"))
+            .json(changes);
+        Ok(Self::send(request).await?.json().await?)
+    }
+
+    pub async fn delete_user(&self, id: &str) -> Result<()> {
+        self.delete(&format!("/users/{id}")).await
+    }
+
+    pub async fn get_user_channels(&self, user_id: &str) -> Result<UserChannels> {
+        self.get(&format!("/users/{user_id}/channels")).await
+    }
+
+    pub async fn set_user_channels(
+        &self,
+        user_id: &str,
+        channels: &UserChannels,
+    ) -> Result<UserChannels> {
+        let request = self
+            .request(Method::PUT, &format!("/users/{user_id}/channels"))
+            .json(channels);
+        Ok(Self::send(request).await?.json().await?)
+    }
+
+    pub async fn list_oncall_schedules(&self) -> Result<Vec<OnCallSchedule>> {
+        self.get("/oncall/schedules").await
+    }
+
+    pub async fn create_oncall_schedule(
+        &self,
+        schedule: &CreateOnCallSchedule,
+    ) -> Result<OnCallSchedule> {
+        self.post("/oncall/schedules", schedule).await
+    }
+
+    pub async fn update_oncall_schedule(
+        &self,
+        id: &str,
+        schedule: &CreateOnCallSchedule,
+    ) -> Result<OnCallSchedule> {
+        let request = self
+            .request(Method::PUT, &format!("/oncall/schedules/{id}"))
+            .json(schedule);
+        Ok(Self::send(request).await?.json().await?)
+    }
+
+    pub async fn delete_oncall_schedule(&self, id: &str) -> Result<()> {
+        self.delete(&format!("/oncall/schedules/{id}")).await
+    }
+
+    pub async fn create_oncall_override(
+        &self,
+        schedule_id: &str,
+        override_: &CreateOnCallOverride,
+    ) -> Result<OnCallOverride> {
+        self.post(
+            &format!("/oncall/schedules/{schedule_id}/overrides"),
+            override_,
+        )
+        .await
+    }
+
+    pub async fn delete_oncall_override(&self, schedule_id: &str, id: &str) -> Result<()> {
+        self.delete(&format!("/oncall/schedules/{schedule_id}/overrides/{id}"))
+            .await
+    }
+
+    /// Who is on call right now for every schedule.
+    pub async fn oncall_now(&self) -> Result<Vec<OnCall>> {
+        self.get("/oncall/now").await
+    }
+
+    pub async fn list_api_keys(&self, user_id: &str) -> Result<Vec<ApiKey>> {
+        self.get(&format!("/users/{user_id}/api-keys")).await
+    }
+
+    pub async fn create_api_key(&self, user_id: &str, name: &str) -> Result<CreatedToken> {
+        self.post(
+            &format!("/users/{user_id}/api-keys"),
+            &serde_json::json!({ "name": name }),
+        )
+        .await
+    }
+
+    pub async fn delete_api_key(&self, id: &str) -> Result<()> {
+        self.delete(&format!("/api-keys/{id}")).await
+    }
+
+    pub async fn list_orgs(&self) -> Result<Vec<Org>> {
+        self.get("/orgs").await
+    }
+
+    pub async fn create_org(&self, name: &str) -> Result<Org> {
+        self.post("/orgs", &serde_json::json!({ "name": name }))
+            .await
+    }
+
+    pub async fn list_invitations(&self) -> Result<Vec<Invitation>> {
+        self.get("/invitations").await
+    }
+
+    pub async fn create_invitation(&self, email: &str, role: &str) -> Result<CreatedInvitation> {
+        self.post(
+            "/invitations",
+            &serde_json::json!({ "email": email, "role": role }),
+        )
+        .await
+    }
+
+    pub async fn accept_invitation(
+        &self,
+        token: &str,
+        name: Option<&str>,
+    ) -> Result<AcceptedInvitation> {
+        self.post(
+            "/invitations/accept",
+            &serde_json::json!({ "token": token, "name": name }),
+        )
+        .await
+    }
+
+    pub async fn audit_log(&self, query: &AuditQuery) -> Result<Vec<AuditEntry>> {
+        let request = self.request(Method::GET, "/audit-log").query(query);
+        Ok(Self::send(request).await?.json().await?)
+    }
+
+    pub async fn plan(&self) -> Result<PlanUsage> {
+        self.get("/plan").await
+    }
+
+    pub async fn probe_defaults(&self) -> Result<ProbeDefaults> {
+        self.get("/probe-defaults").await
+    }
+
+    pub async fn set_probe_defaults(&self, defaults: &ProbeDefaults) -> Result<ProbeDefaults> {
+        let request = self.request(Method::PUT, "/probe-defaults").json(defaults);
+        Ok(Self::send(request).await?.json().await?)
+    }
+
+    /// Returns the Stripe Checkout URL to send the user to.
+    pub async fn checkout(&self, plan: &str) -> Result<String> {
+        let response: serde_json::Value = self
+            .post("/billing/checkout", &serde_json::json!({ "plan": plan }))
+            .await?;
+        Ok(response["url"].as_str().unwrap_or_default().to_string())
+    }
+
+    pub async fn list_agents(&self) -> Result<Vec<Agent>> {
+        self.get("/agents").await
+    }
+
+    pub async fn create_agent(&self, name: &str, region: &str) -> Result<CreatedToken> {
+        self.post(
+            "/agents",
+            &serde_json::json!({ "name": name, "region": region }),
+        )
+        .await
+    }
+
+    pub async fn delete_agent(&self, id: &str) -> Result<()> {
+        self.delete(&format!("/agents/{id}")).await
+    }
+
+    /// Called with the agent's token.
+    pub async fn agent_assignments(&self) -> Result<Vec<AgentAssignment>> {
+        self.get("/agent/assignments").await
+    }
+
+    /// Called with the agent's token.
+    pub async fn submit_agent_results(&self, results: &[AgentResult]) -> Result<()> {
+        let request = self
+            .request(Method::POST, "/agent/results")
+            .json(&serde_json::json!({ "results": results }));
+        Self::send(request).await?;
+        Ok(())
+    }
+
+    pub async fn list_ingest_sources(&self) -> Result<Vec<IngestSource>> {
+        self.get("/ingest/sources").await
+    }
+
+    /// `source` is `generic`, `pingdom` or `uptimerobot`; the returned token goes in that
+    /// monitor's webhook.
+    pub async fn create_ingest_source(&self, source: &str) -> Result<CreatedToken> {
+        self.post("/ingest/sources", &serde_json::json!({ "source": source }))
+            .await
+    }
+
+    pub async fn delete_ingest_source(&self, id: &str) -> Result<()> {
+        self.delete(&format!("/ingest/sources/{id}")).await
+    }
+
+    /// Records a result from another monitor with the `token` of its ingest source, `payload`
+    /// being its webhook body. Without `check_id`, the check is the one with the payload's
+    /// URL.
+    pub async fn ingest_external(
+        &self,
+        token: &str,
+        check_id: Option<&str>,
+        payload: &serde_json::Value,
+    ) -> Result<()> {
+        let mut request = self
+            .request(Method::POST, "/ingest/external")
+            .header("X-Ingest-Token", token);
+        if let Some(check_id) = check_id {
+            request = request.query(&[("check_id", check_id)]);
+        }
+        Self::send(request.json(payload)).await?;
+        Ok(())
+    }
+
+    /// Makes the organization's checks, channels and status pages match `spec`, an object
+    /// with any of `checks`, `channels` and `status_pages`; with `dry_run` only the plan is
+    /// returned.
+    pub async fn apply(&self, spec: &serde_json::Value, dry_run: bool) -> Result<ApplyPlan> {
+        let request = self
+            .request(Method::POST, "/apply")
+            .query(&[("dry_run", dry_run)])
+            .json(spec);
+        Ok(Self::send(request).await?.json().await?)
+    }
+
+    pub async fn worker_status(&self) -> Result<WorkerStatus> {
+        self.get("/admin/worker").await
+    }
+
+    pub async fn admin_stats(&self) -> Result<AdminStats> {
+        self.get("/admin/stats").await
+    }
+
+    /// The organization's outbox; without `status`, the notifications that haven't been sent.
+    pub async fn list_notifications(&self, status: Option<&str>) -> Result<Vec<Notification>> {
+        let mut request = self.request(Method::GET, "/admin/notifications");
+        if let Some(status) = status {
+            request = request.query(&[("status", status)]);
+        }
+        Ok(Self::send(request).await?.json().await?)
+    }
+
+    /// Every organization's outbox, for the instance's operators.
+    pub async fn list_all_notifications(&self, status: Option<&str>) -> Result<Vec<Notification>> {
+        let mut request = self
+            .request(Method::GET, "/admin/notifications")
+            .query(&[("all", "true")]);
+        if let Some(status) = status {
+            request = request.query(&[("status", status)]);
+        }
+        Ok(Self::send(request).await?.json().await?)
+    }
+
+    pub async fn retry_notification(&self, id: &str) -> Result<Notification> {
+        Ok(
+            Self::send(self.request(Method::POST, &format!("/admin/notifications/{id}/retry")))
+                .await?
+                .json()
+                .await?,
+        )
+    }
+
+    pub async fn backup(&self) -> Result<Backup> {
+        Ok(Self::send(self.request(Method::POST, "/admin/backup"))
+            .await?
+            .json()
+            .await?)
+    }
+}
@@ -0,0 +1,809 @@
+//! Request and response bodies of the API. Timestamps are RFC 3339 strings, as the server
+//! sends them.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Check {
+    pub id: String,
+    pub name: String,
+    pub url: String,
+    pub interval_seconds: i64,
+    pub alert_email: Option<String>,
+    pub is_active: i64,
+    pub last_status: Option<String>,
+    pub last_checked_at: Option<String>,
+    pub check_type: String,
+    pub content_selector: Option<String>,
+    pub content_hash: Option<String>,
+    pub dns_resolver: Option<String>,
+    pub ip_version: Option<i64>,
+    pub proxy_url: Option<String>,
+    pub client_cert_pem: Option<String>,
+    pub client_key_secret_id: Option<String>,
+    pub auth_header_secret_id: Option<String>,
+    pub regions: Option<String>,
+    pub quorum: Option<i64>,
+    pub quorum_window_seconds: Option<i64>,
+    pub leased_by: Option<String>,
+    pub leased_until: Option<String>,
+    pub jitter_seconds: Option<i64>,
+    pub next_run_at: Option<String>,
+    pub persist_mode: String,
+    pub persist_every: Option<i64>,
+    pub samples_since_persist: i64,
+    pub last_probe_status: Option<String>,
+    pub alert_template: Option<String>,
+    pub org_id: String,
+    /// Pass it back to [`crate::Client::update_check`] and [`crate::Client::delete_check`].
+    pub version: i64,
+    pub updated_at: Option<String>,
+    /// `info`, `warning` or `critical`.
+    pub severity: String,
+    /// Successful responses with a smaller body are DOWN.
+    pub min_response_bytes: Option<i64>,
+    /// Override the organization's [`ProbeDefaults`].
+    pub user_agent: Option<String>,
+    pub headers: Option<BTreeMap<String, String>>,
+    /// Caps the backoff of a check that stays DOWN.
+    pub backoff_max_seconds: Option<i64>,
+    /// DOWN probes in a row.
+    pub down_probes: i64,
+    /// Comma-separated statuses that count as UP besides 2xx.
+    pub accepted_statuses: Option<String>,
+    /// `1.1` or `2`: probes served over another protocol are DOWN.
+    pub http_version: Option<String>,
+    /// IP probes connect to, keeping the URL's host as Host and SNI.
+    pub connect_to: Option<String>,
+    /// Cron expression the check runs on instead of its interval.
+    pub cron: Option<String>,
+    /// IANA zone `cron` is evaluated in; UTC when unset.
+    pub timezone: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CreateCheck {
+    pub name: String,
+    pub url: String,
+    pub interval_seconds: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alert_email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub check_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_selector: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dns_resolver: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ip_version: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_cert: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_key_secret_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth_header_secret_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub regions: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quorum: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quorum_window_seconds: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jitter_seconds: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub persist_mode: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub persist_every: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alert_template: Option<String>,
+    /// `info`, `warning` or `critical` (the default).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub severity: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_response_bytes: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub headers: Option<BTreeMap<String, String>>,
+    /// Once DOWN, the wait between probes doubles up to this many seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backoff_max_seconds: Option<i64>,
+    /// E.g. `401,403` for an endpoint that rejects unauthenticated requests.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub accepted_statuses: Option<String>,
+    /// `1.1` or `2`, e.g. to notice a CDN that stopped serving HTTP/2.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http_version: Option<String>,
+    /// Probe this IP instead of resolving the URL's host, e.g. a new origin before a DNS
+    /// cutover.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connect_to: Option<String>,
+    /// Run at these times instead of every `interval_seconds`, e.g. `*/5 9-17 * * MON-FRI`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cron: Option<String>,
+    /// IANA zone `cron` is evaluated in, e.g. `Europe/Madrid`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+}
+
+/// Only the fields that are set are changed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpdateCheck {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interval_seconds: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alert_email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_active: Option<bool>,
+    /// An empty string clears it, as for `accepted_statuses`, `http_version` and `connect_to`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alert_template: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub severity: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_response_bytes: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub headers: Option<BTreeMap<String, String>>,
+    /// Once DOWN, the wait between probes doubles up to this many seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backoff_max_seconds: Option<i64>,
+    /// E.g. `401,403` for an endpoint that rejects unauthenticated requests.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub accepted_statuses: Option<String>,
+    /// `1.1` or `2`, e.g. to notice a CDN that stopped serving HTTP/2.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http_version: Option<String>,
+    /// Probe this IP instead of resolving the URL's host, e.g. a new origin before a DNS
+    /// cutover.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connect_to: Option<String>,
+    /// An empty string goes back to the interval.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cron: Option<String>,
+    /// An empty string goes back to UTC.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Created {
+    pub id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckResult {
+    pub id: i64,
+    pub check_id: String,
+    pub checked_at: String,
+    pub status: String,
+    pub http_status: Option<i64>,
+    pub latency_ms: Option<i64>,
+    pub error: Option<String>,
+    pub content_hash: Option<String>,
+    pub location: Option<String>,
+    pub response_bytes: Option<i64>,
+    /// Negotiated with the target, e.g. `HTTP/2`.
+    pub protocol: Option<String>,
+    /// Why the probe failed: `dns`, `connect_timeout`, `connect`, `tls`, `timeout`, `body`,
+    /// `http_5xx`, `http_status`, `assertion` or `config`.
+    #[serde(default)]
+    pub error_kind: Option<String>,
+    /// Reported by another monitor, whose source is the `location`. Left out of stats.
+    #[serde(default)]
+    pub external: bool,
+    /// The annotations of this result and those spanning its time.
+    #[serde(default)]
+    pub annotations: Vec<Annotation>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Annotation {
+    pub id: String,
+    pub check_id: String,
+    pub result_id: Option<i64>,
+    pub incident_id: Option<String>,
+    pub text: String,
+    pub author: String,
+    pub at: String,
+    pub ends_at: Option<String>,
+    /// Also shown on the status pages of the check.
+    pub public: bool,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CreateAnnotation {
+    pub text: String,
+    /// RFC 3339; defaults to the result's or incident's time, or now.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ends_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result_id: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub incident_id: Option<String>,
+    pub public: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Incident {
+    pub id: String,
+    pub check_id: String,
+    pub started_at: String,
+    pub resolved_at: Option<String>,
+    pub acknowledged_at: Option<String>,
+    pub failed_probes: i64,
+    pub last_error: Option<String>,
+    pub max_latency_ms: Option<i64>,
+    pub caused_by_check_id: Option<String>,
+    pub severity: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IncidentUpdate {
+    pub id: String,
+    pub incident_id: String,
+    pub status: String,
+    pub message: String,
+    pub author: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateIncidentUpdate {
+    /// `investigating`, `identified`, `monitoring` or `resolved`.
+    pub status: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckStats {
+    pub period: String,
+    /// The first whole hour of `period`, where the figures start.
+    #[serde(default)]
+    pub since: String,
+    pub samples: i64,
+    pub up_samples: i64,
+    pub uptime_percent: Option<f64>,
+    pub avg_latency_ms: Option<f64>,
+    pub max_latency_ms: Option<i64>,
+    pub avg_response_bytes: Option<f64>,
+    /// Samples taken during maintenance windows, which the other figures leave out.
+    pub planned_samples: i64,
+    /// Failed samples per error kind.
+    #[serde(default)]
+    pub errors_by_kind: BTreeMap<String, i64>,
+    pub timezone: String,
+    pub daily: Vec<DailyUptime>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LatencyByRegion {
+    pub period: String,
+    pub regions: Vec<RegionLatency>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegionLatency {
+    /// `None` for the server's own workers.
+    pub region: Option<String>,
+    pub samples: i64,
+    pub up_samples: i64,
+    pub uptime_percent: Option<f64>,
+    pub avg_latency_ms: Option<f64>,
+    pub max_latency_ms: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DailyUptime {
+    /// `YYYY-MM-DD` in the requested timezone.
+    pub date: String,
+    pub samples: i64,
+    pub up_samples: i64,
+    pub uptime_percent: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Dependencies {
+    pub depends_on: Vec<String>,
+}
+
+/// The channels that receive a check's alerts; when empty, every channel does.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CheckNotifications {
+    pub channel_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Channel {
+    pub id: String,
+    pub name: String,
+    pub kind: String,
+    pub target: String,
+    pub template: Option<String>,
+    pub created_at: String,
+    pub quiet_start: Option<String>,
+    pub quiet_end: Option<String>,
+    pub timezone: String,
+    pub digest_seconds: Option<i64>,
+    pub max_alerts_per_hour: Option<i64>,
+    pub org_id: String,
+    pub min_severity: String,
+    pub secret_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CreateChannel {
+    pub name: String,
+    /// `telegram`, `slack`, `webhook`, `email` or `oncall`; an `oncall` target is a schedule id.
+    pub kind: String,
+    pub target: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quiet_start: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quiet_end: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub digest_seconds: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_alerts_per_hour: Option<i64>,
+    /// Alerts of less severe checks are not sent to the channel; `info` by default.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_severity: Option<String>,
+    /// The bot token of a `telegram` channel or the `Authorization` header of a `webhook`,
+    /// stored encrypted. Use `secret_id` instead to reference an existing secret.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaintenanceWindow {
+    pub id: String,
+    pub org_id: String,
+    /// `None` when it covers every check of the organization.
+    pub check_id: Option<String>,
+    pub title: String,
+    pub starts_at: String,
+    pub ends_at: String,
+    pub rrule: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CreateMaintenanceWindow {
+    pub title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub check_id: Option<String>,
+    /// RFC 3339.
+    pub starts_at: String,
+    pub ends_at: String,
+    /// An iCalendar `RRULE` such as `FREQ=WEEKLY;BYDAY=SU`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rrule: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportedMaintenance {
+    pub imported: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusPage {
+    pub id: String,
+    pub org_id: String,
+    pub slug: String,
+    pub title: String,
+    pub created_at: String,
+    pub check_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CreateStatusPage {
+    /// Lowercase letters, digits and dashes.
+    pub slug: String,
+    pub title: String,
+    pub check_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckGroup {
+    pub id: String,
+    pub org_id: String,
+    pub name: String,
+    pub alert_threshold_percent: Option<i64>,
+    pub degraded: bool,
+    pub created_at: String,
+    pub check_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CreateCheckGroup {
+    pub name: String,
+    pub check_ids: Vec<String>,
+    /// When set, members stop alerting on their own and the group alerts once more than
+    /// this percentage of them is DOWN.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alert_threshold_percent: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupMember {
+    pub id: String,
+    pub name: String,
+    pub status: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupStatus {
+    pub id: String,
+    pub name: String,
+    /// DOWN when any member is.
+    pub status: Option<String>,
+    pub down_checks: i64,
+    pub total_checks: i64,
+    pub degraded: bool,
+    pub period: String,
+    pub uptime_percent: Option<f64>,
+    pub checks: Vec<GroupMember>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Secret {
+    pub id: String,
+    pub name: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateSecret {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct User {
+    pub id: String,
+    pub email: String,
+    pub name: String,
+    pub role: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateUser {
+    pub email: String,
+    pub name: String,
+    /// `viewer`, `editor` or `admin`.
+    pub role: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpdateUser {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OnCallSchedule {
+    pub id: String,
+    pub org_id: String,
+    pub name: String,
+    /// Start of the first member's turn; the next ones hand over every week.
+    pub rotation_start: String,
+    pub created_at: String,
+    /// In rotation order.
+    pub user_ids: Vec<String>,
+    /// The ones that haven't ended yet.
+    pub overrides: Vec<OnCallOverride>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CreateOnCallSchedule {
+    pub name: String,
+    /// RFC 3339.
+    pub rotation_start: String,
+    pub user_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OnCallOverride {
+    pub id: String,
+    pub schedule_id: String,
+    pub user_id: String,
+    pub starts_at: String,
+    pub ends_at: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CreateOnCallOverride {
+    pub user_id: String,
+    /// RFC 3339.
+    pub starts_at: String,
+    pub ends_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OnCall {
+    pub schedule_id: String,
+    pub schedule_name: String,
+    /// `None` when the schedule has no members left.
+    pub user: Option<User>,
+    /// End of the turn, or of the override that put them on call.
+    pub until: Option<String>,
+    pub override_id: Option<String>,
+}
+
+/// The channels an on-call page reaches a user on; when empty, they get an email.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UserChannels {
+    pub channel_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKey {
+    pub id: String,
+    pub user_id: String,
+    pub name: String,
+    pub created_at: String,
+    pub last_used_at: Option<String>,
+}
+
+/// The token is only ever returned here.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreatedToken {
+    pub id: String,
+    pub token: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Org {
+    pub id: String,
+    pub name: String,
+    pub created_at: String,
+    pub plan: String,
+    pub subscription_status: Option<String>,
+}
+
+/// What `POST /users` did: new users join right away, existing ones are invited.
+#[derive(Debug, Clone)]
+pub enum AddedUser {
+    Created(User),
+    Invited(CreatedInvitation),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Invitation {
+    pub id: String,
+    pub org_id: String,
+    pub email: String,
+    pub role: String,
+    pub created_at: String,
+    pub expires_at: String,
+    pub accepted_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreatedInvitation {
+    pub id: String,
+    pub token: String,
+    pub expires_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AcceptedInvitation {
+    pub org_id: String,
+    pub user_id: String,
+    /// Only for users that did not exist yet.
+    pub api_key: Option<CreatedToken>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub org_id: String,
+    pub actor_id: Option<String>,
+    pub actor: String,
+    pub action: String,
+    pub entity_type: String,
+    pub entity_id: String,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AuditQuery {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entity_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entity_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actor: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub action: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub since: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub until: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Plan {
+    pub name: String,
+    pub max_checks: Option<i64>,
+    pub min_interval_seconds: i64,
+    pub retention_days: Option<i64>,
+    pub sms_per_month: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlanUsage {
+    pub plan: Plan,
+    pub checks: i64,
+}
+
+/// Sent by every HTTP probe of the organization unless its check overrides them.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProbeDefaults {
+    pub user_agent: Option<String>,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IngestSource {
+    pub id: String,
+    pub org_id: String,
+    pub source: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Agent {
+    pub id: String,
+    pub name: String,
+    pub region: String,
+    pub created_at: String,
+    pub last_seen_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentAssignment {
+    #[serde(flatten)]
+    pub check: Check,
+    pub client_key: Option<String>,
+    pub auth_header: Option<String>,
+    /// Including the User-Agent.
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentResult {
+    pub check_id: String,
+    pub checked_at: String,
+    pub status: String,
+    pub http_status: Option<i64>,
+    pub latency_ms: Option<i64>,
+    pub error: Option<String>,
+    pub content_hash: Option<String>,
+    #[serde(default)]
+    pub response_bytes: Option<i64>,
+    #[serde(default)]
+    pub protocol: Option<String>,
+    #[serde(default)]
+    pub error_kind: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Probe {
+    pub ok: bool,
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Readiness {
+    pub status: String,
+    pub database: Probe,
+    pub migrations: Probe,
+    pub worker: Probe,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkerStatus {
+    pub instance_id: String,
+    pub stalled: bool,
+    pub last_scheduler_pass_at: Option<String>,
+    pub scheduler_lag_seconds: f64,
+    pub overdue_checks: i64,
+    pub queue_depth: i64,
+    pub jobs_in_flight: i64,
+    pub probes_total: u64,
+    pub probe_errors_total: u64,
+    /// Probes of checks that can't be set up, not counted in `probe_errors_total`.
+    #[serde(default)]
+    pub probe_config_errors_total: u64,
+    pub probe_error_ratio: f64,
+}
+
+/// An alert in the delivery outbox: `pending`, `sent` or `dead` once out of attempts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Notification {
+    pub id: String,
+    pub org_id: String,
+    /// `None` for the instance's Telegram chat or an alert email.
+    pub channel_id: Option<String>,
+    pub text: String,
+    pub body: serde_json::Value,
+    pub status: String,
+    pub attempts: i64,
+    pub next_attempt_at: String,
+    pub last_error: Option<String>,
+    pub created_at: String,
+    pub sent_at: Option<String>,
+    /// The check the alert is about; digests cover several and have none.
+    pub check_id: Option<String>,
+    /// A check's alert email, for notifications that aren't sent to a channel.
+    #[serde(default)]
+    pub email: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdminStats {
+    pub checks: i64,
+    pub active_checks: i64,
+    pub results: i64,
+    pub database_bytes: i64,
+    pub open_incidents: i64,
+    pub notifications_last_24h: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Backup {
+    pub file: String,
+    pub bytes: u64,
+    pub s3_key: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApplyPlan {
+    pub dry_run: bool,
+    pub changes: Vec<ApplyChange>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApplyChange {
+    /// `create`, `update` or `delete`.
+    pub action: String,
+    /// `check`, `channel` or `status_page`.
+    pub kind: String,
+    /// The name, or the slug of a status page.
+    pub key: String,
+    pub id: String,
+    /// What an update changes.
+    #[serde(default)]
+    pub fields: Vec<String>,
+}
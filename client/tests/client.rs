@@ -0,0 +1,161 @@
+use serde_json::{json, Value};
+use uptime_client::{Client, CreateCheck, Error, UpdateCheck};
+use wiremock::matchers::{body_partial_json, header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn check(version: i64) -> Value {
+    json!({
+        "id": "c1",
+        "name": "Homepage",
+        "url": "https://example.com",
+        "interval_seconds": 60,
+        "is_active": 1,
+        "check_type": "http",
+        "persist_mode": "all",
+        "samples_since_persist": 0,
+        "org_id": "default",
+        "version": version,
+        "severity": "critical",
+        "down_probes": 0
+    })
+}
+
+#[tokio::test]
+async fn updates_and_deletes_send_the_version_read() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/checks"))
+        .and(header("authorization", "Bearer secret"))
+        .and(header("x-org-id", "acme"))
+        .and(body_partial_json(
+            json!({ "name": "Homepage", "interval_seconds": 60 }),
+        ))
+        .respond_with(ResponseTemplate::new(201).set_body_json(json!({ "id": "c1" })))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/checks/c1"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("etag", "\"3\"")
+                .set_body_json(check(3)),
+        )
+        .mount(&server)
+        .await;
+    Mock::given(method("PATCH"))
+        .and(path("/checks/c1"))
+        .and(header("if-match", "\"3\""))
+        .and(body_partial_json(json!({ "interval_seconds": 300 })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(check(4)))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("DELETE"))
+        .and(path("/checks/c1"))
+        .and(header("if-match", "\"4\""))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let client = Client::new(format!("{}/", server.uri()))
+        .api_key("secret")
+        .org("acme");
+
+    let created = client
+        .create_check(
+            &CreateCheck {
+                name: "Homepage".into(),
+                url: "https://example.com".into(),
+                interval_seconds: 60,
+                ..Default::default()
+            },
+            None,
+        )
+        .await
+        .unwrap();
+    let read = client.get_check(&created.id).await.unwrap();
+    assert_eq!(read.version, 3);
+    let updated = client
+        .update_check(
+            &read.id,
+            read.version,
+            &UpdateCheck {
+                interval_seconds: Some(300),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    client
+        .delete_check(&updated.id, updated.version)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn idempotency_key_is_passed_through() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/checks"))
+        .and(header("idempotency-key", "retry-1"))
+        .respond_with(ResponseTemplate::new(201).set_body_json(json!({ "id": "c1" })))
+        .expect(2)
+        .mount(&server)
+        .await;
+    let client = Client::new(server.uri());
+    let check = CreateCheck {
+        name: "Homepage".into(),
+        url: "https://example.com".into(),
+        interval_seconds: 60,
+        ..Default::default()
+    };
+
+    for _ in 0..2 {
+        let created = client.create_check(&check, Some("retry-1")).await.unwrap();
+        assert_eq!(created.id, "c1");
+    }
+    // Without a key no header is sent
+    let without = client.create_check(&check, None).await.unwrap_err();
+    assert!(
+        matches!(without, Error::Api { status: 404, .. }),
+        "{without}"
+    );
+}
+
+#[tokio::test]
+async fn error_statuses_carry_the_message() {
+    let server = MockServer::start().await;
+    Mock::given(method("PATCH"))
+        .and(path("/checks/c1"))
+        .respond_with(ResponseTemplate::new(412).set_body_string("versión desactualizada"))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/checks"))
+        .respond_with(ResponseTemplate::new(401).set_body_string("API key requerida"))
+        .mount(&server)
+        .await;
+    let client = Client::new(server.uri());
+
+    let stale = client
+        .update_check("c1", 1, &UpdateCheck::default())
+        .await
+        .unwrap_err();
+    match stale {
+        Error::Api { status, message } => {
+            assert_eq!(status, 412);
+            assert_eq!(message, "versión desactualizada");
+        }
+        other => panic!("unexpected error {other}"),
+    }
+    let unauthorized = client.list_checks().await.unwrap_err();
+    assert_eq!(
+        unauthorized.to_string(),
+        "API returned 401: API key requerida"
+    );
+
+    // Nothing listening is a transport error, not an API one
+    let closed = Client::new("http://127.0.0.1:9").list_checks().await;
+    assert!(matches!(closed, Err(Error::Http(_))));
+}
@@ -0,0 +1,5 @@
+ALTER TABLE checks ADD COLUMN check_type TEXT NOT NULL DEFAULT 'http';
+ALTER TABLE checks ADD COLUMN content_selector TEXT;
+ALTER TABLE checks ADD COLUMN content_hash TEXT;
+
+ALTER TABLE check_results ADD COLUMN content_hash TEXT;
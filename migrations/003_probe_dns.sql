@@ -0,0 +1,2 @@
+ALTER TABLE checks ADD COLUMN dns_resolver TEXT;
+ALTER TABLE checks ADD COLUMN ip_version INTEGER;
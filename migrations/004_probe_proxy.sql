@@ -0,0 +1 @@
+ALTER TABLE checks ADD COLUMN proxy_url TEXT;
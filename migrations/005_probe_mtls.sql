@@ -0,0 +1,2 @@
+ALTER TABLE checks ADD COLUMN client_cert_pem TEXT;
+ALTER TABLE checks ADD COLUMN client_key_encrypted TEXT;
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS secrets (
+  id TEXT PRIMARY KEY,
+  name TEXT NOT NULL,
+  wrapped_key TEXT NOT NULL,
+  ciphertext TEXT NOT NULL,
+  created_at TEXT NOT NULL
+);
+
+ALTER TABLE checks ADD COLUMN client_key_secret_id TEXT REFERENCES secrets(id);
+ALTER TABLE checks ADD COLUMN auth_header_secret_id TEXT REFERENCES secrets(id);
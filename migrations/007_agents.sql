@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS agents (
+  id TEXT PRIMARY KEY,
+  name TEXT NOT NULL,
+  region TEXT NOT NULL,
+  token_hash TEXT NOT NULL UNIQUE,
+  created_at TEXT NOT NULL,
+  last_seen_at TEXT
+);
+
+ALTER TABLE checks ADD COLUMN regions TEXT;
+
+ALTER TABLE check_results ADD COLUMN location TEXT;
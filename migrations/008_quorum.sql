@@ -0,0 +1,2 @@
+ALTER TABLE checks ADD COLUMN quorum INTEGER;
+ALTER TABLE checks ADD COLUMN quorum_window_seconds INTEGER;
@@ -0,0 +1,2 @@
+ALTER TABLE checks ADD COLUMN leased_by TEXT;
+ALTER TABLE checks ADD COLUMN leased_until TEXT;
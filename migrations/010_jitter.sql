@@ -0,0 +1,4 @@
+ALTER TABLE checks ADD COLUMN jitter_seconds INTEGER;
+ALTER TABLE checks ADD COLUMN next_run_at TEXT;
+
+UPDATE checks SET next_run_at = strftime('%Y-%m-%dT%H:%M:%S+00:00', 'now', '+' || (abs(random()) % interval_seconds) || ' seconds');
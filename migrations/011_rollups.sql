@@ -0,0 +1,27 @@
+ALTER TABLE checks ADD COLUMN persist_mode TEXT NOT NULL DEFAULT 'all';
+ALTER TABLE checks ADD COLUMN persist_every INTEGER;
+ALTER TABLE checks ADD COLUMN samples_since_persist INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE checks ADD COLUMN last_probe_status TEXT;
+
+CREATE TABLE IF NOT EXISTS check_rollups (
+  check_id TEXT NOT NULL,
+  bucket_start TEXT NOT NULL,
+  samples INTEGER NOT NULL,
+  up_samples INTEGER NOT NULL,
+  latency_sum INTEGER NOT NULL,
+  latency_samples INTEGER NOT NULL,
+  latency_max INTEGER,
+  PRIMARY KEY (check_id, bucket_start),
+  FOREIGN KEY(check_id) REFERENCES checks(id)
+);
+
+INSERT INTO check_rollups (check_id, bucket_start, samples, up_samples, latency_sum, latency_samples, latency_max)
+SELECT check_id,
+       substr(checked_at, 1, 13) || ':00:00+00:00',
+       COUNT(*),
+       SUM(CASE WHEN status = 'DOWN' THEN 0 ELSE 1 END),
+       COALESCE(SUM(latency_ms), 0),
+       COUNT(latency_ms),
+       MAX(latency_ms)
+FROM check_results
+GROUP BY check_id, substr(checked_at, 1, 13);
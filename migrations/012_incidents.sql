@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS incidents (
+  id TEXT PRIMARY KEY,
+  check_id TEXT NOT NULL,
+  started_at TEXT NOT NULL,
+  resolved_at TEXT,
+  FOREIGN KEY(check_id) REFERENCES checks(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_incidents_check_time ON incidents(check_id, started_at);
@@ -0,0 +1,3 @@
+ALTER TABLE incidents ADD COLUMN failed_probes INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE incidents ADD COLUMN last_error TEXT;
+ALTER TABLE incidents ADD COLUMN max_latency_ms INTEGER;
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS notification_channels (
+  id TEXT PRIMARY KEY,
+  name TEXT NOT NULL,
+  kind TEXT NOT NULL,
+  target TEXT NOT NULL,
+  template TEXT,
+  created_at TEXT NOT NULL
+);
+
+ALTER TABLE checks ADD COLUMN alert_template TEXT;
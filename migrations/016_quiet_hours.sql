@@ -0,0 +1,12 @@
+ALTER TABLE notification_channels ADD COLUMN quiet_start TEXT;
+ALTER TABLE notification_channels ADD COLUMN quiet_end TEXT;
+ALTER TABLE notification_channels ADD COLUMN timezone TEXT NOT NULL DEFAULT 'UTC';
+
+CREATE TABLE IF NOT EXISTS suppressed_alerts (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  channel_id TEXT NOT NULL,
+  check_id TEXT NOT NULL,
+  event TEXT NOT NULL,
+  created_at TEXT NOT NULL,
+  FOREIGN KEY(channel_id) REFERENCES notification_channels(id)
+);
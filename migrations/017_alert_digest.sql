@@ -0,0 +1,2 @@
+ALTER TABLE notification_channels ADD COLUMN digest_seconds INTEGER;
+ALTER TABLE notification_channels ADD COLUMN max_alerts_per_hour INTEGER;
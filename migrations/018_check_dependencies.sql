@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS check_dependencies (
+  check_id TEXT NOT NULL,
+  depends_on_id TEXT NOT NULL,
+  PRIMARY KEY (check_id, depends_on_id),
+  FOREIGN KEY(check_id) REFERENCES checks(id),
+  FOREIGN KEY(depends_on_id) REFERENCES checks(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_check_dependencies_upstream ON check_dependencies(depends_on_id);
+
+ALTER TABLE incidents ADD COLUMN caused_by_check_id TEXT;
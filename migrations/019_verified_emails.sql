@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS verified_emails (
+  email TEXT PRIMARY KEY,
+  verified_at TEXT NOT NULL
+);
@@ -0,0 +1,43 @@
+CREATE TABLE IF NOT EXISTS orgs (
+  id TEXT PRIMARY KEY,
+  name TEXT NOT NULL,
+  created_at TEXT NOT NULL
+);
+
+INSERT INTO orgs (id, name, created_at) VALUES ('default', 'Default', strftime('%Y-%m-%dT%H:%M:%S+00:00', 'now'));
+
+CREATE TABLE IF NOT EXISTS memberships (
+  org_id TEXT NOT NULL,
+  user_id TEXT NOT NULL,
+  role TEXT NOT NULL,
+  created_at TEXT NOT NULL,
+  PRIMARY KEY(org_id, user_id),
+  FOREIGN KEY(org_id) REFERENCES orgs(id),
+  FOREIGN KEY(user_id) REFERENCES users(id)
+);
+
+INSERT INTO memberships (org_id, user_id, role, created_at) SELECT 'default', id, role, created_at FROM users;
+
+ALTER TABLE users DROP COLUMN role;
+
+CREATE TABLE IF NOT EXISTS invitations (
+  id TEXT PRIMARY KEY,
+  org_id TEXT NOT NULL,
+  email TEXT NOT NULL,
+  role TEXT NOT NULL,
+  token_hash TEXT NOT NULL UNIQUE,
+  created_at TEXT NOT NULL,
+  expires_at TEXT NOT NULL,
+  accepted_at TEXT,
+  FOREIGN KEY(org_id) REFERENCES orgs(id)
+);
+
+ALTER TABLE checks ADD COLUMN org_id TEXT NOT NULL DEFAULT 'default';
+
+ALTER TABLE notification_channels ADD COLUMN org_id TEXT NOT NULL DEFAULT 'default';
+
+ALTER TABLE secrets ADD COLUMN org_id TEXT NOT NULL DEFAULT 'default';
+
+CREATE INDEX IF NOT EXISTS idx_checks_org ON checks(org_id);
+
+CREATE INDEX IF NOT EXISTS idx_memberships_user ON memberships(user_id);
@@ -0,0 +1,3 @@
+ALTER TABLE orgs ADD COLUMN plan TEXT NOT NULL DEFAULT 'free';
+
+UPDATE orgs SET plan = 'unlimited' WHERE id = 'default';
@@ -0,0 +1,3 @@
+ALTER TABLE orgs ADD COLUMN stripe_customer_id TEXT;
+ALTER TABLE orgs ADD COLUMN stripe_subscription_id TEXT;
+ALTER TABLE orgs ADD COLUMN subscription_status TEXT;
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS idempotency_keys (
+  org_id TEXT NOT NULL,
+  key TEXT NOT NULL,
+  request_hash TEXT NOT NULL,
+  check_id TEXT,
+  created_at TEXT NOT NULL,
+  PRIMARY KEY(org_id, key)
+);
@@ -0,0 +1,2 @@
+ALTER TABLE checks ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE checks ADD COLUMN updated_at TEXT;
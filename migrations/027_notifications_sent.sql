@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS notifications_sent (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  org_id TEXT NOT NULL,
+  kind TEXT NOT NULL,
+  channel_id TEXT,
+  sent_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_notifications_sent_at ON notifications_sent(sent_at);
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS latency_baselines (
+  check_id TEXT PRIMARY KEY,
+  mean_ms REAL NOT NULL,
+  variance REAL NOT NULL,
+  samples INTEGER NOT NULL,
+  outliers INTEGER NOT NULL DEFAULT 0,
+  anomalous INTEGER NOT NULL DEFAULT 0,
+  last_result_id INTEGER NOT NULL,
+  FOREIGN KEY(check_id) REFERENCES checks(id)
+);
@@ -0,0 +1,16 @@
+CREATE TABLE IF NOT EXISTS maintenance_windows (
+  id TEXT PRIMARY KEY,
+  org_id TEXT NOT NULL,
+  check_id TEXT,
+  title TEXT NOT NULL,
+  starts_at TEXT NOT NULL,
+  ends_at TEXT NOT NULL,
+  rrule TEXT,
+  created_at TEXT NOT NULL,
+  FOREIGN KEY(org_id) REFERENCES orgs(id),
+  FOREIGN KEY(check_id) REFERENCES checks(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_maintenance_windows_org ON maintenance_windows(org_id, starts_at);
+
+ALTER TABLE check_rollups ADD COLUMN planned_samples INTEGER NOT NULL DEFAULT 0;
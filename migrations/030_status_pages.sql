@@ -0,0 +1,16 @@
+CREATE TABLE IF NOT EXISTS status_pages (
+  id TEXT PRIMARY KEY,
+  org_id TEXT NOT NULL,
+  slug TEXT NOT NULL UNIQUE,
+  title TEXT NOT NULL,
+  created_at TEXT NOT NULL,
+  FOREIGN KEY(org_id) REFERENCES orgs(id)
+);
+
+CREATE TABLE IF NOT EXISTS status_page_checks (
+  status_page_id TEXT NOT NULL,
+  check_id TEXT NOT NULL,
+  PRIMARY KEY (status_page_id, check_id),
+  FOREIGN KEY(status_page_id) REFERENCES status_pages(id),
+  FOREIGN KEY(check_id) REFERENCES checks(id)
+);
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS incident_updates (
+  id TEXT PRIMARY KEY,
+  incident_id TEXT NOT NULL,
+  status TEXT NOT NULL,
+  message TEXT NOT NULL,
+  author TEXT NOT NULL,
+  created_at TEXT NOT NULL,
+  FOREIGN KEY(incident_id) REFERENCES incidents(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_incident_updates_incident ON incident_updates(incident_id, created_at);
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS check_notifications (
+  check_id TEXT NOT NULL,
+  channel_id TEXT NOT NULL,
+  PRIMARY KEY (check_id, channel_id),
+  FOREIGN KEY(check_id) REFERENCES checks(id),
+  FOREIGN KEY(channel_id) REFERENCES notification_channels(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_check_notifications_channel ON check_notifications(channel_id);
@@ -0,0 +1,3 @@
+ALTER TABLE checks ADD COLUMN severity TEXT NOT NULL DEFAULT 'critical';
+ALTER TABLE incidents ADD COLUMN severity TEXT NOT NULL DEFAULT 'critical';
+ALTER TABLE notification_channels ADD COLUMN min_severity TEXT NOT NULL DEFAULT 'info';
@@ -0,0 +1,4 @@
+ALTER TABLE checks ADD COLUMN min_response_bytes INTEGER;
+ALTER TABLE check_results ADD COLUMN response_bytes INTEGER;
+ALTER TABLE check_rollups ADD COLUMN bytes_sum INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE check_rollups ADD COLUMN bytes_samples INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,4 @@
+ALTER TABLE orgs ADD COLUMN probe_user_agent TEXT;
+ALTER TABLE orgs ADD COLUMN probe_headers TEXT;
+ALTER TABLE checks ADD COLUMN user_agent TEXT;
+ALTER TABLE checks ADD COLUMN headers TEXT;
@@ -0,0 +1,2 @@
+ALTER TABLE checks ADD COLUMN backoff_max_seconds INTEGER;
+ALTER TABLE checks ADD COLUMN down_probes INTEGER NOT NULL DEFAULT 0;
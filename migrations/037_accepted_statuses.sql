@@ -0,0 +1 @@
+ALTER TABLE checks ADD COLUMN accepted_statuses TEXT;
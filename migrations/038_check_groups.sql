@@ -0,0 +1,19 @@
+CREATE TABLE IF NOT EXISTS check_groups (
+  id TEXT PRIMARY KEY,
+  org_id TEXT NOT NULL,
+  name TEXT NOT NULL,
+  alert_threshold_percent INTEGER,
+  degraded INTEGER NOT NULL DEFAULT 0,
+  created_at TEXT NOT NULL,
+  FOREIGN KEY(org_id) REFERENCES orgs(id)
+);
+
+CREATE TABLE IF NOT EXISTS check_group_members (
+  group_id TEXT NOT NULL,
+  check_id TEXT NOT NULL,
+  PRIMARY KEY (group_id, check_id),
+  FOREIGN KEY(group_id) REFERENCES check_groups(id),
+  FOREIGN KEY(check_id) REFERENCES checks(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_check_group_members_check ON check_group_members(check_id);
@@ -0,0 +1,2 @@
+ALTER TABLE checks ADD COLUMN http_version TEXT;
+ALTER TABLE check_results ADD COLUMN protocol TEXT;
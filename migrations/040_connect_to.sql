@@ -0,0 +1 @@
+ALTER TABLE checks ADD COLUMN connect_to TEXT;
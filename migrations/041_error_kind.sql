@@ -0,0 +1 @@
+ALTER TABLE check_results ADD COLUMN error_kind TEXT;
@@ -0,0 +1,40 @@
+CREATE TABLE IF NOT EXISTS oncall_schedules (
+  id TEXT PRIMARY KEY,
+  org_id TEXT NOT NULL,
+  name TEXT NOT NULL,
+  rotation_start TEXT NOT NULL,
+  created_at TEXT NOT NULL,
+  FOREIGN KEY(org_id) REFERENCES orgs(id)
+);
+
+CREATE TABLE IF NOT EXISTS oncall_members (
+  schedule_id TEXT NOT NULL,
+  position INTEGER NOT NULL,
+  user_id TEXT NOT NULL,
+  PRIMARY KEY (schedule_id, position),
+  FOREIGN KEY(schedule_id) REFERENCES oncall_schedules(id),
+  FOREIGN KEY(user_id) REFERENCES users(id)
+);
+
+CREATE TABLE IF NOT EXISTS oncall_overrides (
+  id TEXT PRIMARY KEY,
+  schedule_id TEXT NOT NULL,
+  user_id TEXT NOT NULL,
+  starts_at TEXT NOT NULL,
+  ends_at TEXT NOT NULL,
+  created_at TEXT NOT NULL,
+  FOREIGN KEY(schedule_id) REFERENCES oncall_schedules(id),
+  FOREIGN KEY(user_id) REFERENCES users(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_oncall_overrides_schedule ON oncall_overrides(schedule_id, ends_at);
+
+CREATE TABLE IF NOT EXISTS user_channels (
+  user_id TEXT NOT NULL,
+  channel_id TEXT NOT NULL,
+  PRIMARY KEY (user_id, channel_id),
+  FOREIGN KEY(user_id) REFERENCES users(id),
+  FOREIGN KEY(channel_id) REFERENCES notification_channels(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_user_channels_channel ON user_channels(channel_id);
@@ -0,0 +1 @@
+ALTER TABLE notification_channels ADD COLUMN secret_id TEXT REFERENCES secrets(id);
@@ -0,0 +1,15 @@
+CREATE TABLE IF NOT EXISTS annotations (
+  id TEXT PRIMARY KEY,
+  check_id TEXT NOT NULL,
+  result_id INTEGER,
+  incident_id TEXT,
+  text TEXT NOT NULL,
+  author TEXT NOT NULL,
+  at TEXT NOT NULL,
+  ends_at TEXT,
+  public INTEGER NOT NULL DEFAULT 0,
+  created_at TEXT NOT NULL,
+  FOREIGN KEY(check_id) REFERENCES checks(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_annotations_check_at ON annotations(check_id, at);
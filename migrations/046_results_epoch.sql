@@ -0,0 +1,7 @@
+ALTER TABLE check_results ADD COLUMN checked_at_ms INTEGER GENERATED ALWAYS AS (CAST(round((julianday(checked_at) - 2440587.5) * 86400000) AS INTEGER)) VIRTUAL;
+
+CREATE INDEX IF NOT EXISTS idx_results_check_epoch ON check_results(check_id, checked_at_ms);
+
+CREATE INDEX IF NOT EXISTS idx_results_check_errors ON check_results(check_id, error_kind, checked_at_ms) WHERE error_kind IS NOT NULL;
+
+DROP INDEX IF EXISTS idx_results_check_time
@@ -0,0 +1,3 @@
+ALTER TABLE checks ADD COLUMN cron TEXT;
+
+ALTER TABLE checks ADD COLUMN timezone TEXT
@@ -0,0 +1,7 @@
+-- Authentication turns on with the first API key and stays on even if every key is deleted
+CREATE TABLE IF NOT EXISTS instance_settings (
+  id INTEGER PRIMARY KEY CHECK (id = 1),
+  auth_enabled INTEGER NOT NULL
+);
+
+INSERT INTO instance_settings (id, auth_enabled) SELECT 1, EXISTS (SELECT 1 FROM api_keys);
@@ -0,0 +1,5 @@
+ALTER TABLE notification_outbox ADD COLUMN check_id TEXT;
+
+UPDATE notification_outbox SET check_id = json_extract(body, '$.alert.check.id');
+
+CREATE INDEX IF NOT EXISTS idx_notification_outbox_check ON notification_outbox(check_id)
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS ingest_sources (
+  id TEXT PRIMARY KEY,
+  org_id TEXT NOT NULL,
+  source TEXT NOT NULL,
+  token_hash TEXT NOT NULL UNIQUE,
+  created_at TEXT NOT NULL
+);
+
+ALTER TABLE check_results ADD COLUMN external INTEGER NOT NULL DEFAULT 0;
+
+UPDATE check_results SET external = 1 WHERE location IN ('generic', 'pingdom', 'uptimerobot') AND location NOT IN (SELECT region FROM agents)
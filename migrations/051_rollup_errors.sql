@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS check_rollup_errors (
+  check_id TEXT NOT NULL,
+  bucket_start TEXT NOT NULL,
+  error_kind TEXT NOT NULL,
+  samples INTEGER NOT NULL,
+  PRIMARY KEY (check_id, bucket_start, error_kind)
+);
+
+INSERT OR IGNORE INTO check_rollup_errors (check_id, bucket_start, error_kind, samples)
+SELECT check_id, strftime('%Y-%m-%dT%H:00:00+00:00', checked_at_ms / 1000, 'unixepoch'), error_kind, COUNT(*)
+FROM check_results WHERE error_kind IS NOT NULL AND external = 0
+GROUP BY 1, 2, 3
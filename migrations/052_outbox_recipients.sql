@@ -0,0 +1,3 @@
+ALTER TABLE notification_outbox ADD COLUMN email TEXT;
+
+ALTER TABLE notification_outbox ADD COLUMN summary TEXT
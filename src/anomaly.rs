@@ -0,0 +1,180 @@
+//! Latency anomaly detection: a background analyzer keeps an exponentially weighted mean and
+//! variance of every check's latency and alerts when probes stay well above that baseline,
+//! so a slowing target is noticed before it goes DOWN.
+
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info};
+
+use crate::domain::{Alert, CheckRow};
+use crate::events::Event;
+use crate::notify::send_alert;
+use crate::AppState;
+
+const ANALYZE_INTERVAL_SECONDS: u64 = 60;
+/// Weight of each new sample in the baseline.
+const ALPHA: f64 = 0.1;
+/// No sample is an outlier until the baseline has seen this many.
+const WARMUP_SAMPLES: i64 = 20;
+const THRESHOLD_STDDEVS: f64 = 3.0;
+/// Below this, a deviation is jitter however stable the target usually is.
+const MIN_DEVIATION_MS: f64 = 50.0;
+/// Outliers in a row that make an anomaly.
+const CONSECUTIVE_OUTLIERS: i64 = 3;
+
+#[derive(Debug, Clone, Default, PartialEq, sqlx::FromRow)]
+pub(crate) struct Baseline {
+    pub(crate) mean_ms: f64,
+    pub(crate) variance: f64,
+    pub(crate) samples: i64,
+    pub(crate) outliers: i64,
+    pub(crate) anomalous: bool,
+    pub(crate) last_result_id: i64,
+}
+
+impl Baseline {
+    /// Folds one latency into the baseline. Outliers are left out so they don't widen the
+    /// band they are judged against, until `WARMUP_SAMPLES` of them in a row make a new normal
+    /// that the baseline starts over from.
+    pub(crate) fn observe(&mut self, latency_ms: i64) {
+        let latency = latency_ms as f64;
+        let deviation = latency - self.mean_ms;
+        let outlier = self.samples >= WARMUP_SAMPLES
+            && deviation > (THRESHOLD_STDDEVS * self.variance.sqrt()).max(MIN_DEVIATION_MS);
+
+        if outlier {
+            self.outliers += 1;
+            self.anomalous = self.outliers >= CONSECUTIVE_OUTLIERS;
+            if self.outliers < WARMUP_SAMPLES {
+                return;
+            }
+            *self = Baseline {
+                last_result_id: self.last_result_id,
+                ..Baseline::default()
+            };
+        }
+        self.outliers = 0;
+        self.anomalous = false;
+        if self.samples == 0 {
+            self.mean_ms = latency;
+        } else {
+            let diff = latency - self.mean_ms;
+            let step = ALPHA * diff;
+            self.mean_ms += step;
+            self.variance = (1.0 - ALPHA) * (self.variance + diff * step);
+        }
+        self.samples += 1;
+    }
+}
+
+pub(crate) async fn anomaly_loop(state: Arc<AppState>) {
+    loop {
+        let checks: Vec<CheckRow> = state
+            .checks
+            .subscribe()
+            .borrow()
+            .values()
+            .cloned()
+            .collect();
+        for check in &checks {
+            if let Err(e) = analyze_check(&state, check).await {
+                error!("Error analyzing latency of {}: {e}", check.name);
+            }
+        }
+        state
+            .clock
+            .sleep(Duration::from_secs(ANALYZE_INTERVAL_SECONDS))
+            .await;
+    }
+}
+
+/// Feeds the results recorded since the last pass into the check's baseline and alerts when
+/// it starts or stops being anomalous. The history of a check seen for the first time only
+/// trains the baseline.
+async fn analyze_check(state: &AppState, check: &CheckRow) -> Result<(), sqlx::Error> {
+    let stored = sqlx::query_as::<_, Baseline>(
+        "SELECT mean_ms, variance, samples, outliers, anomalous, last_result_id FROM latency_baselines WHERE check_id = ?",
+    )
+    .bind(&check.id)
+    .fetch_optional(&state.db)
+    .await?;
+    let cursor = stored.as_ref().map_or(0, |b| b.last_result_id);
+    let results: Vec<(i64, String, Option<i64>)> = sqlx::query_as(
+        "SELECT id, status, latency_ms FROM check_results WHERE check_id = ? AND id > ? AND external = 0 ORDER BY id",
+    )
+    .bind(&check.id)
+    .bind(cursor)
+    .fetch_all(&state.db)
+    .await?;
+    let Some((last_id, ..)) = results.last() else {
+        return Ok(());
+    };
+
+    let mut baseline = stored.clone().unwrap_or_default();
+    let mut latest = None;
+    for (_, status, latency_ms) in &results {
+        // Failed probes are the incidents' business
+        if let (true, Some(latency_ms)) = (status == "UP", latency_ms) {
+            baseline.observe(*latency_ms);
+            latest = Some(*latency_ms);
+        }
+    }
+    baseline.last_result_id = *last_id;
+
+    // Another instance that got here first has already alerted
+    let saved = sqlx::query(
+        r#"
+        INSERT INTO latency_baselines (check_id, mean_ms, variance, samples, outliers, anomalous, last_result_id)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+        ON CONFLICT(check_id) DO UPDATE SET mean_ms = ?2, variance = ?3, samples = ?4, outliers = ?5,
+          anomalous = ?6, last_result_id = ?7
+        WHERE latency_baselines.last_result_id = ?8
+        "#,
+    )
+    .bind(&check.id)
+    .bind(baseline.mean_ms)
+    .bind(baseline.variance)
+    .bind(baseline.samples)
+    .bind(baseline.outliers)
+    .bind(baseline.anomalous)
+    .bind(baseline.last_result_id)
+    .bind(cursor)
+    .execute(&state.db)
+    .await?;
+    let Some(stored) = stored else {
+        return Ok(());
+    };
+    if saved.rows_affected() == 0 || stored.anomalous == baseline.anomalous {
+        return Ok(());
+    }
+
+    let at = state.clock.now();
+    let baseline_ms = baseline.mean_ms.round() as i64;
+    let latency_ms = latest.unwrap_or_default();
+    let mut alert = Alert::new("latency_normal", check, at);
+    alert.latency_ms = latest;
+    alert.baseline_ms = Some(baseline_ms);
+    if baseline.anomalous {
+        info!(
+            "LATENCY ANOMALY: {} {latency_ms} ms, baseline {baseline_ms} ms",
+            check.name
+        );
+        alert.event = "latency_anomaly";
+        state
+            .events
+            .publish(&Event::LatencyAnomaly {
+                check_id: &check.id,
+                check_name: &check.name,
+                url: &check.url,
+                latency_ms,
+                baseline_ms,
+                severity: &check.severity,
+                at,
+            })
+            .await;
+    } else {
+        info!("LATENCY NORMAL: {} {latency_ms} ms", check.name);
+    }
+    send_alert(state, check, &alert).await;
+    Ok(())
+}
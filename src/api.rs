@@ -0,0 +1,5167 @@
+//! HTTP handlers, request/response bodies, authentication and the router.
+
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use axum::{
+    async_trait,
+    body::{Body, Bytes},
+    extract::{ConnectInfo, FromRequestParts, Path, Query, Request, State},
+    http::{
+        header::{AUTHORIZATION, ETAG, IF_MATCH, RETRY_AFTER},
+        request::Parts,
+        HeaderMap, HeaderName, HeaderValue, Method, StatusCode,
+    },
+    middleware::{self, Next},
+    response::{Html, IntoResponse, Response},
+    routing::{delete, get, patch, post, put},
+    Json, Router,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL, Engine};
+use chrono::{DateTime, DurationRound, NaiveTime, Utc};
+use chrono_tz::Tz;
+use futures_util::stream;
+use reqwest::Url;
+use scraper::Selector;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::types::Json as SqlJson;
+use sqlx::SqliteConnection;
+use std::env;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    net::SocketAddr,
+    sync::{atomic::Ordering, Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
+use tower_http::trace::TraceLayer;
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::apply;
+use crate::atom;
+use crate::billing;
+use crate::calendar::{self, Recurrence};
+#[cfg(feature = "clickhouse")]
+use crate::clickhouse;
+use crate::domain::{
+    check_runs_in_region, daily_uptime, failure_cause, format_duration, group_degraded,
+    initial_run_at, normalize_email, parse_period, probe_headers, rollup_bucket, status_transition,
+    validate_accepted_statuses, validate_check_url, validate_connect_to, validate_cron,
+    validate_http_version, validate_identity, validate_min_response_bytes, validate_probe_headers,
+    validate_role, validate_severity, validate_slug, validate_template, worst_status,
+    AgentAssignment, AgentResultsRequest, AgentRow, AnnotationRow, ApiKeyRow, AuditRow, ChannelRow,
+    CheckGroupRow, CheckRow, CheckStats, GroupStatus, IncidentRow, IncidentUpdateRow,
+    IngestSourceRow, InvitationRow, LatencyByRegion, MaintenanceWindowRow, NotificationRow, OrgRow,
+    Plan, ProbeDefaults, RegionLatency, ResultRow, Role, RollupDelta, SecretRow, StatusPageRow,
+    UserRow, CHANNEL_KINDS, CHECK_TYPE_CONTENT_CHANGE, CHECK_TYPE_HTTP, DEFAULT_ORG, ERROR_KINDS,
+    INCIDENT_UPDATE_STATUSES, INVITATION_DAYS, PERSIST_ALL, PERSIST_CHANGES, PLANS,
+};
+use crate::grafana;
+use crate::graphql;
+use crate::ingest;
+use crate::notify::{
+    deliver, notify_status_change, redeliver_notification, request_email_verification,
+    NOTIFICATION_LEASE_SECONDS,
+};
+use crate::oncall;
+use crate::result_log::LoggedResult;
+use crate::scheduler::{load_probe_secrets, parse_resolver_addr, quorum_status, reload_check};
+use crate::store::{
+    auth_enabled, check_annotations, count_checks, create_backup, ensure_org_secret, find_member,
+    group_members, in_maintenance, insert_api_key, load_member, load_secret, org_plan,
+    org_status_pages, probe_defaults, record_audit, record_incident_failure, snapshot,
+    store_secret, upsert_user, AuditEntry, Backup, CheckStore, Db, SecretCipher, MIGRATIONS,
+};
+use crate::AppState;
+
+/// Kept from the client when present, otherwise generated; echoed back on every response.
+pub(crate) const X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+pub(crate) const IDEMPOTENCY_TTL_HOURS: i64 = 24;
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct CreateSecretRequest {
+    pub(crate) name: String,
+    pub(crate) value: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct VerifyEmailQuery {
+    pub(crate) token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct CreateChannelRequest {
+    pub(crate) name: String,
+    pub(crate) kind: String,
+    pub(crate) target: String,
+    pub(crate) template: Option<String>,
+    pub(crate) quiet_start: Option<String>,
+    pub(crate) quiet_end: Option<String>,
+    pub(crate) timezone: Option<String>,
+    pub(crate) digest_seconds: Option<i64>,
+    pub(crate) max_alerts_per_hour: Option<i64>,
+    pub(crate) min_severity: Option<String>,
+    /// Stored encrypted as a new secret; never returned.
+    #[serde(default, skip_serializing)]
+    pub(crate) secret: Option<String>,
+    pub(crate) secret_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct DependenciesRequest {
+    pub(crate) depends_on: Vec<String>,
+}
+
+/// The channels that receive the check's alerts; when empty, every channel of the
+/// organization does.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct CheckNotificationsRequest {
+    pub(crate) channel_ids: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct CreateCheckRequest {
+    pub(crate) name: String,
+    pub(crate) url: String,
+    pub(crate) interval_seconds: i64,
+    pub(crate) alert_email: Option<String>,
+    pub(crate) check_type: Option<String>,
+    pub(crate) content_selector: Option<String>,
+    pub(crate) dns_resolver: Option<String>,
+    pub(crate) ip_version: Option<i64>,
+    pub(crate) proxy_url: Option<String>,
+    pub(crate) client_cert: Option<String>,
+    pub(crate) client_key: Option<String>,
+    pub(crate) client_key_secret_id: Option<String>,
+    pub(crate) auth_header_secret_id: Option<String>,
+    pub(crate) regions: Option<String>,
+    pub(crate) quorum: Option<i64>,
+    pub(crate) quorum_window_seconds: Option<i64>,
+    pub(crate) jitter_seconds: Option<i64>,
+    pub(crate) persist_mode: Option<String>,
+    pub(crate) persist_every: Option<i64>,
+    pub(crate) alert_template: Option<String>,
+    pub(crate) severity: Option<String>,
+    pub(crate) min_response_bytes: Option<i64>,
+    pub(crate) user_agent: Option<String>,
+    pub(crate) headers: Option<BTreeMap<String, String>>,
+    pub(crate) backoff_max_seconds: Option<i64>,
+    pub(crate) accepted_statuses: Option<String>,
+    pub(crate) http_version: Option<String>,
+    pub(crate) connect_to: Option<String>,
+    pub(crate) cron: Option<String>,
+    pub(crate) timezone: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct UpdateCheckRequest {
+    pub(crate) name: Option<String>,
+    pub(crate) url: Option<String>,
+    pub(crate) interval_seconds: Option<i64>,
+    pub(crate) alert_email: Option<String>,
+    pub(crate) is_active: Option<bool>,
+    /// An empty string clears it, as for `accepted_statuses`, `http_version` and `connect_to`.
+    pub(crate) alert_template: Option<String>,
+    pub(crate) severity: Option<String>,
+    pub(crate) min_response_bytes: Option<i64>,
+    pub(crate) user_agent: Option<String>,
+    pub(crate) headers: Option<BTreeMap<String, String>>,
+    pub(crate) backoff_max_seconds: Option<i64>,
+    pub(crate) accepted_statuses: Option<String>,
+    pub(crate) http_version: Option<String>,
+    pub(crate) connect_to: Option<String>,
+    /// An empty string goes back to `interval_seconds`, or to UTC for `timezone`.
+    pub(crate) cron: Option<String>,
+    pub(crate) timezone: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct CreateCheckResponse {
+    pub(crate) id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct StatsQuery {
+    pub(crate) period: Option<String>,
+    /// IANA zone the `daily` breakdown follows; UTC by default.
+    pub(crate) timezone: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct CreateUserRequest {
+    pub(crate) email: String,
+    pub(crate) name: String,
+    pub(crate) role: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct UpdateUserRequest {
+    pub(crate) name: Option<String>,
+    pub(crate) role: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct CreateApiKeyRequest {
+    pub(crate) name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct AuditQuery {
+    pub(crate) entity_type: Option<String>,
+    pub(crate) entity_id: Option<String>,
+    pub(crate) actor: Option<String>,
+    pub(crate) action: Option<String>,
+    pub(crate) since: Option<DateTime<Utc>>,
+    pub(crate) until: Option<DateTime<Utc>>,
+    pub(crate) limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct CreateApiKeyResponse {
+    pub(crate) id: String,
+    pub(crate) token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct CheckoutRequest {
+    pub(crate) plan: String,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct CheckoutResponse {
+    pub(crate) url: String,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct PlanUsage {
+    pub(crate) plan: &'static Plan,
+    pub(crate) checks: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct CreateOrgRequest {
+    pub(crate) name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct CreateInvitationRequest {
+    pub(crate) email: String,
+    pub(crate) role: String,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct CreateInvitationResponse {
+    pub(crate) id: String,
+    pub(crate) token: String,
+    pub(crate) expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct AcceptInvitationRequest {
+    pub(crate) token: String,
+    pub(crate) name: Option<String>,
+}
+
+/// `api_key` is only set when accepting the invitation created the user.
+#[derive(Debug, Serialize)]
+pub(crate) struct AcceptInvitationResponse {
+    pub(crate) org_id: String,
+    pub(crate) user_id: String,
+    pub(crate) api_key: Option<CreateApiKeyResponse>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct CreateAgentRequest {
+    pub(crate) name: String,
+    pub(crate) region: String,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct CreateAgentResponse {
+    pub(crate) id: String,
+    pub(crate) token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct CreateMaintenanceRequest {
+    pub(crate) title: String,
+    /// Unset for the whole organization.
+    pub(crate) check_id: Option<String>,
+    pub(crate) starts_at: DateTime<Utc>,
+    pub(crate) ends_at: DateTime<Utc>,
+    pub(crate) rrule: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ImportMaintenanceQuery {
+    pub(crate) check_id: Option<String>,
+    /// Zone of the calendar's floating dates and times; UTC by default.
+    pub(crate) timezone: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct ImportMaintenanceResponse {
+    pub(crate) imported: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct CreateIncidentUpdateRequest {
+    pub(crate) status: String,
+    pub(crate) message: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct CreateAnnotationRequest {
+    pub(crate) text: String,
+    /// Defaults to the result's or incident's time, or now.
+    pub(crate) at: Option<DateTime<Utc>>,
+    pub(crate) ends_at: Option<DateTime<Utc>>,
+    pub(crate) result_id: Option<i64>,
+    pub(crate) incident_id: Option<String>,
+    #[serde(default)]
+    pub(crate) public: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct CreateGroupRequest {
+    pub(crate) name: String,
+    pub(crate) check_ids: Vec<String>,
+    pub(crate) alert_threshold_percent: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct IngestQuery {
+    /// For monitors whose webhooks can't set headers; `x-ingest-token` otherwise.
+    pub(crate) token: Option<String>,
+    /// Without it, the check is the one with the payload's URL.
+    pub(crate) check_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct CreateIngestSourceRequest {
+    pub(crate) source: String,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct CreateIngestSourceResponse {
+    pub(crate) id: String,
+    pub(crate) source: String,
+    pub(crate) token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct PeriodQuery {
+    pub(crate) period: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ResultsQuery {
+    pub(crate) error_kind: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct CreateStatusPageRequest {
+    pub(crate) slug: String,
+    pub(crate) title: String,
+    pub(crate) check_ids: Vec<String>,
+}
+
+pub(crate) fn router(state: Arc<AppState>) -> anyhow::Result<Router> {
+    let app = Router::new()
+        .route("/", get(index))
+        .route("/app.js", get(app_js))
+        .route("/styles.css", get(styles_css))
+        .route("/health", get(health))
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/verify-email", get(verify_email))
+        .route("/metrics", get(metrics))
+        .route("/checks", post(create_check).get(list_checks))
+        .route(
+            "/checks/:id",
+            get(get_check).patch(update_check).delete(delete_check),
+        )
+        .route("/checks/:id/results", get(list_results))
+        .route("/checks/:id/results.ndjson", get(export_results))
+        .route("/checks/:id/incidents", get(list_incidents))
+        .route(
+            "/checks/:id/annotations",
+            post(create_annotation).get(list_annotations),
+        )
+        .route("/annotations/:id", delete(delete_annotation))
+        .route(
+            "/incidents/:id/updates",
+            post(create_incident_update).get(list_incident_updates),
+        )
+        .route(
+            "/checks/:id/dependencies",
+            get(get_dependencies).put(set_dependencies),
+        )
+        .route(
+            "/checks/:id/notifications",
+            get(get_check_notifications).put(set_check_notifications),
+        )
+        .route("/checks/:id/stats", get(check_stats))
+        .route("/checks/:id/latency-by-region", get(latency_by_region))
+        .route(
+            "/graphql",
+            post(graphql::graphql_handler).get(graphql::graphql_ws),
+        )
+        .route("/channels", post(create_channel).get(list_channels))
+        .route("/channels/:id", delete(delete_channel))
+        .route("/channels/:id/test", post(test_channel))
+        .route(
+            "/oncall/schedules",
+            post(oncall::create_schedule).get(oncall::list_schedules),
+        )
+        .route(
+            "/oncall/schedules/:id",
+            put(oncall::update_schedule).delete(oncall::delete_schedule),
+        )
+        .route(
+            "/oncall/schedules/:id/overrides",
+            post(oncall::create_override),
+        )
+        .route(
+            "/oncall/schedules/:id/overrides/:override_id",
+            delete(oncall::delete_override),
+        )
+        .route("/oncall/now", get(oncall::oncall_now))
+        .route("/secrets", post(create_secret).get(list_secrets))
+        .route("/secrets/:id", delete(delete_secret))
+        .route("/users", post(create_user).get(list_users))
+        .route("/users/:id", patch(update_user).delete(delete_user))
+        .route(
+            "/users/:id/channels",
+            get(oncall::get_user_channels).put(oncall::set_user_channels),
+        )
+        .route(
+            "/users/:id/api-keys",
+            post(create_api_key).get(list_api_keys),
+        )
+        .route("/api-keys/:id", delete(delete_api_key))
+        .route("/orgs", post(create_org).get(list_orgs))
+        .route("/audit-log", get(list_audit_log))
+        .route("/plan", get(get_plan))
+        .route(
+            "/probe-defaults",
+            get(get_probe_defaults).put(set_probe_defaults),
+        )
+        .route("/admin/worker", get(worker_status))
+        .route("/admin/stats", get(admin_stats))
+        .route("/admin/backup", post(trigger_backup))
+        .route("/admin/notifications", get(list_notifications))
+        .route("/admin/notifications/:id/retry", post(retry_notification))
+        .route("/billing/checkout", post(create_checkout))
+        .route("/billing/webhook", post(stripe_webhook))
+        .route(
+            "/invitations",
+            post(create_invitation).get(list_invitations),
+        )
+        .route("/invitations/accept", post(accept_invitation))
+        .route(
+            "/maintenance",
+            post(create_maintenance).get(list_maintenance),
+        )
+        .route("/maintenance/import", post(import_maintenance))
+        .route("/maintenance/:id", delete(delete_maintenance))
+        .route("/maintenance.ics", get(maintenance_feed))
+        .route(
+            "/status-pages",
+            post(create_status_page).get(list_status_pages),
+        )
+        .route(
+            "/status-pages/:id",
+            get(status_page).delete(delete_status_page),
+        )
+        .route("/groups", post(create_group).get(list_groups))
+        .route("/groups/:id", delete(delete_group))
+        .route("/groups/:id/status", get(group_status))
+        .route("/status-pages/:slug/feed.atom", get(status_page_feed))
+        .route("/agents", post(create_agent).get(list_agents))
+        .route("/agents/:id", delete(delete_agent))
+        .route("/agent/assignments", get(agent_assignments))
+        .route("/agent/results", post(agent_results))
+        .route(
+            "/ingest/sources",
+            post(create_ingest_source).get(list_ingest_sources),
+        )
+        .route("/ingest/sources/:id", delete(delete_ingest_source))
+        .route("/ingest/external", post(ingest_external))
+        .route("/apply", post(apply::apply))
+        .route("/grafana", get(grafana::grafana_root))
+        .route("/grafana/search", post(grafana::search))
+        .route("/grafana/query", post(grafana::query))
+        .route("/grafana/annotations", post(grafana::annotations))
+        .fallback(get(fallback))
+        .layer(middleware::from_fn_with_state(state.clone(), rate_limit))
+        .with_state(state);
+    let app = match cors_layer()? {
+        Some(cors) => app.layer(cors),
+        None => app,
+    };
+    let app = app
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(|request: &axum::http::Request<_>| {
+                    let request_id = request
+                        .headers()
+                        .get(X_REQUEST_ID)
+                        .and_then(|value| value.to_str().ok())
+                        .unwrap_or_default();
+                    tracing::info_span!(
+                        "request",
+                        request_id,
+                        method = %request.method(),
+                        path = request.uri().path(),
+                    )
+                })
+                .on_request(())
+                .on_response(
+                    |response: &Response, latency: Duration, _: &tracing::Span| {
+                        info!(
+                            status = response.status().as_u16(),
+                            duration_ms = latency.as_millis() as u64,
+                            "request finished"
+                        );
+                    },
+                ),
+        )
+        .layer(PropagateRequestIdLayer::new(X_REQUEST_ID))
+        .layer(SetRequestIdLayer::new(X_REQUEST_ID, MakeRequestUuid));
+    Ok(app)
+}
+
+/// Enabled by `CORS_ALLOWED_ORIGINS` (comma-separated origins, or `*`).
+/// `CORS_ALLOWED_METHODS` and `CORS_ALLOWED_HEADERS` override the defaults, which cover every
+/// method and header the API uses.
+pub(crate) fn cors_layer() -> anyhow::Result<Option<CorsLayer>> {
+    let Ok(origins) = env::var("CORS_ALLOWED_ORIGINS") else {
+        return Ok(None);
+    };
+    let list = |name: &str, default: &str| -> Vec<String> {
+        env::var(name)
+            .unwrap_or_else(|_| default.to_string())
+            .split(',')
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+            .collect()
+    };
+
+    let origins = if origins.trim() == "*" {
+        AllowOrigin::any()
+    } else {
+        let origins = list("CORS_ALLOWED_ORIGINS", "")
+            .iter()
+            .map(|origin| origin.parse::<HeaderValue>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| anyhow::anyhow!("invalid CORS_ALLOWED_ORIGINS"))?;
+        AllowOrigin::list(origins)
+    };
+    let methods = list("CORS_ALLOWED_METHODS", "GET,POST,PUT,PATCH,DELETE")
+        .iter()
+        .map(|method| method.parse::<Method>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| anyhow::anyhow!("invalid CORS_ALLOWED_METHODS"))?;
+    let headers = list(
+        "CORS_ALLOWED_HEADERS",
+        "authorization,content-type,x-org-id,idempotency-key,if-match",
+    )
+    .iter()
+    .map(|header| header.parse::<HeaderName>())
+    .collect::<Result<Vec<_>, _>>()
+    .map_err(|_| anyhow::anyhow!("invalid CORS_ALLOWED_HEADERS"))?;
+
+    Ok(Some(
+        CorsLayer::new()
+            .allow_origin(origins)
+            .allow_methods(methods)
+            .allow_headers(headers)
+            .expose_headers([RETRY_AFTER, X_REQUEST_ID, ETAG])
+            .max_age(Duration::from_secs(3600)),
+    ))
+}
+
+pub(crate) struct Bucket {
+    pub(crate) tokens: f64,
+    pub(crate) updated: Instant,
+}
+
+/// Token buckets keyed by API key (or agent token) and, for anonymous requests or tokens that
+/// aren't one, client IP. The IP is the peer address, so clients behind a reverse proxy all
+/// share the proxy's bucket, and without connection info they all share `ip:unknown`.
+pub(crate) struct RateLimiter {
+    pub(crate) burst: f64,
+    pub(crate) per_second: f64,
+    pub(crate) buckets: Mutex<HashMap<String, Bucket>>,
+    /// Hashes of tokens recently found in the database, so known keys skip the lookup.
+    pub(crate) known_tokens: Mutex<HashMap<String, Instant>>,
+}
+
+/// How long a token stays known without being used, which bounds how long a revoked key
+/// keeps its own bucket.
+const KNOWN_TOKEN_TTL: Duration = Duration::from_secs(600);
+
+/// Keys are remembered when created and whenever they authenticate, so a known key keeps its
+/// own bucket even while its client IP is limited.
+fn remember_token(state: &AppState, hash: String) {
+    if let Some(limiter) = &state.rate_limiter {
+        limiter.remember(hash);
+    }
+}
+
+impl RateLimiter {
+    /// `RATE_LIMIT_PER_MINUTE` (default 600, `0` disables) refills each bucket, which holds up to
+    /// `RATE_LIMIT_BURST` requests (default 100).
+    pub(crate) fn from_env() -> Option<Self> {
+        let per_minute: f64 = env::var("RATE_LIMIT_PER_MINUTE")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(600.0);
+        if per_minute <= 0.0 {
+            return None;
+        }
+        Some(RateLimiter {
+            burst: env::var("RATE_LIMIT_BURST")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(100.0),
+            per_second: per_minute / 60.0,
+            buckets: Mutex::default(),
+            known_tokens: Mutex::default(),
+        })
+    }
+
+    fn is_known(&self, hash: &str) -> bool {
+        let known = self.known_tokens.lock().unwrap();
+        known
+            .get(hash)
+            .is_some_and(|seen| seen.elapsed() < KNOWN_TOKEN_TTL)
+    }
+
+    fn remember(&self, hash: String) {
+        let now = Instant::now();
+        let mut known = self.known_tokens.lock().unwrap();
+        if known.len() > 10_000 {
+            known.retain(|_, seen| now.duration_since(*seen) < KNOWN_TOKEN_TTL);
+        }
+        known.insert(hash, now);
+    }
+
+    /// `Err` carries how long until the next request would be allowed.
+    pub(crate) fn acquire(&self, key: &str) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() > 10_000 {
+            // Buckets that have refilled completely hold no state worth keeping.
+            buckets.retain(|_, b| {
+                b.tokens + now.duration_since(b.updated).as_secs_f64() * self.per_second
+                    < self.burst
+            });
+        }
+
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        bucket.tokens = (bucket.tokens
+            + now.duration_since(bucket.updated).as_secs_f64() * self.per_second)
+            .min(self.burst);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+        Err(Duration::from_secs_f64(
+            (1.0 - bucket.tokens) / self.per_second,
+        ))
+    }
+}
+
+fn too_many_requests(wait: Duration) -> Response {
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(RETRY_AFTER, wait.as_secs().max(1).to_string())],
+        "demasiadas solicitudes".to_string(),
+    )
+        .into_response()
+}
+
+pub(crate) async fn rate_limit(
+    State(state): State<Arc<AppState>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(limiter) = &state.rate_limiter else {
+        return next.run(request).await;
+    };
+    if matches!(request.uri().path(), "/health" | "/healthz" | "/readyz") {
+        return next.run(request).await;
+    }
+
+    let ip_key = match connect_info {
+        Some(ConnectInfo(addr)) => format!("ip:{}", addr.ip()),
+        None => "ip:unknown".to_string(),
+    };
+    let Some(token) = bearer_token(request.headers()) else {
+        return match limiter.acquire(&ip_key) {
+            Ok(()) => next.run(request).await,
+            Err(wait) => too_many_requests(wait),
+        };
+    };
+    let hash = hash_token(token);
+    if limiter.is_known(&hash) {
+        return match limiter.acquire(&format!("key:{hash}")) {
+            Ok(()) => next.run(request).await,
+            Err(wait) => too_many_requests(wait),
+        };
+    }
+
+    // A made-up token must not get a fresh bucket, or rotating them would dodge the IP limit,
+    // and it pays the IP bucket before the lookup so a flood of them never reaches the database
+    if let Err(wait) = limiter.acquire(&ip_key) {
+        return too_many_requests(wait);
+    }
+    let known: Result<Option<i64>, _> = sqlx::query_scalar(
+        "SELECT 1 FROM api_keys WHERE token_hash = ?1 UNION ALL SELECT 1 FROM agents WHERE token_hash = ?1 LIMIT 1",
+    )
+    .bind(&hash)
+    .fetch_optional(&state.db)
+    .await;
+    match known {
+        Ok(Some(_)) => limiter.remember(hash),
+        Ok(None) => {}
+        Err(e) => error!("Error looking up the rate limit key: {e}"),
+    }
+    next.run(request).await
+}
+
+pub(crate) async fn health() -> &'static str {
+    "ok"
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct Probe {
+    pub(crate) ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) detail: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct Readiness {
+    pub(crate) status: &'static str,
+    pub(crate) database: Probe,
+    pub(crate) migrations: Probe,
+    pub(crate) worker: Probe,
+}
+
+/// A scheduler that has not completed a pass for this long is considered stalled.
+pub(crate) const HEARTBEAT_STALE_SECONDS: i64 = 30;
+
+pub(crate) fn worker_probe(state: &AppState) -> Probe {
+    let heartbeat = state.metrics.scheduler_heartbeat_ms.load(Ordering::Relaxed);
+    if heartbeat == 0 {
+        return Probe {
+            ok: false,
+            detail: Some("scheduler has not run yet".to_string()),
+        };
+    }
+    let age = (state.clock.now().timestamp_millis() - heartbeat) / 1000;
+    Probe {
+        ok: age <= HEARTBEAT_STALE_SECONDS,
+        detail: Some(format!("last scheduler pass {age}s ago")),
+    }
+}
+
+pub(crate) fn probe_response<T: Serialize>(ok: bool, body: T) -> (StatusCode, Json<T>) {
+    let status = if ok {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(body))
+}
+
+/// Liveness: fails only when the scheduler has stalled, which a restart fixes.
+pub(crate) async fn healthz(State(state): State<Arc<AppState>>) -> (StatusCode, Json<Probe>) {
+    let worker = worker_probe(&state);
+    probe_response(worker.ok, worker)
+}
+
+/// Readiness: the database answers, every migration is applied and the scheduler is alive.
+pub(crate) async fn readyz(State(state): State<Arc<AppState>>) -> (StatusCode, Json<Readiness>) {
+    let database = match tokio::time::timeout(
+        Duration::from_secs(2),
+        sqlx::query("SELECT 1").execute(&state.db),
+    )
+    .await
+    {
+        Ok(Ok(_)) => Probe {
+            ok: true,
+            detail: None,
+        },
+        Ok(Err(e)) => Probe {
+            ok: false,
+            detail: Some(e.to_string()),
+        },
+        Err(_) => Probe {
+            ok: false,
+            detail: Some("timed out".to_string()),
+        },
+    };
+    let migrations = match sqlx::query_as::<_, (String,)>("SELECT version FROM schema_migrations")
+        .fetch_all(&state.db)
+        .await
+    {
+        Ok(applied) => {
+            let missing: Vec<&str> = MIGRATIONS
+                .iter()
+                .map(|(version, _)| *version)
+                .filter(|version| !applied.iter().any(|(v,)| v == version))
+                .collect();
+            Probe {
+                ok: missing.is_empty(),
+                detail: (!missing.is_empty()).then(|| format!("missing {}", missing.join(", "))),
+            }
+        }
+        Err(e) => Probe {
+            ok: false,
+            detail: Some(e.to_string()),
+        },
+    };
+    let worker = worker_probe(&state);
+
+    let ok = database.ok && migrations.ok && worker.ok;
+    probe_response(
+        ok,
+        Readiness {
+            status: if ok { "ok" } else { "degraded" },
+            database,
+            migrations,
+            worker,
+        },
+    )
+}
+
+pub(crate) async fn index() -> Html<&'static str> {
+    Html(include_str!("web/index.html"))
+}
+
+pub(crate) async fn app_js() -> impl IntoResponse {
+    (
+        StatusCode::OK,
+        [(axum::http::header::CONTENT_TYPE, "text/javascript")],
+        include_str!("web/app.js"),
+    )
+}
+
+pub(crate) async fn styles_css() -> impl IntoResponse {
+    (
+        StatusCode::OK,
+        [(axum::http::header::CONTENT_TYPE, "text/css")],
+        include_str!("web/styles.css"),
+    )
+}
+
+pub(crate) async fn fallback(uri: axum::http::Uri) -> impl IntoResponse {
+    match uri.path() {
+        path if path.ends_with("app.js") => app_js().await.into_response(),
+        path if path.ends_with("styles.css") => styles_css().await.into_response(),
+        _ => index().await.into_response(),
+    }
+}
+
+/// Retries carrying the same `Idempotency-Key` within `IDEMPOTENCY_TTL_HOURS` get the check
+/// created by the first request instead of a duplicate.
+pub(crate) async fn create_check(
+    State(state): State<Arc<AppState>>,
+    Editor(caller): Editor,
+    headers: HeaderMap,
+    Json(payload): Json<CreateCheckRequest>,
+) -> Result<(StatusCode, Json<CreateCheckResponse>), (StatusCode, String)> {
+    let Some(key) = headers
+        .get("idempotency-key")
+        .and_then(|value| value.to_str().ok())
+    else {
+        let id = insert_check(&state, &caller, payload).await?;
+        return Ok((StatusCode::CREATED, Json(CreateCheckResponse { id })));
+    };
+    if key.is_empty() || key.len() > 255 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Idempotency-Key debe tener entre 1 y 255 caracteres".to_string(),
+        ));
+    }
+    let request_hash = format!(
+        "{:x}",
+        Sha256::digest(serde_json::to_vec(&payload).unwrap_or_default())
+    );
+    let cutoff = Utc::now() - chrono::Duration::hours(IDEMPOTENCY_TTL_HOURS);
+
+    sqlx::query("DELETE FROM idempotency_keys WHERE org_id = ? AND key = ? AND created_at < ?")
+        .bind(&caller.org_id)
+        .bind(key)
+        .bind(cutoff)
+        .execute(&state.db)
+        .await
+        .map_err(internal_error)?;
+    let claimed = sqlx::query(
+        "INSERT INTO idempotency_keys (org_id, key, request_hash, created_at) VALUES (?, ?, ?, ?) ON CONFLICT DO NOTHING",
+    )
+    .bind(&caller.org_id)
+    .bind(key)
+    .bind(&request_hash)
+    .bind(Utc::now())
+    .execute(&state.db)
+    .await
+    .map_err(internal_error)?;
+    if claimed.rows_affected() == 0 {
+        let (stored_hash, check_id): (String, Option<String>) = sqlx::query_as(
+            "SELECT request_hash, check_id FROM idempotency_keys WHERE org_id = ? AND key = ?",
+        )
+        .bind(&caller.org_id)
+        .bind(key)
+        .fetch_one(&state.db)
+        .await
+        .map_err(internal_error)?;
+        if stored_hash != request_hash {
+            return Err((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "Idempotency-Key ya usada con otra solicitud".to_string(),
+            ));
+        }
+        return match check_id {
+            Some(id) => Ok((StatusCode::CREATED, Json(CreateCheckResponse { id }))),
+            None => Err((
+                StatusCode::CONFLICT,
+                "otra solicitud con esta Idempotency-Key está en curso".to_string(),
+            )),
+        };
+    }
+
+    match insert_check(&state, &caller, payload).await {
+        Ok(id) => {
+            sqlx::query("UPDATE idempotency_keys SET check_id = ? WHERE org_id = ? AND key = ?")
+                .bind(&id)
+                .bind(&caller.org_id)
+                .bind(key)
+                .execute(&state.db)
+                .await
+                .map_err(internal_error)?;
+            Ok((StatusCode::CREATED, Json(CreateCheckResponse { id })))
+        }
+        Err(e) => {
+            // Let a corrected retry reuse the key.
+            sqlx::query("DELETE FROM idempotency_keys WHERE org_id = ? AND key = ?")
+                .bind(&caller.org_id)
+                .bind(key)
+                .execute(&state.db)
+                .await
+                .map_err(internal_error)?;
+            Err(e)
+        }
+    }
+}
+
+/// How many locations can report on a check: the server's own probe plus one per region,
+/// either those listed in `regions` or, without it, every region with an agent.
+pub(crate) async fn probe_locations(db: &Db, regions: Option<&str>) -> Result<i64, sqlx::Error> {
+    let regions = match regions {
+        Some(regions) => regions
+            .split(',')
+            .map(str::trim)
+            .filter(|r| !r.is_empty())
+            .collect::<HashSet<_>>()
+            .len() as i64,
+        None => {
+            sqlx::query_scalar("SELECT COUNT(DISTINCT region) FROM agents")
+                .fetch_one(db)
+                .await?
+        }
+    };
+    Ok(regions + 1)
+}
+
+pub(crate) async fn insert_check(
+    state: &AppState,
+    caller: &Caller,
+    payload: CreateCheckRequest,
+) -> Result<String, (StatusCode, String)> {
+    let plan = org_plan(&state.db, &caller.org_id)
+        .await
+        .map_err(internal_error)?;
+    plan.check_interval(state.min_interval_seconds, payload.interval_seconds)?;
+    let cron = validate_cron(payload.cron.as_deref(), payload.timezone.as_deref())?;
+    if let Some(cron) = &cron {
+        plan.check_interval(state.min_interval_seconds, cron.min_gap_seconds(Utc::now()))?;
+    }
+    if let Some(max_checks) = plan.max_checks {
+        let checks = count_checks(&state.db, &caller.org_id)
+            .await
+            .map_err(internal_error)?;
+        if checks >= max_checks {
+            return Err(
+                plan.limit_error(format!("máximo {max_checks} checks"), |plan| {
+                    plan.max_checks.is_none_or(|max| checks < max)
+                }),
+            );
+        }
+    }
+
+    validate_check_url(&payload.url)?;
+
+    let check_type = payload.check_type.as_deref().unwrap_or(CHECK_TYPE_HTTP);
+    if check_type != CHECK_TYPE_HTTP && check_type != CHECK_TYPE_CONTENT_CHANGE {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("check_type debe ser {CHECK_TYPE_HTTP} o {CHECK_TYPE_CONTENT_CHANGE}"),
+        ));
+    }
+    if let Some(selector) = &payload.content_selector {
+        if check_type != CHECK_TYPE_CONTENT_CHANGE {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "content_selector solo aplica a checks content_change".to_string(),
+            ));
+        }
+        Selector::parse(selector).map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                "content_selector inválido".to_string(),
+            )
+        })?;
+    }
+
+    if let Some(resolver) = &payload.dns_resolver {
+        parse_resolver_addr(resolver)
+            .ok_or((StatusCode::BAD_REQUEST, "dns_resolver inválido".to_string()))?;
+    }
+    if !matches!(payload.ip_version, None | Some(4) | Some(6)) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "ip_version debe ser 4 o 6".to_string(),
+        ));
+    }
+    if payload
+        .jitter_seconds
+        .is_some_and(|j| j < 0 || j > payload.interval_seconds)
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "jitter_seconds debe estar entre 0 e interval_seconds".to_string(),
+        ));
+    }
+    let persist_mode = payload.persist_mode.as_deref().unwrap_or(PERSIST_ALL);
+    if persist_mode != PERSIST_ALL && persist_mode != PERSIST_CHANGES {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("persist_mode debe ser {PERSIST_ALL} o {PERSIST_CHANGES}"),
+        ));
+    }
+    if payload.persist_every.is_some_and(|n| n < 1) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "persist_every mínimo: 1".to_string(),
+        ));
+    }
+    if payload.quorum.is_some_and(|q| q < 1) {
+        return Err((StatusCode::BAD_REQUEST, "quorum mínimo: 1".to_string()));
+    }
+    if let Some(quorum) = payload.quorum.filter(|q| *q > 1) {
+        let locations = probe_locations(&state.db, payload.regions.as_deref())
+            .await
+            .map_err(internal_error)?;
+        if quorum > locations {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("quorum máximo: {locations} (la sonda local y una por región)"),
+            ));
+        }
+    }
+    if payload
+        .quorum_window_seconds
+        .is_some_and(|w| w < payload.interval_seconds)
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "quorum_window_seconds debe ser >= interval_seconds".to_string(),
+        ));
+    }
+    if payload
+        .backoff_max_seconds
+        .is_some_and(|m| m < payload.interval_seconds)
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "backoff_max_seconds debe ser >= interval_seconds".to_string(),
+        ));
+    }
+    if let Some(proxy_url) = &payload.proxy_url {
+        let proxy = Url::parse(proxy_url)
+            .map_err(|_| (StatusCode::BAD_REQUEST, "proxy_url inválida".to_string()))?;
+        if !matches!(proxy.scheme(), "http" | "https" | "socks5" | "socks5h") {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "proxy_url debe usar http/https/socks5/socks5h".to_string(),
+            ));
+        }
+    }
+
+    let client_key_secret_id = match (
+        &payload.client_cert,
+        &payload.client_key,
+        &payload.client_key_secret_id,
+    ) {
+        (None, None, None) => None,
+        (Some(cert), Some(key), None) => {
+            validate_identity(cert, key)?;
+            let cipher = require_cipher(state)?;
+            let secret_name = format!("{} client key", payload.name);
+            Some(
+                store_secret(&state.db, cipher, &caller.org_id, &secret_name, key)
+                    .await
+                    .map_err(internal_error)?,
+            )
+        }
+        (Some(cert), None, Some(secret_id)) => {
+            ensure_org_secret(state, caller, secret_id).await?;
+            let key = load_secret(state, secret_id).await?;
+            validate_identity(cert, &key)?;
+            Some(secret_id.clone())
+        }
+        _ => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "client_cert requiere client_key o client_key_secret_id".to_string(),
+            ))
+        }
+    };
+    if let Some(secret_id) = &payload.auth_header_secret_id {
+        ensure_org_secret(state, caller, secret_id).await?;
+        load_secret(state, secret_id).await?;
+    }
+    if let Some(template) = &payload.alert_template {
+        validate_template(template)?;
+    }
+    let severity = payload.severity.as_deref().unwrap_or("critical");
+    validate_severity("severity", severity)?;
+    validate_min_response_bytes(payload.min_response_bytes)?;
+    let headers = validate_probe_headers(payload.user_agent.as_deref(), payload.headers.as_ref())?;
+    if let Some(statuses) = &payload.accepted_statuses {
+        validate_accepted_statuses(statuses)?;
+    }
+    if let Some(version) = &payload.http_version {
+        validate_http_version(version)?;
+    }
+    if let Some(ip) = &payload.connect_to {
+        validate_connect_to(ip)?;
+    }
+    let alert_email = payload
+        .alert_email
+        .as_deref()
+        .map(normalize_email)
+        .transpose()?;
+
+    let id = Uuid::new_v4().to_string();
+
+    sqlx::query(
+        r#"
+        INSERT INTO checks (id, name, url, interval_seconds, alert_email, is_active, check_type, content_selector, dns_resolver, ip_version, proxy_url, client_cert_pem, client_key_secret_id, auth_header_secret_id, regions, quorum, quorum_window_seconds, jitter_seconds, next_run_at, persist_mode, persist_every, alert_template, org_id, severity, min_response_bytes, user_agent, headers, backoff_max_seconds, accepted_statuses, http_version, connect_to, cron, timezone)
+        VALUES (?, ?, ?, ?, ?, 1, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(&id)
+    .bind(&payload.name)
+    .bind(&payload.url)
+    .bind(payload.interval_seconds)
+    .bind(&alert_email)
+    .bind(check_type)
+    .bind(&payload.content_selector)
+    .bind(&payload.dns_resolver)
+    .bind(payload.ip_version)
+    .bind(&payload.proxy_url)
+    .bind(&payload.client_cert)
+    .bind(&client_key_secret_id)
+    .bind(&payload.auth_header_secret_id)
+    .bind(&payload.regions)
+    .bind(payload.quorum)
+    .bind(payload.quorum_window_seconds)
+    .bind(payload.jitter_seconds)
+    .bind(initial_run_at(
+        payload.interval_seconds,
+        cron.as_ref(),
+        state.clock.now(),
+    ))
+    .bind(persist_mode)
+    .bind(payload.persist_every)
+    .bind(&payload.alert_template)
+    .bind(&caller.org_id)
+    .bind(severity)
+    .bind(payload.min_response_bytes)
+    .bind(&payload.user_agent)
+    .bind(headers.map(SqlJson))
+    .bind(payload.backoff_max_seconds)
+    .bind(&payload.accepted_statuses)
+    .bind(&payload.http_version)
+    .bind(&payload.connect_to)
+    .bind(&payload.cron)
+    .bind(&payload.timezone)
+    .execute(&state.db)
+    .await
+    .map_err(internal_error)?;
+    let check = reload_check(state, &id).await.map_err(internal_error)?;
+    record_audit(
+        &state.db,
+        AuditEntry {
+            after: check.as_ref().and_then(snapshot),
+            ..caller.audit("create", "check", &id)
+        },
+    )
+    .await
+    .map_err(internal_error)?;
+    if let Some(email) = &alert_email {
+        request_email_verification(state, email).await?;
+    }
+
+    Ok(id)
+}
+
+pub(crate) async fn get_plan(
+    State(state): State<Arc<AppState>>,
+    Viewer(caller): Viewer,
+) -> Result<Json<PlanUsage>, (StatusCode, String)> {
+    let plan = org_plan(&state.db, &caller.org_id)
+        .await
+        .map_err(internal_error)?;
+    let checks = count_checks(&state.db, &caller.org_id)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(Json(PlanUsage { plan, checks }))
+}
+
+pub(crate) async fn get_probe_defaults(
+    State(state): State<Arc<AppState>>,
+    Viewer(caller): Viewer,
+) -> Result<Json<ProbeDefaults>, (StatusCode, String)> {
+    let defaults = probe_defaults(&state.db, &caller.org_id)
+        .await
+        .map_err(internal_error)?;
+    Ok(Json(defaults))
+}
+
+pub(crate) async fn set_probe_defaults(
+    State(state): State<Arc<AppState>>,
+    Admin(caller): Admin,
+    Json(payload): Json<ProbeDefaults>,
+) -> Result<Json<ProbeDefaults>, (StatusCode, String)> {
+    let headers = validate_probe_headers(payload.user_agent.as_deref(), Some(&payload.headers))?
+        .unwrap_or_default();
+    let defaults = ProbeDefaults {
+        user_agent: payload.user_agent,
+        headers,
+    };
+    let before = probe_defaults(&state.db, &caller.org_id)
+        .await
+        .map_err(internal_error)?;
+
+    let mut tx = state.db.begin().await.map_err(internal_error)?;
+    sqlx::query("UPDATE orgs SET probe_user_agent = ?, probe_headers = ? WHERE id = ?")
+        .bind(&defaults.user_agent)
+        .bind(SqlJson(&defaults.headers))
+        .bind(&caller.org_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(internal_error)?;
+    record_audit(
+        &mut *tx,
+        AuditEntry {
+            before: snapshot(&before),
+            after: snapshot(&defaults),
+            ..caller.audit("update", "probe_defaults", &caller.org_id)
+        },
+    )
+    .await
+    .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+
+    Ok(Json(defaults))
+}
+
+pub(crate) fn require_stripe(state: &AppState) -> Result<&billing::Stripe, (StatusCode, String)> {
+    state.stripe.as_deref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "Stripe no está configurado (STRIPE_SECRET_KEY)".to_string(),
+    ))
+}
+
+pub(crate) fn stripe_error(e: anyhow::Error) -> (StatusCode, String) {
+    error!("Stripe error: {e:#}");
+    (
+        StatusCode::BAD_GATEWAY,
+        "error al contactar Stripe".to_string(),
+    )
+}
+
+/// Returns the Stripe Checkout url for subscribing to `plan`. The plan only changes once the
+/// webhook reports the subscription.
+pub(crate) async fn create_checkout(
+    State(state): State<Arc<AppState>>,
+    Admin(caller): Admin,
+    Json(payload): Json<CheckoutRequest>,
+) -> Result<Json<CheckoutResponse>, (StatusCode, String)> {
+    let stripe = require_stripe(&state)?;
+    if stripe.price_for(&payload.plan).is_none() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("el plan {} no se puede contratar", payload.plan),
+        ));
+    }
+
+    let (name, customer): (String, Option<String>) =
+        sqlx::query_as("SELECT name, stripe_customer_id FROM orgs WHERE id = ?")
+            .bind(&caller.org_id)
+            .fetch_one(&state.db)
+            .await
+            .map_err(internal_error)?;
+    let customer = match customer {
+        Some(customer) => customer,
+        None => {
+            let customer = stripe
+                .create_customer(&caller.org_id, &name)
+                .await
+                .map_err(stripe_error)?;
+            sqlx::query("UPDATE orgs SET stripe_customer_id = ? WHERE id = ?")
+                .bind(&customer)
+                .bind(&caller.org_id)
+                .execute(&state.db)
+                .await
+                .map_err(internal_error)?;
+            customer
+        }
+    };
+
+    let url = stripe
+        .create_checkout_session(
+            &customer,
+            &caller.org_id,
+            &payload.plan,
+            &format!("{}/?billing=success", public_url()),
+            &format!("{}/?billing=cancel", public_url()),
+        )
+        .await
+        .map_err(stripe_error)?;
+    Ok(Json(CheckoutResponse { url }))
+}
+
+/// Moves organizations between plans as Stripe reports their subscriptions. Active, trialing
+/// and past-due subscriptions get the plan of their price; anything else falls back to free.
+pub(crate) async fn stripe_webhook(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let stripe = require_stripe(&state)?;
+    let signature = headers
+        .get("stripe-signature")
+        .and_then(|value| value.to_str().ok())
+        .ok_or((
+            StatusCode::BAD_REQUEST,
+            "Stripe-Signature requerida".to_string(),
+        ))?;
+    let event = stripe
+        .verify_webhook(&body, signature, Utc::now().timestamp())
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("webhook inválido: {e}")))?;
+    let object = &event["data"]["object"];
+
+    let (org_id, plan, status, subscription) = match event["type"].as_str() {
+        Some("checkout.session.completed") => (
+            object["client_reference_id"].as_str(),
+            object["metadata"]["plan"].as_str(),
+            Some("active"),
+            object["subscription"].as_str(),
+        ),
+        Some(
+            "customer.subscription.created"
+            | "customer.subscription.updated"
+            | "customer.subscription.deleted",
+        ) => {
+            let status = object["status"].as_str();
+            let plan = match status {
+                Some("active" | "trialing" | "past_due") => object["items"]["data"][0]["price"]
+                    ["id"]
+                    .as_str()
+                    .and_then(|price| stripe.plan_for(price)),
+                _ => None,
+            };
+            (
+                object["metadata"]["org_id"].as_str(),
+                Some(plan.unwrap_or(PLANS[0].name)),
+                status,
+                object["id"].as_str(),
+            )
+        }
+        _ => return Ok(StatusCode::OK),
+    };
+    let (Some(org_id), Some(plan)) = (org_id, plan) else {
+        tracing::warn!("Ignoring Stripe event {} without organization", event["id"]);
+        return Ok(StatusCode::OK);
+    };
+    let plan = Plan::named(plan).name;
+
+    sqlx::query(
+        "UPDATE orgs SET plan = ?, subscription_status = ?, stripe_subscription_id = COALESCE(?, stripe_subscription_id) WHERE id = ?",
+    )
+    .bind(plan)
+    .bind(status)
+    .bind(subscription)
+    .bind(org_id)
+    .execute(&state.db)
+    .await
+    .map_err(internal_error)?;
+    info!(
+        "Organization {org_id} is on plan {plan} (subscription {})",
+        status.unwrap_or("unknown")
+    );
+    Ok(StatusCode::OK)
+}
+
+/// Checks of other organizations are reported as not found.
+pub(crate) async fn find_check(
+    state: &AppState,
+    caller: &Caller,
+    id: &str,
+) -> Result<CheckRow, (StatusCode, String)> {
+    state
+        .db
+        .org_check(&caller.org_id, id)
+        .await
+        .map_err(internal_error)?
+        .ok_or((StatusCode::NOT_FOUND, "check no encontrado".to_string()))
+}
+
+pub(crate) fn etag(check: &CheckRow) -> [(HeaderName, String); 1] {
+    [(ETAG, format!("\"{}\"", check.version))]
+}
+
+/// Changes must name the version they were based on in `If-Match` (`*` accepts any), so
+/// concurrent edits fail with 412 instead of overwriting each other.
+pub(crate) fn expected_version(
+    headers: &HeaderMap,
+    check: &CheckRow,
+) -> Result<i64, (StatusCode, String)> {
+    let value = headers
+        .get(IF_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .ok_or((
+            StatusCode::PRECONDITION_REQUIRED,
+            "If-Match requerido".to_string(),
+        ))?;
+    let matches = value.split(',').map(str::trim).any(|tag| {
+        tag == "*" || tag.trim_start_matches("W/").trim_matches('"') == check.version.to_string()
+    });
+    if !matches {
+        return Err((
+            StatusCode::PRECONDITION_FAILED,
+            format!("el check cambió (versión actual {})", check.version),
+        ));
+    }
+    Ok(check.version)
+}
+
+pub(crate) async fn get_check(
+    State(state): State<Arc<AppState>>,
+    Viewer(caller): Viewer,
+    Path(id): Path<String>,
+) -> Result<([(HeaderName, String); 1], Json<CheckRow>), (StatusCode, String)> {
+    let check = find_check(&state, &caller, &id).await?;
+    Ok((etag(&check), Json(check)))
+}
+
+pub(crate) async fn update_check(
+    State(state): State<Arc<AppState>>,
+    Editor(caller): Editor,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    Json(payload): Json<UpdateCheckRequest>,
+) -> Result<([(HeaderName, String); 1], Json<CheckRow>), (StatusCode, String)> {
+    let check = find_check(&state, &caller, &id).await?;
+    let version = expected_version(&headers, &check)?;
+
+    let headers = validate_probe_headers(payload.user_agent.as_deref(), payload.headers.as_ref())?;
+    let alert_email = payload
+        .alert_email
+        .as_deref()
+        .map(normalize_email)
+        .transpose()?;
+    // Fields left out keep their value, and an empty string clears the optional ones
+    let optional_field = |value: &Option<String>, current: &Option<String>| match value.as_deref() {
+        Some("") => None,
+        Some(value) => Some(value.to_string()),
+        None => current.clone(),
+    };
+    let merged = CheckRow {
+        url: payload.url.clone().unwrap_or_else(|| check.url.clone()),
+        interval_seconds: payload.interval_seconds.unwrap_or(check.interval_seconds),
+        alert_template: optional_field(&payload.alert_template, &check.alert_template),
+        severity: payload
+            .severity
+            .clone()
+            .unwrap_or_else(|| check.severity.clone()),
+        backoff_max_seconds: payload.backoff_max_seconds.or(check.backoff_max_seconds),
+        accepted_statuses: optional_field(&payload.accepted_statuses, &check.accepted_statuses),
+        http_version: optional_field(&payload.http_version, &check.http_version),
+        connect_to: optional_field(&payload.connect_to, &check.connect_to),
+        cron: optional_field(&payload.cron, &check.cron),
+        timezone: optional_field(&payload.timezone, &check.timezone),
+        ..check.clone()
+    };
+
+    if payload.url.is_some() {
+        validate_check_url(&merged.url)?;
+    }
+    if let Some(template) = &merged.alert_template {
+        validate_template(template)?;
+    }
+    validate_severity("severity", &merged.severity)?;
+    validate_min_response_bytes(payload.min_response_bytes)?;
+    if let Some(statuses) = &merged.accepted_statuses {
+        validate_accepted_statuses(statuses)?;
+    }
+    if let Some(version) = &merged.http_version {
+        validate_http_version(version)?;
+    }
+    if let Some(ip) = &merged.connect_to {
+        validate_connect_to(ip)?;
+    }
+    if payload.interval_seconds.is_some() {
+        org_plan(&state.db, &caller.org_id)
+            .await
+            .map_err(internal_error)?
+            .check_interval(state.min_interval_seconds, merged.interval_seconds)?;
+    }
+    // A new schedule takes effect right away rather than after the run already planned
+    let mut next_run_at = None;
+    if payload.cron.is_some() || payload.timezone.is_some() {
+        let cron = validate_cron(merged.cron.as_deref(), merged.timezone.as_deref())?;
+        if let Some(cron) = &cron {
+            org_plan(&state.db, &caller.org_id)
+                .await
+                .map_err(internal_error)?
+                .check_interval(state.min_interval_seconds, cron.min_gap_seconds(Utc::now()))?;
+        }
+        next_run_at = Some(initial_run_at(
+            merged.interval_seconds,
+            cron.as_ref(),
+            state.clock.now(),
+        ));
+    }
+    if merged
+        .jitter_seconds
+        .is_some_and(|j| j > merged.interval_seconds)
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "jitter_seconds debe estar entre 0 e interval_seconds".to_string(),
+        ));
+    }
+    if merged
+        .quorum_window_seconds
+        .is_some_and(|w| w < merged.interval_seconds)
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "quorum_window_seconds debe ser >= interval_seconds".to_string(),
+        ));
+    }
+    if merged
+        .backoff_max_seconds
+        .is_some_and(|m| m < merged.interval_seconds)
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "backoff_max_seconds debe ser >= interval_seconds".to_string(),
+        ));
+    }
+
+    let result = sqlx::query(
+        r#"
+        UPDATE checks SET name = COALESCE(?, name), url = COALESCE(?, url), interval_seconds = ?,
+          alert_email = COALESCE(?, alert_email), is_active = COALESCE(?, is_active),
+          alert_template = ?, severity = COALESCE(?, severity),
+          min_response_bytes = COALESCE(?, min_response_bytes),
+          user_agent = COALESCE(?, user_agent), headers = COALESCE(?, headers),
+          backoff_max_seconds = COALESCE(?, backoff_max_seconds),
+          accepted_statuses = ?, http_version = ?, connect_to = ?,
+          cron = ?, timezone = ?, next_run_at = COALESCE(?, next_run_at),
+          version = version + 1, updated_at = ?
+        WHERE id = ? AND version = ?
+        "#,
+    )
+    .bind(&payload.name)
+    .bind(&payload.url)
+    .bind(merged.interval_seconds)
+    .bind(&alert_email)
+    .bind(payload.is_active.map(i64::from))
+    .bind(&merged.alert_template)
+    .bind(&payload.severity)
+    .bind(payload.min_response_bytes)
+    .bind(&payload.user_agent)
+    .bind(headers.map(SqlJson))
+    .bind(payload.backoff_max_seconds)
+    .bind(&merged.accepted_statuses)
+    .bind(&merged.http_version)
+    .bind(&merged.connect_to)
+    .bind(&merged.cron)
+    .bind(&merged.timezone)
+    .bind(next_run_at)
+    .bind(Utc::now())
+    .bind(&id)
+    .bind(version)
+    .execute(&state.db)
+    .await
+    .map_err(internal_error)?;
+    if result.rows_affected() == 0 {
+        return Err((
+            StatusCode::PRECONDITION_FAILED,
+            "el check cambió durante la actualización".to_string(),
+        ));
+    }
+
+    if let Some(email) = &alert_email {
+        request_email_verification(&state, email).await?;
+    }
+    let updated = reload_check(&state, &id)
+        .await
+        .map_err(internal_error)?
+        .ok_or((StatusCode::NOT_FOUND, "check no encontrado".to_string()))?;
+    let action = match (check.is_active, updated.is_active) {
+        (1, 0) => "pause",
+        (0, 1) => "resume",
+        _ => "update",
+    };
+    record_audit(
+        &state.db,
+        AuditEntry {
+            before: snapshot(&check),
+            after: snapshot(&updated),
+            ..caller.audit(action, "check", &id)
+        },
+    )
+    .await
+    .map_err(internal_error)?;
+
+    Ok((etag(&updated), Json(updated)))
+}
+
+pub(crate) async fn delete_check(
+    State(state): State<Arc<AppState>>,
+    Editor(caller): Editor,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let check = find_check(&state, &caller, &id).await?;
+    let version = expected_version(&headers, &check)?;
+    let mut tx = state.db.begin().await.map_err(internal_error)?;
+    delete_check_data(&mut tx, &id)
+        .await
+        .map_err(internal_error)?;
+    let result = sqlx::query("DELETE FROM checks WHERE id = ? AND version = ?")
+        .bind(&id)
+        .bind(version)
+        .execute(&mut *tx)
+        .await
+        .map_err(internal_error)?;
+    if result.rows_affected() == 0 {
+        return Err((
+            StatusCode::PRECONDITION_FAILED,
+            "el check cambió durante la eliminación".to_string(),
+        ));
+    }
+    record_audit(
+        &mut *tx,
+        AuditEntry {
+            before: snapshot(&check),
+            ..caller.audit("delete", "check", &id)
+        },
+    )
+    .await
+    .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+
+    state.checks.remove(&id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Everything that refers to a check, to delete before the check itself.
+pub(crate) async fn delete_check_data(
+    conn: &mut SqliteConnection,
+    id: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "DELETE FROM incident_updates WHERE incident_id IN (SELECT id FROM incidents WHERE check_id = ?)",
+    )
+    .bind(id)
+    .execute(&mut *conn)
+    .await?;
+    for table in [
+        "annotations",
+        "check_results",
+        "check_rollups",
+        "check_rollup_errors",
+        "incidents",
+        "suppressed_alerts",
+        "latency_baselines",
+        "maintenance_windows",
+        "status_page_checks",
+        "check_notifications",
+        "check_group_members",
+        "notification_outbox",
+        "idempotency_keys",
+    ] {
+        sqlx::query(&format!("DELETE FROM {table} WHERE check_id = ?"))
+            .bind(id)
+            .execute(&mut *conn)
+            .await?;
+    }
+    sqlx::query("DELETE FROM check_dependencies WHERE check_id = ? OR depends_on_id = ?")
+        .bind(id)
+        .bind(id)
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+pub(crate) async fn list_checks(
+    State(state): State<Arc<AppState>>,
+    Viewer(caller): Viewer,
+) -> Result<Json<Vec<CheckRow>>, (StatusCode, String)> {
+    let rows = state
+        .db
+        .org_checks(&caller.org_id)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(Json(rows))
+}
+
+pub(crate) async fn list_results(
+    State(state): State<Arc<AppState>>,
+    Viewer(caller): Viewer,
+    Path(id): Path<String>,
+    Query(query): Query<ResultsQuery>,
+) -> Result<Json<Vec<ResultRow>>, (StatusCode, String)> {
+    find_check(&state, &caller, &id).await?;
+    let mut rows: Vec<ResultRow> = match query.error_kind {
+        Some(kind) => {
+            if !ERROR_KINDS.contains(&kind.as_str()) {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    format!("error_kind debe ser uno de: {}", ERROR_KINDS.join(", ")),
+                ));
+            }
+            sqlx::query_as(
+                "SELECT * FROM check_results WHERE check_id = ? AND error_kind = ? ORDER BY checked_at_ms DESC, id DESC",
+            )
+            .bind(&id)
+            .bind(kind)
+            .fetch_all(&state.db)
+            .await
+        }
+        None => state.db.results(&id, None).await,
+    }
+    .map_err(internal_error)?;
+    let annotations = check_annotations(&state.db, &id)
+        .await
+        .map_err(internal_error)?;
+    for row in &mut rows {
+        row.annotations = annotations
+            .iter()
+            .filter(|a| a.covers(row))
+            .cloned()
+            .collect();
+    }
+
+    Ok(Json(rows))
+}
+
+pub(crate) async fn create_annotation(
+    State(state): State<Arc<AppState>>,
+    Editor(caller): Editor,
+    Path(id): Path<String>,
+    Json(payload): Json<CreateAnnotationRequest>,
+) -> Result<(StatusCode, Json<AnnotationRow>), (StatusCode, String)> {
+    let check = find_check(&state, &caller, &id).await?;
+    let text = payload.text.trim();
+    if text.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "text requerido".to_string()));
+    }
+    let mut at = payload.at;
+    if let Some(result_id) = payload.result_id {
+        let checked_at: Option<DateTime<Utc>> = sqlx::query_scalar(
+            "SELECT checked_at FROM check_results WHERE id = ? AND check_id = ?",
+        )
+        .bind(result_id)
+        .bind(&check.id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(internal_error)?;
+        let checked_at = checked_at.ok_or((
+            StatusCode::BAD_REQUEST,
+            "result_id no es un resultado del check".to_string(),
+        ))?;
+        at.get_or_insert(checked_at);
+    }
+    if let Some(incident_id) = &payload.incident_id {
+        let incident = find_incident(&state, &caller, incident_id).await?;
+        if incident.check_id != check.id {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "incident_id no es un incidente del check".to_string(),
+            ));
+        }
+        at.get_or_insert(incident.started_at);
+    }
+    let at = at.unwrap_or_else(|| state.clock.now());
+    if payload.ends_at.is_some_and(|ends_at| ends_at < at) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "ends_at debe ser posterior a at".to_string(),
+        ));
+    }
+
+    let annotation = sqlx::query_as::<_, AnnotationRow>(
+        "INSERT INTO annotations (id, check_id, result_id, incident_id, text, author, at, ends_at, public, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?) RETURNING *",
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(&check.id)
+    .bind(payload.result_id)
+    .bind(&payload.incident_id)
+    .bind(text)
+    .bind(
+        caller
+            .user
+            .as_ref()
+            .map_or("anonymous", |user| user.name.as_str()),
+    )
+    .bind(at)
+    .bind(payload.ends_at)
+    .bind(payload.public)
+    .bind(state.clock.now())
+    .fetch_one(&state.db)
+    .await
+    .map_err(internal_error)?;
+    record_audit(
+        &state.db,
+        AuditEntry {
+            after: snapshot(&annotation),
+            ..caller.audit("create", "annotation", &annotation.id)
+        },
+    )
+    .await
+    .map_err(internal_error)?;
+
+    Ok((StatusCode::CREATED, Json(annotation)))
+}
+
+pub(crate) async fn list_annotations(
+    State(state): State<Arc<AppState>>,
+    Viewer(caller): Viewer,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<AnnotationRow>>, (StatusCode, String)> {
+    find_check(&state, &caller, &id).await?;
+    let rows = check_annotations(&state.db, &id)
+        .await
+        .map_err(internal_error)?;
+    Ok(Json(rows))
+}
+
+pub(crate) async fn delete_annotation(
+    State(state): State<Arc<AppState>>,
+    Editor(caller): Editor,
+    Path(id): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let annotation = sqlx::query_as::<_, AnnotationRow>(
+        "SELECT a.* FROM annotations a JOIN checks c ON c.id = a.check_id WHERE a.id = ? AND c.org_id = ?",
+    )
+    .bind(&id)
+    .bind(&caller.org_id)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(internal_error)?
+    .ok_or((StatusCode::NOT_FOUND, "anotación no encontrada".to_string()))?;
+    sqlx::query("DELETE FROM annotations WHERE id = ?")
+        .bind(&id)
+        .execute(&state.db)
+        .await
+        .map_err(internal_error)?;
+    record_audit(
+        &state.db,
+        AuditEntry {
+            before: snapshot(&annotation),
+            ..caller.audit("delete", "annotation", &id)
+        },
+    )
+    .await
+    .map_err(internal_error)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+const EXPORT_PAGE_SIZE: i64 = 500;
+
+/// The whole history of a check, oldest first, one JSON result per line. Pages are only read
+/// as the client consumes the body, so long histories never have to fit in memory.
+pub(crate) async fn export_results(
+    State(state): State<Arc<AppState>>,
+    Viewer(caller): Viewer,
+    Path(id): Path<String>,
+) -> Result<Response, (StatusCode, String)> {
+    find_check(&state, &caller, &id).await?;
+    let db = state.db.clone();
+    let pages = stream::unfold(Some(None), move |cursor| {
+        let db = db.clone();
+        let id = id.clone();
+        async move {
+            let after = cursor?;
+            let rows = match db.results_after(&id, after, EXPORT_PAGE_SIZE).await {
+                Ok(rows) => rows,
+                Err(e) => {
+                    error!(check_id = %id, error = %e, "results export failed");
+                    return Some((Err(e), None));
+                }
+            };
+            let last = rows.last()?;
+            let next =
+                (rows.len() as i64 == EXPORT_PAGE_SIZE).then_some(Some((last.checked_at, last.id)));
+            let mut chunk = Vec::new();
+            for row in &rows {
+                if serde_json::to_writer(&mut chunk, row).is_ok() {
+                    chunk.push(b'\n');
+                }
+            }
+            Some((Ok(Bytes::from(chunk)), next))
+        }
+    });
+
+    Ok((
+        [(axum::http::header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(pages),
+    )
+        .into_response())
+}
+
+pub(crate) async fn get_dependencies(
+    State(state): State<Arc<AppState>>,
+    Viewer(caller): Viewer,
+    Path(id): Path<String>,
+) -> Result<Json<DependenciesRequest>, (StatusCode, String)> {
+    find_check(&state, &caller, &id).await?;
+    let depends_on: Vec<(String,)> =
+        sqlx::query_as("SELECT depends_on_id FROM check_dependencies WHERE check_id = ?")
+            .bind(&id)
+            .fetch_all(&state.db)
+            .await
+            .map_err(internal_error)?;
+
+    Ok(Json(DependenciesRequest {
+        depends_on: depends_on.into_iter().map(|(id,)| id).collect(),
+    }))
+}
+
+/// Replaces the checks this one depends on. Rejects cycles and checks that are unknown or
+/// belong to another organization.
+pub(crate) async fn set_dependencies(
+    State(state): State<Arc<AppState>>,
+    Editor(caller): Editor,
+    Path(id): Path<String>,
+    Json(payload): Json<DependenciesRequest>,
+) -> Result<Json<DependenciesRequest>, (StatusCode, String)> {
+    find_check(&state, &caller, &id).await?;
+    let mut tx = state.db.begin().await.map_err(internal_error)?;
+
+    let before: Vec<String> =
+        sqlx::query_scalar("SELECT depends_on_id FROM check_dependencies WHERE check_id = ?")
+            .bind(&id)
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(internal_error)?;
+    sqlx::query("DELETE FROM check_dependencies WHERE check_id = ?")
+        .bind(&id)
+        .execute(&mut *tx)
+        .await
+        .map_err(internal_error)?;
+    for upstream in &payload.depends_on {
+        let (known, cycle): (i64, i64) = sqlx::query_as(
+            r#"
+            WITH RECURSIVE up(id) AS (
+              SELECT ?1
+              UNION
+              SELECT d.depends_on_id FROM check_dependencies d JOIN up ON d.check_id = up.id
+            )
+            SELECT (SELECT COUNT(*) FROM checks WHERE id = ?1 AND org_id = ?3), (SELECT COUNT(*) FROM up WHERE id = ?2)
+            "#,
+        )
+        .bind(upstream)
+        .bind(&id)
+        .bind(&caller.org_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(internal_error)?;
+        if known == 0 {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("check {upstream} no existe"),
+            ));
+        }
+        if cycle > 0 {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("depender de {upstream} crearía un ciclo"),
+            ));
+        }
+
+        sqlx::query(
+            "INSERT OR IGNORE INTO check_dependencies (check_id, depends_on_id) VALUES (?, ?)",
+        )
+        .bind(&id)
+        .bind(upstream)
+        .execute(&mut *tx)
+        .await
+        .map_err(internal_error)?;
+    }
+    record_audit(
+        &mut *tx,
+        AuditEntry {
+            before: snapshot(&DependenciesRequest { depends_on: before }),
+            after: snapshot(&payload),
+            ..caller.audit("update", "check_dependencies", &id)
+        },
+    )
+    .await
+    .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+
+    Ok(Json(payload))
+}
+
+pub(crate) async fn get_check_notifications(
+    State(state): State<Arc<AppState>>,
+    Viewer(caller): Viewer,
+    Path(id): Path<String>,
+) -> Result<Json<CheckNotificationsRequest>, (StatusCode, String)> {
+    find_check(&state, &caller, &id).await?;
+    let channel_ids = sqlx::query_scalar(
+        "SELECT channel_id FROM check_notifications WHERE check_id = ? ORDER BY channel_id",
+    )
+    .bind(&id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(internal_error)?;
+
+    Ok(Json(CheckNotificationsRequest { channel_ids }))
+}
+
+pub(crate) async fn set_check_notifications(
+    State(state): State<Arc<AppState>>,
+    Editor(caller): Editor,
+    Path(id): Path<String>,
+    Json(mut payload): Json<CheckNotificationsRequest>,
+) -> Result<Json<CheckNotificationsRequest>, (StatusCode, String)> {
+    find_check(&state, &caller, &id).await?;
+    payload.channel_ids.sort();
+    payload.channel_ids.dedup();
+    let mut tx = state.db.begin().await.map_err(internal_error)?;
+
+    sqlx::query("DELETE FROM check_notifications WHERE check_id = ?")
+        .bind(&id)
+        .execute(&mut *tx)
+        .await
+        .map_err(internal_error)?;
+    for channel_id in &payload.channel_ids {
+        let known: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM notification_channels WHERE id = ? AND org_id = ?",
+        )
+        .bind(channel_id)
+        .bind(&caller.org_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(internal_error)?;
+        if known == 0 {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("canal {channel_id} no existe"),
+            ));
+        }
+
+        sqlx::query("INSERT INTO check_notifications (check_id, channel_id) VALUES (?, ?)")
+            .bind(&id)
+            .bind(channel_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(internal_error)?;
+    }
+    record_audit(
+        &mut *tx,
+        AuditEntry {
+            after: snapshot(&payload),
+            ..caller.audit("update", "check_notifications", &id)
+        },
+    )
+    .await
+    .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+
+    Ok(Json(payload))
+}
+
+pub(crate) async fn create_channel(
+    State(state): State<Arc<AppState>>,
+    Admin(caller): Admin,
+    Json(payload): Json<CreateChannelRequest>,
+) -> Result<(StatusCode, Json<ChannelRow>), (StatusCode, String)> {
+    let target = validate_channel(&state, &caller.org_id, &payload).await?;
+    let timezone = payload.timezone.as_deref().unwrap_or("UTC");
+    let min_severity = payload.min_severity.as_deref().unwrap_or("info");
+    let secret_id = match &payload.secret {
+        Some(secret) => {
+            let cipher = require_cipher(&state)?;
+            let secret_name = format!("{} channel secret", payload.name);
+            Some(
+                store_secret(&state.db, cipher, &caller.org_id, &secret_name, secret)
+                    .await
+                    .map_err(internal_error)?,
+            )
+        }
+        None => payload.secret_id.clone(),
+    };
+
+    let channel = sqlx::query_as::<_, ChannelRow>(
+        "INSERT INTO notification_channels (id, name, kind, target, template, created_at, quiet_start, quiet_end, timezone, digest_seconds, max_alerts_per_hour, org_id, min_severity, secret_id) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?) RETURNING *",
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(&payload.name)
+    .bind(&payload.kind)
+    .bind(target)
+    .bind(&payload.template)
+    .bind(Utc::now())
+    .bind(&payload.quiet_start)
+    .bind(&payload.quiet_end)
+    .bind(timezone)
+    .bind(payload.digest_seconds)
+    .bind(payload.max_alerts_per_hour)
+    .bind(&caller.org_id)
+    .bind(min_severity)
+    .bind(secret_id)
+    .fetch_one(&state.db)
+    .await
+    .map_err(internal_error)?;
+    record_audit(
+        &state.db,
+        AuditEntry {
+            after: snapshot(&channel),
+            ..caller.audit("create", "channel", &channel.id)
+        },
+    )
+    .await
+    .map_err(internal_error)?;
+    if channel.kind == "email" {
+        request_email_verification(&state, &channel.target).await?;
+    }
+
+    Ok((StatusCode::CREATED, Json(channel)))
+}
+
+/// Returns the channel's target as it is stored.
+pub(crate) async fn validate_channel(
+    state: &AppState,
+    org_id: &str,
+    payload: &CreateChannelRequest,
+) -> Result<String, (StatusCode, String)> {
+    if !CHANNEL_KINDS.contains(&payload.kind.as_str()) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("kind debe ser uno de: {}", CHANNEL_KINDS.join(", ")),
+        ));
+    }
+    let has_secret = match (&payload.secret, &payload.secret_id) {
+        (None, None) => false,
+        (Some(secret), None) => {
+            if secret.trim().is_empty() {
+                return Err((StatusCode::BAD_REQUEST, "secret vacío".to_string()));
+            }
+            true
+        }
+        (None, Some(secret_id)) => {
+            let secrets: i64 =
+                sqlx::query_scalar("SELECT COUNT(*) FROM secrets WHERE id = ? AND org_id = ?")
+                    .bind(secret_id)
+                    .bind(org_id)
+                    .fetch_one(&state.db)
+                    .await
+                    .map_err(internal_error)?;
+            if secrets == 0 {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    format!("secreto {secret_id} no existe"),
+                ));
+            }
+            true
+        }
+        (Some(_), Some(_)) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "secret y secret_id son excluyentes".to_string(),
+            ))
+        }
+    };
+    if has_secret && !matches!(payload.kind.as_str(), "telegram" | "webhook") {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "secret solo aplica a canales telegram y webhook".to_string(),
+        ));
+    }
+    match payload.kind.as_str() {
+        "telegram" if !has_secret && state.telegram.is_none() => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "Telegram necesita el token del bot en secret (o TELEGRAM_BOT_TOKEN)".to_string(),
+            ))
+        }
+        "email" => {
+            normalize_email(&payload.target)?;
+        }
+        "slack" | "webhook" => {
+            validate_check_url(&payload.target).map_err(|_| {
+                (
+                    StatusCode::BAD_REQUEST,
+                    "target debe ser una url http/https".to_string(),
+                )
+            })?;
+        }
+        "oncall" => {
+            let schedules: i64 = sqlx::query_scalar(
+                "SELECT COUNT(*) FROM oncall_schedules WHERE id = ? AND org_id = ?",
+            )
+            .bind(payload.target.trim())
+            .bind(org_id)
+            .fetch_one(&state.db)
+            .await
+            .map_err(internal_error)?;
+            if schedules == 0 {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    "target debe ser el id de un horario de guardia".to_string(),
+                ));
+            }
+        }
+        _ => {}
+    }
+    if payload.target.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "target requerido".to_string()));
+    }
+    if let Some(template) = &payload.template {
+        validate_template(template)?;
+    }
+    if let Some(timezone) = &payload.timezone {
+        timezone
+            .parse::<Tz>()
+            .map_err(|_| (StatusCode::BAD_REQUEST, "timezone inválida".to_string()))?;
+    }
+    match (&payload.quiet_start, &payload.quiet_end) {
+        (None, None) => {}
+        (Some(start), Some(end))
+            if NaiveTime::parse_from_str(start, "%H:%M").is_ok()
+                && NaiveTime::parse_from_str(end, "%H:%M").is_ok() => {}
+        _ => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "quiet_start y quiet_end deben ir juntos en formato HH:MM".to_string(),
+            ))
+        }
+    }
+
+    if payload.digest_seconds.is_some_and(|s| s < 1) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "digest_seconds mínimo: 1".to_string(),
+        ));
+    }
+    if payload.max_alerts_per_hour.is_some_and(|n| n < 1) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "max_alerts_per_hour mínimo: 1".to_string(),
+        ));
+    }
+    if let Some(min_severity) = &payload.min_severity {
+        validate_severity("min_severity", min_severity)?;
+    }
+
+    if payload.kind == "email" {
+        normalize_email(&payload.target)
+    } else {
+        Ok(payload.target.trim().to_string())
+    }
+}
+
+pub(crate) async fn list_channels(
+    State(state): State<Arc<AppState>>,
+    Admin(caller): Admin,
+) -> Result<Json<Vec<ChannelRow>>, (StatusCode, String)> {
+    let rows = sqlx::query_as::<_, ChannelRow>(
+        "SELECT * FROM notification_channels WHERE org_id = ? ORDER BY created_at",
+    )
+    .bind(&caller.org_id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(internal_error)?;
+
+    Ok(Json(rows))
+}
+
+pub(crate) async fn delete_channel(
+    State(state): State<Arc<AppState>>,
+    Admin(caller): Admin,
+    Path(id): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let mut tx = state.db.begin().await.map_err(internal_error)?;
+    let channel = sqlx::query_as::<_, ChannelRow>(
+        "SELECT * FROM notification_channels WHERE id = ? AND org_id = ?",
+    )
+    .bind(&id)
+    .bind(&caller.org_id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(internal_error)?
+    .ok_or((StatusCode::NOT_FOUND, "canal no encontrado".to_string()))?;
+    delete_channel_rows(&mut tx, &id)
+        .await
+        .map_err(internal_error)?;
+    record_audit(
+        &mut *tx,
+        AuditEntry {
+            before: snapshot(&channel),
+            ..caller.audit("delete", "channel", &id)
+        },
+    )
+    .await
+    .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Sends a test message right away, bypassing quiet hours, digests and the outbox.
+pub(crate) async fn test_channel(
+    State(state): State<Arc<AppState>>,
+    Admin(caller): Admin,
+    Path(id): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let channel = sqlx::query_as::<_, ChannelRow>(
+        "SELECT * FROM notification_channels WHERE id = ? AND org_id = ?",
+    )
+    .bind(&id)
+    .bind(&caller.org_id)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(internal_error)?
+    .ok_or((StatusCode::NOT_FOUND, "canal no encontrado".to_string()))?;
+
+    let text = format!("🔔 Test notification for channel {}", channel.name);
+    let body = serde_json::json!({ "text": text, "event": "test", "channel_id": channel.id });
+    deliver(&state, &channel, &text, &body).await.map_err(|e| {
+        (
+            StatusCode::BAD_GATEWAY,
+            format!("no se pudo enviar el mensaje de prueba: {e}"),
+        )
+    })?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub(crate) async fn delete_channel_rows(
+    conn: &mut SqliteConnection,
+    id: &str,
+) -> Result<(), sqlx::Error> {
+    for table in [
+        "suppressed_alerts",
+        "check_notifications",
+        "user_channels",
+        "notification_outbox",
+    ] {
+        sqlx::query(&format!("DELETE FROM {table} WHERE channel_id = ?"))
+            .bind(id)
+            .execute(&mut *conn)
+            .await?;
+    }
+    sqlx::query("DELETE FROM notification_channels WHERE id = ?")
+        .bind(id)
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+pub(crate) fn public_url() -> String {
+    env::var("PUBLIC_URL")
+        .unwrap_or_else(|_| "http://localhost:8080".to_string())
+        .trim_end_matches('/')
+        .to_string()
+}
+
+pub(crate) async fn verify_email(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<VerifyEmailQuery>,
+) -> Result<Html<String>, (StatusCode, String)> {
+    let email = state.email_tokens.verify(&query.token, Utc::now()).ok_or((
+        StatusCode::BAD_REQUEST,
+        "token inválido o expirado".to_string(),
+    ))?;
+
+    sqlx::query("INSERT OR IGNORE INTO verified_emails (email, verified_at) VALUES (?, ?)")
+        .bind(&email)
+        .bind(Utc::now())
+        .execute(&state.db)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(Html(format!(
+        "<p>{email} verificado. Ya puede recibir alertas.</p>"
+    )))
+}
+
+pub(crate) async fn list_incidents(
+    State(state): State<Arc<AppState>>,
+    Viewer(caller): Viewer,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<IncidentRow>>, (StatusCode, String)> {
+    find_check(&state, &caller, &id).await?;
+    let rows = state
+        .db
+        .incidents(&id, None)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(Json(rows))
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn insert_maintenance_window<'e, E>(
+    executor: E,
+    caller: &Caller,
+    check_id: Option<&str>,
+    title: &str,
+    starts_at: DateTime<Utc>,
+    ends_at: DateTime<Utc>,
+    rrule: Option<&Recurrence>,
+    now: DateTime<Utc>,
+) -> Result<MaintenanceWindowRow, sqlx::Error>
+where
+    E: sqlx::Executor<'e, Database = sqlx::Sqlite>,
+{
+    sqlx::query_as::<_, MaintenanceWindowRow>(
+        "INSERT INTO maintenance_windows (id, org_id, check_id, title, starts_at, ends_at, rrule, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?) RETURNING *",
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(&caller.org_id)
+    .bind(check_id)
+    .bind(title)
+    .bind(starts_at)
+    .bind(ends_at)
+    .bind(rrule.map(Recurrence::to_string))
+    .bind(now)
+    .fetch_one(executor)
+    .await
+}
+
+pub(crate) async fn create_maintenance(
+    State(state): State<Arc<AppState>>,
+    Editor(caller): Editor,
+    Json(payload): Json<CreateMaintenanceRequest>,
+) -> Result<(StatusCode, Json<MaintenanceWindowRow>), (StatusCode, String)> {
+    let title = payload.title.trim();
+    if title.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "title requerido".to_string()));
+    }
+    if payload.ends_at <= payload.starts_at {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "ends_at debe ser posterior a starts_at".to_string(),
+        ));
+    }
+    let rrule = payload
+        .rrule
+        .as_deref()
+        .map(str::parse::<Recurrence>)
+        .transpose()
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("rrule inválida: {e}")))?;
+    if let Some(check_id) = &payload.check_id {
+        find_check(&state, &caller, check_id).await?;
+    }
+
+    let window = insert_maintenance_window(
+        &state.db,
+        &caller,
+        payload.check_id.as_deref(),
+        title,
+        payload.starts_at,
+        payload.ends_at,
+        rrule.as_ref(),
+        state.clock.now(),
+    )
+    .await
+    .map_err(internal_error)?;
+    record_audit(
+        &state.db,
+        AuditEntry {
+            after: snapshot(&window),
+            ..caller.audit("create", "maintenance_window", &window.id)
+        },
+    )
+    .await
+    .map_err(internal_error)?;
+
+    Ok((StatusCode::CREATED, Json(window)))
+}
+
+/// Adds every `VEVENT` of an iCalendar body, e.g. an exported holiday calendar.
+pub(crate) async fn import_maintenance(
+    State(state): State<Arc<AppState>>,
+    Editor(caller): Editor,
+    Query(query): Query<ImportMaintenanceQuery>,
+    body: String,
+) -> Result<(StatusCode, Json<ImportMaintenanceResponse>), (StatusCode, String)> {
+    let floating = match &query.timezone {
+        Some(name) => name
+            .parse::<Tz>()
+            .map_err(|_| (StatusCode::BAD_REQUEST, "timezone inválida".to_string()))?,
+        None => Tz::UTC,
+    };
+    let events = calendar::parse_events(&body, floating)
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("calendario inválido: {e}")))?;
+    if let Some(check_id) = &query.check_id {
+        find_check(&state, &caller, check_id).await?;
+    }
+
+    let now = state.clock.now();
+    let mut tx = state.db.begin().await.map_err(internal_error)?;
+    for event in &events {
+        let title = match event.summary.trim() {
+            "" => "Mantenimiento",
+            summary => summary,
+        };
+        let window = insert_maintenance_window(
+            &mut *tx,
+            &caller,
+            query.check_id.as_deref(),
+            title,
+            event.starts_at,
+            event.ends_at,
+            event.rrule.as_ref(),
+            now,
+        )
+        .await
+        .map_err(internal_error)?;
+        record_audit(
+            &mut *tx,
+            AuditEntry {
+                after: snapshot(&window),
+                ..caller.audit("create", "maintenance_window", &window.id)
+            },
+        )
+        .await
+        .map_err(internal_error)?;
+    }
+    tx.commit().await.map_err(internal_error)?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(ImportMaintenanceResponse {
+            imported: events.len(),
+        }),
+    ))
+}
+
+pub(crate) async fn list_maintenance(
+    State(state): State<Arc<AppState>>,
+    Viewer(caller): Viewer,
+) -> Result<Json<Vec<MaintenanceWindowRow>>, (StatusCode, String)> {
+    let rows = sqlx::query_as::<_, MaintenanceWindowRow>(
+        "SELECT * FROM maintenance_windows WHERE org_id = ? ORDER BY starts_at",
+    )
+    .bind(&caller.org_id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(internal_error)?;
+
+    Ok(Json(rows))
+}
+
+pub(crate) async fn delete_maintenance(
+    State(state): State<Arc<AppState>>,
+    Editor(caller): Editor,
+    Path(id): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let mut tx = state.db.begin().await.map_err(internal_error)?;
+    let window = sqlx::query_as::<_, MaintenanceWindowRow>(
+        "DELETE FROM maintenance_windows WHERE id = ? AND org_id = ? RETURNING *",
+    )
+    .bind(&id)
+    .bind(&caller.org_id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(internal_error)?
+    .ok_or((
+        StatusCode::NOT_FOUND,
+        "ventana de mantenimiento no encontrada".to_string(),
+    ))?;
+    record_audit(
+        &mut *tx,
+        AuditEntry {
+            before: snapshot(&window),
+            ..caller.audit("delete", "maintenance_window", &id)
+        },
+    )
+    .await
+    .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// The organization's maintenance windows as an iCalendar feed to subscribe to.
+pub(crate) async fn maintenance_feed(
+    State(state): State<Arc<AppState>>,
+    Viewer(caller): Viewer,
+) -> Result<Response, (StatusCode, String)> {
+    let windows = sqlx::query_as::<_, MaintenanceWindowRow>(
+        "SELECT * FROM maintenance_windows WHERE org_id = ? ORDER BY starts_at",
+    )
+    .bind(&caller.org_id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(internal_error)?;
+    let checks: HashMap<String, String> = state
+        .db
+        .org_checks(&caller.org_id)
+        .await
+        .map_err(internal_error)?
+        .into_iter()
+        .map(|check| (check.id, check.name))
+        .collect();
+    let uids: Vec<String> = windows
+        .iter()
+        .map(|window| format!("{}@uptime-saas", window.id))
+        .collect();
+    let events: Vec<calendar::FeedEvent> = windows
+        .iter()
+        .zip(&uids)
+        .map(|(window, uid)| calendar::FeedEvent {
+            uid,
+            summary: &window.title,
+            description: window
+                .check_id
+                .as_ref()
+                .and_then(|id| checks.get(id))
+                .map(String::as_str),
+            starts_at: window.starts_at,
+            ends_at: window.ends_at,
+            created_at: window.created_at,
+            rrule: window.rrule.as_deref(),
+        })
+        .collect();
+
+    Ok((
+        [(
+            axum::http::header::CONTENT_TYPE,
+            "text/calendar; charset=utf-8",
+        )],
+        calendar::render("Mantenimiento", &events),
+    )
+        .into_response())
+}
+
+pub(crate) async fn create_status_page(
+    State(state): State<Arc<AppState>>,
+    Editor(caller): Editor,
+    Json(payload): Json<CreateStatusPageRequest>,
+) -> Result<(StatusCode, Json<StatusPageRow>), (StatusCode, String)> {
+    validate_slug(&payload.slug)?;
+    let title = payload.title.trim();
+    if title.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "title requerido".to_string()));
+    }
+    for check_id in &payload.check_ids {
+        if state
+            .db
+            .org_check(&caller.org_id, check_id)
+            .await
+            .map_err(internal_error)?
+            .is_none()
+        {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("check {check_id} no existe"),
+            ));
+        }
+    }
+
+    let mut tx = state.db.begin().await.map_err(internal_error)?;
+    let mut page = sqlx::query_as::<_, StatusPageRow>(
+        "INSERT INTO status_pages (id, org_id, slug, title, created_at) VALUES (?, ?, ?, ?, ?) RETURNING *",
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(&caller.org_id)
+    .bind(&payload.slug)
+    .bind(title)
+    .bind(state.clock.now())
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| match &e {
+        sqlx::Error::Database(db) if db.is_unique_violation() => {
+            (StatusCode::CONFLICT, "slug en uso".to_string())
+        }
+        _ => internal_error(e),
+    })?;
+    for check_id in &payload.check_ids {
+        sqlx::query(
+            "INSERT OR IGNORE INTO status_page_checks (status_page_id, check_id) VALUES (?, ?)",
+        )
+        .bind(&page.id)
+        .bind(check_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(internal_error)?;
+    }
+    page.check_ids = payload.check_ids;
+    page.check_ids.sort();
+    page.check_ids.dedup();
+    record_audit(
+        &mut *tx,
+        AuditEntry {
+            after: snapshot(&page),
+            ..caller.audit("create", "status_page", &page.id)
+        },
+    )
+    .await
+    .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+
+    Ok((StatusCode::CREATED, Json(page)))
+}
+
+pub(crate) async fn list_status_pages(
+    State(state): State<Arc<AppState>>,
+    Viewer(caller): Viewer,
+) -> Result<Json<Vec<StatusPageRow>>, (StatusCode, String)> {
+    let pages = org_status_pages(&state.db, &caller.org_id)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(Json(pages))
+}
+
+pub(crate) async fn delete_status_page(
+    State(state): State<Arc<AppState>>,
+    Editor(caller): Editor,
+    Path(id): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let mut tx = state.db.begin().await.map_err(internal_error)?;
+    let page = sqlx::query_as::<_, StatusPageRow>(
+        "SELECT * FROM status_pages WHERE id = ? AND org_id = ?",
+    )
+    .bind(&id)
+    .bind(&caller.org_id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(internal_error)?
+    .ok_or((
+        StatusCode::NOT_FOUND,
+        "página de estado no encontrada".to_string(),
+    ))?;
+    sqlx::query("DELETE FROM status_page_checks WHERE status_page_id = ?")
+        .bind(&id)
+        .execute(&mut *tx)
+        .await
+        .map_err(internal_error)?;
+    sqlx::query("DELETE FROM status_pages WHERE id = ?")
+        .bind(&id)
+        .execute(&mut *tx)
+        .await
+        .map_err(internal_error)?;
+    record_audit(
+        &mut *tx,
+        AuditEntry {
+            before: snapshot(&page),
+            ..caller.audit("delete", "status_page", &id)
+        },
+    )
+    .await
+    .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub(crate) async fn create_group(
+    State(state): State<Arc<AppState>>,
+    Editor(caller): Editor,
+    Json(payload): Json<CreateGroupRequest>,
+) -> Result<(StatusCode, Json<CheckGroupRow>), (StatusCode, String)> {
+    let name = payload.name.trim();
+    if name.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "name requerido".to_string()));
+    }
+    if payload
+        .alert_threshold_percent
+        .is_some_and(|p| !(0..100).contains(&p))
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "alert_threshold_percent debe estar entre 0 y 99".to_string(),
+        ));
+    }
+    for check_id in &payload.check_ids {
+        if state
+            .db
+            .org_check(&caller.org_id, check_id)
+            .await
+            .map_err(internal_error)?
+            .is_none()
+        {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("check {check_id} no existe"),
+            ));
+        }
+    }
+
+    let mut tx = state.db.begin().await.map_err(internal_error)?;
+    let mut group = sqlx::query_as::<_, CheckGroupRow>(
+        "INSERT INTO check_groups (id, org_id, name, alert_threshold_percent, created_at) VALUES (?, ?, ?, ?, ?) RETURNING *",
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(&caller.org_id)
+    .bind(name)
+    .bind(payload.alert_threshold_percent)
+    .bind(state.clock.now())
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(internal_error)?;
+    for check_id in &payload.check_ids {
+        sqlx::query("INSERT OR IGNORE INTO check_group_members (group_id, check_id) VALUES (?, ?)")
+            .bind(&group.id)
+            .bind(check_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(internal_error)?;
+    }
+    group.check_ids = payload.check_ids;
+    group.check_ids.sort();
+    group.check_ids.dedup();
+    record_audit(
+        &mut *tx,
+        AuditEntry {
+            after: snapshot(&group),
+            ..caller.audit("create", "check_group", &group.id)
+        },
+    )
+    .await
+    .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+
+    Ok((StatusCode::CREATED, Json(group)))
+}
+
+pub(crate) async fn find_group(
+    state: &AppState,
+    caller: &Caller,
+    id: &str,
+) -> Result<CheckGroupRow, (StatusCode, String)> {
+    let mut group = sqlx::query_as::<_, CheckGroupRow>(
+        "SELECT * FROM check_groups WHERE id = ? AND org_id = ?",
+    )
+    .bind(id)
+    .bind(&caller.org_id)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(internal_error)?
+    .ok_or((StatusCode::NOT_FOUND, "grupo no encontrado".to_string()))?;
+    group.check_ids = group_members(&state.db, id)
+        .await
+        .map_err(internal_error)?
+        .into_iter()
+        .map(|m| m.id)
+        .collect();
+    group.check_ids.sort();
+    Ok(group)
+}
+
+pub(crate) async fn list_groups(
+    State(state): State<Arc<AppState>>,
+    Viewer(caller): Viewer,
+) -> Result<Json<Vec<CheckGroupRow>>, (StatusCode, String)> {
+    let ids: Vec<String> =
+        sqlx::query_scalar("SELECT id FROM check_groups WHERE org_id = ? ORDER BY created_at")
+            .bind(&caller.org_id)
+            .fetch_all(&state.db)
+            .await
+            .map_err(internal_error)?;
+    let mut groups = Vec::with_capacity(ids.len());
+    for id in ids {
+        groups.push(find_group(&state, &caller, &id).await?);
+    }
+
+    Ok(Json(groups))
+}
+
+pub(crate) async fn delete_group(
+    State(state): State<Arc<AppState>>,
+    Editor(caller): Editor,
+    Path(id): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let group = find_group(&state, &caller, &id).await?;
+    let mut tx = state.db.begin().await.map_err(internal_error)?;
+    sqlx::query("DELETE FROM check_group_members WHERE group_id = ?")
+        .bind(&id)
+        .execute(&mut *tx)
+        .await
+        .map_err(internal_error)?;
+    sqlx::query("DELETE FROM check_groups WHERE id = ?")
+        .bind(&id)
+        .execute(&mut *tx)
+        .await
+        .map_err(internal_error)?;
+    record_audit(
+        &mut *tx,
+        AuditEntry {
+            before: snapshot(&group),
+            ..caller.audit("delete", "check_group", &id)
+        },
+    )
+    .await
+    .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// The worst status of the members and their uptime together over `period` (24h by default).
+pub(crate) async fn group_status(
+    State(state): State<Arc<AppState>>,
+    Viewer(caller): Viewer,
+    Path(id): Path<String>,
+    Query(query): Query<PeriodQuery>,
+) -> Result<Json<GroupStatus>, (StatusCode, String)> {
+    let group = find_group(&state, &caller, &id).await?;
+    let period = query.period.unwrap_or_else(|| "24h".to_string());
+    let duration = parse_period(&period).ok_or((
+        StatusCode::BAD_REQUEST,
+        "period inválido (ej: 24h, 7d)".to_string(),
+    ))?;
+    let since = rollup_bucket(state.clock.now() - duration);
+
+    let checks = group_members(&state.db, &id)
+        .await
+        .map_err(internal_error)?;
+    let (samples, up_samples): (i64, i64) = sqlx::query_as(
+        r#"
+        SELECT COALESCE(SUM(samples), 0), COALESCE(SUM(up_samples), 0) FROM check_rollups
+        WHERE check_id IN (SELECT check_id FROM check_group_members WHERE group_id = ?)
+          AND bucket_start >= ?
+        "#,
+    )
+    .bind(&id)
+    .bind(since)
+    .fetch_one(&state.db)
+    .await
+    .map_err(internal_error)?;
+    let down_checks = checks
+        .iter()
+        .filter(|c| c.status.as_deref() == Some("DOWN"))
+        .count() as i64;
+    let total_checks = checks.len() as i64;
+
+    Ok(Json(GroupStatus {
+        status: worst_status(&checks).map(str::to_string),
+        degraded: group
+            .alert_threshold_percent
+            .is_some_and(|t| group_degraded(down_checks, total_checks, t)),
+        id: group.id,
+        name: group.name,
+        down_checks,
+        total_checks,
+        period,
+        uptime_percent: (samples > 0).then(|| up_samples as f64 * 100.0 / samples as f64),
+        checks,
+    }))
+}
+
+/// How many incidents the feed goes back.
+const FEED_INCIDENTS: i64 = 50;
+
+/// A status page with the name and last status of its checks and its timeline: one entry
+/// when an incident of those checks opens, one per update posted to it and another once it
+/// is resolved, plus the public annotations, newest first.
+struct StatusPageTimeline {
+    page: StatusPageRow,
+    link: String,
+    checks: Vec<(String, Option<String>)>,
+    entries: Vec<atom::Entry>,
+}
+
+async fn status_page_timeline(
+    state: &AppState,
+    slug: &str,
+) -> Result<StatusPageTimeline, (StatusCode, String)> {
+    let page = sqlx::query_as::<_, StatusPageRow>("SELECT * FROM status_pages WHERE slug = ?")
+        .bind(slug)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(internal_error)?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            "página de estado no encontrada".to_string(),
+        ))?;
+    let rows: Vec<(String, String, Option<String>)> = sqlx::query_as(
+        "SELECT c.id, c.name, c.last_status FROM checks c JOIN status_page_checks s ON s.check_id = c.id WHERE s.status_page_id = ? ORDER BY c.name",
+    )
+    .bind(&page.id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(internal_error)?;
+    let checks: HashMap<String, String> = rows
+        .iter()
+        .map(|(id, name, _)| (id.clone(), name.clone()))
+        .collect();
+    let incidents = sqlx::query_as::<_, IncidentRow>(
+        r#"
+        SELECT * FROM incidents
+        WHERE check_id IN (SELECT check_id FROM status_page_checks WHERE status_page_id = ?)
+        ORDER BY started_at DESC LIMIT ?
+        "#,
+    )
+    .bind(&page.id)
+    .bind(FEED_INCIDENTS)
+    .fetch_all(&state.db)
+    .await
+    .map_err(internal_error)?;
+    let updates = sqlx::query_as::<_, IncidentUpdateRow>(
+        r#"
+        SELECT u.* FROM incident_updates u
+        JOIN incidents i ON i.id = u.incident_id
+        JOIN status_page_checks s ON s.check_id = i.check_id
+        WHERE s.status_page_id = ? AND i.started_at >= ?
+        "#,
+    )
+    .bind(&page.id)
+    .bind(incidents.last().map(|i| i.started_at))
+    .fetch_all(&state.db)
+    .await
+    .map_err(internal_error)?;
+    let annotations = sqlx::query_as::<_, AnnotationRow>(
+        r#"
+        SELECT a.* FROM annotations a
+        JOIN status_page_checks s ON s.check_id = a.check_id
+        WHERE s.status_page_id = ? AND a.public = 1
+        ORDER BY a.at DESC LIMIT ?
+        "#,
+    )
+    .bind(&page.id)
+    .bind(FEED_INCIDENTS)
+    .fetch_all(&state.db)
+    .await
+    .map_err(internal_error)?;
+
+    let link = format!("{}/status-pages/{}", public_url(), page.slug);
+    let mut entries = Vec::new();
+    for incident in &incidents {
+        let check = checks
+            .get(&incident.check_id)
+            .map_or("unknown check", String::as_str);
+        let cause = incident.last_error.as_deref().unwrap_or("no details");
+        entries.push(atom::Entry {
+            id: format!("{link}#incident-{}-opened", incident.id),
+            title: format!("{check} is down"),
+            updated: incident.started_at,
+            summary: format!("Incident opened for {check}: {cause}"),
+            categories: vec![check.to_string()],
+        });
+        if let Some(resolved_at) = incident.resolved_at {
+            entries.push(atom::Entry {
+                id: format!("{link}#incident-{}-resolved", incident.id),
+                title: format!("{check} recovered"),
+                updated: resolved_at,
+                summary: format!(
+                    "Incident resolved for {check} after {} ({} failed probes)",
+                    format_duration(resolved_at - incident.started_at),
+                    incident.failed_probes
+                ),
+                categories: vec![check.to_string()],
+            });
+        }
+    }
+    let incident_checks: HashMap<&str, &str> = incidents
+        .iter()
+        .filter_map(|i| Some((i.id.as_str(), checks.get(&i.check_id)?.as_str())))
+        .collect();
+    for update in &updates {
+        let Some(check) = incident_checks.get(update.incident_id.as_str()) else {
+            continue;
+        };
+        let mut status = update.status.clone();
+        status[..1].make_ascii_uppercase();
+        entries.push(atom::Entry {
+            id: format!("{link}#update-{}", update.id),
+            title: format!("{check}: {status}"),
+            updated: update.created_at,
+            summary: update.message.clone(),
+            categories: vec![check.to_string()],
+        });
+    }
+    for annotation in &annotations {
+        let check = checks
+            .get(&annotation.check_id)
+            .map_or("unknown check", String::as_str);
+        entries.push(atom::Entry {
+            id: format!("{link}#annotation-{}", annotation.id),
+            title: format!("{check}: note"),
+            updated: annotation.at,
+            summary: annotation.text.clone(),
+            categories: vec![check.to_string()],
+        });
+    }
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.updated));
+
+    Ok(StatusPageTimeline {
+        page,
+        link,
+        checks: rows
+            .into_iter()
+            .map(|(_, name, status)| (name, status))
+            .collect(),
+        entries,
+    })
+}
+
+/// Public, read-only.
+pub(crate) async fn status_page(
+    State(state): State<Arc<AppState>>,
+    Path(slug): Path<String>,
+) -> Result<Html<String>, (StatusCode, String)> {
+    let timeline = status_page_timeline(&state, &slug).await?;
+    let title = atom::escape(&timeline.page.title);
+    let mut html = format!(
+        "<!doctype html>\n<html><head><meta charset=\"utf-8\"><title>{title}</title>\
+         <link rel=\"alternate\" type=\"application/atom+xml\" href=\"{}/feed.atom\"></head>\n\
+         <body><h1>{title}</h1>\n<ul class=\"checks\">\n",
+        atom::escape(&timeline.link)
+    );
+    for (name, status) in &timeline.checks {
+        html.push_str(&format!(
+            "<li>{}: {}</li>\n",
+            atom::escape(name),
+            status.as_deref().unwrap_or("UNKNOWN")
+        ));
+    }
+    html.push_str("</ul>\n<h2>Timeline</h2>\n<ul class=\"timeline\">\n");
+    for entry in &timeline.entries {
+        html.push_str(&format!(
+            "<li><time>{}</time> <strong>{}</strong> {}</li>\n",
+            entry.updated.format("%Y-%m-%d %H:%M UTC"),
+            atom::escape(&entry.title),
+            atom::escape(&entry.summary)
+        ));
+    }
+    html.push_str("</ul>\n</body></html>\n");
+
+    Ok(Html(html))
+}
+
+pub(crate) async fn status_page_feed(
+    State(state): State<Arc<AppState>>,
+    Path(slug): Path<String>,
+) -> Result<Response, (StatusCode, String)> {
+    let timeline = status_page_timeline(&state, &slug).await?;
+    let feed = atom::Feed {
+        id: &timeline.link,
+        title: &timeline.page.title,
+        link: &timeline.link,
+        updated: timeline
+            .entries
+            .first()
+            .map_or(timeline.page.created_at, |e| e.updated),
+        entries: timeline.entries,
+    };
+
+    Ok((
+        [(
+            axum::http::header::CONTENT_TYPE,
+            "application/atom+xml; charset=utf-8",
+        )],
+        atom::render(&feed),
+    )
+        .into_response())
+}
+
+/// The incident, when it belongs to one of the caller's checks.
+async fn find_incident(
+    state: &AppState,
+    caller: &Caller,
+    id: &str,
+) -> Result<IncidentRow, (StatusCode, String)> {
+    sqlx::query_as::<_, IncidentRow>(
+        "SELECT i.* FROM incidents i JOIN checks c ON c.id = i.check_id WHERE i.id = ? AND c.org_id = ?",
+    )
+    .bind(id)
+    .bind(&caller.org_id)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(internal_error)?
+    .ok_or((StatusCode::NOT_FOUND, "incidente no encontrado".to_string()))
+}
+
+pub(crate) async fn create_incident_update(
+    State(state): State<Arc<AppState>>,
+    Editor(caller): Editor,
+    Path(id): Path<String>,
+    Json(payload): Json<CreateIncidentUpdateRequest>,
+) -> Result<(StatusCode, Json<IncidentUpdateRow>), (StatusCode, String)> {
+    let incident = find_incident(&state, &caller, &id).await?;
+    if !INCIDENT_UPDATE_STATUSES.contains(&payload.status.as_str()) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "status debe ser uno de: {}",
+                INCIDENT_UPDATE_STATUSES.join(", ")
+            ),
+        ));
+    }
+    let message = payload.message.trim();
+    if message.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "message requerido".to_string()));
+    }
+
+    let update = sqlx::query_as::<_, IncidentUpdateRow>(
+        "INSERT INTO incident_updates (id, incident_id, status, message, author, created_at) VALUES (?, ?, ?, ?, ?, ?) RETURNING *",
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(&incident.id)
+    .bind(&payload.status)
+    .bind(message)
+    .bind(
+        caller
+            .user
+            .as_ref()
+            .map_or("anonymous", |user| user.name.as_str()),
+    )
+    .bind(state.clock.now())
+    .fetch_one(&state.db)
+    .await
+    .map_err(internal_error)?;
+    record_audit(
+        &state.db,
+        AuditEntry {
+            after: snapshot(&update),
+            ..caller.audit("create", "incident_update", &update.id)
+        },
+    )
+    .await
+    .map_err(internal_error)?;
+
+    Ok((StatusCode::CREATED, Json(update)))
+}
+
+pub(crate) async fn list_incident_updates(
+    State(state): State<Arc<AppState>>,
+    Viewer(caller): Viewer,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<IncidentUpdateRow>>, (StatusCode, String)> {
+    find_incident(&state, &caller, &id).await?;
+    let rows = sqlx::query_as::<_, IncidentUpdateRow>(
+        "SELECT * FROM incident_updates WHERE incident_id = ? ORDER BY created_at",
+    )
+    .bind(&id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(internal_error)?;
+
+    Ok(Json(rows))
+}
+
+/// Newest first. Every filter is optional; `actor` matches the email (or `telegram`).
+pub(crate) async fn list_audit_log(
+    State(state): State<Arc<AppState>>,
+    Admin(caller): Admin,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<Vec<AuditRow>>, (StatusCode, String)> {
+    let rows = sqlx::query_as::<_, AuditRow>(
+        r#"
+        SELECT * FROM audit_log
+        WHERE org_id = ?1
+          AND (?2 IS NULL OR entity_type = ?2)
+          AND (?3 IS NULL OR entity_id = ?3)
+          AND (?4 IS NULL OR actor = ?4)
+          AND (?5 IS NULL OR action = ?5)
+          AND (?6 IS NULL OR created_at >= ?6)
+          AND (?7 IS NULL OR created_at < ?7)
+        ORDER BY id DESC
+        LIMIT ?8
+        "#,
+    )
+    .bind(&caller.org_id)
+    .bind(&query.entity_type)
+    .bind(&query.entity_id)
+    .bind(&query.actor)
+    .bind(&query.action)
+    .bind(query.since)
+    .bind(query.until)
+    .bind(query.limit.unwrap_or(100).clamp(1, 1000))
+    .fetch_all(&state.db)
+    .await
+    .map_err(internal_error)?;
+
+    Ok(Json(rows))
+}
+
+pub(crate) async fn create_secret(
+    State(state): State<Arc<AppState>>,
+    Admin(caller): Admin,
+    Json(payload): Json<CreateSecretRequest>,
+) -> Result<(StatusCode, Json<CreateCheckResponse>), (StatusCode, String)> {
+    let cipher = require_cipher(&state)?;
+    let id = store_secret(
+        &state.db,
+        cipher,
+        &caller.org_id,
+        &payload.name,
+        &payload.value,
+    )
+    .await
+    .map_err(internal_error)?;
+
+    Ok((StatusCode::CREATED, Json(CreateCheckResponse { id })))
+}
+
+pub(crate) async fn list_secrets(
+    State(state): State<Arc<AppState>>,
+    Admin(caller): Admin,
+) -> Result<Json<Vec<SecretRow>>, (StatusCode, String)> {
+    let rows = sqlx::query_as::<_, SecretRow>(
+        "SELECT id, name, created_at FROM secrets WHERE org_id = ? ORDER BY created_at",
+    )
+    .bind(&caller.org_id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(internal_error)?;
+
+    Ok(Json(rows))
+}
+
+pub(crate) async fn delete_secret(
+    State(state): State<Arc<AppState>>,
+    Admin(caller): Admin,
+    Path(id): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let result = sqlx::query("DELETE FROM secrets WHERE id = ? AND org_id = ?")
+        .bind(&id)
+        .bind(&caller.org_id)
+        .execute(&state.db)
+        .await
+        .map_err(|e| match &e {
+            sqlx::Error::Database(db) if db.is_foreign_key_violation() => {
+                (StatusCode::CONFLICT, "el secreto está en uso".to_string())
+            }
+            _ => internal_error(e),
+        })?;
+
+    if result.rows_affected() == 0 {
+        return Err((StatusCode::NOT_FOUND, "secreto no encontrado".to_string()));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub(crate) fn require_cipher(state: &AppState) -> Result<&SecretCipher, (StatusCode, String)> {
+    state.cipher.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "SECRETS_KEY no configurada".to_string(),
+    ))
+}
+
+/// Agents probe the checks of every organization, so only the instance's operators manage
+/// them.
+pub(crate) async fn create_agent(
+    State(state): State<Arc<AppState>>,
+    Admin(caller): Admin,
+    Json(payload): Json<CreateAgentRequest>,
+) -> Result<(StatusCode, Json<CreateAgentResponse>), (StatusCode, String)> {
+    require_operator(&caller)?;
+    let id = Uuid::new_v4().to_string();
+    let token = generate_token();
+    let hash = hash_token(&token);
+
+    sqlx::query(
+        "INSERT INTO agents (id, name, region, token_hash, created_at) VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(&id)
+    .bind(&payload.name)
+    .bind(&payload.region)
+    .bind(&hash)
+    .bind(Utc::now())
+    .execute(&state.db)
+    .await
+    .map_err(internal_error)?;
+    remember_token(&state, hash);
+
+    Ok((StatusCode::CREATED, Json(CreateAgentResponse { id, token })))
+}
+
+pub(crate) async fn list_agents(
+    State(state): State<Arc<AppState>>,
+    Admin(caller): Admin,
+) -> Result<Json<Vec<AgentRow>>, (StatusCode, String)> {
+    require_operator(&caller)?;
+    let rows = sqlx::query_as::<_, AgentRow>(
+        "SELECT id, name, region, created_at, last_seen_at FROM agents ORDER BY created_at",
+    )
+    .fetch_all(&state.db)
+    .await
+    .map_err(internal_error)?;
+
+    Ok(Json(rows))
+}
+
+pub(crate) async fn delete_agent(
+    State(state): State<Arc<AppState>>,
+    Admin(caller): Admin,
+    Path(id): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    require_operator(&caller)?;
+    let result = sqlx::query("DELETE FROM agents WHERE id = ?")
+        .bind(&id)
+        .execute(&state.db)
+        .await
+        .map_err(internal_error)?;
+
+    if result.rows_affected() == 0 {
+        return Err((StatusCode::NOT_FOUND, "agente no encontrado".to_string()));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Who is calling, and the organization the request acts on. `user` is `None` in open mode.
+#[derive(Debug, Clone)]
+pub(crate) struct Caller {
+    pub(crate) user: Option<UserRow>,
+    pub(crate) org_id: String,
+}
+
+impl Caller {
+    pub(crate) fn audit<'a>(
+        &'a self,
+        action: &'a str,
+        entity_type: &'a str,
+        entity_id: &'a str,
+    ) -> AuditEntry<'a> {
+        AuditEntry {
+            org_id: &self.org_id,
+            actor_id: self.user.as_ref().map(|user| user.id.as_str()),
+            actor: self
+                .user
+                .as_ref()
+                .map_or("anonymous", |user| user.email.as_str()),
+            action,
+            entity_type,
+            entity_id,
+            before: None,
+            after: None,
+        }
+    }
+}
+
+/// Resolves the user behind `Authorization: Bearer <api key>`. Until the first API key is
+/// created the API stays open and there is no user.
+pub(crate) async fn authenticate(
+    parts: &Parts,
+    state: &AppState,
+) -> Result<Option<String>, (StatusCode, String)> {
+    let Some(token) = bearer_token(&parts.headers) else {
+        if !auth_enabled(&state.db).await.map_err(internal_error)? {
+            return Ok(None);
+        }
+        return Err((StatusCode::UNAUTHORIZED, "API key requerida".to_string()));
+    };
+
+    let hash = hash_token(token);
+    let (key_id, user_id): (String, String) =
+        sqlx::query_as("SELECT id, user_id FROM api_keys WHERE token_hash = ?")
+            .bind(&hash)
+            .fetch_optional(&state.db)
+            .await
+            .map_err(internal_error)?
+            .ok_or((StatusCode::UNAUTHORIZED, "API key inválida".to_string()))?;
+    remember_token(state, hash);
+
+    sqlx::query("UPDATE api_keys SET last_used_at = ? WHERE id = ?")
+        .bind(Utc::now())
+        .bind(&key_id)
+        .execute(&state.db)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(Some(user_id))
+}
+
+/// Picks the organization from `X-Org-Id`, which may be omitted by users that belong to a
+/// single one, and checks the caller's role there is at least `required`.
+pub(crate) async fn authorize(
+    parts: &Parts,
+    state: &AppState,
+    required: Role,
+) -> Result<Caller, (StatusCode, String)> {
+    let requested_org = parts
+        .headers
+        .get("x-org-id")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+
+    let Some(user_id) = authenticate(parts, state).await? else {
+        let org_id = requested_org.unwrap_or_else(|| DEFAULT_ORG.to_string());
+        let org: Option<(String,)> = sqlx::query_as("SELECT id FROM orgs WHERE id = ?")
+            .bind(&org_id)
+            .fetch_optional(&state.db)
+            .await
+            .map_err(internal_error)?;
+        if org.is_none() {
+            return Err((
+                StatusCode::NOT_FOUND,
+                "organización no encontrada".to_string(),
+            ));
+        }
+        return Ok(Caller { user: None, org_id });
+    };
+
+    let mut memberships: Vec<(String,)> =
+        sqlx::query_as("SELECT org_id FROM memberships WHERE user_id = ?")
+            .bind(&user_id)
+            .fetch_all(&state.db)
+            .await
+            .map_err(internal_error)?;
+    let org_id = match requested_org {
+        Some(org_id) => memberships
+            .into_iter()
+            .find(|(id,)| *id == org_id)
+            .map(|(id,)| id),
+        None if memberships.len() > 1 => {
+            return Err((StatusCode::BAD_REQUEST, "X-Org-Id requerido".to_string()))
+        }
+        None => memberships.pop().map(|(id,)| id),
+    }
+    .ok_or((
+        StatusCode::FORBIDDEN,
+        "no perteneces a esta organización".to_string(),
+    ))?;
+
+    let user = find_member(state, &org_id, &user_id)
+        .await?
+        .ok_or((StatusCode::FORBIDDEN, "permiso insuficiente".to_string()))?;
+    if Role::parse(&user.role).is_none_or(|role| role < required) {
+        return Err((StatusCode::FORBIDDEN, "permiso insuficiente".to_string()));
+    }
+
+    Ok(Caller {
+        user: Some(user),
+        org_id,
+    })
+}
+
+/// Can read checks, results and stats.
+pub(crate) struct Viewer(pub(crate) Caller);
+
+/// Can also manage checks.
+pub(crate) struct Editor(pub(crate) Caller);
+
+/// Can also manage members, invitations, API keys, channels, secrets and agents.
+pub(crate) struct Admin(pub(crate) Caller);
+
+/// Any authenticated user, whichever organizations they belong to.
+pub(crate) struct Account(pub(crate) Option<String>);
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for Viewer {
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        authorize(parts, state, Role::Viewer).await.map(Viewer)
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for Editor {
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        authorize(parts, state, Role::Editor).await.map(Editor)
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for Admin {
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        authorize(parts, state, Role::Admin).await.map(Admin)
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for Account {
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        authenticate(parts, state).await.map(Account)
+    }
+}
+
+pub(crate) fn is_self(caller: &Caller, user_id: &str) -> bool {
+    caller.user.as_ref().is_some_and(|user| user.id == user_id)
+}
+
+/// Creates a user with that email in the caller's organization. Someone who already has an
+/// account gets an invitation to accept instead (`202` with the invitation), so no organization
+/// can pull in another's users on its own.
+pub(crate) async fn create_user(
+    State(state): State<Arc<AppState>>,
+    Admin(caller): Admin,
+    Json(payload): Json<CreateUserRequest>,
+) -> Result<Response, (StatusCode, String)> {
+    validate_role(&payload.role)?;
+    let email = normalize_email(&payload.email)?;
+
+    let existing: Option<(String,)> = sqlx::query_as("SELECT id FROM users WHERE email = ?")
+        .bind(&email)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(internal_error)?;
+    if let Some((user_id,)) = existing {
+        if find_member(&state, &caller.org_id, &user_id)
+            .await?
+            .is_some()
+        {
+            return Err((StatusCode::CONFLICT, "el usuario ya es miembro".to_string()));
+        }
+        let invitation = invite(&state, &caller, &email, &payload.role).await?;
+        return Ok((StatusCode::ACCEPTED, Json(invitation)).into_response());
+    }
+
+    let mut tx = state.db.begin().await.map_err(internal_error)?;
+    let user_id = upsert_user(&mut tx, &email, &payload.name)
+        .await
+        .map_err(internal_error)?;
+    sqlx::query("INSERT INTO memberships (org_id, user_id, role, created_at) VALUES (?, ?, ?, ?)")
+        .bind(&caller.org_id)
+        .bind(&user_id)
+        .bind(&payload.role)
+        .bind(Utc::now())
+        .execute(&mut *tx)
+        .await
+        .map_err(internal_error)?;
+    let user = load_member(&mut *tx, &caller.org_id, &user_id)
+        .await
+        .map_err(internal_error)?
+        .ok_or((StatusCode::NOT_FOUND, "usuario no encontrado".to_string()))?;
+    record_audit(
+        &mut *tx,
+        AuditEntry {
+            after: snapshot(&user),
+            ..caller.audit("create", "user", &user_id)
+        },
+    )
+    .await
+    .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+
+    Ok((StatusCode::CREATED, Json(user)).into_response())
+}
+
+pub(crate) async fn list_users(
+    State(state): State<Arc<AppState>>,
+    Admin(caller): Admin,
+) -> Result<Json<Vec<UserRow>>, (StatusCode, String)> {
+    let rows = sqlx::query_as::<_, UserRow>(
+        r#"
+        SELECT users.id, users.email, users.name, memberships.role, users.created_at
+        FROM users JOIN memberships ON memberships.user_id = users.id
+        WHERE memberships.org_id = ?
+        ORDER BY users.created_at
+        "#,
+    )
+    .bind(&caller.org_id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(internal_error)?;
+
+    Ok(Json(rows))
+}
+
+/// `role` is the member's role in the caller's organization.
+pub(crate) async fn update_user(
+    State(state): State<Arc<AppState>>,
+    Admin(caller): Admin,
+    Path(id): Path<String>,
+    Json(payload): Json<UpdateUserRequest>,
+) -> Result<Json<UserRow>, (StatusCode, String)> {
+    if let Some(role) = &payload.role {
+        validate_role(role)?;
+        if is_self(&caller, &id) && role != "admin" {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "no puedes quitarte el rol admin".to_string(),
+            ));
+        }
+    }
+    let before = find_member(&state, &caller.org_id, &id)
+        .await?
+        .ok_or((StatusCode::NOT_FOUND, "usuario no encontrado".to_string()))?;
+
+    let mut tx = state.db.begin().await.map_err(internal_error)?;
+    if payload.role.as_deref().is_some_and(|role| role != "admin") {
+        keep_admin_access(&mut tx, &caller.org_id, Some(&id), None).await?;
+    }
+    sqlx::query("UPDATE users SET name = COALESCE(?, name) WHERE id = ?")
+        .bind(&payload.name)
+        .bind(&id)
+        .execute(&mut *tx)
+        .await
+        .map_err(internal_error)?;
+    sqlx::query("UPDATE memberships SET role = COALESCE(?, role) WHERE org_id = ? AND user_id = ?")
+        .bind(&payload.role)
+        .bind(&caller.org_id)
+        .bind(&id)
+        .execute(&mut *tx)
+        .await
+        .map_err(internal_error)?;
+    let user = load_member(&mut *tx, &caller.org_id, &id)
+        .await
+        .map_err(internal_error)?
+        .ok_or((StatusCode::NOT_FOUND, "usuario no encontrado".to_string()))?;
+    record_audit(
+        &mut *tx,
+        AuditEntry {
+            before: snapshot(&before),
+            after: snapshot(&user),
+            ..caller.audit("update", "user", &id)
+        },
+    )
+    .await
+    .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+
+    Ok(Json(user))
+}
+
+/// Removes the user from the caller's organization. Users left without any organization are
+/// deleted together with their API keys.
+pub(crate) async fn delete_user(
+    State(state): State<Arc<AppState>>,
+    Admin(caller): Admin,
+    Path(id): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    if is_self(&caller, &id) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "no puedes eliminarte a ti mismo".to_string(),
+        ));
+    }
+
+    let mut tx = state.db.begin().await.map_err(internal_error)?;
+    let user = load_member(&mut *tx, &caller.org_id, &id)
+        .await
+        .map_err(internal_error)?
+        .ok_or((StatusCode::NOT_FOUND, "usuario no encontrado".to_string()))?;
+    keep_admin_access(&mut tx, &caller.org_id, Some(&id), None).await?;
+    sqlx::query("DELETE FROM memberships WHERE org_id = ? AND user_id = ?")
+        .bind(&caller.org_id)
+        .bind(&id)
+        .execute(&mut *tx)
+        .await
+        .map_err(internal_error)?;
+    oncall::remove_member(&mut tx, &caller.org_id, &id)
+        .await
+        .map_err(internal_error)?;
+
+    let (remaining,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM memberships WHERE user_id = ?")
+        .bind(&id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(internal_error)?;
+    if remaining == 0 {
+        sqlx::query("DELETE FROM api_keys WHERE user_id = ?")
+            .bind(&id)
+            .execute(&mut *tx)
+            .await
+            .map_err(internal_error)?;
+        sqlx::query("DELETE FROM users WHERE id = ?")
+            .bind(&id)
+            .execute(&mut *tx)
+            .await
+            .map_err(internal_error)?;
+    }
+    record_audit(
+        &mut *tx,
+        AuditEntry {
+            before: snapshot(&user),
+            ..caller.audit("delete", "user", &id)
+        },
+    )
+    .await
+    .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Refuses to take away the last way into the organization: once its admins have API keys, at
+/// least one of them keeps one after `user_id` stops being an admin or `key_id` is deleted.
+pub(crate) async fn keep_admin_access(
+    conn: &mut SqliteConnection,
+    org_id: &str,
+    user_id: Option<&str>,
+    key_id: Option<&str>,
+) -> Result<(), (StatusCode, String)> {
+    let (keys, kept): (i64, i64) = sqlx::query_as(
+        r#"
+        SELECT COUNT(*), COALESCE(SUM(api_keys.user_id IS NOT ?2 AND api_keys.id IS NOT ?3), 0)
+        FROM api_keys JOIN memberships ON memberships.user_id = api_keys.user_id
+        WHERE memberships.org_id = ?1 AND memberships.role = 'admin'
+        "#,
+    )
+    .bind(org_id)
+    .bind(user_id)
+    .bind(key_id)
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(internal_error)?;
+    if keys > 0 && kept == 0 {
+        return Err((
+            StatusCode::CONFLICT,
+            "la organización se quedaría sin admins con API key".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// An API key acts in every organization of its user, so an admin may only manage the keys of
+/// members that belong to no other organization, besides their own keys.
+pub(crate) async fn ensure_key_manager(
+    state: &AppState,
+    caller: &Caller,
+    user_id: &str,
+) -> Result<(), (StatusCode, String)> {
+    if is_self(caller, user_id) {
+        return Ok(());
+    }
+    find_member(state, &caller.org_id, user_id)
+        .await?
+        .ok_or((StatusCode::NOT_FOUND, "usuario no encontrado".to_string()))?;
+
+    let (others,): (i64,) =
+        sqlx::query_as("SELECT COUNT(*) FROM memberships WHERE user_id = ? AND org_id != ?")
+            .bind(user_id)
+            .bind(&caller.org_id)
+            .fetch_one(&state.db)
+            .await
+            .map_err(internal_error)?;
+    if others > 0 {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "el usuario pertenece a otras organizaciones".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// The token is only returned here; the server keeps its hash. Creating the first key turns
+/// authentication on for good.
+pub(crate) async fn create_api_key(
+    State(state): State<Arc<AppState>>,
+    Admin(caller): Admin,
+    Path(user_id): Path<String>,
+    Json(payload): Json<CreateApiKeyRequest>,
+) -> Result<(StatusCode, Json<CreateApiKeyResponse>), (StatusCode, String)> {
+    ensure_key_manager(&state, &caller, &user_id).await?;
+    let mut tx = state.db.begin().await.map_err(internal_error)?;
+    let key = insert_api_key(&mut tx, &user_id, &payload.name)
+        .await
+        .map_err(internal_error)?;
+    let row = load_api_key(&mut tx, &key.id)
+        .await
+        .map_err(internal_error)?;
+    record_audit(
+        &mut *tx,
+        AuditEntry {
+            after: row.as_ref().and_then(snapshot),
+            ..caller.audit("create", "api_key", &key.id)
+        },
+    )
+    .await
+    .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+    remember_token(&state, hash_token(&key.token));
+
+    Ok((StatusCode::CREATED, Json(key)))
+}
+
+pub(crate) async fn list_api_keys(
+    State(state): State<Arc<AppState>>,
+    Admin(caller): Admin,
+    Path(user_id): Path<String>,
+) -> Result<Json<Vec<ApiKeyRow>>, (StatusCode, String)> {
+    find_member(&state, &caller.org_id, &user_id)
+        .await?
+        .ok_or((StatusCode::NOT_FOUND, "usuario no encontrado".to_string()))?;
+    let rows = sqlx::query_as::<_, ApiKeyRow>(
+        "SELECT id, user_id, name, created_at, last_used_at FROM api_keys WHERE user_id = ? ORDER BY created_at",
+    )
+    .bind(user_id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(internal_error)?;
+
+    Ok(Json(rows))
+}
+
+/// Never with its hash, so it can go in the audit log.
+pub(crate) async fn load_api_key(
+    conn: &mut SqliteConnection,
+    id: &str,
+) -> Result<Option<ApiKeyRow>, sqlx::Error> {
+    sqlx::query_as::<_, ApiKeyRow>(
+        "SELECT id, user_id, name, created_at, last_used_at FROM api_keys WHERE id = ?",
+    )
+    .bind(id)
+    .fetch_optional(&mut *conn)
+    .await
+}
+
+pub(crate) async fn delete_api_key(
+    State(state): State<Arc<AppState>>,
+    Admin(caller): Admin,
+    Path(id): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let owner: Option<(String,)> = sqlx::query_as("SELECT user_id FROM api_keys WHERE id = ?")
+        .bind(&id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(internal_error)?;
+    let Some((user_id,)) = owner else {
+        return Err((StatusCode::NOT_FOUND, "API key no encontrada".to_string()));
+    };
+    ensure_key_manager(&state, &caller, &user_id)
+        .await
+        .map_err(|(status, message)| match status {
+            StatusCode::NOT_FOUND => (status, "API key no encontrada".to_string()),
+            _ => (status, message),
+        })?;
+
+    let mut tx = state.db.begin().await.map_err(internal_error)?;
+    let admin_of: Vec<(String,)> =
+        sqlx::query_as("SELECT org_id FROM memberships WHERE user_id = ? AND role = 'admin'")
+            .bind(&user_id)
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(internal_error)?;
+    for (org_id,) in admin_of {
+        keep_admin_access(&mut tx, &org_id, None, Some(&id)).await?;
+    }
+    let row = load_api_key(&mut tx, &id).await.map_err(internal_error)?;
+    sqlx::query("DELETE FROM api_keys WHERE id = ?")
+        .bind(&id)
+        .execute(&mut *tx)
+        .await
+        .map_err(internal_error)?;
+    record_audit(
+        &mut *tx,
+        AuditEntry {
+            before: row.as_ref().and_then(snapshot),
+            ..caller.audit("delete", "api_key", &id)
+        },
+    )
+    .await
+    .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// The creator becomes the organization's first admin. New organizations start on the
+/// `DEFAULT_PLAN` plan (`free` unless configured).
+pub(crate) async fn create_org(
+    State(state): State<Arc<AppState>>,
+    Account(user_id): Account,
+    Json(payload): Json<CreateOrgRequest>,
+) -> Result<(StatusCode, Json<OrgRow>), (StatusCode, String)> {
+    if payload.name.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "name requerido".to_string()));
+    }
+    let now = Utc::now();
+
+    let plan = Plan::named(&env::var("DEFAULT_PLAN").unwrap_or_default()).name;
+
+    let mut tx = state.db.begin().await.map_err(internal_error)?;
+    let org = sqlx::query_as::<_, OrgRow>(
+        "INSERT INTO orgs (id, name, created_at, plan) VALUES (?, ?, ?, ?) RETURNING *",
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(payload.name.trim())
+    .bind(now)
+    .bind(plan)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(internal_error)?;
+    if let Some(user_id) = &user_id {
+        sqlx::query(
+            "INSERT INTO memberships (org_id, user_id, role, created_at) VALUES (?, ?, 'admin', ?)",
+        )
+        .bind(&org.id)
+        .bind(user_id)
+        .bind(now)
+        .execute(&mut *tx)
+        .await
+        .map_err(internal_error)?;
+    }
+    tx.commit().await.map_err(internal_error)?;
+
+    Ok((StatusCode::CREATED, Json(org)))
+}
+
+/// The caller's organizations; every organization in open mode.
+pub(crate) async fn list_orgs(
+    State(state): State<Arc<AppState>>,
+    Account(user_id): Account,
+) -> Result<Json<Vec<OrgRow>>, (StatusCode, String)> {
+    let rows = match user_id {
+        Some(user_id) => {
+            sqlx::query_as::<_, OrgRow>(
+                r#"
+                SELECT orgs.* FROM orgs JOIN memberships ON memberships.org_id = orgs.id
+                WHERE memberships.user_id = ?
+                ORDER BY orgs.created_at
+                "#,
+            )
+            .bind(user_id)
+            .fetch_all(&state.db)
+            .await
+        }
+        None => {
+            sqlx::query_as::<_, OrgRow>("SELECT * FROM orgs ORDER BY created_at")
+                .fetch_all(&state.db)
+                .await
+        }
+    }
+    .map_err(internal_error)?;
+
+    Ok(Json(rows))
+}
+
+/// The token is returned here and mailed to the invitee when SMTP is configured. Accepting it
+/// through `POST /invitations/accept` grants `role` in the caller's organization.
+pub(crate) async fn create_invitation(
+    State(state): State<Arc<AppState>>,
+    Admin(caller): Admin,
+    Json(payload): Json<CreateInvitationRequest>,
+) -> Result<(StatusCode, Json<CreateInvitationResponse>), (StatusCode, String)> {
+    validate_role(&payload.role)?;
+    let email = normalize_email(&payload.email)?;
+    let invitation = invite(&state, &caller, &email, &payload.role).await?;
+    Ok((StatusCode::CREATED, Json(invitation)))
+}
+
+pub(crate) async fn invite(
+    state: &AppState,
+    caller: &Caller,
+    email: &str,
+    role: &str,
+) -> Result<CreateInvitationResponse, (StatusCode, String)> {
+    let org_id = &caller.org_id;
+    let id = Uuid::new_v4().to_string();
+    let token = generate_token();
+    let now = Utc::now();
+    let expires_at = now + chrono::Duration::days(INVITATION_DAYS);
+
+    let mut tx = state.db.begin().await.map_err(internal_error)?;
+    let invitation = sqlx::query_as::<_, InvitationRow>(
+        "INSERT INTO invitations (id, org_id, email, role, token_hash, created_at, expires_at) VALUES (?, ?, ?, ?, ?, ?, ?) RETURNING id, org_id, email, role, created_at, expires_at, accepted_at",
+    )
+    .bind(&id)
+    .bind(org_id)
+    .bind(email)
+    .bind(role)
+    .bind(hash_token(&token))
+    .bind(now)
+    .bind(expires_at)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(internal_error)?;
+    record_audit(
+        &mut *tx,
+        AuditEntry {
+            after: snapshot(&invitation),
+            ..caller.audit("create", "invitation", &id)
+        },
+    )
+    .await
+    .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+
+    if let Some(mailer) = state.mailer.clone() {
+        let (org_name,): (String,) = sqlx::query_as("SELECT name FROM orgs WHERE id = ?")
+            .bind(org_id)
+            .fetch_one(&state.db)
+            .await
+            .map_err(internal_error)?;
+        let body = format!(
+            "You have been invited to join {org_name} as {role}.\n\nAccept the invitation with:\n\ncurl -X POST {}/invitations/accept -H 'Content-Type: application/json' -d '{{\"token\": \"{token}\", \"name\": \"Your name\"}}'\n\nThe invitation expires in {INVITATION_DAYS} days. If you did not expect this email, ignore it.",
+            public_url(),
+        );
+        let email = email.to_string();
+        tokio::spawn(async move {
+            if let Err(e) = mailer
+                .send(&email, &format!("Invitation to {org_name}"), body)
+                .await
+            {
+                error!("Error sending invitation to {email}: {e}");
+            }
+        });
+    }
+
+    Ok(CreateInvitationResponse {
+        id,
+        token,
+        expires_at,
+    })
+}
+
+pub(crate) async fn list_invitations(
+    State(state): State<Arc<AppState>>,
+    Admin(caller): Admin,
+) -> Result<Json<Vec<InvitationRow>>, (StatusCode, String)> {
+    let rows = sqlx::query_as::<_, InvitationRow>(
+        "SELECT id, org_id, email, role, created_at, expires_at, accepted_at FROM invitations WHERE org_id = ? ORDER BY created_at DESC",
+    )
+    .bind(&caller.org_id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(internal_error)?;
+
+    Ok(Json(rows))
+}
+
+/// Makes the invitee a member with the invited role. Invitees without an account get one,
+/// along with their first API key.
+pub(crate) async fn accept_invitation(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<AcceptInvitationRequest>,
+) -> Result<Json<AcceptInvitationResponse>, (StatusCode, String)> {
+    let invalid = || {
+        (
+            StatusCode::BAD_REQUEST,
+            "invitación inválida o expirada".to_string(),
+        )
+    };
+    let mut tx = state.db.begin().await.map_err(internal_error)?;
+    let invitation = sqlx::query_as::<_, InvitationRow>(
+        "SELECT id, org_id, email, role, created_at, expires_at, accepted_at FROM invitations WHERE token_hash = ? AND accepted_at IS NULL",
+    )
+    .bind(hash_token(&payload.token))
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(internal_error)?
+    .ok_or_else(invalid)?;
+    if invitation.expires_at < Utc::now() {
+        return Err(invalid());
+    }
+
+    let existing: Option<(String,)> = sqlx::query_as("SELECT id FROM users WHERE email = ?")
+        .bind(&invitation.email)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(internal_error)?;
+    let name = payload.name.as_deref().unwrap_or(&invitation.email);
+    let user_id = upsert_user(&mut tx, &invitation.email, name)
+        .await
+        .map_err(internal_error)?;
+    let now = Utc::now();
+    sqlx::query(
+        r#"
+        INSERT INTO memberships (org_id, user_id, role, created_at) VALUES (?, ?, ?, ?)
+        ON CONFLICT(org_id, user_id) DO UPDATE SET role = excluded.role
+        "#,
+    )
+    .bind(&invitation.org_id)
+    .bind(&user_id)
+    .bind(&invitation.role)
+    .bind(now)
+    .execute(&mut *tx)
+    .await
+    .map_err(internal_error)?;
+    sqlx::query("UPDATE invitations SET accepted_at = ? WHERE id = ?")
+        .bind(now)
+        .bind(&invitation.id)
+        .execute(&mut *tx)
+        .await
+        .map_err(internal_error)?;
+    let api_key = match existing {
+        Some(_) => None,
+        None => Some(
+            insert_api_key(&mut tx, &user_id, "default")
+                .await
+                .map_err(internal_error)?,
+        ),
+    };
+    tx.commit().await.map_err(internal_error)?;
+
+    Ok(Json(AcceptInvitationResponse {
+        org_id: invitation.org_id,
+        user_id,
+        api_key,
+    }))
+}
+
+/// Authenticates a probe agent from its `Authorization: Bearer <token>` header.
+pub(crate) struct AgentAuth(pub(crate) AgentRow);
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for AgentAuth {
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let unauthorized = || {
+            (
+                StatusCode::UNAUTHORIZED,
+                "token de agente inválido".to_string(),
+            )
+        };
+        let token = bearer_token(&parts.headers).ok_or_else(unauthorized)?;
+        let hash = hash_token(token);
+
+        let agent = sqlx::query_as::<_, AgentRow>(
+            "SELECT id, name, region, created_at, last_seen_at FROM agents WHERE token_hash = ?",
+        )
+        .bind(&hash)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(unauthorized)?;
+        remember_token(state, hash);
+
+        sqlx::query("UPDATE agents SET last_seen_at = ? WHERE id = ?")
+            .bind(Utc::now())
+            .bind(&agent.id)
+            .execute(&state.db)
+            .await
+            .map_err(internal_error)?;
+
+        Ok(AgentAuth(agent))
+    }
+}
+
+pub(crate) async fn agent_assignments(
+    State(state): State<Arc<AppState>>,
+    AgentAuth(agent): AgentAuth,
+) -> Result<Json<Vec<AgentAssignment>>, (StatusCode, String)> {
+    let checks = state.db.active_checks().await.map_err(internal_error)?;
+
+    let mut defaults: HashMap<String, ProbeDefaults> = HashMap::new();
+    let mut assignments = Vec::new();
+    for check in checks {
+        if !check_runs_in_region(&check, &agent.region) {
+            continue;
+        }
+        let secrets = load_probe_secrets(&state, &check)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+        if !defaults.contains_key(&check.org_id) {
+            let org_defaults = probe_defaults(&state.db, &check.org_id)
+                .await
+                .map_err(internal_error)?;
+            defaults.insert(check.org_id.clone(), org_defaults);
+        }
+        let headers = probe_headers(&defaults[&check.org_id], &check);
+        assignments.push(AgentAssignment {
+            check,
+            client_key: secrets.client_key,
+            auth_header: secrets.auth_header,
+            headers,
+        });
+    }
+
+    Ok(Json(assignments))
+}
+
+/// Agents post right after probing, so a result this far from the server clock is bogus.
+const AGENT_MAX_SKEW_SECONDS: i64 = 300;
+
+pub(crate) async fn agent_results(
+    State(state): State<Arc<AppState>>,
+    AgentAuth(agent): AgentAuth,
+    Json(payload): Json<AgentResultsRequest>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let now = state.clock.now();
+    for r in &payload.results {
+        if !matches!(r.status.as_str(), "UP" | "DOWN" | "DEGRADED") {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("status inválido: {} (UP, DOWN o DEGRADED)", r.status),
+            ));
+        }
+        if (r.checked_at - now).num_seconds().abs() > AGENT_MAX_SKEW_SECONDS {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "checked_at fuera de rango: se admiten {AGENT_MAX_SKEW_SECONDS}s de desfase"
+                ),
+            ));
+        }
+    }
+    // Only checks the agent would be assigned, so a leaked token can't write other results
+    let mut assigned = HashSet::new();
+    for r in &payload.results {
+        if assigned.contains(&r.check_id) {
+            continue;
+        }
+        let check = state
+            .db
+            .check(&r.check_id)
+            .await
+            .map_err(internal_error)?
+            .filter(|c| c.is_active == 1 && check_runs_in_region(c, &agent.region));
+        if check.is_none() {
+            return Err((
+                StatusCode::FORBIDDEN,
+                format!("el check {} no está asignado a este agente", r.check_id),
+            ));
+        }
+        assigned.insert(r.check_id.clone());
+    }
+    let mut planned = Vec::with_capacity(payload.results.len());
+    for r in &payload.results {
+        planned.push(
+            in_maintenance(&state.db, &r.check_id, r.checked_at)
+                .await
+                .map_err(internal_error)?,
+        );
+    }
+    if let Some(log) = &state.result_log {
+        let results: Vec<_> = payload
+            .results
+            .iter()
+            .map(|r| LoggedResult {
+                check_id: &r.check_id,
+                checked_at: r.checked_at,
+                status: &r.status,
+                http_status: r.http_status,
+                latency_ms: r.latency_ms,
+                error: r.error.as_deref(),
+                error_kind: r.error_kind.as_deref(),
+                response_bytes: r.response_bytes,
+                location: Some(&agent.region),
+            })
+            .collect();
+        if let Err(e) = log.write(&results).await {
+            error!("Error writing agent results to the result log: {e}");
+        }
+    }
+    let mut tx = state.db.begin().await.map_err(internal_error)?;
+    for (r, &planned) in payload.results.iter().zip(&planned) {
+        sqlx::query(
+            "INSERT INTO check_results (check_id, checked_at, status, http_status, latency_ms, error, content_hash, location, response_bytes, protocol, error_kind) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&r.check_id)
+        .bind(r.checked_at)
+        .bind(&r.status)
+        .bind(r.http_status)
+        .bind(r.latency_ms)
+        .bind(r.error.as_deref())
+        .bind(r.content_hash.as_deref())
+        .bind(&agent.region)
+        .bind(r.response_bytes)
+        .bind(r.protocol.as_deref())
+        .bind(r.error_kind.as_deref())
+        .execute(&mut *tx)
+        .await
+        .map_err(internal_error)?;
+
+        RollupDelta::sample(
+            &r.status,
+            r.latency_ms,
+            r.response_bytes,
+            r.error_kind.as_deref(),
+            planned,
+        )
+        .apply(&mut tx, &r.check_id, &rollup_bucket(r.checked_at))
+        .await
+        .map_err(internal_error)?;
+    }
+    tx.commit().await.map_err(internal_error)?;
+
+    #[cfg(feature = "clickhouse")]
+    if let Some(ch) = &state.clickhouse {
+        let samples: Vec<_> = payload
+            .results
+            .iter()
+            .zip(&planned)
+            .map(|(r, &planned)| clickhouse::Sample {
+                check_id: &r.check_id,
+                checked_at: r.checked_at,
+                status: &r.status,
+                http_status: r.http_status,
+                latency_ms: r.latency_ms,
+                error: r.error.as_deref(),
+                response_bytes: r.response_bytes,
+                location: Some(&agent.region),
+                planned,
+            })
+            .collect();
+        if let Err(e) = ch.insert(&samples).await {
+            error!("Error writing results to ClickHouse: {e}");
+        }
+    }
+
+    let mut check_ids: Vec<&str> = payload
+        .results
+        .iter()
+        .map(|r| r.check_id.as_str())
+        .collect();
+    check_ids.sort_unstable();
+    check_ids.dedup();
+
+    for check_id in check_ids {
+        let check = state.db.check(check_id).await.map_err(internal_error)?;
+        let Some(check) = check.filter(|c| c.quorum.is_some()) else {
+            continue;
+        };
+
+        let status = quorum_status(&state.db, &check, None, state.clock.now())
+            .await
+            .map_err(internal_error)?;
+        if let Some(previous) = status_transition(&check, &status) {
+            sqlx::query("UPDATE checks SET last_status = ? WHERE id = ?")
+                .bind(&status)
+                .bind(&check.id)
+                .execute(&state.db)
+                .await
+                .map_err(internal_error)?;
+            reload_check(&state, &check.id)
+                .await
+                .map_err(internal_error)?;
+            notify_status_change(&state, &check, previous, &status, None).await;
+        }
+    }
+
+    // After the quorum evaluation, so an incident it just opened counts these probes too
+    for r in payload.results.iter().filter(|r| r.status == "DOWN") {
+        let cause = failure_cause(r.error.as_deref(), r.http_status);
+        record_incident_failure(&state.db, &r.check_id, cause.as_deref(), r.latency_ms)
+            .await
+            .map_err(internal_error)?;
+    }
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Each source gets its own token, to be set in the other monitor's webhook, so it can
+/// report results without holding an API key.
+pub(crate) async fn create_ingest_source(
+    State(state): State<Arc<AppState>>,
+    Editor(caller): Editor,
+    Json(payload): Json<CreateIngestSourceRequest>,
+) -> Result<(StatusCode, Json<CreateIngestSourceResponse>), (StatusCode, String)> {
+    if !ingest::SOURCES.contains(&payload.source.as_str()) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("source debe ser uno de: {}", ingest::SOURCES.join(", ")),
+        ));
+    }
+    let token = generate_token();
+    let hash = hash_token(&token);
+
+    let mut tx = state.db.begin().await.map_err(internal_error)?;
+    let source = sqlx::query_as::<_, IngestSourceRow>(
+        "INSERT INTO ingest_sources (id, org_id, source, token_hash, created_at) VALUES (?, ?, ?, ?, ?) RETURNING id, org_id, source, created_at",
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(&caller.org_id)
+    .bind(&payload.source)
+    .bind(&hash)
+    .bind(state.clock.now())
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(internal_error)?;
+    record_audit(
+        &mut *tx,
+        AuditEntry {
+            after: snapshot(&source),
+            ..caller.audit("create", "ingest_source", &source.id)
+        },
+    )
+    .await
+    .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+    remember_token(&state, hash);
+
+    Ok((
+        StatusCode::CREATED,
+        Json(CreateIngestSourceResponse {
+            id: source.id,
+            source: source.source,
+            token,
+        }),
+    ))
+}
+
+pub(crate) async fn list_ingest_sources(
+    State(state): State<Arc<AppState>>,
+    Viewer(caller): Viewer,
+) -> Result<Json<Vec<IngestSourceRow>>, (StatusCode, String)> {
+    let rows = sqlx::query_as::<_, IngestSourceRow>(
+        "SELECT id, org_id, source, created_at FROM ingest_sources WHERE org_id = ? ORDER BY created_at",
+    )
+    .bind(&caller.org_id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(internal_error)?;
+
+    Ok(Json(rows))
+}
+
+pub(crate) async fn delete_ingest_source(
+    State(state): State<Arc<AppState>>,
+    Editor(caller): Editor,
+    Path(id): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let mut tx = state.db.begin().await.map_err(internal_error)?;
+    let source = sqlx::query_as::<_, IngestSourceRow>(
+        "DELETE FROM ingest_sources WHERE id = ? AND org_id = ? RETURNING id, org_id, source, created_at",
+    )
+    .bind(&id)
+    .bind(&caller.org_id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(internal_error)?
+    .ok_or((StatusCode::NOT_FOUND, "fuente no encontrada".to_string()))?;
+    record_audit(
+        &mut *tx,
+        AuditEntry {
+            before: snapshot(&source),
+            ..caller.audit("delete", "ingest_source", &id)
+        },
+    )
+    .await
+    .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Records a result reported by another monitor, e.g. one the check is being migrated from.
+/// It only adds to the check's history: its status, quorum, rollups and stats still follow
+/// its own probes. The token of an ingest source tells the organization and the payload's
+/// format.
+pub(crate) async fn ingest_external(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<IngestQuery>,
+    Json(payload): Json<serde_json::Value>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let unauthorized = || {
+        (
+            StatusCode::UNAUTHORIZED,
+            "token de ingesta inválido".to_string(),
+        )
+    };
+    let token = headers
+        .get("x-ingest-token")
+        .and_then(|value| value.to_str().ok())
+        .or(query.token.as_deref())
+        .ok_or_else(unauthorized)?;
+    let hash = hash_token(token);
+    let source = sqlx::query_as::<_, IngestSourceRow>(
+        "SELECT id, org_id, source, created_at FROM ingest_sources WHERE token_hash = ?",
+    )
+    .bind(&hash)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(internal_error)?
+    .ok_or_else(unauthorized)?;
+    remember_token(&state, hash);
+
+    let result =
+        ingest::parse(&source.source, &payload).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let check = match (&query.check_id, &result.url) {
+        (Some(id), _) => state
+            .db
+            .org_check(&source.org_id, id)
+            .await
+            .map_err(internal_error)?
+            .ok_or((StatusCode::NOT_FOUND, "check no encontrado".to_string()))?,
+        (None, Some(url)) => {
+            let mut checks =
+                sqlx::query_as::<_, CheckRow>("SELECT * FROM checks WHERE org_id = ? AND url = ?")
+                    .bind(&source.org_id)
+                    .bind(url)
+                    .fetch_all(&state.db)
+                    .await
+                    .map_err(internal_error)?;
+            if checks.len() > 1 {
+                return Err((
+                    StatusCode::CONFLICT,
+                    "varios checks tienen esa url, indica check_id".to_string(),
+                ));
+            }
+            checks
+                .pop()
+                .ok_or((StatusCode::NOT_FOUND, "check no encontrado".to_string()))?
+        }
+        (None, None) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "check_id requerido si el payload no trae la url".to_string(),
+            ))
+        }
+    };
+
+    let checked_at = result.checked_at.unwrap_or_else(|| state.clock.now());
+    if let Some(log) = &state.result_log {
+        let logged = LoggedResult {
+            check_id: &check.id,
+            checked_at,
+            status: result.status,
+            http_status: result.http_status,
+            latency_ms: result.latency_ms,
+            error: result.error.as_deref(),
+            error_kind: None,
+            response_bytes: None,
+            location: Some(&source.source),
+        };
+        if let Err(e) = log.write(&[logged]).await {
+            error!("Error writing an ingested result to the result log: {e}");
+        }
+    }
+    // Not in the rollups nor in ClickHouse, where the stats come from: another monitor's
+    // schedule, locations and notion of DOWN would skew the check's uptime
+    sqlx::query(
+        "INSERT INTO check_results (check_id, checked_at, status, http_status, latency_ms, error, location, external) VALUES (?, ?, ?, ?, ?, ?, ?, 1)",
+    )
+    .bind(&check.id)
+    .bind(checked_at)
+    .bind(result.status)
+    .bind(result.http_status)
+    .bind(result.latency_ms)
+    .bind(&result.error)
+    .bind(&source.source)
+    .execute(&state.db)
+    .await
+    .map_err(internal_error)?;
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+pub(crate) fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+}
+
+pub(crate) fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    BASE64_URL.encode(bytes)
+}
+
+pub(crate) fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+pub(crate) async fn check_stats(
+    State(state): State<Arc<AppState>>,
+    Viewer(caller): Viewer,
+    Path(id): Path<String>,
+    Query(query): Query<StatsQuery>,
+) -> Result<Json<CheckStats>, (StatusCode, String)> {
+    find_check(&state, &caller, &id).await?;
+    let period = query.period.unwrap_or_else(|| "24h".to_string());
+    compute_stats(&state, &id, period, query.timezone.as_deref())
+        .await
+        .map(Json)
+}
+
+/// Stats per probe location, to tell a regional degradation from a global one.
+pub(crate) async fn latency_by_region(
+    State(state): State<Arc<AppState>>,
+    Viewer(caller): Viewer,
+    Path(id): Path<String>,
+    Query(query): Query<PeriodQuery>,
+) -> Result<Json<LatencyByRegion>, (StatusCode, String)> {
+    find_check(&state, &caller, &id).await?;
+    let period = query.period.unwrap_or_else(|| "24h".to_string());
+    let duration = parse_period(&period).ok_or((
+        StatusCode::BAD_REQUEST,
+        "period inválido (ej: 24h, 7d)".to_string(),
+    ))?;
+
+    let regions = sqlx::query_as::<_, RegionLatency>(
+        r#"
+        SELECT location AS region, COUNT(*) AS samples, SUM(status != 'DOWN') AS up_samples,
+               100.0 * SUM(status != 'DOWN') / COUNT(*) AS uptime_percent,
+               AVG(latency_ms) AS avg_latency_ms, MAX(latency_ms) AS max_latency_ms
+        FROM check_results WHERE check_id = ? AND checked_at_ms >= ? AND external = 0
+        GROUP BY location ORDER BY location
+        "#,
+    )
+    .bind(&id)
+    .bind((state.clock.now() - duration).timestamp_millis())
+    .fetch_all(&state.db)
+    .await
+    .map_err(internal_error)?;
+    Ok(Json(LatencyByRegion { period, regions }))
+}
+
+pub(crate) async fn compute_stats(
+    state: &AppState,
+    id: &str,
+    period: String,
+    timezone: Option<&str>,
+) -> Result<CheckStats, (StatusCode, String)> {
+    let duration = parse_period(&period)
+        .filter(|d| d.num_minutes() % 60 == 0)
+        .ok_or((
+            StatusCode::BAD_REQUEST,
+            "period inválido, en horas o días (ej: 24h, 7d)".to_string(),
+        ))?;
+    let tz: Tz = timezone
+        .unwrap_or("UTC")
+        .parse()
+        .map_err(|_| (StatusCode::BAD_REQUEST, "timezone inválida".to_string()))?;
+    // The first bucket wholly inside the period, so no sample from before it is counted
+    let start = state.clock.now() - duration;
+    let mut since_at = start
+        .duration_trunc(chrono::Duration::hours(1))
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    if since_at < start {
+        since_at += chrono::Duration::hours(1);
+    }
+    let since = rollup_bucket(since_at);
+
+    // Rollups are kept in SQLite even when raw samples go to ClickHouse
+    let buckets: Vec<(DateTime<Utc>, i64, i64)> = sqlx::query_as(
+        "SELECT bucket_start, samples, up_samples FROM check_rollups WHERE check_id = ? AND bucket_start >= ? ORDER BY bucket_start",
+    )
+    .bind(id)
+    .bind(&since)
+    .fetch_all(&state.db)
+    .await
+    .map_err(internal_error)?;
+    let daily = daily_uptime(&buckets, tz);
+    let (planned_samples, bytes_sum, bytes_samples): (i64, i64, i64) = sqlx::query_as(
+        "SELECT COALESCE(SUM(planned_samples), 0), COALESCE(SUM(bytes_sum), 0), COALESCE(SUM(bytes_samples), 0) FROM check_rollups WHERE check_id = ? AND bucket_start >= ?",
+    )
+    .bind(id)
+    .bind(&since)
+    .fetch_one(&state.db)
+    .await
+    .map_err(internal_error)?;
+    let avg_response_bytes = (bytes_samples > 0).then(|| bytes_sum as f64 / bytes_samples as f64);
+    let errors_by_kind: Vec<(String, i64)> = sqlx::query_as(
+        "SELECT error_kind, SUM(samples) FROM check_rollup_errors WHERE check_id = ? AND bucket_start >= ? GROUP BY error_kind",
+    )
+    .bind(id)
+    .bind(&since)
+    .fetch_all(&state.db)
+    .await
+    .map_err(internal_error)?;
+    let errors_by_kind: BTreeMap<String, i64> = errors_by_kind.into_iter().collect();
+    #[cfg(feature = "clickhouse")]
+    if let Some(ch) = &state.clickhouse {
+        let stats = ch.stats(id, since_at).await.map_err(|e| {
+            error!("Error querying ClickHouse: {e}");
+            (
+                StatusCode::BAD_GATEWAY,
+                "error consultando ClickHouse".to_string(),
+            )
+        })?;
+        return Ok(CheckStats {
+            period,
+            since: since_at,
+            samples: stats.samples,
+            up_samples: stats.up_samples,
+            uptime_percent: (stats.samples > 0)
+                .then(|| stats.up_samples as f64 * 100.0 / stats.samples as f64),
+            avg_latency_ms: stats.avg_latency_ms,
+            max_latency_ms: stats.max_latency_ms,
+            avg_response_bytes,
+            planned_samples,
+            errors_by_kind,
+            timezone: tz.name().to_string(),
+            daily,
+        });
+    }
+
+    let (samples, up_samples, latency_sum, latency_samples, max_latency_ms): (
+        i64,
+        i64,
+        i64,
+        i64,
+        Option<i64>,
+    ) = sqlx::query_as(
+        r#"
+        SELECT COALESCE(SUM(samples), 0), COALESCE(SUM(up_samples), 0),
+               COALESCE(SUM(latency_sum), 0), COALESCE(SUM(latency_samples), 0), MAX(latency_max)
+        FROM check_rollups WHERE check_id = ? AND bucket_start >= ?
+        "#,
+    )
+    .bind(id)
+    .bind(since)
+    .fetch_one(&state.db)
+    .await
+    .map_err(internal_error)?;
+
+    Ok(CheckStats {
+        period,
+        since: since_at,
+        samples,
+        up_samples,
+        uptime_percent: (samples > 0).then(|| up_samples as f64 * 100.0 / samples as f64),
+        avg_latency_ms: (latency_samples > 0).then(|| latency_sum as f64 / latency_samples as f64),
+        max_latency_ms,
+        avg_response_bytes,
+        planned_samples,
+        errors_by_kind,
+        timezone: tz.name().to_string(),
+        daily,
+    })
+}
+
+pub(crate) fn internal_error(e: sqlx::Error) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+/// Access to `/metrics`: the `METRICS_TOKEN` bearer token, or an instance operator's API key.
+pub(crate) struct Scraper;
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for Scraper {
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        if let (Some(expected), Some(token)) = (&state.metrics_token, bearer_token(&parts.headers))
+        {
+            // Compared hashed so the time taken doesn't tell how much of the token matched
+            if hash_token(token) == hash_token(expected) {
+                return Ok(Scraper);
+            }
+        }
+        let caller = authorize(parts, state, Role::Admin).await?;
+        require_operator(&caller)?;
+        Ok(Scraper)
+    }
+}
+
+pub(crate) async fn metrics(State(state): State<Arc<AppState>>, _: Scraper) -> impl IntoResponse {
+    let m = &state.metrics;
+    let body = format!(
+        "# TYPE uptime_queue_depth gauge\n\
+         uptime_queue_depth {}\n\
+         # TYPE uptime_queue_capacity gauge\n\
+         uptime_queue_capacity {}\n\
+         # TYPE uptime_jobs_in_flight gauge\n\
+         uptime_jobs_in_flight {}\n\
+         # TYPE uptime_jobs_completed_total counter\n\
+         uptime_jobs_completed_total {}\n\
+         # TYPE uptime_jobs_rejected_total counter\n\
+         uptime_jobs_rejected_total {}\n\
+         # TYPE uptime_queue_lag_seconds gauge\n\
+         uptime_queue_lag_seconds {:.3}\n\
+         # TYPE uptime_scheduler_heartbeat_timestamp_seconds gauge\n\
+         uptime_scheduler_heartbeat_timestamp_seconds {:.3}\n\
+         # TYPE uptime_scheduler_lag_seconds gauge\n\
+         uptime_scheduler_lag_seconds {:.3}\n\
+         # TYPE uptime_overdue_checks gauge\n\
+         uptime_overdue_checks {}\n\
+         # TYPE uptime_probes_total counter\n\
+         uptime_probes_total {}\n\
+         # TYPE uptime_probe_errors_total counter\n\
+         uptime_probe_errors_total {}\n\
+         # TYPE uptime_probe_config_errors_total counter\n\
+         uptime_probe_config_errors_total {}\n",
+        m.queue_depth.load(Ordering::Relaxed),
+        state.queue.capacity,
+        m.jobs_in_flight.load(Ordering::Relaxed),
+        m.jobs_completed.load(Ordering::Relaxed),
+        m.jobs_rejected.load(Ordering::Relaxed),
+        m.queue_lag_ms.load(Ordering::Relaxed) as f64 / 1000.0,
+        m.scheduler_heartbeat_ms.load(Ordering::Relaxed) as f64 / 1000.0,
+        m.scheduler_lag_ms.load(Ordering::Relaxed) as f64 / 1000.0,
+        m.overdue_checks.load(Ordering::Relaxed),
+        m.probes_total.load(Ordering::Relaxed),
+        m.probe_errors_total.load(Ordering::Relaxed),
+        m.probe_config_errors_total.load(Ordering::Relaxed),
+    );
+    (
+        StatusCode::OK,
+        [(
+            axum::http::header::CONTENT_TYPE,
+            "text/plain; version=0.0.4",
+        )],
+        body,
+    )
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct WorkerStatus {
+    pub(crate) instance_id: String,
+    pub(crate) stalled: bool,
+    pub(crate) last_scheduler_pass_at: Option<DateTime<Utc>>,
+    pub(crate) scheduler_lag_seconds: f64,
+    pub(crate) overdue_checks: i64,
+    pub(crate) queue_depth: i64,
+    pub(crate) jobs_in_flight: i64,
+    pub(crate) probes_total: u64,
+    pub(crate) probe_errors_total: u64,
+    pub(crate) probe_config_errors_total: u64,
+    pub(crate) probe_error_ratio: f64,
+}
+
+/// The `/admin` endpoints report on the whole instance, so they are limited to admins of the
+/// default organization.
+pub(crate) fn require_operator(caller: &Caller) -> Result<(), (StatusCode, String)> {
+    if caller.org_id != DEFAULT_ORG {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "solo los administradores de la instancia".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+pub(crate) async fn worker_status(
+    State(state): State<Arc<AppState>>,
+    Admin(caller): Admin,
+) -> Result<Json<WorkerStatus>, (StatusCode, String)> {
+    require_operator(&caller)?;
+    let m = &state.metrics;
+    let heartbeat = m.scheduler_heartbeat_ms.load(Ordering::Relaxed);
+    Ok(Json(WorkerStatus {
+        instance_id: state.instance_id.clone(),
+        stalled: !worker_probe(&state).ok,
+        last_scheduler_pass_at: DateTime::from_timestamp_millis(heartbeat)
+            .filter(|_| heartbeat > 0),
+        scheduler_lag_seconds: m.scheduler_lag_ms.load(Ordering::Relaxed) as f64 / 1000.0,
+        overdue_checks: m.overdue_checks.load(Ordering::Relaxed),
+        queue_depth: m.queue_depth.load(Ordering::Relaxed),
+        jobs_in_flight: m.jobs_in_flight.load(Ordering::Relaxed),
+        probes_total: m.probes_total.load(Ordering::Relaxed),
+        probe_errors_total: m.probe_errors_total.load(Ordering::Relaxed),
+        probe_config_errors_total: m.probe_config_errors_total.load(Ordering::Relaxed),
+        probe_error_ratio: f64::from_bits(m.probe_error_ratio.load(Ordering::Relaxed)),
+    }))
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct AdminStats {
+    pub(crate) checks: i64,
+    pub(crate) active_checks: i64,
+    pub(crate) results: i64,
+    pub(crate) database_bytes: i64,
+    pub(crate) open_incidents: i64,
+    pub(crate) notifications_last_24h: i64,
+}
+
+pub(crate) async fn admin_stats(
+    State(state): State<Arc<AppState>>,
+    Admin(caller): Admin,
+) -> Result<Json<AdminStats>, (StatusCode, String)> {
+    require_operator(&caller)?;
+    let (checks, active_checks): (i64, i64) =
+        sqlx::query_as("SELECT COUNT(*), COALESCE(SUM(is_active), 0) FROM checks")
+            .fetch_one(&state.db)
+            .await
+            .map_err(internal_error)?;
+    let (results,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM check_results")
+        .fetch_one(&state.db)
+        .await
+        .map_err(internal_error)?;
+    let (database_bytes,): (i64,) = sqlx::query_as(
+        "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+    )
+    .fetch_one(&state.db)
+    .await
+    .map_err(internal_error)?;
+    let (open_incidents,): (i64,) =
+        sqlx::query_as("SELECT COUNT(*) FROM incidents WHERE resolved_at IS NULL")
+            .fetch_one(&state.db)
+            .await
+            .map_err(internal_error)?;
+    let (notifications_last_24h,): (i64,) =
+        sqlx::query_as("SELECT COUNT(*) FROM notifications_sent WHERE sent_at >= ?")
+            .bind(Utc::now() - chrono::Duration::hours(24))
+            .fetch_one(&state.db)
+            .await
+            .map_err(internal_error)?;
+
+    Ok(Json(AdminStats {
+        checks,
+        active_checks,
+        results,
+        database_bytes,
+        open_incidents,
+        notifications_last_24h,
+    }))
+}
+
+pub(crate) async fn trigger_backup(
+    State(state): State<Arc<AppState>>,
+    Admin(caller): Admin,
+) -> Result<(StatusCode, Json<Backup>), (StatusCode, String)> {
+    require_operator(&caller)?;
+    let backup = create_backup(&state).await.map_err(|e| {
+        error!("Error creating backup: {e}");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "no se pudo crear la copia de seguridad".to_string(),
+        )
+    })?;
+    record_audit(
+        &state.db,
+        AuditEntry {
+            after: snapshot(&backup),
+            ..caller.audit("backup", "database", &backup.file)
+        },
+    )
+    .await
+    .map_err(internal_error)?;
+    Ok((StatusCode::CREATED, Json(backup)))
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct NotificationsQuery {
+    pub(crate) status: Option<String>,
+    /// Every organization's, for the instance's operators.
+    #[serde(default)]
+    pub(crate) all: bool,
+}
+
+/// The organization's outbox, newest first; without `status`, what hasn't been sent.
+pub(crate) async fn list_notifications(
+    State(state): State<Arc<AppState>>,
+    Admin(caller): Admin,
+    Query(query): Query<NotificationsQuery>,
+) -> Result<Json<Vec<NotificationRow>>, (StatusCode, String)> {
+    if query.all {
+        require_operator(&caller)?;
+    }
+    if let Some(status) = &query.status {
+        if !["pending", "held", "sent", "dropped", "dead"].contains(&status.as_str()) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "status debe ser pending, held, sent, dropped o dead".to_string(),
+            ));
+        }
+    }
+    let notifications = sqlx::query_as::<_, NotificationRow>(
+        "SELECT * FROM notification_outbox WHERE (status = ?1 OR (?1 IS NULL AND status != 'sent')) AND (?2 OR org_id = ?3) ORDER BY created_at DESC LIMIT 500",
+    )
+    .bind(&query.status)
+    .bind(query.all)
+    .bind(&caller.org_id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(internal_error)?;
+    Ok(Json(notifications))
+}
+
+/// Delivers a pending or dead notification now, with a fresh round of attempts.
+pub(crate) async fn retry_notification(
+    State(state): State<Arc<AppState>>,
+    Admin(caller): Admin,
+    Path(id): Path<String>,
+) -> Result<Json<NotificationRow>, (StatusCode, String)> {
+    // Operators look after every organization's outbox
+    let operator = require_operator(&caller).is_ok();
+    let find = || {
+        sqlx::query_as::<_, NotificationRow>(
+            "SELECT * FROM notification_outbox WHERE id = ? AND (? OR org_id = ?)",
+        )
+        .bind(&id)
+        .bind(operator)
+        .bind(&caller.org_id)
+        .fetch_optional(&state.db)
+    };
+    let before = find().await.map_err(internal_error)?.ok_or((
+        StatusCode::NOT_FOUND,
+        "notificación no encontrada".to_string(),
+    ))?;
+    if before.status == "sent" {
+        return Err((
+            StatusCode::CONFLICT,
+            "la notificación ya se envió".to_string(),
+        ));
+    }
+    if before.status == "held" {
+        return Err((
+            StatusCode::CONFLICT,
+            "la notificación espera al resumen de su canal".to_string(),
+        ));
+    }
+
+    // Leased like a first delivery so the retry loop leaves it alone meanwhile
+    let retry = NotificationRow {
+        status: "pending".to_string(),
+        attempts: 0,
+        next_attempt_at: state.clock.now() + chrono::Duration::seconds(NOTIFICATION_LEASE_SECONDS),
+        ..before.clone()
+    };
+    sqlx::query(
+        "UPDATE notification_outbox SET status = ?, attempts = ?, next_attempt_at = ? WHERE id = ?",
+    )
+    .bind(&retry.status)
+    .bind(retry.attempts)
+    .bind(retry.next_attempt_at)
+    .bind(&id)
+    .execute(&state.db)
+    .await
+    .map_err(internal_error)?;
+    redeliver_notification(&state, &retry)
+        .await
+        .map_err(internal_error)?;
+
+    let after = find().await.map_err(internal_error)?.ok_or((
+        StatusCode::NOT_FOUND,
+        "notificación no encontrada".to_string(),
+    ))?;
+    record_audit(
+        &state.db,
+        AuditEntry {
+            before: snapshot(&before),
+            after: snapshot(&after),
+            ..caller.audit("retry", "notification", &id)
+        },
+    )
+    .await
+    .map_err(internal_error)?;
+    Ok(Json(after))
+}
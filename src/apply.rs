@@ -0,0 +1,790 @@
+//! Monitoring as code: `POST /apply` takes the organization's desired checks, channels and
+//! status pages, diffs them against what exists and creates, updates and deletes to match in
+//! a single transaction. With `?dry_run=true` it only returns the plan.
+
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::types::Json as SqlJson;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::api::{
+    delete_channel_rows, delete_check_data, internal_error, validate_channel, Admin,
+    CreateChannelRequest,
+};
+use crate::cron::Cron;
+use crate::domain::{
+    initial_run_at, normalize_email, validate_accepted_statuses, validate_check_url,
+    validate_connect_to, validate_cron, validate_http_version, validate_min_response_bytes,
+    validate_probe_headers, validate_severity, validate_slug, validate_template, ChannelRow,
+    CheckRow, Plan, StatusPageRow,
+};
+use crate::notify::request_email_verification;
+use crate::scheduler::reload_check;
+use crate::store::{org_plan, org_status_pages, record_audit, snapshot, AuditEntry, CheckStore};
+use crate::AppState;
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ApplyQuery {
+    #[serde(default)]
+    pub(crate) dry_run: bool,
+}
+
+/// A kind that is left out isn't managed: what exists of it stays as it is.
+#[derive(Debug, Deserialize)]
+pub(crate) struct ApplyRequest {
+    pub(crate) checks: Option<Vec<DesiredCheck>>,
+    pub(crate) channels: Option<Vec<CreateChannelRequest>>,
+    pub(crate) status_pages: Option<Vec<DesiredStatusPage>>,
+}
+
+/// The fields of a check that `apply` manages; checks are matched by name. Their other
+/// fields keep their value, or get their defaults on new checks.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct DesiredCheck {
+    pub(crate) name: String,
+    pub(crate) url: String,
+    pub(crate) interval_seconds: i64,
+    #[serde(default = "active")]
+    pub(crate) is_active: bool,
+    pub(crate) alert_email: Option<String>,
+    #[serde(default = "critical")]
+    pub(crate) severity: String,
+    pub(crate) alert_template: Option<String>,
+    pub(crate) min_response_bytes: Option<i64>,
+    pub(crate) user_agent: Option<String>,
+    pub(crate) headers: Option<BTreeMap<String, String>>,
+    pub(crate) backoff_max_seconds: Option<i64>,
+    pub(crate) accepted_statuses: Option<String>,
+    pub(crate) http_version: Option<String>,
+    pub(crate) connect_to: Option<String>,
+    pub(crate) cron: Option<String>,
+    pub(crate) timezone: Option<String>,
+}
+
+fn active() -> bool {
+    true
+}
+
+fn critical() -> String {
+    "critical".to_string()
+}
+
+impl DesiredCheck {
+    fn of(check: &CheckRow) -> Self {
+        DesiredCheck {
+            name: check.name.clone(),
+            url: check.url.clone(),
+            interval_seconds: check.interval_seconds,
+            is_active: check.is_active == 1,
+            alert_email: check.alert_email.clone(),
+            severity: check.severity.clone(),
+            alert_template: check.alert_template.clone(),
+            min_response_bytes: check.min_response_bytes,
+            user_agent: check.user_agent.clone(),
+            headers: check.headers.clone().map(|h| h.0),
+            backoff_max_seconds: check.backoff_max_seconds,
+            accepted_statuses: check.accepted_statuses.clone(),
+            http_version: check.http_version.clone(),
+            connect_to: check.connect_to.clone(),
+            cron: check.cron.clone(),
+            timezone: check.timezone.clone(),
+        }
+    }
+
+    /// Only called once the check is validated.
+    fn schedule(&self) -> Option<Cron> {
+        validate_cron(self.cron.as_deref(), self.timezone.as_deref())
+            .ok()
+            .flatten()
+    }
+}
+
+/// Matched by slug. `checks` are check names.
+#[derive(Debug, Deserialize)]
+pub(crate) struct DesiredStatusPage {
+    pub(crate) slug: String,
+    pub(crate) title: String,
+    #[serde(default)]
+    pub(crate) checks: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct ApplyPlan {
+    pub(crate) dry_run: bool,
+    pub(crate) changes: Vec<Change>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct Change {
+    /// `create`, `update` or `delete`.
+    pub(crate) action: &'static str,
+    /// `check`, `channel` or `status_page`.
+    pub(crate) kind: &'static str,
+    /// The name, or the slug of a status page.
+    pub(crate) key: String,
+    /// Assigned up front to the entities that are created.
+    pub(crate) id: String,
+    /// What an update changes.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) fields: Vec<String>,
+}
+
+enum Op<'a> {
+    CreateCheck(DesiredCheck),
+    UpdateCheck(&'a CheckRow, DesiredCheck),
+    DeleteCheck(&'a CheckRow),
+    CreateChannel(CreateChannelRequest),
+    UpdateChannel(&'a ChannelRow, CreateChannelRequest),
+    DeleteChannel(&'a ChannelRow),
+    CreateStatusPage(String, Vec<String>),
+    UpdateStatusPage(&'a StatusPageRow, String, Vec<String>),
+    DeleteStatusPage(&'a StatusPageRow),
+}
+
+pub(crate) async fn apply(
+    State(state): State<Arc<AppState>>,
+    Admin(caller): Admin,
+    Query(query): Query<ApplyQuery>,
+    Json(payload): Json<ApplyRequest>,
+) -> Result<Json<ApplyPlan>, (StatusCode, String)> {
+    let plan = org_plan(&state.db, &caller.org_id)
+        .await
+        .map_err(internal_error)?;
+    let checks = state
+        .db
+        .org_checks(&caller.org_id)
+        .await
+        .map_err(internal_error)?;
+    let channels = sqlx::query_as::<_, ChannelRow>(
+        "SELECT * FROM notification_channels WHERE org_id = ? ORDER BY created_at",
+    )
+    .bind(&caller.org_id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(internal_error)?;
+    let pages = org_status_pages(&state.db, &caller.org_id)
+        .await
+        .map_err(internal_error)?;
+    let mut ops: Vec<(Change, Op)> = Vec::new();
+
+    // Names of the checks once applied, for the status pages to refer to
+    let mut check_ids: HashMap<String, Vec<String>> = HashMap::new();
+    if let Some(desired) = payload.checks {
+        let existing = by_key(&checks, |c| &c.name);
+        let mut declared = HashSet::new();
+        for desired in desired {
+            let desired = validate_check(state.min_interval_seconds, plan, desired)?;
+            if !declared.insert(desired.name.clone()) {
+                return Err(bad_request(format!("check duplicado: {}", desired.name)));
+            }
+            let check = match existing.get(desired.name.as_str()).map(Vec::as_slice) {
+                None => {
+                    let change = Change::new("create", "check", &desired.name, new_id());
+                    check_ids
+                        .entry(desired.name.clone())
+                        .or_default()
+                        .push(change.id.clone());
+                    ops.push((change, Op::CreateCheck(desired)));
+                    continue;
+                }
+                Some([check]) => check,
+                Some(_) => return Err(ambiguous("varios checks se llaman", &desired.name)),
+            };
+            check_ids
+                .entry(desired.name.clone())
+                .or_default()
+                .push(check.id.clone());
+            validate_check_update(check, &desired)?;
+            let fields = changed_fields(&DesiredCheck::of(check), &desired);
+            if !fields.is_empty() {
+                let mut change = Change::new("update", "check", &desired.name, check.id.clone());
+                change.fields = fields;
+                ops.push((change, Op::UpdateCheck(check, desired)));
+            }
+        }
+        for check in checks.iter().filter(|c| !declared.contains(&c.name)) {
+            let change = Change::new("delete", "check", &check.name, check.id.clone());
+            ops.push((change, Op::DeleteCheck(check)));
+        }
+
+        let created = ops
+            .iter()
+            .filter(|(c, _)| c.kind == "check" && c.action == "create")
+            .count() as i64;
+        let deleted = ops
+            .iter()
+            .filter(|(c, _)| c.kind == "check" && c.action == "delete")
+            .count() as i64;
+        let total = checks.len() as i64 + created - deleted;
+        if let Some(max_checks) = plan.max_checks.filter(|max| created > 0 && total > *max) {
+            return Err(
+                plan.limit_error(format!("máximo {max_checks} checks"), |plan| {
+                    plan.max_checks.is_none_or(|max| total <= max)
+                }),
+            );
+        }
+    } else {
+        for check in &checks {
+            check_ids
+                .entry(check.name.clone())
+                .or_default()
+                .push(check.id.clone());
+        }
+    }
+
+    if let Some(desired) = payload.channels {
+        let existing = by_key(&channels, |c| &c.name);
+        let mut declared = HashSet::new();
+        for desired in desired {
+            if desired.secret.is_some() {
+                return Err(bad_request(format!(
+                    "canal {}: usa secret_id, apply no guarda secretos",
+                    desired.name
+                )));
+            }
+            let target = validate_channel(&state, &caller.org_id, &desired)
+                .await
+                .map_err(|(status, e)| (status, format!("canal {}: {e}", desired.name)))?;
+            let desired = CreateChannelRequest {
+                target,
+                timezone: Some(desired.timezone.unwrap_or_else(|| "UTC".to_string())),
+                min_severity: Some(desired.min_severity.unwrap_or_else(|| "info".to_string())),
+                ..desired
+            };
+            if !declared.insert(desired.name.clone()) {
+                return Err(bad_request(format!("canal duplicado: {}", desired.name)));
+            }
+            match existing.get(desired.name.as_str()).map(Vec::as_slice) {
+                None => {
+                    let change = Change::new("create", "channel", &desired.name, new_id());
+                    ops.push((change, Op::CreateChannel(desired)));
+                }
+                Some([channel]) => {
+                    let fields = changed_fields(&channel_request(channel), &desired);
+                    if !fields.is_empty() {
+                        let mut change =
+                            Change::new("update", "channel", &desired.name, channel.id.clone());
+                        change.fields = fields;
+                        ops.push((change, Op::UpdateChannel(channel, desired)));
+                    }
+                }
+                Some(_) => return Err(ambiguous("varios canales se llaman", &desired.name)),
+            }
+        }
+        for channel in channels.iter().filter(|c| !declared.contains(&c.name)) {
+            let change = Change::new("delete", "channel", &channel.name, channel.id.clone());
+            ops.push((change, Op::DeleteChannel(channel)));
+        }
+    }
+
+    if let Some(desired) = payload.status_pages {
+        let existing = by_key(&pages, |p| &p.slug);
+        let mut declared = HashSet::new();
+        for desired in desired {
+            validate_slug(&desired.slug)?;
+            let title = desired.title.trim().to_string();
+            if title.is_empty() {
+                return Err(bad_request(format!(
+                    "página {}: title requerido",
+                    desired.slug
+                )));
+            }
+            if !declared.insert(desired.slug.clone()) {
+                return Err(bad_request(format!("página duplicada: {}", desired.slug)));
+            }
+            let mut ids = Vec::with_capacity(desired.checks.len());
+            for name in &desired.checks {
+                match check_ids.get(name).map(Vec::as_slice) {
+                    Some([id]) => ids.push(id.clone()),
+                    Some(_) => return Err(ambiguous("varios checks se llaman", name)),
+                    None => return Err(bad_request(format!("check {name} no existe"))),
+                }
+            }
+            ids.sort();
+            ids.dedup();
+            match existing.get(desired.slug.as_str()).map(Vec::as_slice) {
+                Some([page]) => {
+                    let mut fields = Vec::new();
+                    if page.title != title {
+                        fields.push("title".to_string());
+                    }
+                    if page.check_ids != ids {
+                        fields.push("checks".to_string());
+                    }
+                    if !fields.is_empty() {
+                        let mut change =
+                            Change::new("update", "status_page", &page.slug, page.id.clone());
+                        change.fields = fields;
+                        ops.push((change, Op::UpdateStatusPage(page, title, ids)));
+                    }
+                }
+                _ => {
+                    let change = Change::new("create", "status_page", &desired.slug, new_id());
+                    ops.push((change, Op::CreateStatusPage(title, ids)));
+                }
+            }
+        }
+        for page in pages.iter().filter(|p| !declared.contains(&p.slug)) {
+            let change = Change::new("delete", "status_page", &page.slug, page.id.clone());
+            ops.push((change, Op::DeleteStatusPage(page)));
+        }
+    }
+
+    if query.dry_run {
+        let changes = ops.into_iter().map(|(change, _)| change).collect();
+        return Ok(Json(ApplyPlan {
+            dry_run: true,
+            changes,
+        }));
+    }
+
+    let now = state.clock.now();
+    let mut tx = state.db.begin().await.map_err(internal_error)?;
+    let mut emails = Vec::new();
+    for (change, op) in &ops {
+        let audit = caller.audit(change.action, change.kind, &change.id);
+        let entry = match op {
+            Op::CreateCheck(check) => {
+                let created = sqlx::query_as::<_, CheckRow>(
+                    r#"
+                    INSERT INTO checks (id, name, url, interval_seconds, is_active, alert_email, severity, alert_template, min_response_bytes, user_agent, headers, backoff_max_seconds, accepted_statuses, http_version, connect_to, cron, timezone, next_run_at, org_id)
+                    VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                    RETURNING *
+                    "#,
+                )
+                .bind(&change.id)
+                .bind(&check.name)
+                .bind(&check.url)
+                .bind(check.interval_seconds)
+                .bind(i64::from(check.is_active))
+                .bind(&check.alert_email)
+                .bind(&check.severity)
+                .bind(&check.alert_template)
+                .bind(check.min_response_bytes)
+                .bind(&check.user_agent)
+                .bind(check.headers.clone().map(SqlJson))
+                .bind(check.backoff_max_seconds)
+                .bind(&check.accepted_statuses)
+                .bind(&check.http_version)
+                .bind(&check.connect_to)
+                .bind(&check.cron)
+                .bind(&check.timezone)
+                .bind(initial_run_at(
+                    check.interval_seconds,
+                    check.schedule().as_ref(),
+                    now,
+                ))
+                .bind(&caller.org_id)
+                .fetch_one(&mut *tx)
+                .await
+                .map_err(internal_error)?;
+                emails.extend(check.alert_email.clone());
+                AuditEntry {
+                    after: snapshot(&created),
+                    ..audit
+                }
+            }
+            Op::UpdateCheck(before, check) => {
+                let updated = sqlx::query_as::<_, CheckRow>(
+                    r#"
+                    UPDATE checks SET name = ?, url = ?, interval_seconds = ?, is_active = ?,
+                      alert_email = ?, severity = ?, alert_template = ?, min_response_bytes = ?,
+                      user_agent = ?, headers = ?, backoff_max_seconds = ?, accepted_statuses = ?,
+                      http_version = ?, connect_to = ?, cron = ?, timezone = ?,
+                      next_run_at = COALESCE(?, next_run_at), version = version + 1, updated_at = ?
+                    WHERE id = ? AND version = ?
+                    RETURNING *
+                    "#,
+                )
+                .bind(&check.name)
+                .bind(&check.url)
+                .bind(check.interval_seconds)
+                .bind(i64::from(check.is_active))
+                .bind(&check.alert_email)
+                .bind(&check.severity)
+                .bind(&check.alert_template)
+                .bind(check.min_response_bytes)
+                .bind(&check.user_agent)
+                .bind(check.headers.clone().map(SqlJson))
+                .bind(check.backoff_max_seconds)
+                .bind(&check.accepted_statuses)
+                .bind(&check.http_version)
+                .bind(&check.connect_to)
+                .bind(&check.cron)
+                .bind(&check.timezone)
+                .bind(
+                    (check.cron != before.cron || check.timezone != before.timezone).then(|| {
+                        initial_run_at(check.interval_seconds, check.schedule().as_ref(), now)
+                    }),
+                )
+                .bind(Utc::now())
+                .bind(&before.id)
+                .bind(before.version)
+                .fetch_optional(&mut *tx)
+                .await
+                .map_err(internal_error)?
+                .ok_or_else(changed_meanwhile)?;
+                if check.alert_email != before.alert_email {
+                    emails.extend(check.alert_email.clone());
+                }
+                AuditEntry {
+                    before: snapshot(before),
+                    after: snapshot(&updated),
+                    ..audit
+                }
+            }
+            Op::DeleteCheck(check) => {
+                delete_check_data(&mut tx, &check.id)
+                    .await
+                    .map_err(internal_error)?;
+                let deleted = sqlx::query("DELETE FROM checks WHERE id = ? AND version = ?")
+                    .bind(&check.id)
+                    .bind(check.version)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(internal_error)?;
+                if deleted.rows_affected() == 0 {
+                    return Err(changed_meanwhile());
+                }
+                AuditEntry {
+                    before: snapshot(check),
+                    ..audit
+                }
+            }
+            Op::CreateChannel(channel) => {
+                let created = sqlx::query_as::<_, ChannelRow>(
+                    "INSERT INTO notification_channels (id, name, kind, target, template, created_at, quiet_start, quiet_end, timezone, digest_seconds, max_alerts_per_hour, org_id, min_severity, secret_id) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?) RETURNING *",
+                )
+                .bind(&change.id)
+                .bind(&channel.name)
+                .bind(&channel.kind)
+                .bind(&channel.target)
+                .bind(&channel.template)
+                .bind(Utc::now())
+                .bind(&channel.quiet_start)
+                .bind(&channel.quiet_end)
+                .bind(&channel.timezone)
+                .bind(channel.digest_seconds)
+                .bind(channel.max_alerts_per_hour)
+                .bind(&caller.org_id)
+                .bind(&channel.min_severity)
+                .bind(&channel.secret_id)
+                .fetch_one(&mut *tx)
+                .await
+                .map_err(internal_error)?;
+                if created.kind == "email" {
+                    emails.push(created.target.clone());
+                }
+                AuditEntry {
+                    after: snapshot(&created),
+                    ..audit
+                }
+            }
+            Op::UpdateChannel(before, channel) => {
+                let updated = sqlx::query_as::<_, ChannelRow>(
+                    r#"
+                    UPDATE notification_channels SET name = ?, kind = ?, target = ?, template = ?,
+                      quiet_start = ?, quiet_end = ?, timezone = ?, digest_seconds = ?,
+                      max_alerts_per_hour = ?, min_severity = ?, secret_id = ?
+                    WHERE id = ?
+                    RETURNING *
+                    "#,
+                )
+                .bind(&channel.name)
+                .bind(&channel.kind)
+                .bind(&channel.target)
+                .bind(&channel.template)
+                .bind(&channel.quiet_start)
+                .bind(&channel.quiet_end)
+                .bind(&channel.timezone)
+                .bind(channel.digest_seconds)
+                .bind(channel.max_alerts_per_hour)
+                .bind(&channel.min_severity)
+                .bind(&channel.secret_id)
+                .bind(&before.id)
+                .fetch_optional(&mut *tx)
+                .await
+                .map_err(internal_error)?
+                .ok_or_else(changed_meanwhile)?;
+                if updated.kind == "email" && updated.target != before.target {
+                    emails.push(updated.target.clone());
+                }
+                AuditEntry {
+                    before: snapshot(before),
+                    after: snapshot(&updated),
+                    ..audit
+                }
+            }
+            Op::DeleteChannel(channel) => {
+                delete_channel_rows(&mut tx, &channel.id)
+                    .await
+                    .map_err(internal_error)?;
+                AuditEntry {
+                    before: snapshot(channel),
+                    ..audit
+                }
+            }
+            Op::CreateStatusPage(title, ids) => {
+                let mut page = sqlx::query_as::<_, StatusPageRow>(
+                    "INSERT INTO status_pages (id, org_id, slug, title, created_at) VALUES (?, ?, ?, ?, ?) RETURNING *",
+                )
+                .bind(&change.id)
+                .bind(&caller.org_id)
+                .bind(&change.key)
+                .bind(title)
+                .bind(now)
+                .fetch_one(&mut *tx)
+                .await
+                .map_err(|e| match &e {
+                    sqlx::Error::Database(db) if db.is_unique_violation() => (
+                        StatusCode::CONFLICT,
+                        format!("slug en uso: {}", change.key),
+                    ),
+                    _ => internal_error(e),
+                })?;
+                set_status_page_checks(&mut tx, &page.id, ids)
+                    .await
+                    .map_err(internal_error)?;
+                page.check_ids = ids.clone();
+                AuditEntry {
+                    after: snapshot(&page),
+                    ..audit
+                }
+            }
+            Op::UpdateStatusPage(before, title, ids) => {
+                let mut page = sqlx::query_as::<_, StatusPageRow>(
+                    "UPDATE status_pages SET title = ? WHERE id = ? RETURNING *",
+                )
+                .bind(title)
+                .bind(&before.id)
+                .fetch_optional(&mut *tx)
+                .await
+                .map_err(internal_error)?
+                .ok_or_else(changed_meanwhile)?;
+                set_status_page_checks(&mut tx, &page.id, ids)
+                    .await
+                    .map_err(internal_error)?;
+                page.check_ids = ids.clone();
+                AuditEntry {
+                    before: snapshot(before),
+                    after: snapshot(&page),
+                    ..audit
+                }
+            }
+            Op::DeleteStatusPage(page) => {
+                set_status_page_checks(&mut tx, &page.id, &[])
+                    .await
+                    .map_err(internal_error)?;
+                sqlx::query("DELETE FROM status_pages WHERE id = ?")
+                    .bind(&page.id)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(internal_error)?;
+                AuditEntry {
+                    before: snapshot(page),
+                    ..audit
+                }
+            }
+        };
+        record_audit(&mut *tx, entry)
+            .await
+            .map_err(internal_error)?;
+    }
+    tx.commit().await.map_err(internal_error)?;
+
+    for (change, _) in ops.iter().filter(|(c, _)| c.kind == "check") {
+        if change.action == "delete" {
+            state.checks.remove(&change.id);
+        } else {
+            reload_check(&state, &change.id)
+                .await
+                .map_err(internal_error)?;
+        }
+    }
+    emails.sort();
+    emails.dedup();
+    for email in &emails {
+        request_email_verification(&state, email).await?;
+    }
+
+    let changes = ops.into_iter().map(|(change, _)| change).collect();
+    Ok(Json(ApplyPlan {
+        dry_run: false,
+        changes,
+    }))
+}
+
+impl Change {
+    fn new(action: &'static str, kind: &'static str, key: &str, id: String) -> Self {
+        Change {
+            action,
+            kind,
+            key: key.to_string(),
+            id,
+            fields: Vec::new(),
+        }
+    }
+}
+
+fn new_id() -> String {
+    Uuid::new_v4().to_string()
+}
+
+fn bad_request(message: String) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, message)
+}
+
+fn ambiguous(message: &str, key: &str) -> (StatusCode, String) {
+    (StatusCode::CONFLICT, format!("{message} {key}"))
+}
+
+fn changed_meanwhile() -> (StatusCode, String) {
+    (
+        StatusCode::PRECONDITION_FAILED,
+        "la configuración cambió durante el apply".to_string(),
+    )
+}
+
+fn by_key<T>(items: &[T], key: impl Fn(&T) -> &String) -> HashMap<&str, Vec<&T>> {
+    let mut map: HashMap<&str, Vec<&T>> = HashMap::new();
+    for item in items {
+        map.entry(key(item).as_str()).or_default().push(item);
+    }
+    map
+}
+
+/// The top-level fields in which `desired` differs from `current`.
+fn changed_fields(current: &impl Serialize, desired: &impl Serialize) -> Vec<String> {
+    let (Some(Value::Object(current)), Some(Value::Object(desired))) =
+        (snapshot(current), snapshot(desired))
+    else {
+        return Vec::new();
+    };
+    desired
+        .iter()
+        .filter(|(field, value)| current.get(*field) != Some(value))
+        .map(|(field, _)| field.clone())
+        .collect()
+}
+
+/// Checks are validated like `POST /checks` does, and normalized the way they are stored so
+/// that they compare equal to their unchanged rows.
+fn validate_check(
+    server_floor: i64,
+    plan: &Plan,
+    check: DesiredCheck,
+) -> Result<DesiredCheck, (StatusCode, String)> {
+    let name = check.name.clone();
+    let in_check = |(status, e): (StatusCode, String)| (status, format!("check {name}: {e}"));
+    plan.check_interval(server_floor, check.interval_seconds)
+        .map_err(in_check)?;
+    validate_check_url(&check.url).map_err(in_check)?;
+    validate_severity("severity", &check.severity).map_err(in_check)?;
+    if let Some(template) = &check.alert_template {
+        validate_template(template).map_err(in_check)?;
+    }
+    validate_min_response_bytes(check.min_response_bytes).map_err(in_check)?;
+    let headers = validate_probe_headers(check.user_agent.as_deref(), check.headers.as_ref())
+        .map_err(in_check)?;
+    if let Some(statuses) = &check.accepted_statuses {
+        validate_accepted_statuses(statuses).map_err(in_check)?;
+    }
+    if let Some(version) = &check.http_version {
+        validate_http_version(version).map_err(in_check)?;
+    }
+    if let Some(ip) = &check.connect_to {
+        validate_connect_to(ip).map_err(in_check)?;
+    }
+    if let Some(cron) =
+        validate_cron(check.cron.as_deref(), check.timezone.as_deref()).map_err(in_check)?
+    {
+        plan.check_interval(server_floor, cron.min_gap_seconds(Utc::now()))
+            .map_err(in_check)?;
+    }
+    if check
+        .backoff_max_seconds
+        .is_some_and(|m| m < check.interval_seconds)
+    {
+        return Err(in_check((
+            StatusCode::BAD_REQUEST,
+            "backoff_max_seconds debe ser >= interval_seconds".to_string(),
+        )));
+    }
+    let alert_email = check
+        .alert_email
+        .as_deref()
+        .map(normalize_email)
+        .transpose()
+        .map_err(in_check)?;
+    Ok(DesiredCheck {
+        alert_email,
+        headers,
+        ..check
+    })
+}
+
+/// The settings `apply` doesn't manage must still fit the new interval.
+fn validate_check_update(
+    check: &CheckRow,
+    desired: &DesiredCheck,
+) -> Result<(), (StatusCode, String)> {
+    let interval_seconds = desired.interval_seconds;
+    if check.jitter_seconds.is_some_and(|j| j > interval_seconds) {
+        return Err(bad_request(format!(
+            "check {}: jitter_seconds debe estar entre 0 e interval_seconds",
+            check.name
+        )));
+    }
+    if check
+        .quorum_window_seconds
+        .is_some_and(|w| w < interval_seconds)
+    {
+        return Err(bad_request(format!(
+            "check {}: quorum_window_seconds debe ser >= interval_seconds",
+            check.name
+        )));
+    }
+    Ok(())
+}
+
+fn channel_request(channel: &ChannelRow) -> CreateChannelRequest {
+    CreateChannelRequest {
+        name: channel.name.clone(),
+        kind: channel.kind.clone(),
+        target: channel.target.clone(),
+        template: channel.template.clone(),
+        quiet_start: channel.quiet_start.clone(),
+        quiet_end: channel.quiet_end.clone(),
+        timezone: Some(channel.timezone.clone()),
+        digest_seconds: channel.digest_seconds,
+        max_alerts_per_hour: channel.max_alerts_per_hour,
+        min_severity: Some(channel.min_severity.clone()),
+        secret: None,
+        secret_id: channel.secret_id.clone(),
+    }
+}
+
+async fn set_status_page_checks(
+    conn: &mut sqlx::SqliteConnection,
+    status_page_id: &str,
+    check_ids: &[String],
+) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM status_page_checks WHERE status_page_id = ?")
+        .bind(status_page_id)
+        .execute(&mut *conn)
+        .await?;
+    for check_id in check_ids {
+        sqlx::query("INSERT INTO status_page_checks (status_page_id, check_id) VALUES (?, ?)")
+            .bind(status_page_id)
+            .bind(check_id)
+            .execute(&mut *conn)
+            .await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,65 @@
+//! Atom (RFC 4287) rendering for the incident feeds of status pages.
+
+use chrono::{DateTime, SecondsFormat, Utc};
+
+pub struct Feed<'a> {
+    /// A permanent IRI that identifies the feed.
+    pub id: &'a str,
+    pub title: &'a str,
+    /// The page the feed belongs to.
+    pub link: &'a str,
+    pub updated: DateTime<Utc>,
+    pub entries: Vec<Entry>,
+}
+
+pub struct Entry {
+    pub id: String,
+    pub title: String,
+    pub updated: DateTime<Utc>,
+    pub summary: String,
+    pub categories: Vec<String>,
+}
+
+pub fn render(feed: &Feed) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    xml.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    xml.push_str(&format!("  <id>{}</id>\n", escape(feed.id)));
+    xml.push_str(&format!("  <title>{}</title>\n", escape(feed.title)));
+    xml.push_str(&format!("  <link href=\"{}\"/>\n", escape(feed.link)));
+    xml.push_str(&format!(
+        "  <updated>{}</updated>\n",
+        timestamp(feed.updated)
+    ));
+    xml.push_str("  <author><name>uptime-saas</name></author>\n");
+    for entry in &feed.entries {
+        xml.push_str("  <entry>\n");
+        xml.push_str(&format!("    <id>{}</id>\n", escape(&entry.id)));
+        xml.push_str(&format!("    <title>{}</title>\n", escape(&entry.title)));
+        xml.push_str(&format!(
+            "    <updated>{}</updated>\n",
+            timestamp(entry.updated)
+        ));
+        for category in &entry.categories {
+            xml.push_str(&format!("    <category term=\"{}\"/>\n", escape(category)));
+        }
+        xml.push_str(&format!(
+            "    <summary>{}</summary>\n",
+            escape(&entry.summary)
+        ));
+        xml.push_str("  </entry>\n");
+    }
+    xml.push_str("</feed>\n");
+    xml
+}
+
+fn timestamp(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Also good enough for HTML text and attributes.
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,171 @@
+//! Stripe billing: organizations subscribe through Checkout, and subscription webhooks move
+//! them between plans.
+
+use anyhow::{bail, Context};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::Sha256;
+use std::env;
+
+/// Webhooks signed longer ago than this are rejected as replays.
+const WEBHOOK_TOLERANCE_SECONDS: i64 = 300;
+
+pub struct Stripe {
+    http: reqwest::Client,
+    api_url: String,
+    secret_key: String,
+    webhook_secret: String,
+    /// `(plan, price id)` for every plan sold through Stripe.
+    prices: Vec<(&'static str, String)>,
+}
+
+#[derive(Deserialize)]
+struct Created {
+    id: String,
+    url: Option<String>,
+}
+
+impl Stripe {
+    /// Enabled by `STRIPE_SECRET_KEY` and `STRIPE_WEBHOOK_SECRET`. Each sellable plan needs its
+    /// price in `STRIPE_PRICE_<PLAN>`, e.g. `STRIPE_PRICE_PRO`.
+    pub fn from_env(http: reqwest::Client, plans: &[&'static str]) -> Option<Self> {
+        let secret_key = env::var("STRIPE_SECRET_KEY").ok()?;
+        let webhook_secret = env::var("STRIPE_WEBHOOK_SECRET").ok()?;
+        let prices = plans
+            .iter()
+            .filter_map(|plan| {
+                let price = env::var(format!("STRIPE_PRICE_{}", plan.to_uppercase())).ok()?;
+                Some((*plan, price))
+            })
+            .collect();
+        Some(Stripe {
+            http,
+            api_url: env::var("STRIPE_API_URL")
+                .unwrap_or_else(|_| "https://api.stripe.com".to_string()),
+            secret_key,
+            webhook_secret,
+            prices,
+        })
+    }
+
+    pub fn price_for(&self, plan: &str) -> Option<&str> {
+        self.prices
+            .iter()
+            .find(|(name, _)| *name == plan)
+            .map(|(_, price)| price.as_str())
+    }
+
+    pub fn plan_for(&self, price: &str) -> Option<&'static str> {
+        self.prices
+            .iter()
+            .find(|(_, id)| id == price)
+            .map(|(plan, _)| *plan)
+    }
+
+    async fn post(&self, path: &str, form: &[(&str, &str)]) -> anyhow::Result<Created> {
+        let resp = self
+            .http
+            .post(format!("{}{path}", self.api_url.trim_end_matches('/')))
+            .bearer_auth(&self.secret_key)
+            .form(form)
+            .send()
+            .await?;
+        let status = resp.status();
+        if !status.is_success() {
+            bail!("Stripe returned {status}: {}", resp.text().await?);
+        }
+        resp.json().await.context("invalid Stripe response")
+    }
+
+    pub async fn create_customer(&self, org_id: &str, name: &str) -> anyhow::Result<String> {
+        let customer = self
+            .post(
+                "/v1/customers",
+                &[("name", name), ("metadata[org_id]", org_id)],
+            )
+            .await?;
+        Ok(customer.id)
+    }
+
+    /// The subscription carries the organization in its metadata, so later subscription
+    /// webhooks can be mapped back to it.
+    pub async fn create_checkout_session(
+        &self,
+        customer: &str,
+        org_id: &str,
+        plan: &str,
+        success_url: &str,
+        cancel_url: &str,
+    ) -> anyhow::Result<String> {
+        let price = self
+            .price_for(plan)
+            .context("plan without a Stripe price")?;
+        let session = self
+            .post(
+                "/v1/checkout/sessions",
+                &[
+                    ("mode", "subscription"),
+                    ("customer", customer),
+                    ("client_reference_id", org_id),
+                    ("line_items[0][price]", price),
+                    ("line_items[0][quantity]", "1"),
+                    ("metadata[plan]", plan),
+                    ("subscription_data[metadata][org_id]", org_id),
+                    ("success_url", success_url),
+                    ("cancel_url", cancel_url),
+                ],
+            )
+            .await?;
+        session.url.context("Stripe returned no checkout url")
+    }
+
+    /// Checks the `Stripe-Signature` header (`t=<unix time>,v1=<hex hmac>`) and parses the
+    /// event.
+    pub fn verify_webhook(
+        &self,
+        payload: &[u8],
+        signature: &str,
+        now: i64,
+    ) -> anyhow::Result<Value> {
+        let mut timestamp = None;
+        let mut signatures = Vec::new();
+        for part in signature.split(',') {
+            match part.split_once('=') {
+                Some(("t", value)) => timestamp = value.parse::<i64>().ok(),
+                Some(("v1", value)) => signatures.push(value),
+                _ => {}
+            }
+        }
+        let timestamp = timestamp.context("missing timestamp")?;
+        if (now - timestamp).abs() > WEBHOOK_TOLERANCE_SECONDS {
+            bail!("timestamp outside the tolerance");
+        }
+
+        let valid = signatures.iter().any(|candidate| {
+            let Ok(expected) = hex_decode(candidate) else {
+                return false;
+            };
+            let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(self.webhook_secret.as_bytes())
+                .expect("HMAC accepts any key length");
+            mac.update(timestamp.to_string().as_bytes());
+            mac.update(b".");
+            mac.update(payload);
+            mac.verify_slice(&expected).is_ok()
+        });
+        if !valid {
+            bail!("signature mismatch");
+        }
+        serde_json::from_slice(payload).context("invalid event payload")
+    }
+}
+
+fn hex_decode(value: &str) -> anyhow::Result<Vec<u8>> {
+    if !value.len().is_multiple_of(2) || !value.is_ascii() {
+        bail!("invalid hex");
+    }
+    (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&value[i..i + 2], 16).context("invalid hex"))
+        .collect()
+}
@@ -0,0 +1,399 @@
+//! iCalendar (RFC 5545) support for maintenance windows: the `DAILY`, `WEEKLY`, `MONTHLY`
+//! and `YEARLY` recurrence rules, reading `VEVENT`s out of imported calendars and rendering
+//! the feed calendar apps subscribe to.
+
+use chrono::{DateTime, Datelike, Days, Months, NaiveDate, NaiveDateTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+use std::fmt;
+use std::str::FromStr;
+
+const TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// An `RRULE` with `FREQ`, `INTERVAL`, `BYDAY` (weekly rules only), `COUNT` and `UNTIL`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recurrence {
+    pub frequency: Frequency,
+    pub interval: u32,
+    pub by_day: Vec<Weekday>,
+    pub count: Option<u32>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl FromStr for Recurrence {
+    type Err = String;
+
+    fn from_str(rule: &str) -> Result<Self, String> {
+        let mut frequency = None;
+        let mut recurrence = Recurrence {
+            frequency: Frequency::Daily,
+            interval: 1,
+            by_day: Vec::new(),
+            count: None,
+            until: None,
+        };
+        let rule = rule.trim();
+        let rule = rule.strip_prefix("RRULE:").unwrap_or(rule);
+        for part in rule.split(';').filter(|p| !p.is_empty()) {
+            let (name, value) = part
+                .split_once('=')
+                .ok_or_else(|| format!("{part} no es NOMBRE=valor"))?;
+            match name.to_ascii_uppercase().as_str() {
+                "FREQ" => {
+                    frequency = Some(match value.to_ascii_uppercase().as_str() {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        "YEARLY" => Frequency::Yearly,
+                        _ => return Err(format!("FREQ={value} no soportada")),
+                    })
+                }
+                "INTERVAL" => {
+                    recurrence.interval = value
+                        .parse()
+                        .ok()
+                        .filter(|n| *n > 0)
+                        .ok_or_else(|| format!("INTERVAL={value} inválido"))?
+                }
+                "BYDAY" => {
+                    recurrence.by_day = value
+                        .split(',')
+                        .map(parse_weekday)
+                        .collect::<Option<_>>()
+                        .ok_or_else(|| format!("BYDAY={value} inválido"))?
+                }
+                "COUNT" => {
+                    recurrence.count = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("COUNT={value} inválido"))?,
+                    )
+                }
+                "UNTIL" => {
+                    recurrence.until = Some(
+                        parse_date_time(value, &[], Tz::UTC)
+                            .ok_or_else(|| format!("UNTIL={value} inválido"))?,
+                    )
+                }
+                "WKST" => {}
+                _ => return Err(format!("{name} no soportado")),
+            }
+        }
+        recurrence.frequency = frequency.ok_or("falta FREQ")?;
+        if !recurrence.by_day.is_empty() && recurrence.frequency != Frequency::Weekly {
+            return Err("BYDAY solo se admite con FREQ=WEEKLY".to_string());
+        }
+        Ok(recurrence)
+    }
+}
+
+impl fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let frequency = match self.frequency {
+            Frequency::Daily => "DAILY",
+            Frequency::Weekly => "WEEKLY",
+            Frequency::Monthly => "MONTHLY",
+            Frequency::Yearly => "YEARLY",
+        };
+        write!(f, "FREQ={frequency}")?;
+        if self.interval != 1 {
+            write!(f, ";INTERVAL={}", self.interval)?;
+        }
+        if !self.by_day.is_empty() {
+            let days: Vec<_> = self.by_day.iter().map(|d| weekday_code(*d)).collect();
+            write!(f, ";BYDAY={}", days.join(","))?;
+        }
+        if let Some(count) = self.count {
+            write!(f, ";COUNT={count}")?;
+        }
+        if let Some(until) = self.until {
+            write!(f, ";UNTIL={}", until.format(TIMESTAMP_FORMAT))?;
+        }
+        Ok(())
+    }
+}
+
+impl Recurrence {
+    /// Start times of the occurrences, `start` first. Dates a month or year doesn't have
+    /// (the 31st, 29 February) are skipped rather than moved.
+    pub fn occurrences(&self, start: DateTime<Utc>) -> impl Iterator<Item = DateTime<Utc>> + '_ {
+        let time = start.time();
+        let date = start.date_naive();
+        let monday = date - Days::new(u64::from(date.weekday().num_days_from_monday()));
+        let by_day = if self.by_day.is_empty() {
+            vec![date.weekday()]
+        } else {
+            let mut days = self.by_day.clone();
+            days.sort_by_key(|d| d.num_days_from_monday());
+            days
+        };
+        let interval = u64::from(self.interval);
+        (0u64..)
+            .map_while(move |n| {
+                let step = n.checked_mul(interval)?;
+                let dates = match self.frequency {
+                    Frequency::Daily => vec![date.checked_add_days(Days::new(step))?],
+                    Frequency::Weekly => by_day
+                        .iter()
+                        .filter_map(|d| {
+                            monday.checked_add_days(Days::new(
+                                step.checked_mul(7)? + u64::from(d.num_days_from_monday()),
+                            ))
+                        })
+                        .collect(),
+                    Frequency::Monthly | Frequency::Yearly => {
+                        let months = if self.frequency == Frequency::Yearly {
+                            step.checked_mul(12)?
+                        } else {
+                            step
+                        };
+                        let first = date
+                            .with_day(1)?
+                            .checked_add_months(Months::new(u32::try_from(months).ok()?))?;
+                        first.with_day(date.day()).into_iter().collect()
+                    }
+                };
+                Some(dates)
+            })
+            .flatten()
+            .map(move |d| d.and_time(time).and_utc())
+            .filter(move |at| *at >= start)
+            .take_while(|at| self.until.is_none_or(|until| *at <= until))
+            .take(self.count.map_or(usize::MAX, |c| c as usize))
+    }
+}
+
+fn parse_weekday(code: &str) -> Option<Weekday> {
+    Some(match code.trim().to_ascii_uppercase().as_str() {
+        "MO" => Weekday::Mon,
+        "TU" => Weekday::Tue,
+        "WE" => Weekday::Wed,
+        "TH" => Weekday::Thu,
+        "FR" => Weekday::Fri,
+        "SA" => Weekday::Sat,
+        "SU" => Weekday::Sun,
+        _ => return None,
+    })
+}
+
+fn weekday_code(day: Weekday) -> &'static str {
+    match day {
+        Weekday::Mon => "MO",
+        Weekday::Tue => "TU",
+        Weekday::Wed => "WE",
+        Weekday::Thu => "TH",
+        Weekday::Fri => "FR",
+        Weekday::Sat => "SA",
+        Weekday::Sun => "SU",
+    }
+}
+
+/// A `DTSTART`/`DTEND`/`UNTIL` value: UTC (`...Z`), local to its `TZID` parameter, or
+/// floating, which like plain dates is read in `floating`.
+fn parse_date_time(value: &str, params: &[(&str, &str)], floating: Tz) -> Option<DateTime<Utc>> {
+    let tz = match params
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("TZID"))
+    {
+        Some((_, tzid)) => tzid.parse().ok()?,
+        None => floating,
+    };
+    if let Some(utc) = value.strip_suffix('Z') {
+        return NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S")
+            .ok()
+            .map(|at| at.and_utc());
+    }
+    let local = if value.len() == 8 {
+        NaiveDate::parse_from_str(value, "%Y%m%d")
+            .ok()?
+            .and_hms_opt(0, 0, 0)?
+    } else {
+        NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()?
+    };
+    tz.from_local_datetime(&local)
+        .earliest()
+        .map(|at| at.with_timezone(&Utc))
+}
+
+/// Name, parameters and value of a content line.
+type Property = (String, Vec<(String, String)>, String);
+
+/// One `VEVENT` of an imported calendar.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub summary: String,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub rrule: Option<Recurrence>,
+}
+
+/// Reads the events of a calendar. All-day events without a `DTEND` last one day; dates and
+/// times without a zone are taken to be in `floating`.
+pub fn parse_events(ics: &str, floating: Tz) -> Result<Vec<Event>, String> {
+    // Long lines are folded onto continuation lines that start with a space or a tab
+    let mut lines: Vec<String> = Vec::new();
+    for line in ics.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.to_string()),
+        }
+    }
+
+    let mut events = Vec::new();
+    let mut current: Option<Vec<Property>> = None;
+    for line in &lines {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let mut params = key.split(';');
+        let name = params.next().unwrap_or_default().to_ascii_uppercase();
+        let params: Vec<(String, String)> = params
+            .filter_map(|p| p.split_once('='))
+            .map(|(k, v)| (k.to_string(), v.trim_matches('"').to_string()))
+            .collect();
+        match (name.as_str(), value.trim()) {
+            ("BEGIN", "VEVENT") => current = Some(Vec::new()),
+            ("END", "VEVENT") => {
+                if let Some(properties) = current.take() {
+                    events.push(event_from(&properties, floating)?);
+                }
+            }
+            _ => {
+                if let Some(properties) = &mut current {
+                    properties.push((name, params, value.trim().to_string()));
+                }
+            }
+        }
+    }
+    Ok(events)
+}
+
+fn event_from(properties: &[Property], floating: Tz) -> Result<Event, String> {
+    let property = |wanted: &str| properties.iter().find(|(name, ..)| name == wanted);
+    let date_time = |wanted: &str| -> Result<Option<(DateTime<Utc>, bool)>, String> {
+        let Some((_, params, value)) = property(wanted) else {
+            return Ok(None);
+        };
+        let params: Vec<(&str, &str)> = params
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        let at = parse_date_time(value, &params, floating)
+            .ok_or_else(|| format!("{wanted}:{value} inválido"))?;
+        Ok(Some((at, value.len() == 8)))
+    };
+
+    let summary = property("SUMMARY").map_or_else(String::new, |(.., v)| unescape(v));
+    let (starts_at, all_day) = date_time("DTSTART")?.ok_or("evento sin DTSTART")?;
+    let ends_at = match date_time("DTEND")? {
+        Some((ends_at, _)) => ends_at,
+        None if all_day => starts_at + chrono::Duration::days(1),
+        None => return Err(format!("el evento {summary:?} no tiene DTEND")),
+    };
+    if ends_at <= starts_at {
+        return Err(format!("el evento {summary:?} termina antes de empezar"));
+    }
+    let rrule = property("RRULE")
+        .map(|(.., rule)| rule.parse::<Recurrence>())
+        .transpose()
+        .map_err(|e| format!("RRULE del evento {summary:?}: {e}"))?;
+    Ok(Event {
+        summary,
+        starts_at,
+        ends_at,
+        rrule,
+    })
+}
+
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => out.push('\n'),
+            Some(other) => out.push(other),
+            None => {}
+        }
+    }
+    out
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// A `VEVENT` of the feed.
+pub struct FeedEvent<'a> {
+    pub uid: &'a str,
+    pub summary: &'a str,
+    pub description: Option<&'a str>,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub rrule: Option<&'a str>,
+}
+
+pub fn render(name: &str, events: &[FeedEvent]) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//uptime-saas//maintenance//EN".to_string(),
+        format!("X-WR-CALNAME:{}", escape(name)),
+    ];
+    for event in events {
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!("UID:{}", event.uid));
+        lines.push(format!(
+            "DTSTAMP:{}",
+            event.created_at.format(TIMESTAMP_FORMAT)
+        ));
+        lines.push(format!(
+            "DTSTART:{}",
+            event.starts_at.format(TIMESTAMP_FORMAT)
+        ));
+        lines.push(format!("DTEND:{}", event.ends_at.format(TIMESTAMP_FORMAT)));
+        lines.push(format!("SUMMARY:{}", escape(event.summary)));
+        if let Some(description) = event.description {
+            lines.push(format!("DESCRIPTION:{}", escape(description)));
+        }
+        if let Some(rrule) = event.rrule {
+            lines.push(format!("RRULE:{rrule}"));
+        }
+        lines.push("END:VEVENT".to_string());
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    let mut ics = String::new();
+    for line in &lines {
+        fold(line, &mut ics);
+    }
+    ics
+}
+
+/// Lines are at most 75 octets; the rest continues on lines starting with a space.
+fn fold(line: &str, out: &mut String) {
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            width = 1;
+        }
+        out.push(c);
+        width += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
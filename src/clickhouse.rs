@@ -0,0 +1,161 @@
+//! Optional ClickHouse results backend: every probe sample is written there and period
+//! stats are computed from the raw samples. Check metadata stays in SQLite.
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::env;
+
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.3f";
+
+pub struct ClickHouse {
+    http: reqwest::Client,
+    url: String,
+    database: String,
+    user: Option<String>,
+    password: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct Sample<'a> {
+    pub check_id: &'a str,
+    #[serde(serialize_with = "serialize_timestamp")]
+    pub checked_at: DateTime<Utc>,
+    pub status: &'a str,
+    pub http_status: Option<i64>,
+    pub latency_ms: Option<i64>,
+    pub error: Option<&'a str>,
+    pub response_bytes: Option<i64>,
+    pub location: Option<&'a str>,
+    /// Taken during a maintenance window, which the stats leave out like the rollups do.
+    pub planned: bool,
+}
+
+#[derive(Deserialize)]
+pub struct Stats {
+    pub samples: i64,
+    pub up_samples: i64,
+    pub avg_latency_ms: Option<f64>,
+    pub max_latency_ms: Option<i64>,
+}
+
+fn serialize_timestamp<S: serde::Serializer>(at: &DateTime<Utc>, s: S) -> Result<S::Ok, S::Error> {
+    s.collect_str(&at.format(TIMESTAMP_FORMAT))
+}
+
+impl ClickHouse {
+    /// Enabled by `CLICKHOUSE_URL`, e.g. `http://localhost:8123`.
+    pub fn from_env(http: reqwest::Client) -> Option<Self> {
+        let url = env::var("CLICKHOUSE_URL").ok()?;
+        Some(ClickHouse {
+            http,
+            url,
+            database: env::var("CLICKHOUSE_DATABASE").unwrap_or_else(|_| "default".to_string()),
+            user: env::var("CLICKHOUSE_USER").ok(),
+            password: env::var("CLICKHOUSE_PASSWORD").ok(),
+        })
+    }
+
+    async fn execute(
+        &self,
+        query: &str,
+        params: &[(&str, &str)],
+        body: String,
+    ) -> anyhow::Result<String> {
+        let mut request = self
+            .http
+            .post(&self.url)
+            .query(&[
+                ("database", self.database.as_str()),
+                ("query", query),
+                ("output_format_json_quote_64bit_integers", "0"),
+            ])
+            .query(params)
+            .body(body);
+        if let Some(user) = &self.user {
+            request = request.header("X-ClickHouse-User", user);
+        }
+        if let Some(password) = &self.password {
+            request = request.header("X-ClickHouse-Key", password);
+        }
+
+        let resp = request.send().await?;
+        let status = resp.status();
+        let text = resp.text().await?;
+        if !status.is_success() {
+            anyhow::bail!("ClickHouse returned {status}: {}", text.trim());
+        }
+        Ok(text)
+    }
+
+    pub async fn ensure_schema(&self) -> anyhow::Result<()> {
+        self.execute(
+            r#"
+            CREATE TABLE IF NOT EXISTS check_results (
+              check_id String,
+              checked_at DateTime64(3, 'UTC'),
+              status LowCardinality(String),
+              http_status Nullable(Int64),
+              latency_ms Nullable(Int64),
+              error Nullable(String),
+              location Nullable(String),
+              response_bytes Nullable(Int64),
+              planned Bool DEFAULT false
+            ) ENGINE = MergeTree
+            PARTITION BY toYYYYMM(checked_at)
+            ORDER BY (check_id, checked_at)
+            "#,
+            &[],
+            String::new(),
+        )
+        .await
+        .context("creating ClickHouse schema")?;
+        self.execute(
+            "ALTER TABLE check_results ADD COLUMN IF NOT EXISTS response_bytes Nullable(Int64)",
+            &[],
+            String::new(),
+        )
+        .await
+        .context("migrating ClickHouse schema")?;
+        self.execute(
+            "ALTER TABLE check_results ADD COLUMN IF NOT EXISTS planned Bool DEFAULT false",
+            &[],
+            String::new(),
+        )
+        .await
+        .context("migrating ClickHouse schema")?;
+        Ok(())
+    }
+
+    pub async fn insert(&self, samples: &[Sample<'_>]) -> anyhow::Result<()> {
+        if samples.is_empty() {
+            return Ok(());
+        }
+        let mut body = String::new();
+        for sample in samples {
+            body.push_str(&serde_json::to_string(sample)?);
+            body.push('\n');
+        }
+        self.execute("INSERT INTO check_results FORMAT JSONEachRow", &[], body)
+            .await?;
+        Ok(())
+    }
+
+    /// Samples taken during maintenance are left out.
+    pub async fn stats(&self, check_id: &str, since: DateTime<Utc>) -> anyhow::Result<Stats> {
+        let since = since.format(TIMESTAMP_FORMAT).to_string();
+        let params = [
+            ("param_check_id", check_id),
+            ("param_since", since.as_str()),
+        ];
+        let query = r#"
+            SELECT count() AS samples, countIf(status != 'DOWN') AS up_samples,
+                   avgOrNull(latency_ms) AS avg_latency_ms, max(latency_ms) AS max_latency_ms
+            FROM check_results
+            WHERE check_id = {check_id:String} AND checked_at >= {since:DateTime64(3, 'UTC')} AND NOT planned
+            FORMAT JSONEachRow
+            "#;
+        let text = self.execute(query, &params, String::new()).await?;
+        Ok(serde_json::from_str(text.trim())?)
+    }
+}
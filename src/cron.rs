@@ -0,0 +1,185 @@
+//! Cron schedules for checks: the five classic fields (`minute hour day month weekday`) with
+//! lists, ranges, steps and month/weekday names, plus `@hourly`, `@daily`, `@weekly`,
+//! `@monthly` and `@yearly`, evaluated in the check's timezone.
+
+use chrono::{
+    DateTime, Datelike, Duration, LocalResult, NaiveDate, NaiveDateTime, TimeZone, Timelike, Utc,
+};
+use chrono_tz::Tz;
+
+const MONTHS: &[&str] = &[
+    "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC",
+];
+
+const WEEKDAYS: &[&str] = &["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+
+/// How far ahead [`Cron::next_after`] looks before deciding a schedule never fires.
+const HORIZON_YEARS: i32 = 5;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cron {
+    /// Bit `n` set when the field allows `n`.
+    minutes: u64,
+    hours: u32,
+    days: u32,
+    months: u16,
+    /// Sunday is 0.
+    weekdays: u8,
+    /// As in Vixie cron, a day that matches either field runs when both are restricted.
+    days_restricted: bool,
+    weekdays_restricted: bool,
+    pub timezone: Tz,
+}
+
+impl Cron {
+    pub fn parse(expression: &str, timezone: Tz) -> Result<Self, String> {
+        let expression = match expression.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            other => other,
+        };
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(
+                "cron debe tener 5 campos: minuto hora día mes día-de-la-semana".to_string(),
+            );
+        };
+        let weekdays = parse_field("día de la semana", weekday, 0, 7, WEEKDAYS)?;
+        Ok(Cron {
+            minutes: parse_field("minuto", minute, 0, 59, &[])?,
+            hours: parse_field("hora", hour, 0, 23, &[])? as u32,
+            days: parse_field("día", day, 1, 31, &[])? as u32,
+            months: parse_field("mes", month, 1, 12, MONTHS)? as u16,
+            // 7 is Sunday too
+            weekdays: ((weekdays | weekdays >> 7) & 0x7f) as u8,
+            days_restricted: !day.starts_with('*'),
+            weekdays_restricted: !weekday.starts_with('*'),
+            timezone,
+        })
+    }
+
+    fn runs_on(&self, date: NaiveDate) -> bool {
+        let day = self.days & (1 << date.day()) != 0;
+        let weekday = self.weekdays & (1 << date.weekday().num_days_from_sunday()) != 0;
+        if self.days_restricted && self.weekdays_restricted {
+            day || weekday
+        } else {
+            day && weekday
+        }
+    }
+
+    /// The first run strictly after `after`. Local times skipped by a DST change don't run,
+    /// repeated ones run once. `None` when nothing matches within a few years, like
+    /// `0 0 30 2 *`.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let local = after.with_timezone(&self.timezone).naive_local();
+        let mut at = local.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let last_year = local.year() + HORIZON_YEARS;
+        while at.year() <= last_year {
+            let date = at.date();
+            if self.months & (1 << date.month()) == 0 {
+                let (year, month) = match date.month() {
+                    12 => (date.year() + 1, 1),
+                    month => (date.year(), month + 1),
+                };
+                at = NaiveDate::from_ymd_opt(year, month, 1)?.and_hms_opt(0, 0, 0)?;
+                continue;
+            }
+            if !self.runs_on(date) {
+                at = date.succ_opt()?.and_hms_opt(0, 0, 0)?;
+                continue;
+            }
+            if self.hours & (1 << at.hour()) == 0 {
+                at = date.and_hms_opt(at.hour(), 0, 0)? + Duration::hours(1);
+                continue;
+            }
+            if self.minutes & (1 << at.minute()) != 0 {
+                if let Some(run) = self.to_utc(at).filter(|run| *run > after) {
+                    return Some(run);
+                }
+            }
+            at += Duration::minutes(1);
+        }
+        None
+    }
+
+    fn to_utc(&self, at: NaiveDateTime) -> Option<DateTime<Utc>> {
+        match self.timezone.from_local_datetime(&at) {
+            LocalResult::Single(run) | LocalResult::Ambiguous(run, _) => {
+                Some(run.with_timezone(&Utc))
+            }
+            LocalResult::None => None,
+        }
+    }
+
+    /// The shortest wait between runs over the next thousand of them from `from`, what plan
+    /// limits on the interval are checked against.
+    pub fn min_gap_seconds(&self, from: DateTime<Utc>) -> i64 {
+        let mut previous = self.next_after(from);
+        let mut gap = i64::MAX;
+        for _ in 0..1000 {
+            let Some(run) = previous else { break };
+            let Some(next) = self.next_after(run) else {
+                break;
+            };
+            gap = gap.min((next - run).num_seconds());
+            previous = Some(next);
+        }
+        gap
+    }
+}
+
+/// A bit per allowed value. `names` are accepted in place of numbers, the first one being
+/// `min`.
+fn parse_field(
+    label: &str,
+    field: &str,
+    min: u32,
+    max: u32,
+    names: &[&str],
+) -> Result<u64, String> {
+    let invalid = || format!("cron: {label} inválido: {field}");
+    let value = |text: &str| -> Result<u32, String> {
+        let upper = text.to_ascii_uppercase();
+        let n = match names.iter().position(|name| *name == upper) {
+            Some(index) => index as u32 + min,
+            None => text.parse().map_err(|_| invalid())?,
+        };
+        if n < min || n > max {
+            return Err(invalid());
+        }
+        Ok(n)
+    };
+    let mut bits = 0u64;
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step.parse().ok().filter(|s| *s > 0).ok_or_else(invalid)?;
+                (range, Some(step))
+            }
+            None => (item, None),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (value(start)?, value(end)?),
+                // `5/15` runs from 5 to the end of the range
+                None if step.is_some() => (value(range)?, max),
+                None => {
+                    let n = value(range)?;
+                    (n, n)
+                }
+            },
+        };
+        if start > end {
+            return Err(invalid());
+        }
+        for n in (start..=end).step_by(step.unwrap_or(1) as usize) {
+            bits |= 1 << n;
+        }
+    }
+    Ok(bits)
+}
@@ -0,0 +1,1328 @@
+//! Row types, plans, roles and the pure rules the rest of the crate builds on.
+
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use axum::http::{HeaderName, HeaderValue, StatusCode};
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use chrono_tz::Tz;
+use reqwest::Url;
+use scraper::{Html as HtmlDocument, Selector};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::types::Json;
+use sqlx::SqliteConnection;
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use tracing::error;
+
+use crate::calendar::Recurrence;
+use crate::cron::Cron;
+
+pub(crate) const CHECK_TYPE_HTTP: &str = "http";
+
+/// Sent by probes unless the organization or the check sets another one.
+pub(crate) const DEFAULT_USER_AGENT: &str = concat!("uptime-saas/", env!("CARGO_PKG_VERSION"));
+
+pub(crate) const CHECK_TYPE_CONTENT_CHANGE: &str = "content_change";
+
+pub(crate) const ROLES: &[&str] = &["viewer", "editor", "admin"];
+
+/// Organization that owns everything created before organizations existed, and that open
+/// mode (no API keys yet) works on unless `X-Org-Id` says otherwise.
+pub(crate) const DEFAULT_ORG: &str = "default";
+
+pub(crate) const INVITATION_DAYS: i64 = 7;
+
+/// Server-wide floor of `interval_seconds` unless `MIN_INTERVAL_SECONDS` sets another one.
+pub(crate) const DEFAULT_MIN_INTERVAL_SECONDS: i64 = 10;
+
+/// Plan of the default organization: self-hosted installs keep working without limits.
+pub(crate) const SELF_HOSTED_PLAN: &str = "unlimited";
+
+/// Limits of a billing plan; `None` is unlimited.
+#[derive(Debug, Serialize)]
+pub(crate) struct Plan {
+    pub(crate) name: &'static str,
+    pub(crate) max_checks: Option<i64>,
+    pub(crate) min_interval_seconds: i64,
+    pub(crate) retention_days: Option<i64>,
+    /// Not enforced until an SMS channel exists.
+    pub(crate) sms_per_month: Option<i64>,
+}
+
+/// Smallest first, which is also the order upgrade hints are picked in.
+pub(crate) const PLANS: &[Plan] = &[
+    Plan {
+        name: "free",
+        max_checks: Some(5),
+        min_interval_seconds: 300,
+        retention_days: Some(7),
+        sms_per_month: Some(0),
+    },
+    Plan {
+        name: "pro",
+        max_checks: Some(50),
+        min_interval_seconds: 60,
+        retention_days: Some(90),
+        sms_per_month: Some(100),
+    },
+    Plan {
+        name: "business",
+        max_checks: Some(500),
+        min_interval_seconds: 10,
+        retention_days: Some(365),
+        sms_per_month: Some(1000),
+    },
+    Plan {
+        name: SELF_HOSTED_PLAN,
+        max_checks: None,
+        min_interval_seconds: 10,
+        retention_days: None,
+        sms_per_month: None,
+    },
+];
+
+impl Plan {
+    /// Unknown names fall back to the smallest plan.
+    pub(crate) fn named(name: &str) -> &'static Plan {
+        PLANS
+            .iter()
+            .find(|plan| plan.name == name)
+            .unwrap_or(&PLANS[0])
+    }
+
+    /// `402 Payment Required` naming the smallest plan that `allows` the request.
+    pub(crate) fn limit_error(
+        &self,
+        message: String,
+        allows: impl Fn(&Plan) -> bool,
+    ) -> (StatusCode, String) {
+        let hint = PLANS
+            .iter()
+            .filter(|plan| plan.name != SELF_HOSTED_PLAN)
+            .find(|plan| allows(plan))
+            .map_or(String::new(), |plan| {
+                format!("; mejora al plan {} para permitirlo", plan.name)
+            });
+        (
+            StatusCode::PAYMENT_REQUIRED,
+            format!("{message} en el plan {}{hint}", self.name),
+        )
+    }
+
+    /// Shortest interval its checks may have on a server that allows `server_floor`.
+    pub(crate) fn interval_floor(&self, server_floor: i64) -> i64 {
+        self.min_interval_seconds.max(server_floor)
+    }
+
+    /// The server's floor is a hard limit, the plan's one can be lifted by upgrading.
+    pub(crate) fn check_interval(
+        &self,
+        server_floor: i64,
+        interval_seconds: i64,
+    ) -> Result<(), (StatusCode, String)> {
+        if interval_seconds < server_floor {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("interval_seconds mínimo: {server_floor}"),
+            ));
+        }
+        if interval_seconds < self.min_interval_seconds {
+            return Err(self.limit_error(
+                format!("interval_seconds mínimo: {}", self.min_interval_seconds),
+                |plan| interval_seconds >= plan.min_interval_seconds,
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// An `oncall` channel targets an on-call schedule and pages whoever is on call.
+pub(crate) const CHANNEL_KINDS: &[&str] = &["telegram", "slack", "webhook", "email", "oncall"];
+
+/// Least to most severe. Checks are `critical` unless set otherwise.
+pub(crate) const SEVERITIES: &[&str] = &["info", "warning", "critical"];
+
+pub(crate) const INCIDENT_UPDATE_STATUSES: &[&str] =
+    &["investigating", "identified", "monitoring", "resolved"];
+
+/// Used when neither the check nor the channel defines an alert template.
+pub(crate) const DEFAULT_ALERT_TEMPLATE: &str = r#"
+{%- if event == "content_change" -%}
+📝 Content Change
+{{ check.name }}
+{{ check.url }}
+{%- elif event == "latency_anomaly" -%}
+🐢 Latency Anomaly ({{ severity }})
+{{ check.name }}
+{{ latency_ms }} ms, usually {{ baseline_ms }} ms
+{{ check.url }}
+{%- elif event == "latency_normal" -%}
+⚡ Latency Back to Normal
+{{ check.name }}
+{{ latency_ms }} ms, usually {{ baseline_ms }} ms
+{{ check.url }}
+{%- elif event == "group_degraded" -%}
+🚨 Group Degraded ({{ severity }})
+{{ group }}
+{{ down_checks }} of {{ total_checks }} checks DOWN
+{%- elif event == "group_recovered" -%}
+✅ Group Recovered
+{{ group }}
+{{ down_checks }} of {{ total_checks }} checks DOWN
+{%- elif event == "recovery" -%}
+✅ Recovered
+{{ check.name }}
+{{ previous }} → {{ status }}
+Down for {{ downtime }} ({{ failed_probes }} failed probes)
+{%- if last_error %}
+Last error: {{ last_error }}
+{%- endif %}
+{%- if worst_latency_ms %}
+Worst latency: {{ worst_latency_ms }} ms
+{%- endif %}
+{{ check.url }}
+{%- else -%}
+🚨 Uptime Alert ({{ severity }})
+{{ check.name }}
+{{ previous }} → {{ status }}
+{%- if error_kind %}
+Cause: {{ error_kind }}
+{%- endif %}
+{{ check.url }}
+{%- endif -%}
+"#;
+
+pub(crate) const PERSIST_ALL: &str = "all";
+
+pub(crate) const PERSIST_CHANGES: &str = "changes";
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub(crate) struct SecretRow {
+    pub(crate) id: String,
+    pub(crate) name: String,
+    pub(crate) created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub(crate) struct CheckRow {
+    pub(crate) id: String,
+    pub(crate) name: String,
+    pub(crate) url: String,
+    pub(crate) interval_seconds: i64,
+    pub(crate) alert_email: Option<String>,
+    pub(crate) is_active: i64,
+    pub(crate) last_status: Option<String>,
+    pub(crate) last_checked_at: Option<DateTime<Utc>>,
+    pub(crate) check_type: String,
+    pub(crate) content_selector: Option<String>,
+    pub(crate) content_hash: Option<String>,
+    pub(crate) dns_resolver: Option<String>,
+    pub(crate) ip_version: Option<i64>,
+    pub(crate) proxy_url: Option<String>,
+    pub(crate) client_cert_pem: Option<String>,
+    pub(crate) client_key_secret_id: Option<String>,
+    pub(crate) auth_header_secret_id: Option<String>,
+    pub(crate) regions: Option<String>,
+    pub(crate) quorum: Option<i64>,
+    pub(crate) quorum_window_seconds: Option<i64>,
+    pub(crate) leased_by: Option<String>,
+    pub(crate) leased_until: Option<DateTime<Utc>>,
+    pub(crate) jitter_seconds: Option<i64>,
+    pub(crate) next_run_at: Option<DateTime<Utc>>,
+    pub(crate) persist_mode: String,
+    pub(crate) persist_every: Option<i64>,
+    pub(crate) samples_since_persist: i64,
+    pub(crate) last_probe_status: Option<String>,
+    pub(crate) alert_template: Option<String>,
+    pub(crate) org_id: String,
+    /// Bumped by every configuration change; served as the check's ETag.
+    pub(crate) version: i64,
+    pub(crate) updated_at: Option<DateTime<Utc>>,
+    pub(crate) severity: String,
+    /// Successful responses with a smaller body are DOWN.
+    pub(crate) min_response_bytes: Option<i64>,
+    /// Override the organization's probe defaults.
+    pub(crate) user_agent: Option<String>,
+    pub(crate) headers: Option<Json<BTreeMap<String, String>>>,
+    /// Caps the backoff of a check that stays DOWN; without it, it's probed at its interval.
+    pub(crate) backoff_max_seconds: Option<i64>,
+    /// DOWN probes in a row.
+    pub(crate) down_probes: i64,
+    /// Comma-separated statuses that count as UP besides 2xx, e.g. `401,403` for an
+    /// endpoint that rejects the unauthenticated probe.
+    pub(crate) accepted_statuses: Option<String>,
+    /// `1.1` or `2`: the protocol the target must serve the probe over.
+    pub(crate) http_version: Option<String>,
+    /// IP the probe connects to instead of resolving the URL's host, which is still sent as
+    /// Host and SNI; e.g. to watch a new origin before DNS points at it.
+    pub(crate) connect_to: Option<String>,
+    /// Runs the check at these times instead of every `interval_seconds`, see [`Cron`].
+    pub(crate) cron: Option<String>,
+    /// IANA zone `cron` is evaluated in; UTC by default.
+    pub(crate) timezone: Option<String>,
+}
+
+impl CheckRow {
+    pub(crate) fn cron(&self) -> Option<Cron> {
+        let timezone = self.timezone.as_deref().and_then(|tz| tz.parse().ok());
+        Cron::parse(self.cron.as_deref()?, timezone.unwrap_or(Tz::UTC)).ok()
+    }
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub(crate) struct ResultRow {
+    pub(crate) id: i64,
+    pub(crate) check_id: String,
+    pub(crate) checked_at: DateTime<Utc>,
+    pub(crate) status: String,
+    pub(crate) http_status: Option<i64>,
+    pub(crate) latency_ms: Option<i64>,
+    pub(crate) error: Option<String>,
+    pub(crate) content_hash: Option<String>,
+    pub(crate) location: Option<String>,
+    pub(crate) response_bytes: Option<i64>,
+    /// Negotiated with the target, e.g. `HTTP/2`.
+    pub(crate) protocol: Option<String>,
+    /// One of [`ERROR_KINDS`] when the probe failed.
+    pub(crate) error_kind: Option<String>,
+    /// Reported by another monitor through `/ingest/external`, with its source as `location`.
+    /// It is history only: quorum, rollups and stats leave it out.
+    pub(crate) external: bool,
+    /// Filled in by the results API, see [`AnnotationRow::covers`].
+    #[sqlx(skip)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) annotations: Vec<AnnotationRow>,
+}
+
+/// An operator's note on a check's history, like a deploy or a provider outage. It refers
+/// to one result, one incident, or just a moment or span of time.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub(crate) struct AnnotationRow {
+    pub(crate) id: String,
+    pub(crate) check_id: String,
+    pub(crate) result_id: Option<i64>,
+    pub(crate) incident_id: Option<String>,
+    pub(crate) text: String,
+    pub(crate) author: String,
+    pub(crate) at: DateTime<Utc>,
+    pub(crate) ends_at: Option<DateTime<Utc>>,
+    /// Also shown on the status pages of the check.
+    pub(crate) public: bool,
+    pub(crate) created_at: DateTime<Utc>,
+}
+
+impl AnnotationRow {
+    /// Annotations of a result, and those spanning its time that aren't tied to another one.
+    pub(crate) fn covers(&self, result: &ResultRow) -> bool {
+        match self.result_id {
+            Some(id) => id == result.id,
+            None => {
+                self.at <= result.checked_at && result.checked_at <= self.ends_at.unwrap_or(self.at)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub(crate) struct ChannelRow {
+    pub(crate) id: String,
+    pub(crate) name: String,
+    pub(crate) kind: String,
+    pub(crate) target: String,
+    pub(crate) template: Option<String>,
+    pub(crate) created_at: DateTime<Utc>,
+    pub(crate) quiet_start: Option<String>,
+    pub(crate) quiet_end: Option<String>,
+    pub(crate) timezone: String,
+    pub(crate) digest_seconds: Option<i64>,
+    pub(crate) max_alerts_per_hour: Option<i64>,
+    pub(crate) org_id: String,
+    /// Alerts of checks less severe than this are not sent to the channel.
+    pub(crate) min_severity: String,
+    /// The bot token of a `telegram` channel, or the `Authorization` header of a `webhook`.
+    pub(crate) secret_id: Option<String>,
+}
+
+impl ChannelRow {
+    pub(crate) fn accepts(&self, severity: &str) -> bool {
+        severity_rank(severity) >= severity_rank(&self.min_severity)
+    }
+}
+
+/// A message in the delivery outbox: `pending` until it goes out (`sent`) or runs out of
+/// attempts (`dead`). `channel_id` is unset for the instance's Telegram chat.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub(crate) struct NotificationRow {
+    pub(crate) id: String,
+    pub(crate) org_id: String,
+    pub(crate) channel_id: Option<String>,
+    pub(crate) text: String,
+    pub(crate) body: Json<serde_json::Value>,
+    pub(crate) status: String,
+    pub(crate) attempts: i64,
+    pub(crate) next_attempt_at: DateTime<Utc>,
+    pub(crate) last_error: Option<String>,
+    pub(crate) created_at: DateTime<Utc>,
+    pub(crate) sent_at: Option<DateTime<Utc>>,
+    /// The check the alert is about; digests cover several and have none.
+    pub(crate) check_id: Option<String>,
+    /// A check's `alert_email`, for notifications that aren't sent to a channel.
+    pub(crate) email: Option<String>,
+    /// The alert's line in the digest it is `held` for.
+    pub(crate) summary: Option<String>,
+}
+
+/// Planned downtime of one check, or of every check of the organization when `check_id` is
+/// unset. `rrule` repeats it, each occurrence lasting as long as the first.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub(crate) struct MaintenanceWindowRow {
+    pub(crate) id: String,
+    pub(crate) org_id: String,
+    pub(crate) check_id: Option<String>,
+    pub(crate) title: String,
+    pub(crate) starts_at: DateTime<Utc>,
+    pub(crate) ends_at: DateTime<Utc>,
+    pub(crate) rrule: Option<String>,
+    pub(crate) created_at: DateTime<Utc>,
+}
+
+impl MaintenanceWindowRow {
+    /// Occurrences overlapping `[from, to)`, as `(start, end)`.
+    pub(crate) fn periods(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Vec<(DateTime<Utc>, DateTime<Utc>)> {
+        let duration = self.ends_at - self.starts_at;
+        let rule = self
+            .rrule
+            .as_deref()
+            .and_then(|r| r.parse::<Recurrence>().ok());
+        let starts: Box<dyn Iterator<Item = DateTime<Utc>>> = match &rule {
+            Some(rule) => Box::new(rule.occurrences(self.starts_at)),
+            None => Box::new(std::iter::once(self.starts_at)),
+        };
+        starts
+            .take_while(|start| *start < to)
+            .filter(|start| *start + duration > from)
+            .map(|start| (start, start + duration))
+            .collect()
+    }
+
+    pub(crate) fn covers(&self, at: DateTime<Utc>) -> bool {
+        !self
+            .periods(at, at + chrono::Duration::nanoseconds(1))
+            .is_empty()
+    }
+}
+
+/// A public page about some of the organization's checks, reachable by `slug` without an
+/// API key.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub(crate) struct StatusPageRow {
+    pub(crate) id: String,
+    pub(crate) org_id: String,
+    pub(crate) slug: String,
+    pub(crate) title: String,
+    pub(crate) created_at: DateTime<Utc>,
+    #[sqlx(skip)]
+    pub(crate) check_ids: Vec<String>,
+}
+
+/// Checks watched together, e.g. the nodes of a cluster. With `alert_threshold_percent` its
+/// members stop alerting one by one: the group alerts once more than that percentage of them
+/// is DOWN, and again when it recovers.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub(crate) struct CheckGroupRow {
+    pub(crate) id: String,
+    pub(crate) org_id: String,
+    pub(crate) name: String,
+    pub(crate) alert_threshold_percent: Option<i64>,
+    /// Whether the last group alert was sent for it going over the threshold.
+    pub(crate) degraded: bool,
+    pub(crate) created_at: DateTime<Utc>,
+    #[sqlx(skip)]
+    pub(crate) check_ids: Vec<String>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub(crate) struct GroupMember {
+    pub(crate) id: String,
+    pub(crate) name: String,
+    pub(crate) status: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct GroupStatus {
+    pub(crate) id: String,
+    pub(crate) name: String,
+    pub(crate) status: Option<String>,
+    pub(crate) down_checks: i64,
+    pub(crate) total_checks: i64,
+    pub(crate) degraded: bool,
+    pub(crate) period: String,
+    /// Of all the members' samples together.
+    pub(crate) uptime_percent: Option<f64>,
+    pub(crate) checks: Vec<GroupMember>,
+}
+
+/// DOWN when any member is, UP when every member probed so far is.
+pub(crate) fn worst_status(members: &[GroupMember]) -> Option<&'static str> {
+    if members.iter().any(|m| m.status.as_deref() == Some("DOWN")) {
+        Some("DOWN")
+    } else if members.iter().any(|m| m.status.is_some()) {
+        Some("UP")
+    } else {
+        None
+    }
+}
+
+pub(crate) fn group_degraded(down_checks: i64, total_checks: i64, threshold_percent: i64) -> bool {
+    total_checks > 0 && down_checks * 100 > threshold_percent * total_checks
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub(crate) struct IncidentRow {
+    pub(crate) id: String,
+    pub(crate) check_id: String,
+    pub(crate) started_at: DateTime<Utc>,
+    pub(crate) resolved_at: Option<DateTime<Utc>>,
+    pub(crate) acknowledged_at: Option<DateTime<Utc>>,
+    pub(crate) failed_probes: i64,
+    pub(crate) last_error: Option<String>,
+    pub(crate) max_latency_ms: Option<i64>,
+    pub(crate) caused_by_check_id: Option<String>,
+    /// The check's severity when the incident opened.
+    pub(crate) severity: String,
+}
+
+/// Written by an operator for the status page; it doesn't open or resolve the incident,
+/// which follows the probes.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub(crate) struct IncidentUpdateRow {
+    pub(crate) id: String,
+    pub(crate) incident_id: String,
+    pub(crate) status: String,
+    pub(crate) message: String,
+    pub(crate) author: String,
+    pub(crate) created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct CheckStats {
+    pub(crate) period: String,
+    /// The figures come from hourly rollups, so they start at the first whole hour of
+    /// `period`.
+    pub(crate) since: DateTime<Utc>,
+    pub(crate) samples: i64,
+    pub(crate) up_samples: i64,
+    pub(crate) uptime_percent: Option<f64>,
+    pub(crate) avg_latency_ms: Option<f64>,
+    pub(crate) max_latency_ms: Option<i64>,
+    pub(crate) avg_response_bytes: Option<f64>,
+    /// Samples taken during maintenance windows, which the other figures leave out.
+    pub(crate) planned_samples: i64,
+    /// Failed samples per [`ERROR_KINDS`] entry.
+    pub(crate) errors_by_kind: BTreeMap<String, i64>,
+    pub(crate) timezone: String,
+    pub(crate) daily: Vec<DailyUptime>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct LatencyByRegion {
+    pub(crate) period: String,
+    pub(crate) regions: Vec<RegionLatency>,
+}
+
+/// Figures of one probe location, from the raw results kept for the check.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub(crate) struct RegionLatency {
+    /// `None` for the server's own workers.
+    pub(crate) region: Option<String>,
+    pub(crate) samples: i64,
+    pub(crate) up_samples: i64,
+    pub(crate) uptime_percent: Option<f64>,
+    pub(crate) avg_latency_ms: Option<f64>,
+    pub(crate) max_latency_ms: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct DailyUptime {
+    pub(crate) date: NaiveDate,
+    pub(crate) samples: i64,
+    pub(crate) up_samples: i64,
+    pub(crate) uptime_percent: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub(crate) struct UserRow {
+    pub(crate) id: String,
+    pub(crate) email: String,
+    pub(crate) name: String,
+    pub(crate) role: String,
+    pub(crate) created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub(crate) struct ApiKeyRow {
+    pub(crate) id: String,
+    pub(crate) user_id: String,
+    pub(crate) name: String,
+    pub(crate) created_at: DateTime<Utc>,
+    pub(crate) last_used_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub(crate) struct AuditRow {
+    pub(crate) id: i64,
+    pub(crate) org_id: String,
+    pub(crate) actor_id: Option<String>,
+    pub(crate) actor: String,
+    pub(crate) action: String,
+    pub(crate) entity_type: String,
+    pub(crate) entity_id: String,
+    pub(crate) before: Option<sqlx::types::Json<serde_json::Value>>,
+    pub(crate) after: Option<sqlx::types::Json<serde_json::Value>>,
+    pub(crate) created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub(crate) struct OrgRow {
+    pub(crate) id: String,
+    pub(crate) name: String,
+    pub(crate) created_at: DateTime<Utc>,
+    pub(crate) plan: String,
+    pub(crate) subscription_status: Option<String>,
+}
+
+/// What every HTTP probe of an organization sends unless its check says otherwise, e.g. so
+/// that a WAF can allowlist the monitor.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct ProbeDefaults {
+    pub(crate) user_agent: Option<String>,
+    #[serde(default)]
+    pub(crate) headers: BTreeMap<String, String>,
+}
+
+/// Headers a probe of `check` sends: the organization's defaults with the check's on top.
+pub(crate) fn probe_headers(
+    defaults: &ProbeDefaults,
+    check: &CheckRow,
+) -> BTreeMap<String, String> {
+    let mut headers = defaults.headers.clone();
+    if let Some(own) = &check.headers {
+        headers.extend(own.0.clone());
+    }
+    let user_agent = check
+        .user_agent
+        .as_deref()
+        .or(defaults.user_agent.as_deref())
+        .unwrap_or(DEFAULT_USER_AGENT);
+    headers.insert("user-agent".to_string(), user_agent.to_string());
+    headers
+}
+
+/// Returns the headers with lowercase names. The User-Agent has a field of its own.
+pub(crate) fn validate_probe_headers(
+    user_agent: Option<&str>,
+    headers: Option<&BTreeMap<String, String>>,
+) -> Result<Option<BTreeMap<String, String>>, (StatusCode, String)> {
+    if user_agent.is_some_and(|ua| ua.is_empty() || HeaderValue::from_str(ua).is_err()) {
+        return Err((StatusCode::BAD_REQUEST, "user_agent inválido".to_string()));
+    }
+    let Some(headers) = headers else {
+        return Ok(None);
+    };
+    let mut valid = BTreeMap::new();
+    for (name, value) in headers {
+        let name = name.to_ascii_lowercase();
+        if name == "user-agent" {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "usa user_agent para la cabecera User-Agent".to_string(),
+            ));
+        }
+        if HeaderName::from_bytes(name.as_bytes()).is_err() || HeaderValue::from_str(value).is_err()
+        {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("cabecera inválida: {name}"),
+            ));
+        }
+        valid.insert(name, value.clone());
+    }
+    Ok(Some(valid))
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub(crate) struct InvitationRow {
+    pub(crate) id: String,
+    pub(crate) org_id: String,
+    pub(crate) email: String,
+    pub(crate) role: String,
+    pub(crate) created_at: DateTime<Utc>,
+    pub(crate) expires_at: DateTime<Utc>,
+    pub(crate) accepted_at: Option<DateTime<Utc>>,
+}
+
+/// Another monitor allowed to report results through `/ingest/external`, with its own token.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub(crate) struct IngestSourceRow {
+    pub(crate) id: String,
+    pub(crate) org_id: String,
+    pub(crate) source: String,
+    pub(crate) created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub(crate) struct AgentRow {
+    pub(crate) id: String,
+    pub(crate) name: String,
+    pub(crate) region: String,
+    pub(crate) created_at: DateTime<Utc>,
+    pub(crate) last_seen_at: Option<DateTime<Utc>>,
+}
+
+/// A check handed to a remote agent, with its secrets resolved so the agent can probe it.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct AgentAssignment {
+    #[serde(flatten)]
+    pub(crate) check: CheckRow,
+    pub(crate) client_key: Option<String>,
+    pub(crate) auth_header: Option<String>,
+    #[serde(default)]
+    pub(crate) headers: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct AgentResult {
+    pub(crate) check_id: String,
+    pub(crate) checked_at: DateTime<Utc>,
+    pub(crate) status: String,
+    pub(crate) http_status: Option<i64>,
+    pub(crate) latency_ms: Option<i64>,
+    pub(crate) error: Option<String>,
+    pub(crate) content_hash: Option<String>,
+    #[serde(default)]
+    pub(crate) response_bytes: Option<i64>,
+    #[serde(default)]
+    pub(crate) protocol: Option<String>,
+    #[serde(default)]
+    pub(crate) error_kind: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct AgentResultsRequest {
+    pub(crate) results: Vec<AgentResult>,
+}
+
+pub(crate) fn validate_check_url(value: &str) -> Result<(), (StatusCode, String)> {
+    let url =
+        Url::parse(value).map_err(|_| (StatusCode::BAD_REQUEST, "url inválida".to_string()))?;
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "url debe usar http/https".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn severity_rank(severity: &str) -> usize {
+    SEVERITIES
+        .iter()
+        .position(|s| *s == severity)
+        .unwrap_or(SEVERITIES.len())
+}
+
+pub(crate) fn validate_severity(field: &str, value: &str) -> Result<(), (StatusCode, String)> {
+    if !SEVERITIES.contains(&value) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("{field} debe ser uno de: {}", SEVERITIES.join(", ")),
+        ));
+    }
+    Ok(())
+}
+
+pub(crate) fn validate_min_response_bytes(value: Option<i64>) -> Result<(), (StatusCode, String)> {
+    if value.is_some_and(|bytes| bytes < 0) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "min_response_bytes mínimo: 0".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Lowercase letters, digits and dashes, since it ends up in public URLs.
+pub(crate) fn validate_slug(value: &str) -> Result<(), (StatusCode, String)> {
+    let valid = (1..=64).contains(&value.len())
+        && value
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        && !value.starts_with('-')
+        && !value.ends_with('-');
+    if !valid {
+        return Err((StatusCode::BAD_REQUEST, "slug inválido".to_string()));
+    }
+    Ok(())
+}
+
+pub(crate) fn normalize_email(value: &str) -> Result<String, (StatusCode, String)> {
+    let email = value.trim().to_lowercase();
+    email
+        .parse::<lettre::Address>()
+        .map_err(|_| (StatusCode::BAD_REQUEST, "email inválido".to_string()))?;
+    Ok(email)
+}
+
+pub(crate) fn validate_template(template: &str) -> Result<(), (StatusCode, String)> {
+    minijinja::Environment::new()
+        .template_from_str(template)
+        .map(|_| ())
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("template inválido: {e}")))
+}
+
+pub(crate) fn validate_identity(cert: &str, key: &str) -> Result<(), (StatusCode, String)> {
+    reqwest::Identity::from_pem(format!("{cert}\n{key}").as_bytes())
+        .map(|_| ())
+        .map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                "client_cert/client_key PEM inválidos".to_string(),
+            )
+        })
+}
+
+/// Access levels, each including the ones below it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Role {
+    Viewer,
+    Editor,
+    Admin,
+}
+
+impl Role {
+    pub(crate) fn parse(value: &str) -> Option<Self> {
+        match value {
+            "viewer" => Some(Role::Viewer),
+            "editor" => Some(Role::Editor),
+            "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) fn validate_role(role: &str) -> Result<(), (StatusCode, String)> {
+    if Role::parse(role).is_none() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("role debe ser uno de: {}", ROLES.join(", ")),
+        ));
+    }
+    Ok(())
+}
+
+/// `regions` is a comma-separated list; checks without it run in every region.
+pub(crate) fn check_runs_in_region(check: &CheckRow, region: &str) -> bool {
+    match &check.regions {
+        None => true,
+        Some(regions) => regions.split(',').any(|r| r.trim() == region),
+    }
+}
+
+pub(crate) fn is_accepted_status(check: &CheckRow, status: u16) -> bool {
+    (200..300).contains(&status)
+        || check
+            .accepted_statuses
+            .as_deref()
+            .is_some_and(|accepted| accepted.split(',').any(|s| s.trim().parse() == Ok(status)))
+}
+
+pub(crate) fn validate_accepted_statuses(value: &str) -> Result<(), (StatusCode, String)> {
+    let valid = value.split(',').all(|s| {
+        s.trim()
+            .parse::<u16>()
+            .is_ok_and(|status| (100..600).contains(&status))
+    });
+    if !valid {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "accepted_statuses debe ser una lista de códigos HTTP separados por comas".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Why a DOWN probe failed, stored in `error_kind`: `dns`, `connect_timeout`, `connect`
+/// (refused, unreachable...), `tls`, `timeout` (waiting for the response), `body`, `http_5xx`,
+/// `http_status` (any other unaccepted status), `assertion` (the response was read but failed
+/// one of the check's expectations) and `config` (the probe couldn't be set up).
+pub(crate) const ERROR_KINDS: &[&str] = &[
+    "dns",
+    "connect_timeout",
+    "connect",
+    "tls",
+    "timeout",
+    "body",
+    "http_5xx",
+    "http_status",
+    "assertion",
+    "config",
+];
+
+pub(crate) fn http_error_kind(status: u16) -> &'static str {
+    if status >= 500 {
+        "http_5xx"
+    } else {
+        "http_status"
+    }
+}
+
+/// The protocol a check with `http_version` must be served over, as results record it.
+pub(crate) fn expected_protocol(check: &CheckRow) -> Option<&'static str> {
+    match check.http_version.as_deref()? {
+        "1.1" => Some("HTTP/1.1"),
+        "2" => Some("HTTP/2"),
+        _ => None,
+    }
+}
+
+pub(crate) fn validate_http_version(value: &str) -> Result<(), (StatusCode, String)> {
+    if !matches!(value, "1.1" | "2") {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "http_version debe ser 1.1 o 2".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+pub(crate) fn validate_connect_to(value: &str) -> Result<(), (StatusCode, String)> {
+    if value.parse::<IpAddr>().is_err() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "connect_to debe ser una dirección IP".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// `timezone` is checked even without `cron`, so it can be set first.
+pub(crate) fn validate_cron(
+    cron: Option<&str>,
+    timezone: Option<&str>,
+) -> Result<Option<Cron>, (StatusCode, String)> {
+    let timezone: Tz = timezone
+        .unwrap_or("UTC")
+        .parse()
+        .map_err(|_| (StatusCode::BAD_REQUEST, "timezone inválida".to_string()))?;
+    let Some(cron) = cron else {
+        return Ok(None);
+    };
+    let cron = Cron::parse(cron, timezone).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    if cron.next_after(Utc::now()).is_none() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "cron no se cumple nunca".to_string(),
+        ));
+    }
+    Ok(Some(cron))
+}
+
+/// Parses `30m`, `24h` or `7d`.
+pub(crate) fn parse_period(value: &str) -> Option<chrono::Duration> {
+    let (amount, unit) = value.split_at(value.len().checked_sub(1)?);
+    let amount: i64 = amount.parse().ok().filter(|a| *a > 0)?;
+    match unit {
+        "m" => Some(chrono::Duration::minutes(amount)),
+        "h" => Some(chrono::Duration::hours(amount)),
+        "d" => Some(chrono::Duration::days(amount)),
+        _ => None,
+    }
+}
+
+/// Hourly bucket a sample belongs to, formatted like the stored timestamps.
+pub(crate) fn rollup_bucket(at: DateTime<Utc>) -> String {
+    at.format("%Y-%m-%dT%H:00:00+00:00").to_string()
+}
+
+/// Groups hourly `(bucket_start, samples, up_samples)` rollups, oldest first, by their local
+/// date in `tz`. Buckets are whole UTC hours, so zones with a half-hour offset split days at
+/// the start of the bucket.
+pub(crate) fn daily_uptime(buckets: &[(DateTime<Utc>, i64, i64)], tz: Tz) -> Vec<DailyUptime> {
+    let mut days: Vec<DailyUptime> = Vec::new();
+    for (start, samples, up_samples) in buckets {
+        let date = start.with_timezone(&tz).date_naive();
+        match days.last_mut() {
+            Some(day) if day.date == date => {
+                day.samples += samples;
+                day.up_samples += up_samples;
+            }
+            _ => days.push(DailyUptime {
+                date,
+                samples: *samples,
+                up_samples: *up_samples,
+                uptime_percent: None,
+            }),
+        }
+    }
+    for day in &mut days {
+        day.uptime_percent =
+            (day.samples > 0).then(|| day.up_samples as f64 * 100.0 / day.samples as f64);
+    }
+    days
+}
+
+/// Samples to add to one hourly rollup bucket. Every sample is counted, whether or not
+/// its raw row is kept.
+#[derive(Default)]
+pub(crate) struct RollupDelta {
+    pub(crate) planned_samples: i64,
+    pub(crate) samples: i64,
+    pub(crate) up_samples: i64,
+    pub(crate) latency_sum: i64,
+    pub(crate) latency_samples: i64,
+    pub(crate) latency_max: Option<i64>,
+    pub(crate) bytes_sum: i64,
+    pub(crate) bytes_samples: i64,
+    /// Failed samples per [`ERROR_KINDS`] entry, kept in `check_rollup_errors`.
+    pub(crate) errors: BTreeMap<String, i64>,
+}
+
+impl RollupDelta {
+    pub(crate) fn sample(
+        status: &str,
+        latency_ms: Option<i64>,
+        response_bytes: Option<i64>,
+        error_kind: Option<&str>,
+        planned: bool,
+    ) -> Self {
+        let mut delta = RollupDelta::default();
+        delta.add(status, latency_ms, response_bytes, error_kind, planned);
+        delta
+    }
+
+    /// `planned` samples, taken during maintenance, are only counted.
+    pub(crate) fn add(
+        &mut self,
+        status: &str,
+        latency_ms: Option<i64>,
+        response_bytes: Option<i64>,
+        error_kind: Option<&str>,
+        planned: bool,
+    ) {
+        if planned {
+            self.planned_samples += 1;
+            return;
+        }
+        self.samples += 1;
+        self.up_samples += i64::from(status != "DOWN");
+        if let Some(kind) = error_kind {
+            *self.errors.entry(kind.to_string()).or_default() += 1;
+        }
+        if let Some(latency) = latency_ms {
+            self.latency_sum += latency;
+            self.latency_samples += 1;
+            self.latency_max = Some(self.latency_max.map_or(latency, |m| m.max(latency)));
+        }
+        if let Some(bytes) = response_bytes {
+            self.bytes_sum += bytes;
+            self.bytes_samples += 1;
+        }
+    }
+
+    pub(crate) async fn apply(
+        &self,
+        conn: &mut SqliteConnection,
+        check_id: &str,
+        bucket: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO check_rollups (check_id, bucket_start, samples, up_samples, latency_sum, latency_samples, latency_max, planned_samples, bytes_sum, bytes_samples)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT (check_id, bucket_start) DO UPDATE SET
+              planned_samples = planned_samples + excluded.planned_samples,
+              samples = samples + excluded.samples,
+              up_samples = up_samples + excluded.up_samples,
+              latency_sum = latency_sum + excluded.latency_sum,
+              latency_samples = latency_samples + excluded.latency_samples,
+              latency_max = MAX(COALESCE(latency_max, 0), COALESCE(excluded.latency_max, 0)),
+              bytes_sum = bytes_sum + excluded.bytes_sum,
+              bytes_samples = bytes_samples + excluded.bytes_samples
+            "#,
+        )
+        .bind(check_id)
+        .bind(bucket)
+        .bind(self.samples)
+        .bind(self.up_samples)
+        .bind(self.latency_sum)
+        .bind(self.latency_samples)
+        .bind(self.latency_max)
+        .bind(self.planned_samples)
+        .bind(self.bytes_sum)
+        .bind(self.bytes_samples)
+        .execute(&mut *conn)
+        .await?;
+        for (kind, samples) in &self.errors {
+            sqlx::query(
+                r#"
+                INSERT INTO check_rollup_errors (check_id, bucket_start, error_kind, samples) VALUES (?, ?, ?, ?)
+                ON CONFLICT (check_id, bucket_start, error_kind) DO UPDATE SET samples = samples + excluded.samples
+                "#,
+            )
+            .bind(check_id)
+            .bind(bucket)
+            .bind(kind)
+            .bind(samples)
+            .execute(&mut *conn)
+            .await?;
+        }
+        Ok(())
+    }
+}
+
+/// Hashes the response body, or only the elements matching `selector` when given.
+pub(crate) fn content_hash(body: &str, selector: Option<&str>) -> String {
+    let mut hasher = Sha256::new();
+    match selector.and_then(|s| Selector::parse(s).ok()) {
+        Some(selector) => {
+            let document = HtmlDocument::parse_document(body);
+            for element in document.select(&selector) {
+                hasher.update(element.html().as_bytes());
+            }
+        }
+        None => hasher.update(body.as_bytes()),
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// In `changes` mode a raw result is kept only when the probe status or content changes,
+/// or every `persist_every` samples. Quorum checks always persist: the quorum reads them.
+pub(crate) fn should_persist(check: &CheckRow, probe_status: &str, content_changed: bool) -> bool {
+    if check.persist_mode != PERSIST_CHANGES || check.quorum.is_some() || content_changed {
+        return true;
+    }
+    if check.last_probe_status.as_deref() != Some(probe_status) {
+        return true;
+    }
+    check
+        .persist_every
+        .is_some_and(|n| check.samples_since_persist + 1 >= n)
+}
+
+/// The previous status when `status` differs from it, i.e. when a change must be alerted.
+pub(crate) fn status_transition<'a>(check: &'a CheckRow, status: &str) -> Option<&'a str> {
+    let previous = check.last_status.as_deref().unwrap_or("UNKNOWN");
+    (previous != status).then_some(previous)
+}
+
+/// `1h 5m`, `12m 30s` or `45s`.
+pub(crate) fn format_duration(duration: chrono::Duration) -> String {
+    let secs = duration.num_seconds().max(0);
+    let (h, m, s) = (secs / 3600, secs % 3600 / 60, secs % 60);
+    if h > 0 {
+        format!("{h}h {m}m")
+    } else if m > 0 {
+        format!("{m}m {s}s")
+    } else {
+        format!("{s}s")
+    }
+}
+
+/// The probe error, or the HTTP status when the request itself succeeded.
+pub(crate) fn failure_cause(error: Option<&str>, http_status: Option<i64>) -> Option<String> {
+    error
+        .map(str::to_string)
+        .or_else(|| http_status.map(|s| format!("HTTP {s}")))
+}
+
+/// Template context of an alert: `{{ check.name }}`, `{{ severity }}`, `{{ status }}`,
+/// `{{ latency_ms }}`, `{{ downtime }}`... `event` is `status_change`, `recovery`, `content_change`,
+/// `latency_anomaly`, `latency_normal`, `group_degraded` or `group_recovered`. Group alerts
+/// carry `{{ group }}`, `{{ down_checks }}` and `{{ total_checks }}`, and `check` is the
+/// member whose change set them off. Alerts of a failed probe carry its `{{ error_kind }}`.
+#[derive(Serialize)]
+pub(crate) struct Alert<'a> {
+    pub(crate) event: &'static str,
+    pub(crate) check: &'a CheckRow,
+    pub(crate) severity: &'a str,
+    pub(crate) at: DateTime<Utc>,
+    pub(crate) previous: Option<&'a str>,
+    pub(crate) status: Option<&'a str>,
+    pub(crate) latency_ms: Option<i64>,
+    pub(crate) downtime: Option<String>,
+    pub(crate) failed_probes: Option<i64>,
+    pub(crate) last_error: Option<&'a str>,
+    pub(crate) error_kind: Option<&'a str>,
+    pub(crate) worst_latency_ms: Option<i64>,
+    pub(crate) baseline_ms: Option<i64>,
+    pub(crate) group: Option<&'a str>,
+    pub(crate) down_checks: Option<i64>,
+    pub(crate) total_checks: Option<i64>,
+}
+
+impl<'a> Alert<'a> {
+    pub(crate) fn new(event: &'static str, check: &'a CheckRow, at: DateTime<Utc>) -> Self {
+        Alert {
+            event,
+            check,
+            severity: &check.severity,
+            at,
+            previous: None,
+            status: None,
+            latency_ms: None,
+            downtime: None,
+            failed_probes: None,
+            last_error: None,
+            error_kind: None,
+            worst_latency_ms: None,
+            baseline_ms: None,
+            group: None,
+            down_checks: None,
+            total_checks: None,
+        }
+    }
+
+    /// One line describing the alert inside a digest.
+    pub(crate) fn summary(&self) -> String {
+        match self.event {
+            "content_change" => format!("📝 {}: content changed", self.check.name),
+            "latency_anomaly" => format!(
+                "🐢 {}: {} ms, usually {} ms",
+                self.check.name,
+                self.latency_ms.unwrap_or_default(),
+                self.baseline_ms.unwrap_or_default(),
+            ),
+            "latency_normal" => format!("⚡ {}: latency back to normal", self.check.name),
+            "group_degraded" | "group_recovered" => format!(
+                "{} {}: {}/{} checks DOWN",
+                if self.event == "group_degraded" {
+                    "🔴"
+                } else {
+                    "🟢"
+                },
+                self.group.unwrap_or_default(),
+                self.down_checks.unwrap_or_default(),
+                self.total_checks.unwrap_or_default(),
+            ),
+            _ => format!(
+                "{} {}: {} → {}",
+                if self.status == Some("DOWN") {
+                    "🔴"
+                } else {
+                    "🟢"
+                },
+                self.check.name,
+                self.previous.unwrap_or("UNKNOWN"),
+                self.status.unwrap_or("UNKNOWN"),
+            ),
+        }
+    }
+}
+
+/// The check's template wins over the channel's, which wins over the default one.
+pub(crate) fn render_alert(alert: &Alert, channel_template: Option<&str>) -> String {
+    let template = alert
+        .check
+        .alert_template
+        .as_deref()
+        .or(channel_template)
+        .unwrap_or(DEFAULT_ALERT_TEMPLATE);
+    minijinja::Environment::new()
+        .render_str(template, alert)
+        .unwrap_or_else(|e| {
+            error!(
+                "Error rendering alert template for {}: {e}",
+                alert.check.name
+            );
+            minijinja::Environment::new()
+                .render_str(DEFAULT_ALERT_TEMPLATE, alert)
+                .unwrap_or_default()
+        })
+}
+
+/// `quiet_start`..`quiet_end` in the channel's timezone; the window may wrap past midnight.
+pub(crate) fn in_quiet_hours(channel: &ChannelRow, now: DateTime<Utc>) -> bool {
+    let (Some(start), Some(end)) = (&channel.quiet_start, &channel.quiet_end) else {
+        return false;
+    };
+    let (Ok(start), Ok(end)) = (
+        NaiveTime::parse_from_str(start, "%H:%M"),
+        NaiveTime::parse_from_str(end, "%H:%M"),
+    ) else {
+        return false;
+    };
+    let tz: Tz = channel.timezone.parse().unwrap_or(Tz::UTC);
+    let local = now.with_timezone(&tz).time();
+    if start <= end {
+        start <= local && local < end
+    } else {
+        local >= start || local < end
+    }
+}
+
+pub(crate) fn should_run_check(check: &CheckRow, now: DateTime<Utc>) -> bool {
+    due_at(check).is_none_or(|next| now >= next)
+}
+
+/// When the check is next due; `None` means it has never run.
+pub(crate) fn due_at(check: &CheckRow) -> Option<DateTime<Utc>> {
+    if let Some(next) = check.next_run_at {
+        return Some(next);
+    }
+    Some(check.last_checked_at? + chrono::Duration::seconds(check.interval_seconds))
+}
+
+/// New checks start at a random phase within their interval, so checks created together
+/// (or sharing an interval) don't all fire in the same second. The phase is never zero: a
+/// check isn't probed while it is being created. Cron checks start at their next run.
+pub(crate) fn initial_run_at(
+    interval_seconds: i64,
+    cron: Option<&Cron>,
+    now: DateTime<Utc>,
+) -> DateTime<Utc> {
+    match cron.and_then(|cron| cron.next_after(now)) {
+        Some(next) => next,
+        None => now + chrono::Duration::seconds(1 + random_below(interval_seconds)),
+    }
+}
+
+/// `down_probes` counts the DOWN probes in a row up to the one just taken. Cron checks just
+/// run at their next time, without backoff.
+pub(crate) fn schedule_next(
+    check: &CheckRow,
+    checked_at: DateTime<Utc>,
+    down_probes: i64,
+) -> DateTime<Utc> {
+    let jitter = chrono::Duration::seconds(random_below(check.jitter_seconds.unwrap_or(0) + 1));
+    if let Some(next) = check.cron().and_then(|cron| cron.next_after(checked_at)) {
+        return next + jitter;
+    }
+    checked_at + chrono::Duration::seconds(probe_interval(check, down_probes)) + jitter
+}
+
+/// The first failure is retried at the normal interval to confirm it; from then on a check
+/// with `backoff_max_seconds` waits twice as long after every DOWN probe, up to that cap.
+pub(crate) fn probe_interval(check: &CheckRow, down_probes: i64) -> i64 {
+    match check.backoff_max_seconds {
+        Some(max) if down_probes > 1 => {
+            let doublings = (down_probes - 1).min(32) as u32;
+            check
+                .interval_seconds
+                .saturating_mul(1 << doublings)
+                .min(max.max(check.interval_seconds))
+        }
+        _ => check.interval_seconds,
+    }
+}
+
+pub(crate) fn random_below(bound: i64) -> i64 {
+    if bound <= 0 {
+        return 0;
+    }
+    (OsRng.next_u64() % bound as u64) as i64
+}
@@ -0,0 +1,156 @@
+//! Monitoring events published to a message broker so other systems can react to them
+//! without polling the API. NATS (feature `nats`) and Kafka (feature `kafka`) are
+//! configured from the environment; without either, publishing is a no-op.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+#[cfg(any(feature = "nats", feature = "kafka"))]
+use std::env;
+#[cfg(any(feature = "nats", feature = "kafka"))]
+use tracing::error;
+#[cfg(any(feature = "nats", feature = "kafka"))]
+use tracing::info;
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event<'a> {
+    StatusChanged {
+        check_id: &'a str,
+        check_name: &'a str,
+        url: &'a str,
+        previous: &'a str,
+        status: &'a str,
+        severity: &'a str,
+        at: DateTime<Utc>,
+    },
+    IncidentOpened {
+        incident_id: &'a str,
+        check_id: &'a str,
+        check_name: &'a str,
+        severity: &'a str,
+        started_at: DateTime<Utc>,
+    },
+    IncidentResolved {
+        incident_id: &'a str,
+        check_id: &'a str,
+        check_name: &'a str,
+        started_at: DateTime<Utc>,
+        resolved_at: DateTime<Utc>,
+    },
+    LatencyAnomaly {
+        check_id: &'a str,
+        check_name: &'a str,
+        url: &'a str,
+        latency_ms: i64,
+        baseline_ms: i64,
+        severity: &'a str,
+        at: DateTime<Utc>,
+    },
+}
+
+impl Event<'_> {
+    #[cfg(any(feature = "nats", feature = "kafka"))]
+    fn kind(&self) -> &'static str {
+        match self {
+            Event::StatusChanged { .. } => "status_changed",
+            Event::IncidentOpened { .. } => "incident_opened",
+            Event::IncidentResolved { .. } => "incident_resolved",
+            Event::LatencyAnomaly { .. } => "latency_anomaly",
+        }
+    }
+
+    #[cfg(feature = "kafka")]
+    fn check_id(&self) -> &str {
+        match self {
+            Event::StatusChanged { check_id, .. }
+            | Event::IncidentOpened { check_id, .. }
+            | Event::IncidentResolved { check_id, .. }
+            | Event::LatencyAnomaly { check_id, .. } => check_id,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct EventPublisher {
+    #[cfg(feature = "nats")]
+    nats: Option<(async_nats::Client, String)>,
+    #[cfg(feature = "kafka")]
+    kafka: Option<rskafka::client::partition::PartitionClient>,
+}
+
+impl EventPublisher {
+    /// NATS is enabled by `NATS_URL` (subjects `<NATS_SUBJECT_PREFIX>.<event type>`, prefix
+    /// `uptime` by default); Kafka by `KAFKA_BROKERS` (topic `KAFKA_TOPIC`, default
+    /// `uptime-events`, keyed by check id).
+    pub async fn from_env() -> anyhow::Result<Self> {
+        #[allow(unused_mut)]
+        let mut publisher = EventPublisher::default();
+
+        #[cfg(feature = "nats")]
+        if let Ok(url) = env::var("NATS_URL") {
+            let client = async_nats::connect(&url).await?;
+            let prefix = env::var("NATS_SUBJECT_PREFIX").unwrap_or_else(|_| "uptime".to_string());
+            info!("Publishing events to NATS at {url}");
+            publisher.nats = Some((client, prefix));
+        }
+
+        #[cfg(feature = "kafka")]
+        if let Ok(brokers) = env::var("KAFKA_BROKERS") {
+            use rskafka::client::{partition::UnknownTopicHandling, ClientBuilder};
+
+            let topic = env::var("KAFKA_TOPIC").unwrap_or_else(|_| "uptime-events".to_string());
+            let client = ClientBuilder::new(brokers.split(',').map(str::to_string).collect())
+                .build()
+                .await?;
+            let partition = client
+                .partition_client(topic.clone(), 0, UnknownTopicHandling::Retry)
+                .await?;
+            info!("Publishing events to Kafka topic {topic}");
+            publisher.kafka = Some(partition);
+        }
+
+        Ok(publisher)
+    }
+
+    /// Delivery failures are logged; they never block alerting.
+    pub async fn publish(&self, event: &Event<'_>) {
+        #[cfg(any(feature = "nats", feature = "kafka"))]
+        let payload = match serde_json::to_vec(event) {
+            Ok(payload) => payload,
+            Err(e) => {
+                error!("Error encoding {} event: {e}", event.kind());
+                return;
+            }
+        };
+
+        #[cfg(feature = "nats")]
+        if let Some((client, prefix)) = &self.nats {
+            let subject = format!("{prefix}.{}", event.kind());
+            if let Err(e) = client.publish(subject, payload.clone().into()).await {
+                error!("Error publishing {} event to NATS: {e}", event.kind());
+            }
+        }
+
+        #[cfg(feature = "kafka")]
+        if let Some(partition) = &self.kafka {
+            let record = rskafka::record::Record {
+                key: Some(event.check_id().as_bytes().to_vec()),
+                value: Some(payload.clone()),
+                headers: Default::default(),
+                timestamp: chrono::Utc::now(),
+            };
+            if let Err(e) = partition
+                .produce(
+                    vec![record],
+                    rskafka::client::partition::Compression::NoCompression,
+                )
+                .await
+            {
+                error!("Error publishing {} event to Kafka: {e}", event.kind());
+            }
+        }
+
+        #[cfg(not(any(feature = "nats", feature = "kafka")))]
+        let _ = event;
+    }
+}
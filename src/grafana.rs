@@ -0,0 +1,287 @@
+//! The Grafana JSON datasource contract, so dashboards can chart checks without Prometheus:
+//! every check offers a `latency_ms` and an `uptime_percent` series, and its annotations.
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use crate::api::{internal_error, Viewer};
+use crate::domain::{rollup_bucket, AnnotationRow, CheckRow};
+use crate::store::CheckStore;
+use crate::AppState;
+
+pub(crate) const METRICS: &[&str] = &["latency_ms", "uptime_percent"];
+
+/// From this interval on, series are read from the hourly rollups instead of the raw results.
+pub(crate) const ROLLUP_INTERVAL_MS: i64 = 3_600_000;
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct SearchRequest {
+    #[serde(default)]
+    pub(crate) target: String,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct SearchTarget {
+    pub(crate) text: String,
+    /// `<check id>:<metric>`, what queries ask for.
+    pub(crate) value: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct TimeRange {
+    pub(crate) from: DateTime<Utc>,
+    pub(crate) to: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct QueryRequest {
+    pub(crate) range: TimeRange,
+    pub(crate) interval_ms: Option<i64>,
+    pub(crate) max_data_points: Option<i64>,
+    pub(crate) targets: Vec<QueryTarget>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct QueryTarget {
+    #[serde(default)]
+    pub(crate) target: String,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct Series {
+    pub(crate) target: String,
+    /// `[value, unix milliseconds]`, oldest first.
+    pub(crate) datapoints: Vec<(f64, i64)>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct AnnotationsRequest {
+    pub(crate) range: TimeRange,
+    pub(crate) annotation: AnnotationQuery,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct AnnotationQuery {
+    pub(crate) name: Option<String>,
+    /// A check id; every check of the organization when empty.
+    #[serde(default)]
+    pub(crate) query: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GrafanaAnnotation {
+    pub(crate) annotation: AnnotationQuery,
+    pub(crate) time: i64,
+    pub(crate) time_end: Option<i64>,
+    pub(crate) title: String,
+    pub(crate) text: String,
+    pub(crate) tags: Vec<String>,
+}
+
+/// Samples of one bucket of a series.
+#[derive(Default)]
+pub(crate) struct Bucket {
+    pub(crate) samples: i64,
+    pub(crate) up_samples: i64,
+    pub(crate) latency_sum: i64,
+    pub(crate) latency_samples: i64,
+}
+
+impl Bucket {
+    pub(crate) fn value(&self, metric: &str) -> Option<f64> {
+        match metric {
+            "latency_ms" => (self.latency_samples > 0)
+                .then(|| self.latency_sum as f64 / self.latency_samples as f64),
+            _ => (self.samples > 0).then(|| self.up_samples as f64 * 100.0 / self.samples as f64),
+        }
+    }
+}
+
+/// Grafana's "Test connection".
+pub(crate) async fn grafana_root(Viewer(_caller): Viewer) -> &'static str {
+    "OK"
+}
+
+pub(crate) async fn search(
+    State(state): State<Arc<AppState>>,
+    Viewer(caller): Viewer,
+    Json(payload): Json<SearchRequest>,
+) -> Result<Json<Vec<SearchTarget>>, (StatusCode, String)> {
+    let filter = payload.target.to_lowercase();
+    let mut checks = state
+        .db
+        .org_checks(&caller.org_id)
+        .await
+        .map_err(internal_error)?;
+    checks.sort_by(|a, b| a.name.cmp(&b.name));
+    let targets = checks
+        .iter()
+        .filter(|c| c.name.to_lowercase().contains(&filter))
+        .flat_map(|c| {
+            METRICS.iter().map(|metric| SearchTarget {
+                text: format!("{} {metric}", c.name),
+                value: format!("{}:{metric}", c.id),
+            })
+        })
+        .collect();
+    Ok(Json(targets))
+}
+
+/// One series per target. The bucket size is Grafana's interval, widened to keep within
+/// `maxDataPoints`.
+pub(crate) async fn query(
+    State(state): State<Arc<AppState>>,
+    Viewer(caller): Viewer,
+    Json(payload): Json<QueryRequest>,
+) -> Result<Json<Vec<Series>>, (StatusCode, String)> {
+    let TimeRange { from, to } = payload.range;
+    if to <= from {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "range.to debe ser posterior a range.from".to_string(),
+        ));
+    }
+    let span_ms = (to - from).num_milliseconds();
+    let mut interval_ms = payload.interval_ms.unwrap_or(60_000).max(1000);
+    if let Some(points) = payload.max_data_points.filter(|p| *p > 0) {
+        interval_ms = interval_ms.max((span_ms + points - 1) / points);
+    }
+
+    let checks: HashMap<String, CheckRow> = state
+        .db
+        .org_checks(&caller.org_id)
+        .await
+        .map_err(internal_error)?
+        .into_iter()
+        .map(|c| (c.id.clone(), c))
+        .collect();
+    let mut series = Vec::new();
+    for QueryTarget { target } in payload.targets {
+        let (check, metric) = target
+            .split_once(':')
+            .and_then(|(id, metric)| Some((checks.get(id)?, metric)))
+            .filter(|(_, metric)| METRICS.contains(metric))
+            .ok_or((
+                StatusCode::BAD_REQUEST,
+                format!("target desconocido: {target}"),
+            ))?;
+        let buckets = buckets(&state, &check.id, from, to, interval_ms)
+            .await
+            .map_err(internal_error)?;
+        series.push(Series {
+            target: format!("{} {metric}", check.name),
+            datapoints: buckets
+                .iter()
+                .filter_map(|(at, bucket)| Some((bucket.value(metric)?, *at)))
+                .collect(),
+        });
+    }
+    Ok(Json(series))
+}
+
+/// Keyed by the bucket start in unix milliseconds.
+pub(crate) async fn buckets(
+    state: &AppState,
+    check_id: &str,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    interval_ms: i64,
+) -> Result<BTreeMap<i64, Bucket>, sqlx::Error> {
+    let mut buckets: BTreeMap<i64, Bucket> = BTreeMap::new();
+    let bucket_of = |at: DateTime<Utc>| at.timestamp_millis().div_euclid(interval_ms) * interval_ms;
+    if interval_ms >= ROLLUP_INTERVAL_MS {
+        let rows: Vec<(DateTime<Utc>, i64, i64, i64, i64)> = sqlx::query_as(
+            "SELECT bucket_start, samples, up_samples, latency_sum, latency_samples FROM check_rollups WHERE check_id = ? AND bucket_start >= ? AND bucket_start <= ?",
+        )
+        .bind(check_id)
+        .bind(rollup_bucket(from))
+        .bind(rollup_bucket(to))
+        .fetch_all(&state.db)
+        .await?;
+        for (at, samples, up_samples, latency_sum, latency_samples) in rows {
+            let bucket = buckets.entry(bucket_of(at)).or_default();
+            bucket.samples += samples;
+            bucket.up_samples += up_samples;
+            bucket.latency_sum += latency_sum;
+            bucket.latency_samples += latency_samples;
+        }
+    } else {
+        let rows: Vec<(i64, String, Option<i64>)> = sqlx::query_as(
+            "SELECT checked_at_ms, status, latency_ms FROM check_results WHERE check_id = ? AND checked_at_ms >= ? AND checked_at_ms <= ?",
+        )
+        .bind(check_id)
+        .bind(from.timestamp_millis())
+        .bind(to.timestamp_millis())
+        .fetch_all(&state.db)
+        .await?;
+        for (at, status, latency_ms) in rows {
+            let bucket = buckets
+                .entry(at.div_euclid(interval_ms) * interval_ms)
+                .or_default();
+            bucket.samples += 1;
+            bucket.up_samples += i64::from(status != "DOWN");
+            if let Some(latency) = latency_ms {
+                bucket.latency_sum += latency;
+                bucket.latency_samples += 1;
+            }
+        }
+    }
+    Ok(buckets)
+}
+
+pub(crate) async fn annotations(
+    State(state): State<Arc<AppState>>,
+    Viewer(caller): Viewer,
+    Json(payload): Json<AnnotationsRequest>,
+) -> Result<Json<Vec<GrafanaAnnotation>>, (StatusCode, String)> {
+    let check_id = payload.annotation.query.trim();
+    let rows = sqlx::query_as::<_, AnnotationRow>(
+        r#"
+        SELECT a.* FROM annotations a JOIN checks c ON c.id = a.check_id
+        WHERE c.org_id = ?1 AND (?2 = '' OR a.check_id = ?2)
+          AND a.at <= ?4 AND COALESCE(a.ends_at, a.at) >= ?3
+        ORDER BY a.at
+        "#,
+    )
+    .bind(&caller.org_id)
+    .bind(check_id)
+    .bind(payload.range.from)
+    .bind(payload.range.to)
+    .fetch_all(&state.db)
+    .await
+    .map_err(internal_error)?;
+    let names: HashMap<String, String> = state
+        .db
+        .org_checks(&caller.org_id)
+        .await
+        .map_err(internal_error)?
+        .into_iter()
+        .map(|c| (c.id, c.name))
+        .collect();
+
+    Ok(Json(
+        rows.into_iter()
+            .map(|a| {
+                let check = names.get(&a.check_id).cloned().unwrap_or_default();
+                GrafanaAnnotation {
+                    annotation: payload.annotation.clone(),
+                    time: a.at.timestamp_millis(),
+                    time_end: a
+                        .ends_at
+                        .filter(|ends_at| *ends_at > a.at)
+                        .map(|ends_at| ends_at.timestamp_millis()),
+                    title: check.clone(),
+                    text: a.text,
+                    tags: vec![check, a.author],
+                }
+            })
+            .collect(),
+    ))
+}
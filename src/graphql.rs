@@ -0,0 +1,441 @@
+//! GraphQL API at `/graphql`, alongside the REST one: checks, their results, incidents and
+//! stats as nested queries, and a `statusChanged` subscription over WebSocket. Requests are
+//! authorized like `GET` REST calls and only see the caller's organization.
+
+use crate::api::{compute_stats, Caller, Viewer};
+use crate::domain::{CheckRow, CheckStats, IncidentRow, ResultRow};
+use crate::store::CheckStore;
+use crate::AppState;
+use async_graphql::futures_util::{future, stream, SinkExt, Stream, StreamExt};
+use async_graphql::http::{WebSocket, WebSocketProtocols, WsMessage, ALL_WEBSOCKET_PROTOCOLS};
+use async_graphql::{Context, Data, EmptyMutation, Object, Schema, SimpleObject, Subscription, ID};
+use axum::extract::ws::{CloseFrame, Message};
+use axum::extract::{State, WebSocketUpgrade};
+use axum::http::{header::SEC_WEBSOCKET_PROTOCOL, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use chrono::{DateTime, NaiveDate, Utc};
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+pub type ApiSchema = Schema<QueryRoot, EmptyMutation, SubscriptionRoot>;
+
+/// Lists take at most this many items per field.
+const MAX_LIMIT: i64 = 100;
+
+/// Bounds what one query may fetch: nesting is capped, and each list counts `limit` times
+/// its children, so `incidents { check { results { incident { ... } } } }` can't fan out.
+pub fn schema() -> ApiSchema {
+    Schema::build(QueryRoot, EmptyMutation, SubscriptionRoot)
+        .limit_depth(8)
+        .limit_complexity(2_000)
+        .finish()
+}
+
+fn page(limit: i64) -> async_graphql::Result<i64> {
+    if !(1..=MAX_LIMIT).contains(&limit) {
+        return Err(async_graphql::Error::new(format!(
+            "limit debe estar entre 1 y {MAX_LIMIT}"
+        )));
+    }
+    Ok(limit)
+}
+
+fn fan_out(limit: i64, child_complexity: usize) -> usize {
+    limit.clamp(1, MAX_LIMIT) as usize * child_complexity
+}
+
+/// Published for every status transition; subscribers filter by organization.
+#[derive(Clone, SimpleObject)]
+pub struct StatusChange {
+    #[graphql(skip)]
+    pub org_id: String,
+    pub check_id: ID,
+    pub check_name: String,
+    pub url: String,
+    pub previous: String,
+    pub status: String,
+    pub at: DateTime<Utc>,
+}
+
+pub async fn graphql_handler(
+    State(state): State<Arc<AppState>>,
+    Viewer(caller): Viewer,
+    Json(request): Json<async_graphql::Request>,
+) -> Json<async_graphql::Response> {
+    let request = request.data(state.clone()).data(caller);
+    Json(state.graphql.execute(request).await)
+}
+
+/// Subscriptions speak `graphql-transport-ws` or the older `graphql-ws`, whichever the client
+/// asks for.
+pub async fn graphql_ws(
+    State(state): State<Arc<AppState>>,
+    Viewer(caller): Viewer,
+    headers: HeaderMap,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    let protocol = headers
+        .get(SEC_WEBSOCKET_PROTOCOL)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| {
+            value
+                .split(',')
+                .find_map(|protocol| WebSocketProtocols::from_str(protocol.trim()).ok())
+        });
+    let Some(protocol) = protocol else {
+        return (StatusCode::BAD_REQUEST, "protocolo WebSocket no soportado").into_response();
+    };
+
+    let schema = state.graphql.clone();
+    upgrade
+        .protocols(ALL_WEBSOCKET_PROTOCOLS)
+        .on_upgrade(move |socket| async move {
+            let mut data = Data::default();
+            data.insert(state);
+            data.insert(caller);
+
+            let (mut sink, incoming) = socket.split();
+            let incoming = incoming
+                .take_while(|message| future::ready(message.is_ok()))
+                .filter_map(|message| {
+                    future::ready(match message {
+                        Ok(Message::Text(text)) => Some(text.into_bytes()),
+                        Ok(Message::Binary(bytes)) => Some(bytes),
+                        _ => None,
+                    })
+                });
+            let mut outgoing = WebSocket::new(schema, incoming, protocol).connection_data(data);
+            while let Some(message) = outgoing.next().await {
+                let message = match message {
+                    WsMessage::Text(text) => Message::Text(text),
+                    WsMessage::Close(code, reason) => Message::Close(Some(CloseFrame {
+                        code,
+                        reason: reason.into(),
+                    })),
+                };
+                if sink.send(message).await.is_err() {
+                    break;
+                }
+            }
+        })
+}
+
+fn api_error((_, message): (StatusCode, String)) -> async_graphql::Error {
+    async_graphql::Error::new(message)
+}
+
+fn scope<'a>(ctx: &Context<'a>) -> (&'a Arc<AppState>, &'a Caller) {
+    (ctx.data_unchecked(), ctx.data_unchecked())
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    async fn checks(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Check>> {
+        let (state, caller) = scope(ctx);
+        let mut rows = state.db.org_checks(&caller.org_id).await?;
+        rows.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(rows.into_iter().map(Check).collect())
+    }
+
+    async fn check(&self, ctx: &Context<'_>, id: ID) -> async_graphql::Result<Option<Check>> {
+        let (state, caller) = scope(ctx);
+        let row = state.db.org_check(&caller.org_id, id.as_str()).await?;
+        Ok(row.map(Check))
+    }
+
+    /// Incidents of every check, newest first; `open` keeps only the unresolved ones.
+    #[graphql(complexity = "fan_out(limit, child_complexity)")]
+    async fn incidents(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = false)] open: bool,
+        #[graphql(default = 50)] limit: i64,
+    ) -> async_graphql::Result<Vec<Incident>> {
+        let (state, caller) = scope(ctx);
+        let rows = sqlx::query_as::<_, IncidentRow>(
+            r#"
+            SELECT * FROM incidents
+            WHERE check_id IN (SELECT id FROM checks WHERE org_id = ?)
+              AND (? = 0 OR resolved_at IS NULL)
+            ORDER BY started_at DESC LIMIT ?
+            "#,
+        )
+        .bind(&caller.org_id)
+        .bind(open)
+        .bind(page(limit)?)
+        .fetch_all(&state.db)
+        .await?;
+        Ok(rows.into_iter().map(Incident).collect())
+    }
+}
+
+pub struct Check(CheckRow);
+
+#[Object]
+impl Check {
+    async fn id(&self) -> ID {
+        ID(self.0.id.clone())
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn url(&self) -> &str {
+        &self.0.url
+    }
+
+    async fn check_type(&self) -> &str {
+        &self.0.check_type
+    }
+
+    async fn interval_seconds(&self) -> i64 {
+        self.0.interval_seconds
+    }
+
+    async fn severity(&self) -> &str {
+        &self.0.severity
+    }
+
+    async fn active(&self) -> bool {
+        self.0.is_active != 0
+    }
+
+    async fn last_status(&self) -> Option<&str> {
+        self.0.last_status.as_deref()
+    }
+
+    async fn last_checked_at(&self) -> Option<DateTime<Utc>> {
+        self.0.last_checked_at
+    }
+
+    /// Newest first.
+    #[graphql(complexity = "fan_out(limit, child_complexity)")]
+    async fn results(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 10)] limit: i64,
+    ) -> async_graphql::Result<Vec<CheckResult>> {
+        let (state, _) = scope(ctx);
+        let rows = state.db.results(&self.0.id, Some(page(limit)?)).await?;
+        Ok(rows.into_iter().map(CheckResult).collect())
+    }
+
+    #[graphql(complexity = "fan_out(limit, child_complexity)")]
+    async fn incidents(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 10)] limit: i64,
+    ) -> async_graphql::Result<Vec<Incident>> {
+        let (state, _) = scope(ctx);
+        let rows = state.db.incidents(&self.0.id, Some(page(limit)?)).await?;
+        Ok(rows.into_iter().map(Incident).collect())
+    }
+
+    /// Same periods as `/checks/:id/stats`, e.g. `24h` or `7d`; `timezone` is an IANA zone
+    /// for the daily breakdown.
+    async fn stats(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default_with = "\"24h\".to_string()")] period: String,
+        timezone: Option<String>,
+    ) -> async_graphql::Result<Stats> {
+        let (state, _) = scope(ctx);
+        compute_stats(state, &self.0.id, period, timezone.as_deref())
+            .await
+            .map(Stats::from)
+            .map_err(api_error)
+    }
+}
+
+pub struct CheckResult(ResultRow);
+
+#[Object]
+impl CheckResult {
+    async fn id(&self) -> i64 {
+        self.0.id
+    }
+
+    async fn checked_at(&self) -> DateTime<Utc> {
+        self.0.checked_at
+    }
+
+    async fn status(&self) -> &str {
+        &self.0.status
+    }
+
+    async fn http_status(&self) -> Option<i64> {
+        self.0.http_status
+    }
+
+    async fn latency_ms(&self) -> Option<i64> {
+        self.0.latency_ms
+    }
+
+    async fn response_bytes(&self) -> Option<i64> {
+        self.0.response_bytes
+    }
+
+    async fn protocol(&self) -> Option<&str> {
+        self.0.protocol.as_deref()
+    }
+
+    async fn error_kind(&self) -> Option<&str> {
+        self.0.error_kind.as_deref()
+    }
+
+    async fn error(&self) -> Option<&str> {
+        self.0.error.as_deref()
+    }
+
+    async fn location(&self) -> Option<&str> {
+        self.0.location.as_deref()
+    }
+
+    /// Reported by another monitor, whose source is the `location`.
+    async fn external(&self) -> bool {
+        self.0.external
+    }
+
+    /// The incident that was open when this result was recorded.
+    async fn incident(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<Incident>> {
+        let (state, _) = scope(ctx);
+        let row = sqlx::query_as::<_, IncidentRow>(
+            r#"
+            SELECT * FROM incidents
+            WHERE check_id = ?1 AND started_at <= ?2 AND (resolved_at IS NULL OR resolved_at >= ?2)
+            ORDER BY started_at DESC LIMIT 1
+            "#,
+        )
+        .bind(&self.0.check_id)
+        .bind(self.0.checked_at)
+        .fetch_optional(&state.db)
+        .await?;
+        Ok(row.map(Incident))
+    }
+}
+
+pub struct Incident(IncidentRow);
+
+#[Object]
+impl Incident {
+    async fn id(&self) -> ID {
+        ID(self.0.id.clone())
+    }
+
+    async fn started_at(&self) -> DateTime<Utc> {
+        self.0.started_at
+    }
+
+    async fn resolved_at(&self) -> Option<DateTime<Utc>> {
+        self.0.resolved_at
+    }
+
+    async fn acknowledged_at(&self) -> Option<DateTime<Utc>> {
+        self.0.acknowledged_at
+    }
+
+    async fn failed_probes(&self) -> i64 {
+        self.0.failed_probes
+    }
+
+    async fn last_error(&self) -> Option<&str> {
+        self.0.last_error.as_deref()
+    }
+
+    async fn max_latency_ms(&self) -> Option<i64> {
+        self.0.max_latency_ms
+    }
+
+    async fn severity(&self) -> &str {
+        &self.0.severity
+    }
+
+    async fn check(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<Check>> {
+        let (state, _) = scope(ctx);
+        let row = state.db.check(&self.0.check_id).await?;
+        Ok(row.map(Check))
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct Stats {
+    period: String,
+    samples: i64,
+    up_samples: i64,
+    uptime_percent: Option<f64>,
+    avg_latency_ms: Option<f64>,
+    max_latency_ms: Option<i64>,
+    avg_response_bytes: Option<f64>,
+    planned_samples: i64,
+    timezone: String,
+    daily: Vec<DailyStats>,
+}
+
+#[derive(SimpleObject)]
+pub struct DailyStats {
+    date: NaiveDate,
+    samples: i64,
+    up_samples: i64,
+    uptime_percent: Option<f64>,
+}
+
+impl From<CheckStats> for Stats {
+    fn from(stats: CheckStats) -> Self {
+        Stats {
+            period: stats.period,
+            samples: stats.samples,
+            up_samples: stats.up_samples,
+            uptime_percent: stats.uptime_percent,
+            avg_latency_ms: stats.avg_latency_ms,
+            max_latency_ms: stats.max_latency_ms,
+            avg_response_bytes: stats.avg_response_bytes,
+            planned_samples: stats.planned_samples,
+            timezone: stats.timezone,
+            daily: stats
+                .daily
+                .into_iter()
+                .map(|day| DailyStats {
+                    date: day.date,
+                    samples: day.samples,
+                    up_samples: day.up_samples,
+                    uptime_percent: day.uptime_percent,
+                })
+                .collect(),
+        }
+    }
+}
+
+pub struct SubscriptionRoot;
+
+#[Subscription]
+impl SubscriptionRoot {
+    /// Every status transition of the organization's checks, or only of `checkId`.
+    async fn status_changed(
+        &self,
+        ctx: &Context<'_>,
+        check_id: Option<ID>,
+    ) -> impl Stream<Item = StatusChange> {
+        let (state, caller) = scope(ctx);
+        let org_id = caller.org_id.clone();
+        stream::unfold(state.status_changes.subscribe(), |mut changes| async move {
+            loop {
+                match changes.recv().await {
+                    Ok(change) => return Some((change, changes)),
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })
+        .filter(move |change| {
+            future::ready(
+                change.org_id == org_id
+                    && check_id.as_ref().is_none_or(|id| *id == change.check_id),
+            )
+        })
+    }
+}
@@ -0,0 +1,110 @@
+//! Adapters from the webhook payloads of third-party monitors to probe results, so checks
+//! still watched elsewhere can keep their history here while they are migrated.
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Accepted by the `source` of `POST /ingest/external`.
+pub const SOURCES: &[&str] = &["generic", "pingdom", "uptimerobot"];
+
+pub struct ExternalResult {
+    /// The monitored URL, when the payload carries it.
+    pub url: Option<String>,
+    pub status: &'static str,
+    /// `None` when the payload has no timestamp; the time it arrived is used instead.
+    pub checked_at: Option<DateTime<Utc>>,
+    pub http_status: Option<i64>,
+    pub latency_ms: Option<i64>,
+    pub error: Option<String>,
+}
+
+pub fn parse(source: &str, payload: &Value) -> Result<ExternalResult, String> {
+    match source {
+        "generic" => generic(payload),
+        "pingdom" => pingdom(payload),
+        "uptimerobot" => uptimerobot(payload),
+        _ => Err(format!("source debe ser uno de: {}", SOURCES.join(", "))),
+    }
+}
+
+#[derive(Deserialize)]
+struct Generic {
+    url: Option<String>,
+    status: String,
+    checked_at: Option<DateTime<Utc>>,
+    http_status: Option<i64>,
+    latency_ms: Option<i64>,
+    error: Option<String>,
+}
+
+/// Our own result fields: `status` (`UP` or `DOWN`), `url`, `checked_at`, `http_status`,
+/// `latency_ms` and `error`.
+fn generic(payload: &Value) -> Result<ExternalResult, String> {
+    let r = Generic::deserialize(payload).map_err(|e| format!("payload inválido: {e}"))?;
+    let status = match r.status.as_str() {
+        "UP" => "UP",
+        "DOWN" => "DOWN",
+        _ => return Err("status debe ser UP o DOWN".to_string()),
+    };
+    Ok(ExternalResult {
+        url: r.url,
+        status,
+        checked_at: r.checked_at,
+        http_status: r.http_status,
+        latency_ms: r.latency_ms,
+        error: r.error,
+    })
+}
+
+/// Pingdom's state change webhooks.
+fn pingdom(payload: &Value) -> Result<ExternalResult, String> {
+    let status = match text(payload, "current_state").as_deref() {
+        Some("UP" | "SUCCESS") => "UP",
+        Some("DOWN" | "FAILING") => "DOWN",
+        _ => return Err("current_state inválido".to_string()),
+    };
+    Ok(ExternalResult {
+        url: text(&payload["check_params"], "full_url"),
+        status,
+        checked_at: timestamp(payload, "state_changed_timestamp"),
+        http_status: None,
+        latency_ms: None,
+        error: (status == "DOWN")
+            .then(|| text(payload, "long_description").or_else(|| text(payload, "description")))
+            .flatten(),
+    })
+}
+
+/// UptimeRobot's alert contact webhooks, sent as JSON. Its values may come as strings or
+/// numbers depending on how the contact was set up.
+fn uptimerobot(payload: &Value) -> Result<ExternalResult, String> {
+    let status = match text(payload, "alertType").as_deref() {
+        Some("1") => "DOWN",
+        Some("2") => "UP",
+        _ => return Err("alertType debe ser 1 (down) o 2 (up)".to_string()),
+    };
+    Ok(ExternalResult {
+        url: text(payload, "monitorURL"),
+        status,
+        checked_at: timestamp(payload, "alertDateTime"),
+        http_status: None,
+        latency_ms: None,
+        error: (status == "DOWN")
+            .then(|| text(payload, "alertDetails"))
+            .flatten(),
+    })
+}
+
+fn text(payload: &Value, field: &str) -> Option<String> {
+    match &payload[field] {
+        Value::String(s) if !s.is_empty() => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+/// Unix seconds.
+fn timestamp(payload: &Value, field: &str) -> Option<DateTime<Utc>> {
+    DateTime::from_timestamp(text(payload, field)?.parse().ok()?, 0)
+}
@@ -0,0 +1,206 @@
+//! Uptime monitoring service: the HTTP API, the check worker and the alerting around them.
+
+use axum::Router;
+use std::env;
+use std::{net::SocketAddr, sync::Arc};
+use tokio::sync::{broadcast, mpsc};
+use tracing::info;
+use uuid::Uuid;
+
+mod anomaly;
+pub mod api;
+mod apply;
+mod atom;
+mod billing;
+mod calendar;
+#[cfg(feature = "clickhouse")]
+mod clickhouse;
+mod cron;
+pub mod domain;
+mod events;
+mod grafana;
+mod graphql;
+mod ingest;
+pub mod notify;
+mod oncall;
+mod result_log;
+mod s3;
+pub mod scheduler;
+pub mod store;
+
+use crate::anomaly::anomaly_loop;
+use crate::api::RateLimiter;
+use crate::domain::{DEFAULT_MIN_INTERVAL_SECONDS, PLANS, SELF_HOSTED_PLAN};
+use crate::events::EventPublisher;
+use crate::notify::{
+    alert_digest_loop, notification_outbox_loop, quiet_hours_loop, telegram_bot_loop, AlertBatcher,
+    EmailTokens, Mailer, TelegramConfig,
+};
+use crate::result_log::ResultLog;
+use crate::scheduler::{
+    agent_loop, reload_checks, result_writer_loop, retention_loop, worker_loop,
+    worker_watchdog_loop, CheckCache, Clock, HttpTuning, JobQueue, Metrics, PendingWrite,
+};
+use crate::store::{
+    auth_enabled, backup_loop, clamp_check_intervals, migrate_legacy_client_keys, Backups, Db,
+    SecretCipher,
+};
+
+#[derive(Clone)]
+pub(crate) struct AppState {
+    pub(crate) db: Db,
+    pub(crate) cipher: Option<SecretCipher>,
+    pub(crate) http: reqwest::Client,
+    pub(crate) telegram: Option<TelegramConfig>,
+    pub(crate) instance_id: String,
+    pub(crate) queue: Arc<JobQueue>,
+    pub(crate) metrics: Arc<Metrics>,
+    pub(crate) writer: mpsc::Sender<PendingWrite>,
+    pub(crate) checks: CheckCache,
+    pub(crate) events: Arc<EventPublisher>,
+    pub(crate) alerts: Arc<AlertBatcher>,
+    pub(crate) mailer: Option<Arc<Mailer>>,
+    pub(crate) email_tokens: EmailTokens,
+    pub(crate) stripe: Option<Arc<billing::Stripe>>,
+    pub(crate) rate_limiter: Option<Arc<RateLimiter>>,
+    pub(crate) s3: Option<Arc<s3::S3>>,
+    pub(crate) result_log: Option<Arc<ResultLog>>,
+    /// `RESULTS_ARCHIVE_PREFIX`: results are uploaded under it to S3 before being pruned.
+    pub(crate) archive_prefix: Option<String>,
+    /// `RESULTS_ARCHIVE_BATCH`: most results per archived object, so a pass never holds more.
+    pub(crate) archive_batch: i64,
+    /// `MIN_INTERVAL_SECONDS`: no check is probed more often, whatever its plan allows.
+    pub(crate) min_interval_seconds: i64,
+    /// `METRICS_TOKEN`: the bearer token Prometheus scrapes `/metrics` with.
+    pub(crate) metrics_token: Option<String>,
+    pub(crate) backups: Arc<Backups>,
+    pub(crate) graphql: graphql::ApiSchema,
+    pub(crate) status_changes: broadcast::Sender<graphql::StatusChange>,
+    pub(crate) clock: Clock,
+    #[cfg(feature = "clickhouse")]
+    pub(crate) clickhouse: Option<Arc<clickhouse::ClickHouse>>,
+}
+
+/// Runs the API and the worker, or the agent with `agent` as first argument. `--log-results`
+/// also prints every probe result to stdout as JSON lines.
+pub async fn run() -> anyhow::Result<()> {
+    dotenvy::from_path(".env").ok();
+    dotenvy::from_path("../.env").ok();
+    if env::var("LOG_FORMAT").as_deref() == Ok("json") {
+        tracing_subscriber::fmt().json().init();
+    } else {
+        tracing_subscriber::fmt::init();
+    }
+
+    if env::args().nth(1).as_deref() == Some("agent") {
+        return agent_loop().await;
+    }
+
+    let db = store::connect("sqlite://data/uptime.db").await?;
+    let app = start(db, Clock::default()).await?;
+
+    let addr = env::var("BIND_ADDR").unwrap_or_else(|_| "0.0.0.0:8080".to_string());
+    info!("API running on http://{addr}");
+    let listener = tokio::net::TcpListener::bind(&addr).await?;
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Builds the state from the environment, starts the worker and the background loops, and
+/// returns the API router. `db` must already be migrated, see [`store::connect`].
+pub async fn start(db: Db, clock: Clock) -> anyhow::Result<Router> {
+    let (writer, writes) = mpsc::channel(1024);
+    let http = HttpTuning::from_env("OUTBOUND_HTTP").builder().build()?;
+    #[cfg(feature = "clickhouse")]
+    let clickhouse = match clickhouse::ClickHouse::from_env(http.clone()) {
+        Some(ch) => {
+            ch.ensure_schema().await?;
+            info!("Writing results to ClickHouse");
+            Some(Arc::new(ch))
+        }
+        None => None,
+    };
+    let sellable_plans: Vec<&'static str> = PLANS
+        .iter()
+        .map(|plan| plan.name)
+        .filter(|name| *name != SELF_HOSTED_PLAN)
+        .collect();
+    let stripe = billing::Stripe::from_env(http.clone(), &sellable_plans).map(Arc::new);
+    let s3 = s3::S3::from_env(http.clone())?.map(Arc::new);
+    let archive_prefix = env::var("RESULTS_ARCHIVE_PREFIX").ok();
+    if archive_prefix.is_some() && s3.is_none() {
+        anyhow::bail!("RESULTS_ARCHIVE_PREFIX requires S3_BUCKET");
+    }
+    let state = Arc::new(AppState {
+        db: db.clone(),
+        cipher: SecretCipher::from_env()?,
+        http: http.clone(),
+        telegram: TelegramConfig::from_env(),
+        instance_id: env::var("INSTANCE_ID").unwrap_or_else(|_| Uuid::new_v4().to_string()),
+        queue: Arc::new(JobQueue::new(
+            env::var("QUEUE_CAPACITY")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(1000),
+        )),
+        metrics: Arc::default(),
+        writer,
+        checks: CheckCache::default(),
+        events: Arc::new(EventPublisher::from_env().await?),
+        alerts: Arc::default(),
+        mailer: Mailer::from_env()?.map(Arc::new),
+        email_tokens: EmailTokens::from_env(),
+        stripe,
+        rate_limiter: RateLimiter::from_env().map(Arc::new),
+        s3: s3.clone(),
+        result_log: ResultLog::from_env().await?.map(Arc::new),
+        archive_prefix,
+        archive_batch: env::var("RESULTS_ARCHIVE_BATCH")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|batch| *batch > 0)
+            .unwrap_or(10_000),
+        metrics_token: env::var("METRICS_TOKEN").ok(),
+        min_interval_seconds: env::var("MIN_INTERVAL_SECONDS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_MIN_INTERVAL_SECONDS),
+        backups: Arc::new(Backups::from_env()),
+        graphql: graphql::schema(),
+        status_changes: broadcast::channel(256).0,
+        clock,
+        #[cfg(feature = "clickhouse")]
+        clickhouse,
+    });
+    info!("Worker instance {}", state.instance_id);
+
+    migrate_legacy_client_keys(&state).await?;
+    clamp_check_intervals(&state).await?;
+    if !auth_enabled(&state.db).await? {
+        info!("No API keys yet: the API is open until the first one is created");
+    }
+    reload_checks(&state).await?;
+    tokio::spawn(result_writer_loop(state.clone(), writes));
+
+    // Worker
+    tokio::spawn(worker_loop(state.clone()));
+    tokio::spawn(quiet_hours_loop(state.clone()));
+    tokio::spawn(alert_digest_loop(state.clone()));
+    tokio::spawn(notification_outbox_loop(state.clone()));
+    tokio::spawn(retention_loop(state.clone()));
+    tokio::spawn(anomaly_loop(state.clone()));
+    tokio::spawn(worker_watchdog_loop(state.clone()));
+    if let Some(hours) = state.backups.interval_hours {
+        tokio::spawn(backup_loop(state.clone(), hours));
+    }
+    if state.telegram.is_some() {
+        tokio::spawn(telegram_bot_loop(state.clone()));
+    }
+
+    api::router(state)
+}
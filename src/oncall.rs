@@ -0,0 +1,654 @@
+//! On-call schedules: members take turns of a week from `rotation_start`, and overrides hand
+//! part of a turn to someone else. `oncall` channels page whoever is on call on that person's
+//! own channels.
+
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Sqlite, SqliteConnection};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::api::{internal_error, Admin, Caller, Viewer};
+use crate::domain::{ChannelRow, UserRow};
+use crate::notify::{deliver_to, send_alert_email};
+use crate::store::{find_member, record_audit, snapshot, AuditEntry, Db};
+use crate::AppState;
+
+pub(crate) const SHIFT_DAYS: i64 = 7;
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub(crate) struct ScheduleRow {
+    pub(crate) id: String,
+    pub(crate) org_id: String,
+    pub(crate) name: String,
+    /// Start of the first member's turn; the next ones hand over every [`SHIFT_DAYS`].
+    pub(crate) rotation_start: DateTime<Utc>,
+    pub(crate) created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub(crate) struct OverrideRow {
+    pub(crate) id: String,
+    pub(crate) schedule_id: String,
+    pub(crate) user_id: String,
+    pub(crate) starts_at: DateTime<Utc>,
+    pub(crate) ends_at: DateTime<Utc>,
+    pub(crate) created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct Schedule {
+    #[serde(flatten)]
+    pub(crate) schedule: ScheduleRow,
+    /// In rotation order.
+    pub(crate) user_ids: Vec<String>,
+    /// The ones that haven't ended yet.
+    pub(crate) overrides: Vec<OverrideRow>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct ScheduleRequest {
+    pub(crate) name: String,
+    pub(crate) rotation_start: DateTime<Utc>,
+    pub(crate) user_ids: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct OverrideRequest {
+    pub(crate) user_id: String,
+    pub(crate) starts_at: DateTime<Utc>,
+    pub(crate) ends_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct OnCall {
+    pub(crate) schedule_id: String,
+    pub(crate) schedule_name: String,
+    /// `None` when the schedule has no members left.
+    pub(crate) user: Option<UserRow>,
+    /// End of the turn, or of the override that put them on call.
+    pub(crate) until: Option<DateTime<Utc>>,
+    pub(crate) override_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct UserChannelsRequest {
+    pub(crate) channel_ids: Vec<String>,
+}
+
+/// The member whose turn covers `at`, and when it ends.
+pub(crate) fn turn(
+    rotation_start: DateTime<Utc>,
+    members: usize,
+    at: DateTime<Utc>,
+) -> (usize, DateTime<Utc>) {
+    let shift = Duration::days(SHIFT_DAYS).num_seconds();
+    let turns = (at - rotation_start).num_seconds().div_euclid(shift);
+    (
+        turns.rem_euclid(members as i64) as usize,
+        rotation_start + Duration::seconds((turns + 1) * shift),
+    )
+}
+
+/// The latest override covering `at` wins over the rotation.
+pub(crate) async fn on_call(
+    db: &Db,
+    schedule: &ScheduleRow,
+    at: DateTime<Utc>,
+) -> Result<OnCall, sqlx::Error> {
+    let overriding = sqlx::query_as::<_, OverrideRow>(
+        "SELECT * FROM oncall_overrides WHERE schedule_id = ? AND starts_at <= ? AND ends_at > ? ORDER BY created_at DESC LIMIT 1",
+    )
+    .bind(&schedule.id)
+    .bind(at)
+    .bind(at)
+    .fetch_optional(db)
+    .await?;
+    let (user_id, until) = match &overriding {
+        Some(o) => (Some(o.user_id.clone()), Some(o.ends_at)),
+        None => {
+            let members = schedule_members(db, &schedule.id).await?;
+            if members.is_empty() {
+                (None, None)
+            } else {
+                let (index, until) = turn(schedule.rotation_start, members.len(), at);
+                (Some(members[index].clone()), Some(until))
+            }
+        }
+    };
+    let user = match user_id {
+        Some(user_id) => {
+            sqlx::query_as::<_, UserRow>(
+                r#"
+                SELECT users.id, users.email, users.name, memberships.role, users.created_at
+                FROM users JOIN memberships ON memberships.user_id = users.id
+                WHERE memberships.org_id = ? AND users.id = ?
+                "#,
+            )
+            .bind(&schedule.org_id)
+            .bind(user_id)
+            .fetch_optional(db)
+            .await?
+        }
+        None => None,
+    };
+
+    Ok(OnCall {
+        schedule_id: schedule.id.clone(),
+        schedule_name: schedule.name.clone(),
+        user,
+        until,
+        override_id: overriding.map(|o| o.id),
+    })
+}
+
+/// Delivers to the channels of whoever is on call on the `oncall` channel's schedule, or to
+/// their email when they have none.
+pub(crate) async fn page(
+    state: &AppState,
+    channel: &ChannelRow,
+    text: &str,
+    body: &serde_json::Value,
+) -> Result<(), String> {
+    let schedule = sqlx::query_as::<_, ScheduleRow>(
+        "SELECT * FROM oncall_schedules WHERE id = ? AND org_id = ?",
+    )
+    .bind(&channel.target)
+    .bind(&channel.org_id)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|e| e.to_string())?
+    .ok_or("on-call schedule not found")?;
+    let on_call = on_call(&state.db, &schedule, state.clock.now())
+        .await
+        .map_err(|e| e.to_string())?;
+    let user = on_call
+        .user
+        .ok_or_else(|| format!("nobody is on call for {}", schedule.name))?;
+
+    let channels = sqlx::query_as::<_, ChannelRow>(
+        r#"
+        SELECT * FROM notification_channels
+        WHERE org_id = ? AND kind != 'oncall'
+          AND id IN (SELECT channel_id FROM user_channels WHERE user_id = ?)
+        "#,
+    )
+    .bind(&channel.org_id)
+    .bind(&user.id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(|e| e.to_string())?;
+    if channels.is_empty() {
+        return send_alert_email(state, &channel.org_id, &user.email, text)
+            .await
+            .map_err(|e| format!("{} has no channels and can't be emailed: {e}", user.name));
+    }
+    let mut failed = Vec::new();
+    for own in &channels {
+        if let Err(e) = deliver_to(state, own, text, body).await {
+            failed.push(format!("{}: {e}", own.name));
+        }
+    }
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(failed.join("; "))
+    }
+}
+
+pub(crate) async fn schedule_members<'e, E>(
+    executor: E,
+    schedule_id: &str,
+) -> Result<Vec<String>, sqlx::Error>
+where
+    E: sqlx::Executor<'e, Database = Sqlite>,
+{
+    sqlx::query_scalar("SELECT user_id FROM oncall_members WHERE schedule_id = ? ORDER BY position")
+        .bind(schedule_id)
+        .fetch_all(executor)
+        .await
+}
+
+async fn find_schedule(
+    state: &AppState,
+    caller: &Caller,
+    id: &str,
+) -> Result<ScheduleRow, (StatusCode, String)> {
+    sqlx::query_as::<_, ScheduleRow>("SELECT * FROM oncall_schedules WHERE id = ? AND org_id = ?")
+        .bind(id)
+        .bind(&caller.org_id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(internal_error)?
+        .ok_or((StatusCode::NOT_FOUND, "horario no encontrado".to_string()))
+}
+
+async fn schedule_view(
+    state: &AppState,
+    schedule: ScheduleRow,
+) -> Result<Schedule, (StatusCode, String)> {
+    let user_ids = schedule_members(&state.db, &schedule.id)
+        .await
+        .map_err(internal_error)?;
+    let overrides = sqlx::query_as::<_, OverrideRow>(
+        "SELECT * FROM oncall_overrides WHERE schedule_id = ? AND ends_at > ? ORDER BY starts_at",
+    )
+    .bind(&schedule.id)
+    .bind(state.clock.now())
+    .fetch_all(&state.db)
+    .await
+    .map_err(internal_error)?;
+    Ok(Schedule {
+        schedule,
+        user_ids,
+        overrides,
+    })
+}
+
+async fn validate_schedule(
+    state: &AppState,
+    caller: &Caller,
+    payload: &ScheduleRequest,
+) -> Result<(), (StatusCode, String)> {
+    if payload.name.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "name requerido".to_string()));
+    }
+    if payload.user_ids.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "user_ids requerido".to_string()));
+    }
+    for user_id in &payload.user_ids {
+        require_member(state, caller, user_id).await?;
+    }
+    Ok(())
+}
+
+async fn require_member(
+    state: &AppState,
+    caller: &Caller,
+    user_id: &str,
+) -> Result<(), (StatusCode, String)> {
+    if find_member(state, &caller.org_id, user_id).await?.is_none() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("usuario {user_id} no es miembro de la organización"),
+        ));
+    }
+    Ok(())
+}
+
+async fn replace_members(
+    conn: &mut SqliteConnection,
+    schedule_id: &str,
+    user_ids: &[String],
+) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM oncall_members WHERE schedule_id = ?")
+        .bind(schedule_id)
+        .execute(&mut *conn)
+        .await?;
+    for (position, user_id) in user_ids.iter().enumerate() {
+        sqlx::query("INSERT INTO oncall_members (schedule_id, position, user_id) VALUES (?, ?, ?)")
+            .bind(schedule_id)
+            .bind(position as i64)
+            .bind(user_id)
+            .execute(&mut *conn)
+            .await?;
+    }
+    Ok(())
+}
+
+pub(crate) async fn list_schedules(
+    State(state): State<Arc<AppState>>,
+    Viewer(caller): Viewer,
+) -> Result<Json<Vec<Schedule>>, (StatusCode, String)> {
+    let rows = sqlx::query_as::<_, ScheduleRow>(
+        "SELECT * FROM oncall_schedules WHERE org_id = ? ORDER BY name",
+    )
+    .bind(&caller.org_id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(internal_error)?;
+    let mut schedules = Vec::with_capacity(rows.len());
+    for row in rows {
+        schedules.push(schedule_view(&state, row).await?);
+    }
+
+    Ok(Json(schedules))
+}
+
+pub(crate) async fn create_schedule(
+    State(state): State<Arc<AppState>>,
+    Admin(caller): Admin,
+    Json(payload): Json<ScheduleRequest>,
+) -> Result<(StatusCode, Json<Schedule>), (StatusCode, String)> {
+    validate_schedule(&state, &caller, &payload).await?;
+
+    let mut tx = state.db.begin().await.map_err(internal_error)?;
+    let schedule = sqlx::query_as::<_, ScheduleRow>(
+        "INSERT INTO oncall_schedules (id, org_id, name, rotation_start, created_at) VALUES (?, ?, ?, ?, ?) RETURNING *",
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(&caller.org_id)
+    .bind(payload.name.trim())
+    .bind(payload.rotation_start)
+    .bind(Utc::now())
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(internal_error)?;
+    replace_members(&mut tx, &schedule.id, &payload.user_ids)
+        .await
+        .map_err(internal_error)?;
+    record_audit(
+        &mut *tx,
+        AuditEntry {
+            after: snapshot(&payload),
+            ..caller.audit("create", "oncall_schedule", &schedule.id)
+        },
+    )
+    .await
+    .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(schedule_view(&state, schedule).await?),
+    ))
+}
+
+/// Replaces the name, the start of the rotation and its members.
+pub(crate) async fn update_schedule(
+    State(state): State<Arc<AppState>>,
+    Admin(caller): Admin,
+    Path(id): Path<String>,
+    Json(payload): Json<ScheduleRequest>,
+) -> Result<Json<Schedule>, (StatusCode, String)> {
+    let before = schedule_view(&state, find_schedule(&state, &caller, &id).await?).await?;
+    validate_schedule(&state, &caller, &payload).await?;
+
+    let mut tx = state.db.begin().await.map_err(internal_error)?;
+    let schedule = sqlx::query_as::<_, ScheduleRow>(
+        "UPDATE oncall_schedules SET name = ?, rotation_start = ? WHERE id = ? RETURNING *",
+    )
+    .bind(payload.name.trim())
+    .bind(payload.rotation_start)
+    .bind(&id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(internal_error)?;
+    replace_members(&mut tx, &id, &payload.user_ids)
+        .await
+        .map_err(internal_error)?;
+    record_audit(
+        &mut *tx,
+        AuditEntry {
+            before: snapshot(&before),
+            after: snapshot(&payload),
+            ..caller.audit("update", "oncall_schedule", &id)
+        },
+    )
+    .await
+    .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+
+    Ok(Json(schedule_view(&state, schedule).await?))
+}
+
+/// Refused while an `oncall` channel pages through the schedule.
+pub(crate) async fn delete_schedule(
+    State(state): State<Arc<AppState>>,
+    Admin(caller): Admin,
+    Path(id): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let schedule = schedule_view(&state, find_schedule(&state, &caller, &id).await?).await?;
+    let channel: Option<String> = sqlx::query_scalar(
+        "SELECT name FROM notification_channels WHERE kind = 'oncall' AND target = ? AND org_id = ?",
+    )
+    .bind(&id)
+    .bind(&caller.org_id)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(internal_error)?;
+    if let Some(channel) = channel {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("el canal {channel} usa este horario"),
+        ));
+    }
+
+    let mut tx = state.db.begin().await.map_err(internal_error)?;
+    for table in ["oncall_members", "oncall_overrides"] {
+        sqlx::query(&format!("DELETE FROM {table} WHERE schedule_id = ?"))
+            .bind(&id)
+            .execute(&mut *tx)
+            .await
+            .map_err(internal_error)?;
+    }
+    sqlx::query("DELETE FROM oncall_schedules WHERE id = ?")
+        .bind(&id)
+        .execute(&mut *tx)
+        .await
+        .map_err(internal_error)?;
+    record_audit(
+        &mut *tx,
+        AuditEntry {
+            before: snapshot(&schedule),
+            ..caller.audit("delete", "oncall_schedule", &id)
+        },
+    )
+    .await
+    .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Puts someone else on call for a while, e.g. to swap part of a turn.
+pub(crate) async fn create_override(
+    State(state): State<Arc<AppState>>,
+    Admin(caller): Admin,
+    Path(id): Path<String>,
+    Json(payload): Json<OverrideRequest>,
+) -> Result<(StatusCode, Json<OverrideRow>), (StatusCode, String)> {
+    find_schedule(&state, &caller, &id).await?;
+    if payload.ends_at <= payload.starts_at {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "ends_at debe ser posterior a starts_at".to_string(),
+        ));
+    }
+    require_member(&state, &caller, &payload.user_id).await?;
+
+    let row = sqlx::query_as::<_, OverrideRow>(
+        "INSERT INTO oncall_overrides (id, schedule_id, user_id, starts_at, ends_at, created_at) VALUES (?, ?, ?, ?, ?, ?) RETURNING *",
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(&id)
+    .bind(&payload.user_id)
+    .bind(payload.starts_at)
+    .bind(payload.ends_at)
+    .bind(Utc::now())
+    .fetch_one(&state.db)
+    .await
+    .map_err(internal_error)?;
+    record_audit(
+        &state.db,
+        AuditEntry {
+            after: snapshot(&row),
+            ..caller.audit("create", "oncall_override", &row.id)
+        },
+    )
+    .await
+    .map_err(internal_error)?;
+
+    Ok((StatusCode::CREATED, Json(row)))
+}
+
+pub(crate) async fn delete_override(
+    State(state): State<Arc<AppState>>,
+    Admin(caller): Admin,
+    Path((id, override_id)): Path<(String, String)>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    find_schedule(&state, &caller, &id).await?;
+    let row = sqlx::query_as::<_, OverrideRow>(
+        "DELETE FROM oncall_overrides WHERE id = ? AND schedule_id = ? RETURNING *",
+    )
+    .bind(&override_id)
+    .bind(&id)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(internal_error)?
+    .ok_or((StatusCode::NOT_FOUND, "override no encontrado".to_string()))?;
+    record_audit(
+        &state.db,
+        AuditEntry {
+            before: snapshot(&row),
+            ..caller.audit("delete", "oncall_override", &override_id)
+        },
+    )
+    .await
+    .map_err(internal_error)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Who each schedule of the organization pages right now.
+pub(crate) async fn oncall_now(
+    State(state): State<Arc<AppState>>,
+    Viewer(caller): Viewer,
+) -> Result<Json<Vec<OnCall>>, (StatusCode, String)> {
+    let schedules = sqlx::query_as::<_, ScheduleRow>(
+        "SELECT * FROM oncall_schedules WHERE org_id = ? ORDER BY name",
+    )
+    .bind(&caller.org_id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(internal_error)?;
+    let now = state.clock.now();
+    let mut on_calls = Vec::with_capacity(schedules.len());
+    for schedule in &schedules {
+        on_calls.push(
+            on_call(&state.db, schedule, now)
+                .await
+                .map_err(internal_error)?,
+        );
+    }
+
+    Ok(Json(on_calls))
+}
+
+pub(crate) async fn get_user_channels(
+    State(state): State<Arc<AppState>>,
+    Admin(caller): Admin,
+    Path(id): Path<String>,
+) -> Result<Json<UserChannelsRequest>, (StatusCode, String)> {
+    find_member(&state, &caller.org_id, &id)
+        .await?
+        .ok_or((StatusCode::NOT_FOUND, "usuario no encontrado".to_string()))?;
+    let channel_ids = sqlx::query_scalar(
+        r#"
+        SELECT channel_id FROM user_channels
+        WHERE user_id = ? AND channel_id IN (SELECT id FROM notification_channels WHERE org_id = ?)
+        ORDER BY channel_id
+        "#,
+    )
+    .bind(&id)
+    .bind(&caller.org_id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(internal_error)?;
+
+    Ok(Json(UserChannelsRequest { channel_ids }))
+}
+
+/// The channels that page the user while on call, in place of their email.
+pub(crate) async fn set_user_channels(
+    State(state): State<Arc<AppState>>,
+    Admin(caller): Admin,
+    Path(id): Path<String>,
+    Json(mut payload): Json<UserChannelsRequest>,
+) -> Result<Json<UserChannelsRequest>, (StatusCode, String)> {
+    find_member(&state, &caller.org_id, &id)
+        .await?
+        .ok_or((StatusCode::NOT_FOUND, "usuario no encontrado".to_string()))?;
+    payload.channel_ids.sort();
+    payload.channel_ids.dedup();
+    let mut tx = state.db.begin().await.map_err(internal_error)?;
+
+    sqlx::query(
+        "DELETE FROM user_channels WHERE user_id = ? AND channel_id IN (SELECT id FROM notification_channels WHERE org_id = ?)",
+    )
+    .bind(&id)
+    .bind(&caller.org_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(internal_error)?;
+    for channel_id in &payload.channel_ids {
+        let kind: Option<String> = sqlx::query_scalar(
+            "SELECT kind FROM notification_channels WHERE id = ? AND org_id = ?",
+        )
+        .bind(channel_id)
+        .bind(&caller.org_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(internal_error)?;
+        match kind.as_deref() {
+            None => {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    format!("canal {channel_id} no existe"),
+                ))
+            }
+            Some("oncall") => {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    format!("canal {channel_id} es de guardia"),
+                ))
+            }
+            Some(_) => {}
+        }
+
+        sqlx::query("INSERT INTO user_channels (user_id, channel_id) VALUES (?, ?)")
+            .bind(&id)
+            .bind(channel_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(internal_error)?;
+    }
+    record_audit(
+        &mut *tx,
+        AuditEntry {
+            after: snapshot(&payload),
+            ..caller.audit("update", "user_channels", &id)
+        },
+    )
+    .await
+    .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+
+    Ok(Json(payload))
+}
+
+/// Takes a user who leaves the organization off its schedules and its channels.
+pub(crate) async fn remove_member(
+    conn: &mut SqliteConnection,
+    org_id: &str,
+    user_id: &str,
+) -> Result<(), sqlx::Error> {
+    for table in ["oncall_members", "oncall_overrides"] {
+        sqlx::query(&format!(
+            "DELETE FROM {table} WHERE user_id = ? AND schedule_id IN (SELECT id FROM oncall_schedules WHERE org_id = ?)"
+        ))
+        .bind(user_id)
+        .bind(org_id)
+        .execute(&mut *conn)
+        .await?;
+    }
+    sqlx::query(
+        "DELETE FROM user_channels WHERE user_id = ? AND channel_id IN (SELECT id FROM notification_channels WHERE org_id = ?)",
+    )
+    .bind(user_id)
+    .bind(org_id)
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
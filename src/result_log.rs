@@ -0,0 +1,64 @@
+//! Optional JSON lines log of every probe result, written before the database so it can be
+//! piped into other tools or replayed when a write fails.
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::env;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::Mutex;
+
+pub(crate) struct ResultLog {
+    sink: Mutex<Box<dyn AsyncWrite + Send + Unpin>>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct LoggedResult<'a> {
+    pub(crate) check_id: &'a str,
+    pub(crate) checked_at: DateTime<Utc>,
+    pub(crate) status: &'a str,
+    pub(crate) http_status: Option<i64>,
+    pub(crate) latency_ms: Option<i64>,
+    pub(crate) error: Option<&'a str>,
+    pub(crate) error_kind: Option<&'a str>,
+    pub(crate) response_bytes: Option<i64>,
+    /// The agent region or external source; `None` for the local worker.
+    pub(crate) location: Option<&'a str>,
+}
+
+impl ResultLog {
+    /// Enabled by `RESULT_LOG_PATH`, appended to, or `-` for stdout. `--log-results` is the
+    /// same as `RESULT_LOG_PATH=-`.
+    pub(crate) async fn from_env() -> anyhow::Result<Option<Self>> {
+        let path = match env::var("RESULT_LOG_PATH") {
+            Ok(path) => path,
+            Err(_) if env::args().any(|arg| arg == "--log-results") => "-".to_string(),
+            Err(_) => return Ok(None),
+        };
+        let sink: Box<dyn AsyncWrite + Send + Unpin> = if path == "-" {
+            Box::new(tokio::io::stdout())
+        } else {
+            let file = tokio::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .await
+                .with_context(|| format!("opening RESULT_LOG_PATH {path}"))?;
+            Box::new(file)
+        };
+        Ok(Some(ResultLog {
+            sink: Mutex::new(sink),
+        }))
+    }
+
+    pub(crate) async fn write(&self, results: &[LoggedResult<'_>]) -> std::io::Result<()> {
+        let mut lines = Vec::new();
+        for result in results {
+            serde_json::to_writer(&mut lines, result)?;
+            lines.push(b'\n');
+        }
+        let mut sink = self.sink.lock().await;
+        sink.write_all(&lines).await?;
+        sink.flush().await
+    }
+}
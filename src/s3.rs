@@ -0,0 +1,123 @@
+//! Minimal S3-compatible object storage client: path-style `PUT`s signed with AWS
+//! Signature Version 4, which works with AWS, MinIO, R2 and similar services.
+
+use anyhow::{bail, Context};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::env;
+
+pub struct S3 {
+    http: reqwest::Client,
+    endpoint: reqwest::Url,
+    bucket: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+}
+
+impl S3 {
+    /// Enabled by `S3_BUCKET`, `S3_ACCESS_KEY_ID` and `S3_SECRET_ACCESS_KEY`. `S3_ENDPOINT`
+    /// defaults to AWS in `S3_REGION` (`us-east-1`).
+    pub fn from_env(http: reqwest::Client) -> anyhow::Result<Option<Self>> {
+        let Ok(bucket) = env::var("S3_BUCKET") else {
+            return Ok(None);
+        };
+        let region = env::var("S3_REGION").unwrap_or_else(|_| "us-east-1".to_string());
+        let endpoint = env::var("S3_ENDPOINT")
+            .unwrap_or_else(|_| format!("https://s3.{region}.amazonaws.com"));
+        Ok(Some(S3 {
+            http,
+            endpoint: endpoint.parse().context("invalid S3_ENDPOINT")?,
+            bucket,
+            region,
+            access_key: env::var("S3_ACCESS_KEY_ID").context("S3_ACCESS_KEY_ID is not set")?,
+            secret_key: env::var("S3_SECRET_ACCESS_KEY")
+                .context("S3_SECRET_ACCESS_KEY is not set")?,
+        }))
+    }
+
+    pub async fn put_object(
+        &self,
+        key: &str,
+        body: Vec<u8>,
+        content_type: &str,
+    ) -> anyhow::Result<()> {
+        let path = format!(
+            "{}/{}/{}",
+            self.endpoint.path().trim_end_matches('/'),
+            uri_encode(&self.bucket),
+            key.split('/').map(uri_encode).collect::<Vec<_>>().join("/")
+        );
+        let host = match self.endpoint.port() {
+            Some(port) => format!("{}:{port}", self.endpoint.host_str().unwrap_or_default()),
+            None => self.endpoint.host_str().unwrap_or_default().to_string(),
+        };
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let payload_hash = hex(&Sha256::digest(&body));
+
+        let signed_headers = "content-type;host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "PUT\n{path}\n\ncontent-type:{content_type}\nhost:{host}\nx-amz-content-sha256:{payload_hash}\nx-amz-date:{amz_date}\n\n{signed_headers}\n{payload_hash}"
+        );
+        let scope = format!("{date}/{}/s3/aws4_request", self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+            hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+        let mut key_bytes = hmac(format!("AWS4{}", self.secret_key).as_bytes(), &date);
+        for part in [self.region.as_str(), "s3", "aws4_request"] {
+            key_bytes = hmac(&key_bytes, part);
+        }
+        let signature = hex(&hmac(&key_bytes, &string_to_sign));
+
+        let mut url = self.endpoint.clone();
+        url.set_path(&path);
+        let resp = self
+            .http
+            .put(url)
+            .header("content-type", content_type)
+            .header("x-amz-content-sha256", &payload_hash)
+            .header("x-amz-date", &amz_date)
+            .header(
+                "authorization",
+                format!(
+                    "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+                    self.access_key
+                ),
+            )
+            .body(body)
+            .send()
+            .await?;
+        let status = resp.status();
+        if !status.is_success() {
+            bail!("S3 returned {status}: {}", resp.text().await?);
+        }
+        Ok(())
+    }
+}
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Percent-encodes everything but the unreserved characters, as SigV4 expects.
+fn uri_encode(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
@@ -0,0 +1,1435 @@
+//! Worker loop, job queue, probes, result writes and retention.
+
+use chrono::{DateTime, Utc};
+use flate2::{write::GzEncoder, Compression};
+use hickory_resolver::config::{
+    LookupIpStrategy, NameServerConfigGroup, ResolverConfig, ResolverOpts,
+};
+use hickory_resolver::system_conf::read_system_conf;
+use hickory_resolver::TokioAsyncResolver;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use std::env;
+use std::io::Write;
+use std::{
+    collections::{BTreeMap, BinaryHeap, HashMap, HashSet},
+    net::{IpAddr, SocketAddr},
+    ops::RangeInclusive,
+    sync::{
+        atomic::{AtomicI64, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::{mpsc, oneshot, watch, Notify};
+use tokio::time::sleep;
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::api::{worker_probe, IDEMPOTENCY_TTL_HOURS};
+#[cfg(feature = "clickhouse")]
+use crate::clickhouse;
+use crate::domain::{
+    content_hash, due_at, expected_protocol, failure_cause, http_error_kind, initial_run_at,
+    is_accepted_status, probe_headers, schedule_next, should_persist, should_run_check,
+    status_transition, AgentAssignment, AgentResult, AgentResultsRequest, Alert, CheckRow,
+    IncidentRow, Plan, ProbeDefaults, ResultRow, CHECK_TYPE_CONTENT_CHANGE,
+};
+use crate::events::Event;
+use crate::notify::{notify_status_change, send_alert};
+use crate::result_log::LoggedResult;
+use crate::s3;
+use crate::store::{flush_writes, in_maintenance, load_secret, probe_defaults, CheckStore, Db};
+use crate::AppState;
+
+pub(crate) const RETENTION_INTERVAL_SECONDS: u64 = 3600;
+
+/// Deletes the results and rollups older than the retention of their organization's plan.
+pub(crate) async fn retention_loop(state: Arc<AppState>) {
+    loop {
+        if let Err(e) = prune_history(&state).await {
+            error!("Error pruning check history: {e}");
+        }
+        sleep(Duration::from_secs(RETENTION_INTERVAL_SECONDS)).await;
+    }
+}
+
+pub(crate) async fn prune_history(state: &AppState) -> anyhow::Result<()> {
+    sqlx::query("DELETE FROM idempotency_keys WHERE created_at < ?")
+        .bind(Utc::now() - chrono::Duration::hours(IDEMPOTENCY_TTL_HOURS))
+        .execute(&state.db)
+        .await?;
+    sqlx::query("DELETE FROM notifications_sent WHERE sent_at < ?")
+        .bind(Utc::now() - chrono::Duration::days(NOTIFICATION_LOG_DAYS))
+        .execute(&state.db)
+        .await?;
+    sqlx::query("DELETE FROM notification_outbox WHERE status = 'sent' AND sent_at < ?")
+        .bind(Utc::now() - chrono::Duration::days(NOTIFICATION_LOG_DAYS))
+        .execute(&state.db)
+        .await?;
+    sqlx::query("DELETE FROM notification_outbox WHERE status = 'dropped' AND created_at < ?")
+        .bind(Utc::now() - chrono::Duration::days(NOTIFICATION_LOG_DAYS))
+        .execute(&state.db)
+        .await?;
+
+    let orgs: Vec<(String, String)> = sqlx::query_as("SELECT id, plan FROM orgs")
+        .fetch_all(&state.db)
+        .await?;
+
+    for (org_id, plan) in orgs {
+        let Some(days) = Plan::named(&plan).retention_days else {
+            continue;
+        };
+        let cutoff = Utc::now() - chrono::Duration::days(days);
+        let pruned = match (&state.s3, &state.archive_prefix) {
+            (Some(s3), Some(prefix)) => {
+                // Only what made it into the archive is deleted, batch by batch. Results written
+                // during the pass wait for the next one.
+                let (last_id,): (Option<i64>,) = sqlx::query_as(
+                    "SELECT MAX(id) FROM check_results WHERE checked_at_ms < ? AND check_id IN (SELECT id FROM checks WHERE org_id = ?)",
+                )
+                .bind(cutoff.timestamp_millis())
+                .bind(&org_id)
+                .fetch_one(&state.db)
+                .await?;
+                let mut pruned = 0;
+                let mut from_id = 0;
+                while let Some(until_id) = archive_results(
+                    state,
+                    s3,
+                    prefix,
+                    &org_id,
+                    cutoff,
+                    from_id..=last_id.unwrap_or(0),
+                )
+                .await?
+                {
+                    pruned += prune_results(state, &org_id, cutoff, until_id).await?;
+                    from_id = until_id + 1;
+                }
+                pruned
+            }
+            _ => prune_results(state, &org_id, cutoff, i64::MAX).await?,
+        };
+        sqlx::query(
+            "DELETE FROM check_rollup_errors WHERE bucket_start < ? AND check_id IN (SELECT id FROM checks WHERE org_id = ?)",
+        )
+        .bind(cutoff)
+        .bind(&org_id)
+        .execute(&state.db)
+        .await?;
+        let rollups = sqlx::query(
+            "DELETE FROM check_rollups WHERE bucket_start < ? AND check_id IN (SELECT id FROM checks WHERE org_id = ?)",
+        )
+        .bind(cutoff)
+        .bind(&org_id)
+        .execute(&state.db)
+        .await?;
+        if pruned + rollups.rows_affected() > 0 {
+            info!(
+                "Pruned {pruned} results and {} rollups older than {days} days from org {org_id}",
+                rollups.rows_affected()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Deletes the organization's results older than `cutoff` up to `until_id`.
+pub(crate) async fn prune_results(
+    state: &AppState,
+    org_id: &str,
+    cutoff: DateTime<Utc>,
+    until_id: i64,
+) -> Result<u64, sqlx::Error> {
+    let results = sqlx::query(
+        "DELETE FROM check_results WHERE checked_at_ms < ? AND id <= ? AND check_id IN (SELECT id FROM checks WHERE org_id = ?)",
+    )
+    .bind(cutoff.timestamp_millis())
+    .bind(until_id)
+    .bind(org_id)
+    .execute(&state.db)
+    .await?;
+    Ok(results.rows_affected())
+}
+
+/// Uploads the first `archive_batch` of the organization's results older than `cutoff` within
+/// `ids` as one gzipped JSON lines object and returns the highest archived id, or `None` when
+/// there was nothing left to archive.
+pub(crate) async fn archive_results(
+    state: &AppState,
+    s3: &s3::S3,
+    prefix: &str,
+    org_id: &str,
+    cutoff: DateTime<Utc>,
+    ids: RangeInclusive<i64>,
+) -> anyhow::Result<Option<i64>> {
+    let rows = sqlx::query_as::<_, ResultRow>(
+        "SELECT * FROM check_results WHERE checked_at_ms < ? AND id BETWEEN ? AND ? AND check_id IN (SELECT id FROM checks WHERE org_id = ?) ORDER BY id LIMIT ?",
+    )
+    .bind(cutoff.timestamp_millis())
+    .bind(ids.start())
+    .bind(ids.end())
+    .bind(org_id)
+    .bind(state.archive_batch)
+    .fetch_all(&state.db)
+    .await?;
+    let Some(last) = rows.last() else {
+        return Ok(None);
+    };
+
+    let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+    for row in &rows {
+        serde_json::to_writer(&mut gz, row)?;
+        gz.write_all(b"\n")?;
+    }
+    let key = format!(
+        "{prefix}{org_id}/results-{}-{}.jsonl.gz",
+        rows[0].id, last.id
+    );
+    s3.put_object(&key, gz.finish()?, "application/gzip")
+        .await?;
+    info!("Archived {} results of org {org_id} to {key}", rows.len());
+    Ok(Some(last.id))
+}
+
+/// Agent mode: pulls assignments from `AGENT_SERVER_URL` with `AGENT_TOKEN`, probes them
+/// locally and reports results back. No database is opened in this mode.
+pub(crate) async fn agent_loop() -> anyhow::Result<()> {
+    let server = env::var("AGENT_SERVER_URL")?;
+    let server = server.trim_end_matches('/');
+    let token = env::var("AGENT_TOKEN")?;
+
+    let client = HttpTuning::from_env("OUTBOUND_HTTP").builder().build()?;
+    let mut probe_clients = ProbeClients::new(HttpTuning::from_env("PROBE_HTTP"));
+    let mut assignments: Vec<AgentAssignment> = Vec::new();
+    let mut last_refresh: Option<Instant> = None;
+    let mut next_run: HashMap<String, DateTime<Utc>> = HashMap::new();
+    let mut down_probes: HashMap<String, i64> = HashMap::new();
+
+    info!("Agent polling {server}");
+
+    loop {
+        if last_refresh.is_none_or(|t| t.elapsed() >= Duration::from_secs(30)) {
+            match client
+                .get(format!("{server}/agent/assignments"))
+                .bearer_auth(&token)
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+            {
+                Ok(resp) => match resp.json::<Vec<AgentAssignment>>().await {
+                    Ok(list) => {
+                        assignments = list;
+                        last_refresh = Some(Instant::now());
+                    }
+                    Err(e) => error!("Invalid assignments payload: {e}"),
+                },
+                Err(e) => error!("Error fetching assignments: {e}"),
+            }
+        }
+
+        let now = Utc::now();
+        let mut results = Vec::new();
+
+        for a in &mut assignments {
+            let due = *next_run.entry(a.check.id.clone()).or_insert_with(|| {
+                initial_run_at(a.check.interval_seconds, a.check.cron().as_ref(), now)
+            });
+            if now < due {
+                continue;
+            }
+
+            let probe = match probe_clients.get(&a.check, a.client_key.as_deref()) {
+                Ok(probe_client) => {
+                    probe_check(
+                        &probe_client,
+                        &a.check,
+                        a.auth_header.as_deref(),
+                        &a.headers,
+                    )
+                    .await
+                }
+                Err(err) => ProbeOutcome::failed("config", None, None, err),
+            };
+            let checked_at = Utc::now();
+            let down = down_probes.entry(a.check.id.clone()).or_default();
+            *down = if probe.status == "DOWN" { *down + 1 } else { 0 };
+            next_run.insert(
+                a.check.id.clone(),
+                schedule_next(&a.check, checked_at, *down),
+            );
+
+            results.push(AgentResult {
+                check_id: a.check.id.clone(),
+                checked_at,
+                status: probe.status,
+                http_status: probe.http_status,
+                latency_ms: probe.latency_ms,
+                error: probe.error,
+                content_hash: probe.content_hash,
+                response_bytes: probe.response_bytes,
+                protocol: probe.protocol.map(String::from),
+                error_kind: probe.error_kind.map(String::from),
+            });
+        }
+
+        if !results.is_empty() {
+            let sent = client
+                .post(format!("{server}/agent/results"))
+                .bearer_auth(&token)
+                .json(&AgentResultsRequest { results })
+                .send()
+                .await
+                .and_then(|r| r.error_for_status());
+            if let Err(e) = sent {
+                error!("Error reporting results: {e}");
+            }
+        }
+
+        sleep(Duration::from_secs(1)).await;
+    }
+}
+
+pub(crate) struct Job {
+    pub(crate) check: CheckRow,
+    pub(crate) due_at: DateTime<Utc>,
+}
+
+impl PartialEq for Job {
+    fn eq(&self, other: &Self) -> bool {
+        self.due_at == other.due_at && self.check.id == other.check.id
+    }
+}
+
+impl Eq for Job {}
+
+impl PartialOrd for Job {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Job {
+    // BinaryHeap is a max-heap: the most overdue job must compare greatest.
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        other
+            .due_at
+            .cmp(&self.due_at)
+            .then_with(|| other.check.id.cmp(&self.check.id))
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct QueueInner {
+    pub(crate) heap: BinaryHeap<Job>,
+    /// Checks queued or being probed, so a slow probe is never enqueued twice.
+    pub(crate) pending: HashSet<String>,
+}
+
+/// Bounded priority queue between the scheduler and the probe workers.
+pub(crate) struct JobQueue {
+    pub(crate) inner: Mutex<QueueInner>,
+    pub(crate) notify: Notify,
+    pub(crate) capacity: usize,
+}
+
+impl JobQueue {
+    pub(crate) fn new(capacity: usize) -> Self {
+        JobQueue {
+            inner: Mutex::new(QueueInner::default()),
+            notify: Notify::new(),
+            capacity,
+        }
+    }
+
+    /// Returns false when the job is already pending or the queue is full.
+    pub(crate) fn push(&self, job: Job, metrics: &Metrics) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.pending.contains(&job.check.id) {
+            return false;
+        }
+        if inner.heap.len() >= self.capacity {
+            metrics.jobs_rejected.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        inner.pending.insert(job.check.id.clone());
+        inner.heap.push(job);
+        metrics
+            .queue_depth
+            .store(inner.heap.len() as i64, Ordering::Relaxed);
+        drop(inner);
+        self.notify.notify_one();
+        true
+    }
+
+    /// The caller decrements `jobs_in_flight` once the job is done.
+    pub(crate) async fn pop(&self, metrics: &Metrics) -> Job {
+        loop {
+            {
+                let mut inner = self.inner.lock().unwrap();
+                if let Some(job) = inner.heap.pop() {
+                    // Counted in flight before it leaves the queue, so it always shows up in one of them
+                    metrics.jobs_in_flight.fetch_add(1, Ordering::Relaxed);
+                    metrics
+                        .queue_depth
+                        .store(inner.heap.len() as i64, Ordering::Relaxed);
+                    return job;
+                }
+            }
+            self.notify.notified().await;
+        }
+    }
+
+    pub(crate) fn done(&self, check_id: &str) {
+        self.inner.lock().unwrap().pending.remove(check_id);
+    }
+}
+
+/// Counters exposed on `/metrics` in Prometheus text format.
+#[derive(Default)]
+pub(crate) struct Metrics {
+    pub(crate) queue_depth: AtomicI64,
+    pub(crate) jobs_in_flight: AtomicI64,
+    pub(crate) jobs_completed: AtomicU64,
+    pub(crate) jobs_rejected: AtomicU64,
+    /// Delay between a job's due time and the moment a worker picked it up.
+    pub(crate) queue_lag_ms: AtomicI64,
+    /// Unix time in milliseconds of the scheduler's last pass; 0 until the first one.
+    pub(crate) scheduler_heartbeat_ms: AtomicI64,
+    /// Worst delay, in the last pass, between a due check's due time and the pass.
+    pub(crate) scheduler_lag_ms: AtomicI64,
+    /// Due checks still waiting more than a full interval after their due time.
+    pub(crate) overdue_checks: AtomicI64,
+    pub(crate) probes_total: AtomicU64,
+    /// Probes that got no HTTP response at all (DNS, connect, TLS, timeouts...). A spike
+    /// usually means the worker's own network is broken rather than the targets.
+    pub(crate) probe_errors_total: AtomicU64,
+    /// Probes that never went out because the check can't be set up (unreadable secrets, bad
+    /// client certificates...); the check needs fixing, not the network.
+    pub(crate) probe_config_errors_total: AtomicU64,
+    /// Share of probe errors over the last `WATCHDOG_INTERVAL_SECONDS`, as `f64` bits.
+    pub(crate) probe_error_ratio: AtomicU64,
+}
+
+pub(crate) const WATCHDOG_INTERVAL_SECONDS: u64 = 60;
+
+pub(crate) const NOTIFICATION_LOG_DAYS: i64 = 30;
+
+/// Computes the recent probe error ratio and warns when the scheduler stalls, checks pile up
+/// overdue or most probes fail to get any response.
+pub(crate) async fn worker_watchdog_loop(state: Arc<AppState>) {
+    let m = &state.metrics;
+    let (mut probes, mut errors) = (0, 0);
+    let mut stalled = false;
+    loop {
+        sleep(Duration::from_secs(WATCHDOG_INTERVAL_SECONDS)).await;
+
+        let (total, failed) = (
+            m.probes_total.load(Ordering::Relaxed),
+            m.probe_errors_total.load(Ordering::Relaxed),
+        );
+        let ratio = match total - probes {
+            0 => 0.0,
+            n => (failed - errors) as f64 / n as f64,
+        };
+        (probes, errors) = (total, failed);
+        m.probe_error_ratio
+            .store(ratio.to_bits(), Ordering::Relaxed);
+        if ratio > 0.5 {
+            tracing::warn!(
+                "{:.0}% of probes got no response in the last minute",
+                ratio * 100.0
+            );
+        }
+
+        let worker = worker_probe(&state);
+        if !worker.ok && !stalled {
+            tracing::warn!("Scheduler stalled: {}", worker.detail.unwrap_or_default());
+        } else if worker.ok && stalled {
+            info!("Scheduler recovered");
+        }
+        stalled = !worker.ok;
+
+        let overdue = m.overdue_checks.load(Ordering::Relaxed);
+        if overdue > 0 {
+            tracing::warn!("{overdue} checks are overdue by more than one interval");
+        }
+    }
+}
+
+/// Where the scheduler reads the time from; the default is the system clock. A manual clock
+/// only moves when [`Clock::advance`] is called, so tests can step through intervals instead
+/// of waiting for them.
+#[derive(Clone, Default)]
+pub struct Clock {
+    manual: Option<Arc<watch::Sender<DateTime<Utc>>>>,
+}
+
+impl Clock {
+    pub fn manual(start: DateTime<Utc>) -> Self {
+        Clock {
+            manual: Some(Arc::new(watch::channel(start).0)),
+        }
+    }
+
+    pub fn now(&self) -> DateTime<Utc> {
+        match &self.manual {
+            Some(time) => *time.borrow(),
+            None => Utc::now(),
+        }
+    }
+
+    /// Moves a manual clock forward; the system clock ignores it.
+    pub fn advance(&self, by: chrono::Duration) {
+        if let Some(time) = &self.manual {
+            time.send_modify(|now| *now += by);
+        }
+    }
+
+    /// Sleeps for `duration`, or less if a manual clock moves in the meantime.
+    pub(crate) async fn sleep(&self, duration: Duration) {
+        let Some(time) = &self.manual else {
+            return sleep(duration).await;
+        };
+        let mut moved = time.subscribe();
+        tokio::select! {
+            _ = sleep(duration) => {}
+            _ = moved.changed() => {}
+        }
+    }
+}
+
+/// Enqueues due checks; `WORKER_CONCURRENCY` probe workers drain the queue.
+pub(crate) async fn worker_loop(state: Arc<AppState>) {
+    let concurrency = env::var("WORKER_CONCURRENCY")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(8);
+    let probe_clients = Arc::new(Mutex::new(ProbeClients::new(HttpTuning::from_env(
+        "PROBE_HTTP",
+    ))));
+    for _ in 0..concurrency {
+        tokio::spawn(probe_worker(state.clone(), probe_clients.clone()));
+    }
+
+    // Other instances only change the database, so the snapshot is still reloaded now and then
+    let refresh_every = Duration::from_secs(
+        env::var("CHECKS_REFRESH_SECONDS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(60),
+    );
+    let mut last_refresh = Instant::now();
+    let mut changes = state.checks.subscribe();
+
+    loop {
+        if last_refresh.elapsed() >= refresh_every {
+            if let Err(e) = reload_checks(&state).await {
+                error!("Error loading checks: {e}");
+            }
+            last_refresh = Instant::now();
+        }
+
+        let now = state.clock.now();
+        let (mut lag_ms, mut overdue) = (0, 0);
+        for c in state.checks.due(now) {
+            // Retrying would only fail to lease it again until the holder's lease runs out
+            if c.leased_by.as_ref() != Some(&state.instance_id)
+                && c.leased_until.is_some_and(|until| until > now)
+            {
+                continue;
+            }
+            let due_at = due_at(&c).unwrap_or(now);
+            let lag = now.signed_duration_since(due_at);
+            lag_ms = lag_ms.max(lag.num_milliseconds());
+            if lag.num_seconds() > c.interval_seconds {
+                overdue += 1;
+            }
+            state.queue.push(Job { check: c, due_at }, &state.metrics);
+        }
+        let m = &state.metrics;
+        m.scheduler_lag_ms.store(lag_ms, Ordering::Relaxed);
+        m.overdue_checks.store(overdue, Ordering::Relaxed);
+        m.scheduler_heartbeat_ms
+            .store(now.timestamp_millis(), Ordering::Relaxed);
+
+        tokio::select! {
+            _ = state.clock.sleep(Duration::from_secs(1)) => {}
+            _ = changes.changed() => {}
+        }
+    }
+}
+
+/// Snapshot of the active checks the scheduler works from, updated on every local mutation
+/// instead of re-reading the table each tick.
+#[derive(Clone)]
+pub(crate) struct CheckCache {
+    pub(crate) tx: Arc<watch::Sender<HashMap<String, CheckRow>>>,
+}
+
+impl Default for CheckCache {
+    fn default() -> Self {
+        CheckCache {
+            tx: Arc::new(watch::Sender::new(HashMap::new())),
+        }
+    }
+}
+
+impl CheckCache {
+    pub(crate) fn subscribe(&self) -> watch::Receiver<HashMap<String, CheckRow>> {
+        self.tx.subscribe()
+    }
+
+    pub(crate) fn due(&self, now: DateTime<Utc>) -> Vec<CheckRow> {
+        self.tx
+            .borrow()
+            .values()
+            .filter(|c| should_run_check(c, now))
+            .cloned()
+            .collect()
+    }
+
+    pub(crate) fn replace(&self, checks: Vec<CheckRow>) {
+        self.tx
+            .send_replace(checks.into_iter().map(|c| (c.id.clone(), c)).collect());
+    }
+
+    pub(crate) fn upsert(&self, check: CheckRow) {
+        self.tx.send_modify(|checks| {
+            if check.is_active == 1 {
+                checks.insert(check.id.clone(), check);
+            } else {
+                checks.remove(&check.id);
+            }
+        });
+    }
+
+    pub(crate) fn remove(&self, id: &str) {
+        self.tx
+            .send_if_modified(|checks| checks.remove(id).is_some());
+    }
+}
+
+pub(crate) async fn reload_checks(state: &AppState) -> Result<(), sqlx::Error> {
+    let checks = state.db.active_checks().await?;
+    state.checks.replace(checks);
+    Ok(())
+}
+
+/// Re-reads one check into the snapshot and returns it.
+pub(crate) async fn reload_check(
+    state: &AppState,
+    id: &str,
+) -> Result<Option<CheckRow>, sqlx::Error> {
+    let check = state.db.check(id).await?;
+    match &check {
+        Some(c) => state.checks.upsert(c.clone()),
+        None => state.checks.remove(id),
+    }
+    Ok(check)
+}
+
+pub(crate) async fn probe_worker(state: Arc<AppState>, probe_clients: Arc<Mutex<ProbeClients>>) {
+    loop {
+        let job = state.queue.pop(&state.metrics).await;
+        let lag = state
+            .clock
+            .now()
+            .signed_duration_since(job.due_at)
+            .num_milliseconds();
+        state
+            .metrics
+            .queue_lag_ms
+            .store(lag.max(0), Ordering::Relaxed);
+
+        run_check(&state, &probe_clients, &job.check).await;
+
+        state.metrics.jobs_in_flight.fetch_sub(1, Ordering::Relaxed);
+        state.metrics.jobs_completed.fetch_add(1, Ordering::Relaxed);
+        state.queue.done(&job.check.id);
+    }
+}
+
+pub(crate) async fn run_check(state: &AppState, probe_clients: &Mutex<ProbeClients>, c: &CheckRow) {
+    match try_lease(state, c).await {
+        Ok(true) => {}
+        Ok(false) => {
+            // Another instance probed it: pick up its schedule
+            if let Err(e) = reload_check(state, &c.id).await {
+                error!("Error loading check {}: {e}", c.name);
+            }
+            return;
+        }
+        Err(e) => {
+            error!("Error leasing check {}: {e}", c.name);
+            return;
+        }
+    }
+
+    let defaults = probe_defaults(&state.db, &c.org_id)
+        .await
+        .unwrap_or_else(|e| {
+            error!("Error loading probe defaults of {}: {e}", c.name);
+            ProbeDefaults::default()
+        });
+    let headers = probe_headers(&defaults, c);
+    let probe = match load_probe_secrets(state, c).await {
+        Ok(secrets) => {
+            let probe_client = probe_clients
+                .lock()
+                .unwrap()
+                .get(c, secrets.client_key.as_deref());
+            match probe_client {
+                Ok(probe_client) => {
+                    probe_check(&probe_client, c, secrets.auth_header.as_deref(), &headers).await
+                }
+                Err(err) => ProbeOutcome::failed("config", None, None, err),
+            }
+        }
+        Err(err) => ProbeOutcome::failed("config", None, None, err),
+    };
+    state.metrics.probes_total.fetch_add(1, Ordering::Relaxed);
+    if probe.error_kind == Some("config") {
+        state
+            .metrics
+            .probe_config_errors_total
+            .fetch_add(1, Ordering::Relaxed);
+    } else if probe.http_status.is_none() {
+        state
+            .metrics
+            .probe_errors_total
+            .fetch_add(1, Ordering::Relaxed);
+    }
+    let now = state.clock.now();
+    let planned = in_maintenance(&state.db, &c.id, now)
+        .await
+        .unwrap_or_else(|e| {
+            error!("Error loading maintenance windows of {}: {e}", c.name);
+            false
+        });
+
+    let content_changed = match (&c.content_hash, &probe.content_hash) {
+        (Some(old), Some(new)) => old != new,
+        _ => false,
+    };
+    let persist = should_persist(c, &probe.status, content_changed);
+
+    let status = if c.quorum.is_some() {
+        match quorum_status(&state.db, c, Some(&probe.status), state.clock.now()).await {
+            Ok(status) => status,
+            Err(e) => {
+                error!("Error evaluating quorum for {}: {e}", c.name);
+                probe.status.clone()
+            }
+        }
+    } else {
+        probe.status.clone()
+    };
+    let previous = status_transition(c, &status);
+    let down_probes = if probe.status == "DOWN" {
+        c.down_probes + 1
+    } else {
+        0
+    };
+
+    let (done, flushed) = oneshot::channel();
+    let write = PendingWrite {
+        result: NewResult {
+            check_id: c.id.clone(),
+            checked_at: now,
+            status: probe.status.clone(),
+            http_status: probe.http_status,
+            latency_ms: probe.latency_ms,
+            error: probe.error.clone(),
+            content_hash: probe.content_hash.clone(),
+            response_bytes: probe.response_bytes,
+            protocol: probe.protocol,
+            error_kind: probe.error_kind,
+            planned,
+        },
+        persist,
+        update: CheckUpdate {
+            check_id: c.id.clone(),
+            last_checked_at: now,
+            next_run_at: schedule_next(c, now, down_probes),
+            content_hash: probe.content_hash.clone(),
+            last_probe_status: probe.status.clone(),
+            samples_since_persist: if persist {
+                0
+            } else {
+                c.samples_since_persist + 1
+            },
+            last_status: previous.map(|_| status.clone()),
+            down_probes,
+        },
+        leased_by: state.instance_id.clone(),
+        done,
+    };
+    if state.writer.send(write).await.is_ok() {
+        flushed.await.ok();
+    }
+
+    if let Some(previous) = previous {
+        notify_status_change(state, c, previous, &status, Some(&probe)).await;
+    }
+
+    if content_changed {
+        info!("CONTENT CHANGE: {}", c.name);
+
+        let at = state.clock.now();
+        send_alert(state, c, &Alert::new("content_change", c, at)).await;
+    }
+}
+
+pub(crate) struct NewResult {
+    pub(crate) check_id: String,
+    pub(crate) checked_at: DateTime<Utc>,
+    pub(crate) status: String,
+    pub(crate) http_status: Option<i64>,
+    pub(crate) latency_ms: Option<i64>,
+    pub(crate) error: Option<String>,
+    pub(crate) content_hash: Option<String>,
+    pub(crate) response_bytes: Option<i64>,
+    pub(crate) protocol: Option<&'static str>,
+    pub(crate) error_kind: Option<&'static str>,
+    /// Taken during a maintenance window.
+    pub(crate) planned: bool,
+}
+
+/// Check columns rewritten after every probe; only the latest one per check is applied.
+pub(crate) struct CheckUpdate {
+    pub(crate) check_id: String,
+    pub(crate) last_checked_at: DateTime<Utc>,
+    pub(crate) next_run_at: DateTime<Utc>,
+    pub(crate) content_hash: Option<String>,
+    pub(crate) last_probe_status: String,
+    pub(crate) samples_since_persist: i64,
+    pub(crate) last_status: Option<String>,
+    pub(crate) down_probes: i64,
+}
+
+pub(crate) struct PendingWrite {
+    pub(crate) result: NewResult,
+    pub(crate) persist: bool,
+    pub(crate) update: CheckUpdate,
+    pub(crate) leased_by: String,
+    pub(crate) done: oneshot::Sender<()>,
+}
+
+pub(crate) const WRITE_BATCH_MAX: usize = 256;
+
+pub(crate) const WRITE_BATCH_WINDOW: Duration = Duration::from_millis(200);
+
+/// Collects probe writes for a short window and commits them in a single transaction:
+/// result rows are inserted together, rollups and check updates are coalesced.
+pub(crate) async fn result_writer_loop(
+    state: Arc<AppState>,
+    mut writes: mpsc::Receiver<PendingWrite>,
+) {
+    while let Some(first) = writes.recv().await {
+        let mut batch = vec![first];
+        let deadline = tokio::time::Instant::now() + WRITE_BATCH_WINDOW;
+        while batch.len() < WRITE_BATCH_MAX {
+            match tokio::time::timeout_at(deadline, writes.recv()).await {
+                Ok(Some(write)) => batch.push(write),
+                _ => break,
+            }
+        }
+
+        if let Some(log) = &state.result_log {
+            let results: Vec<_> = batch
+                .iter()
+                .map(|w| LoggedResult {
+                    check_id: &w.result.check_id,
+                    checked_at: w.result.checked_at,
+                    status: &w.result.status,
+                    http_status: w.result.http_status,
+                    latency_ms: w.result.latency_ms,
+                    error: w.result.error.as_deref(),
+                    error_kind: w.result.error_kind,
+                    response_bytes: w.result.response_bytes,
+                    location: None,
+                })
+                .collect();
+            if let Err(e) = log.write(&results).await {
+                error!(
+                    "Error writing {} probe results to the result log: {e}",
+                    batch.len()
+                );
+            }
+        }
+        if let Err(e) = flush_writes(&state.db, &batch).await {
+            error!("Error writing {} probe results: {e}", batch.len());
+        }
+        #[cfg(feature = "clickhouse")]
+        if let Some(ch) = &state.clickhouse {
+            let samples: Vec<_> = batch
+                .iter()
+                .map(|w| clickhouse::Sample {
+                    check_id: &w.result.check_id,
+                    checked_at: w.result.checked_at,
+                    status: &w.result.status,
+                    http_status: w.result.http_status,
+                    latency_ms: w.result.latency_ms,
+                    error: w.result.error.as_deref(),
+                    response_bytes: w.result.response_bytes,
+                    location: None,
+                    planned: w.result.planned,
+                })
+                .collect();
+            if let Err(e) = ch.insert(&samples).await {
+                error!("Error writing results to ClickHouse: {e}");
+            }
+        }
+        let check_ids: HashSet<&str> = batch.iter().map(|w| w.update.check_id.as_str()).collect();
+        for check_id in check_ids {
+            if let Err(e) = reload_check(&state, check_id).await {
+                error!("Error loading check {check_id}: {e}");
+            }
+        }
+        for write in batch {
+            write.done.send(()).ok();
+        }
+    }
+}
+
+pub(crate) struct ProbeOutcome {
+    pub(crate) status: String,
+    pub(crate) http_status: Option<i64>,
+    pub(crate) latency_ms: Option<i64>,
+    pub(crate) error: Option<String>,
+    pub(crate) content_hash: Option<String>,
+    pub(crate) response_bytes: Option<i64>,
+    pub(crate) protocol: Option<&'static str>,
+    /// One of [`ERROR_KINDS`](crate::domain::ERROR_KINDS) when the probe is DOWN.
+    pub(crate) error_kind: Option<&'static str>,
+}
+
+impl ProbeOutcome {
+    pub(crate) fn failed(
+        kind: &'static str,
+        http_status: Option<i64>,
+        latency_ms: Option<i64>,
+        error: String,
+    ) -> Self {
+        ProbeOutcome {
+            status: "DOWN".to_string(),
+            http_status,
+            latency_ms,
+            error: Some(error),
+            content_hash: None,
+            response_bytes: None,
+            protocol: None,
+            error_kind: Some(kind),
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct ProbeSecrets {
+    pub(crate) client_key: Option<String>,
+    pub(crate) auth_header: Option<String>,
+}
+
+pub(crate) async fn load_probe_secrets(
+    state: &AppState,
+    check: &CheckRow,
+) -> Result<ProbeSecrets, String> {
+    let mut secrets = ProbeSecrets::default();
+    if let Some(id) = &check.client_key_secret_id {
+        secrets.client_key = Some(load_secret(state, id).await.map_err(|(_, e)| e)?);
+    }
+    if let Some(id) = &check.auth_header_secret_id {
+        secrets.auth_header = Some(load_secret(state, id).await.map_err(|(_, e)| e)?);
+    }
+    Ok(secrets)
+}
+
+/// Settings that require a dedicated reqwest client; checks sharing them share a client.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub(crate) struct ProbeClientKey {
+    pub(crate) dns_resolver: Option<String>,
+    pub(crate) ip_version: Option<i64>,
+    pub(crate) proxy_url: Option<String>,
+    pub(crate) client_cert_pem: Option<String>,
+    pub(crate) client_key_secret_id: Option<String>,
+    pub(crate) http_version: Option<String>,
+    /// HTTP/2 over plain `http://` has no ALPN to negotiate it, the client has to assume it.
+    pub(crate) h2c: bool,
+    /// The URL's host and the IP it is pinned to.
+    pub(crate) connect_to: Option<(String, String)>,
+}
+
+impl ProbeClientKey {
+    pub(crate) fn for_check(check: &CheckRow) -> Self {
+        ProbeClientKey {
+            dns_resolver: check.dns_resolver.clone(),
+            ip_version: check.ip_version,
+            proxy_url: check.proxy_url.clone(),
+            client_cert_pem: check.client_cert_pem.clone(),
+            client_key_secret_id: check.client_key_secret_id.clone(),
+            http_version: check.http_version.clone(),
+            h2c: check.http_version.as_deref() == Some("2") && check.url.starts_with("http://"),
+            connect_to: check.connect_to.as_ref().and_then(|ip| {
+                let url = reqwest::Url::parse(&check.url).ok()?;
+                Some((url.host_str()?.to_string(), ip.clone()))
+            }),
+        }
+    }
+
+    pub(crate) fn build(
+        &self,
+        tuning: &HttpTuning,
+        client_key: Option<&str>,
+    ) -> Result<reqwest::Client, String> {
+        let http2 = tuning.http2 || self.http_version.as_deref() == Some("2");
+        let mut builder = HttpTuning {
+            http2,
+            ..tuning.clone()
+        }
+        .builder();
+
+        match self.http_version.as_deref() {
+            Some("1.1") => builder = builder.http1_only(),
+            Some("2") if self.h2c => builder = builder.http2_prior_knowledge(),
+            _ => {}
+        }
+        if self.dns_resolver.is_some() || self.ip_version.is_some() {
+            let resolver = ProbeResolver::new(self.dns_resolver.as_deref(), self.ip_version)?;
+            builder = builder.dns_resolver(Arc::new(resolver));
+        }
+        if let Some((host, ip)) = &self.connect_to {
+            // The port is the URL's, reqwest ignores this one
+            let ip: IpAddr = ip.parse().map_err(|_| "connect_to inválido")?;
+            builder = builder.resolve(host, SocketAddr::new(ip, 0));
+        }
+        if let Some(proxy_url) = &self.proxy_url {
+            builder = builder.proxy(reqwest::Proxy::all(proxy_url).map_err(|e| e.to_string())?);
+        }
+        if let (Some(cert), Some(key)) = (&self.client_cert_pem, client_key) {
+            let pem = format!("{cert}\n{key}");
+            let identity =
+                reqwest::Identity::from_pem(pem.as_bytes()).map_err(|e| e.to_string())?;
+            builder = builder.use_rustls_tls().identity(identity);
+        }
+
+        builder.build().map_err(|e| e.to_string())
+    }
+}
+
+pub(crate) struct ProbeClients {
+    pub(crate) tuning: HttpTuning,
+    pub(crate) clients: HashMap<ProbeClientKey, reqwest::Client>,
+}
+
+impl ProbeClients {
+    pub(crate) fn new(tuning: HttpTuning) -> Self {
+        ProbeClients {
+            tuning,
+            clients: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn get(
+        &mut self,
+        check: &CheckRow,
+        client_key: Option<&str>,
+    ) -> Result<reqwest::Client, String> {
+        let key = ProbeClientKey::for_check(check);
+        if let Some(client) = self.clients.get(&key) {
+            return Ok(client.clone());
+        }
+        let client = key.build(&self.tuning, client_key)?;
+        self.clients.insert(key, client.clone());
+        Ok(client)
+    }
+}
+
+/// reqwest settings of a family of clients, from `{prefix}_TIMEOUT_SECONDS` (10 by default),
+/// `{prefix}_CONNECT_TIMEOUT_SECONDS`, `{prefix}_POOL_MAX_IDLE_PER_HOST`,
+/// `{prefix}_POOL_IDLE_TIMEOUT_SECONDS`, `{prefix}_TCP_KEEPALIVE_SECONDS` and `{prefix}_HTTP2`
+/// (`false` keeps them to HTTP/1.1). Probes are tuned with `PROBE_HTTP` and use clients of
+/// their own, so a flood of them can't hold up alerts and the other `OUTBOUND_HTTP` calls.
+#[derive(Debug, Clone)]
+pub(crate) struct HttpTuning {
+    pub(crate) timeout: Duration,
+    pub(crate) connect_timeout: Option<Duration>,
+    pub(crate) pool_max_idle_per_host: Option<usize>,
+    pub(crate) pool_idle_timeout: Option<Duration>,
+    pub(crate) tcp_keepalive: Option<Duration>,
+    pub(crate) http2: bool,
+}
+
+impl HttpTuning {
+    pub(crate) fn from_env(prefix: &str) -> Self {
+        let var = |name: &str| env::var(format!("{prefix}_{name}")).ok();
+        let seconds = |name: &str| {
+            var(name)
+                .and_then(|v| v.parse().ok())
+                .map(Duration::from_secs)
+        };
+        HttpTuning {
+            timeout: seconds("TIMEOUT_SECONDS").unwrap_or(Duration::from_secs(10)),
+            connect_timeout: seconds("CONNECT_TIMEOUT_SECONDS"),
+            pool_max_idle_per_host: var("POOL_MAX_IDLE_PER_HOST").and_then(|v| v.parse().ok()),
+            pool_idle_timeout: seconds("POOL_IDLE_TIMEOUT_SECONDS"),
+            tcp_keepalive: seconds("TCP_KEEPALIVE_SECONDS"),
+            http2: var("HTTP2").is_none_or(|v| v != "false"),
+        }
+    }
+
+    pub(crate) fn builder(&self) -> reqwest::ClientBuilder {
+        let mut builder = reqwest::Client::builder()
+            .timeout(self.timeout)
+            .tcp_keepalive(self.tcp_keepalive);
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(max) = self.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max);
+        }
+        if let Some(timeout) = self.pool_idle_timeout {
+            builder = builder.pool_idle_timeout(timeout);
+        }
+        if !self.http2 {
+            builder = builder.http1_only();
+        }
+        builder
+    }
+}
+
+/// DNS resolution through a specific nameserver and/or restricted to one IP family.
+pub(crate) struct ProbeResolver {
+    pub(crate) resolver: TokioAsyncResolver,
+}
+
+impl ProbeResolver {
+    pub(crate) fn new(nameserver: Option<&str>, ip_version: Option<i64>) -> Result<Self, String> {
+        let (config, mut opts) = match nameserver {
+            Some(addr) => {
+                let addr = parse_resolver_addr(addr).ok_or("dns_resolver inválido")?;
+                let group = NameServerConfigGroup::from_ips_clear(&[addr.ip()], addr.port(), true);
+                (
+                    ResolverConfig::from_parts(None, vec![], group),
+                    ResolverOpts::default(),
+                )
+            }
+            None => read_system_conf().map_err(|e| e.to_string())?,
+        };
+        opts.ip_strategy = match ip_version {
+            Some(4) => LookupIpStrategy::Ipv4Only,
+            Some(6) => LookupIpStrategy::Ipv6Only,
+            _ => LookupIpStrategy::Ipv4thenIpv6,
+        };
+
+        Ok(ProbeResolver {
+            resolver: TokioAsyncResolver::tokio(config, opts),
+        })
+    }
+}
+
+impl Resolve for ProbeResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let resolver = self.resolver.clone();
+        Box::pin(async move {
+            let lookup = resolver.lookup_ip(name.as_str()).await?;
+            let addrs: Addrs = Box::new(
+                lookup
+                    .into_iter()
+                    .map(|ip| SocketAddr::new(ip, 0))
+                    .collect::<Vec<_>>()
+                    .into_iter(),
+            );
+            Ok(addrs)
+        })
+    }
+}
+
+/// Accepts `1.1.1.1`, `1.1.1.1:53`, `2606:4700:4700::1111` or `[2606:4700:4700::1111]:53`.
+pub(crate) fn parse_resolver_addr(value: &str) -> Option<SocketAddr> {
+    value.parse::<SocketAddr>().ok().or_else(|| {
+        value
+            .parse::<IpAddr>()
+            .ok()
+            .map(|ip| SocketAddr::new(ip, 53))
+    })
+}
+
+pub(crate) async fn probe_check(
+    client: &reqwest::Client,
+    check: &CheckRow,
+    auth_header: Option<&str>,
+    headers: &BTreeMap<String, String>,
+) -> ProbeOutcome {
+    let mut request = client.get(&check.url);
+    for (name, value) in headers {
+        request = request.header(name, value);
+    }
+    if let Some(value) = auth_header {
+        request = request.header(reqwest::header::AUTHORIZATION, value);
+    }
+
+    let start = Instant::now();
+    let resp = match request.send().await {
+        Ok(resp) => resp,
+        Err(err) => {
+            return ProbeOutcome::failed(
+                request_error_kind(&err),
+                None,
+                Some(start.elapsed().as_millis() as i64),
+                err.to_string(),
+            )
+        }
+    };
+
+    let status_code = resp.status().as_u16();
+    let http_status = Some(status_code as i64);
+    let success = is_accepted_status(check, status_code);
+    let headers_ms = start.elapsed().as_millis() as i64;
+    let protocol = protocol_name(resp.version());
+
+    let body = match resp.bytes().await {
+        Ok(body) => body,
+        Err(err) => {
+            return ProbeOutcome::failed(
+                if err.is_timeout() { "timeout" } else { "body" },
+                http_status,
+                Some(start.elapsed().as_millis() as i64),
+                err.to_string(),
+            )
+        }
+    };
+    // Content checks are timed until the whole body is in, the others until the headers
+    let content_check = check.check_type == CHECK_TYPE_CONTENT_CHANGE;
+    let latency_ms = Some(if content_check {
+        start.elapsed().as_millis() as i64
+    } else {
+        headers_ms
+    });
+    let response_bytes = body.len() as i64;
+
+    if !success {
+        return ProbeOutcome {
+            status: "DOWN".to_string(),
+            http_status,
+            latency_ms,
+            error: None,
+            content_hash: None,
+            response_bytes: Some(response_bytes),
+            protocol: Some(protocol),
+            error_kind: Some(http_error_kind(status_code)),
+        };
+    }
+    if let Some(expected) = expected_protocol(check).filter(|p| *p != protocol) {
+        return ProbeOutcome {
+            response_bytes: Some(response_bytes),
+            protocol: Some(protocol),
+            ..ProbeOutcome::failed(
+                "assertion",
+                http_status,
+                latency_ms,
+                format!("expected {expected}, got {protocol}"),
+            )
+        };
+    }
+    if let Some(min) = check.min_response_bytes.filter(|min| response_bytes < *min) {
+        return ProbeOutcome {
+            response_bytes: Some(response_bytes),
+            protocol: Some(protocol),
+            ..ProbeOutcome::failed(
+                "assertion",
+                http_status,
+                latency_ms,
+                format!("response body of {response_bytes} bytes is smaller than {min}"),
+            )
+        };
+    }
+
+    ProbeOutcome {
+        status: "UP".to_string(),
+        http_status,
+        latency_ms,
+        error: None,
+        content_hash: content_check.then(|| {
+            content_hash(
+                &String::from_utf8_lossy(&body),
+                check.content_selector.as_deref(),
+            )
+        }),
+        response_bytes: Some(response_bytes),
+        protocol: Some(protocol),
+        error_kind: None,
+    }
+}
+
+/// reqwest only tells timeouts and connect errors apart, DNS and TLS failures are recognized
+/// by the messages of the errors that caused them.
+pub(crate) fn request_error_kind(err: &reqwest::Error) -> &'static str {
+    let mut causes = String::new();
+    let mut source: Option<&dyn std::error::Error> = Some(err);
+    while let Some(e) = source {
+        causes.push_str(&e.to_string().to_lowercase());
+        causes.push('\n');
+        source = e.source();
+    }
+    let tls = ["certificate", "tls", "handshake", "alert"]
+        .iter()
+        .any(|marker| causes.contains(marker));
+
+    if causes.contains("dns error") {
+        "dns"
+    } else if err.is_connect() && err.is_timeout() {
+        "connect_timeout"
+    } else if tls {
+        "tls"
+    } else if err.is_timeout() {
+        "timeout"
+    } else if err.is_connect() {
+        "connect"
+    } else if err.is_builder() {
+        "config"
+    } else {
+        "connect"
+    }
+}
+
+pub(crate) fn protocol_name(version: reqwest::Version) -> &'static str {
+    match version {
+        reqwest::Version::HTTP_09 => "HTTP/0.9",
+        reqwest::Version::HTTP_10 => "HTTP/1.0",
+        reqwest::Version::HTTP_2 => "HTTP/2",
+        reqwest::Version::HTTP_3 => "HTTP/3",
+        _ => "HTTP/1.1",
+    }
+}
+
+/// How long a worker instance owns a check it is probing before others may take it over.
+pub(crate) const LEASE_SECONDS: i64 = 60;
+
+/// Claims the check for this instance. Fails when another instance holds a live lease, or
+/// already probed it since we loaded it (its `last_checked_at` moved).
+pub(crate) async fn try_lease(state: &AppState, check: &CheckRow) -> Result<bool, sqlx::Error> {
+    let now = state.clock.now();
+    let result = sqlx::query(
+        r#"
+        UPDATE checks SET leased_by = ?, leased_until = ?
+        WHERE id = ?
+          AND last_checked_at IS ?
+          AND (leased_until IS NULL OR leased_until < ? OR leased_by = ?)
+        "#,
+    )
+    .bind(&state.instance_id)
+    .bind(now + chrono::Duration::seconds(LEASE_SECONDS))
+    .bind(&check.id)
+    .bind(check.last_checked_at)
+    .bind(now)
+    .bind(&state.instance_id)
+    .execute(&state.db)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+/// A DOWN transition opens an incident for the check; the next non-DOWN one resolves it and
+/// returns the resolved incident.
+pub(crate) async fn track_incident(
+    state: &AppState,
+    check: &CheckRow,
+    status: &str,
+    at: DateTime<Utc>,
+    trigger: Option<&ProbeOutcome>,
+    caused_by: Option<&str>,
+) -> Result<Option<IncidentRow>, sqlx::Error> {
+    let open: Option<(String, DateTime<Utc>)> = sqlx::query_as(
+        "SELECT id, started_at FROM incidents WHERE check_id = ? AND resolved_at IS NULL",
+    )
+    .bind(&check.id)
+    .fetch_optional(&state.db)
+    .await?;
+
+    match (status, open) {
+        ("DOWN", None) => {
+            let id = Uuid::new_v4().to_string();
+            sqlx::query(
+                "INSERT INTO incidents (id, check_id, started_at, failed_probes, last_error, max_latency_ms, caused_by_check_id, severity) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(&id)
+            .bind(&check.id)
+            .bind(at)
+            .bind(i64::from(trigger.is_some()))
+            .bind(trigger.and_then(|p| failure_cause(p.error.as_deref(), p.http_status)))
+            .bind(trigger.and_then(|p| p.latency_ms))
+            .bind(caused_by)
+            .bind(&check.severity)
+            .execute(&state.db)
+            .await?;
+            state
+                .events
+                .publish(&Event::IncidentOpened {
+                    incident_id: &id,
+                    check_id: &check.id,
+                    check_name: &check.name,
+                    severity: &check.severity,
+                    started_at: at,
+                })
+                .await;
+        }
+        (status, Some((id, started_at))) if status != "DOWN" => {
+            let incident = sqlx::query_as::<_, IncidentRow>(
+                "UPDATE incidents SET resolved_at = ? WHERE id = ? RETURNING *",
+            )
+            .bind(at)
+            .bind(&id)
+            .fetch_one(&state.db)
+            .await?;
+            state
+                .events
+                .publish(&Event::IncidentResolved {
+                    incident_id: &id,
+                    check_id: &check.id,
+                    check_name: &check.name,
+                    started_at,
+                    resolved_at: at,
+                })
+                .await;
+            return Ok(Some(incident));
+        }
+        _ => {}
+    }
+    Ok(None)
+}
+
+/// Combines the latest result of every location inside the quorum window: the check is DOWN
+/// only when at least `quorum` locations currently see it failing. `local` is the server's
+/// own probe that may not have been written yet.
+pub(crate) async fn quorum_status(
+    db: &Db,
+    check: &CheckRow,
+    local: Option<&str>,
+    now: DateTime<Utc>,
+) -> Result<String, sqlx::Error> {
+    let quorum = check.quorum.unwrap_or(1);
+    let window = check
+        .quorum_window_seconds
+        .unwrap_or(check.interval_seconds * 2);
+    let since = now - chrono::Duration::seconds(window);
+
+    let rows: Vec<(Option<String>, String)> = sqlx::query_as(
+        "SELECT location, status FROM check_results WHERE check_id = ? AND checked_at_ms >= ? AND external = 0 ORDER BY id DESC",
+    )
+    .bind(&check.id)
+    .bind(since.timestamp_millis())
+    .fetch_all(db)
+    .await?;
+
+    let mut latest: HashMap<Option<String>, String> = HashMap::new();
+    if let Some(status) = local {
+        latest.insert(None, status.to_string());
+    }
+    for (location, status) in rows {
+        latest.entry(location).or_insert(status);
+    }
+    let down = latest.values().filter(|s| s.as_str() == "DOWN").count() as i64;
+
+    Ok(if down >= quorum { "DOWN" } else { "UP" }.to_string())
+}
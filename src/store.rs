@@ -0,0 +1,1167 @@
+//! SQLite access: migrations, the check repository, secrets, audit log and backups.
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use anyhow::Context;
+use axum::http::StatusCode;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteSynchronous};
+use sqlx::types::Json;
+use sqlx::{Connection, Pool, Sqlite, SqliteConnection};
+use std::env;
+use std::{
+    collections::{BTreeMap, HashMap},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::time::sleep;
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::api::{
+    generate_token, hash_token, internal_error, require_cipher, Caller, CreateApiKeyResponse,
+};
+use crate::domain::{
+    failure_cause, rollup_bucket, AnnotationRow, CheckRow, GroupMember, IncidentRow,
+    MaintenanceWindowRow, Plan, ProbeDefaults, ResultRow, RollupDelta, StatusPageRow, UserRow,
+};
+use crate::scheduler::PendingWrite;
+use crate::AppState;
+
+pub type Db = Pool<Sqlite>;
+
+/// Opens the database at `url`, creating it if needed, checks it is sound (see
+/// [`check_integrity`]) and applies pending migrations. An in-memory database
+/// (`sqlite::memory:`) is kept on a single connection, since every new connection would
+/// otherwise open its own empty one.
+pub async fn connect(url: &str) -> anyhow::Result<Db> {
+    let opts = SqliteConnectOptions::from_str(url)?.create_if_missing(true);
+    if url.contains(":memory:") {
+        let db = SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect_with(opts)
+            .await?;
+        let mut conn = db.acquire().await?;
+        run_migrations(&mut conn).await?;
+        ensure_indexes(&mut conn).await?;
+        drop(conn);
+        return Ok(db);
+    }
+    let opts = opts
+        .journal_mode(SqliteJournalMode::Wal)
+        .synchronous(SqliteSynchronous::Normal);
+
+    // Migrations run on their own connection so pooled connections never see a stale schema
+    let mut conn = SqliteConnection::connect_with(&opts)
+        .await
+        .with_context(|| {
+            format!("opening the database {url}; if the file is corrupt, restore the latest backup")
+        })?;
+    check_integrity(&mut conn)
+        .await
+        .with_context(|| format!("refusing to start on {url}"))?;
+    run_migrations(&mut conn).await?;
+    ensure_indexes(&mut conn).await?;
+    conn.close().await?;
+
+    Ok(SqlitePoolOptions::new()
+        .max_connections(5)
+        .connect_with(opts)
+        .await?)
+}
+
+/// Reads of checks and their history. Lookups by org are the tenant-facing ones; the
+/// unscoped ones are for the worker. A `None` limit returns every row.
+pub(crate) trait CheckStore {
+    async fn org_checks(&self, org_id: &str) -> sqlx::Result<Vec<CheckRow>>;
+    async fn org_check(&self, org_id: &str, id: &str) -> sqlx::Result<Option<CheckRow>>;
+    async fn check(&self, id: &str) -> sqlx::Result<Option<CheckRow>>;
+    async fn active_checks(&self) -> sqlx::Result<Vec<CheckRow>>;
+    /// Newest first.
+    async fn results(&self, check_id: &str, limit: Option<i64>) -> sqlx::Result<Vec<ResultRow>>;
+    /// Oldest first, resuming after the `(checked_at, id)` of the last row already read.
+    async fn results_after(
+        &self,
+        check_id: &str,
+        after: Option<(DateTime<Utc>, i64)>,
+        limit: i64,
+    ) -> sqlx::Result<Vec<ResultRow>>;
+    /// Newest first.
+    async fn incidents(&self, check_id: &str, limit: Option<i64>)
+        -> sqlx::Result<Vec<IncidentRow>>;
+}
+
+impl CheckStore for Db {
+    async fn org_checks(&self, org_id: &str) -> sqlx::Result<Vec<CheckRow>> {
+        sqlx::query_as("SELECT * FROM checks WHERE org_id = ?")
+            .bind(org_id)
+            .fetch_all(self)
+            .await
+    }
+
+    async fn org_check(&self, org_id: &str, id: &str) -> sqlx::Result<Option<CheckRow>> {
+        sqlx::query_as("SELECT * FROM checks WHERE id = ? AND org_id = ?")
+            .bind(id)
+            .bind(org_id)
+            .fetch_optional(self)
+            .await
+    }
+
+    async fn check(&self, id: &str) -> sqlx::Result<Option<CheckRow>> {
+        sqlx::query_as("SELECT * FROM checks WHERE id = ?")
+            .bind(id)
+            .fetch_optional(self)
+            .await
+    }
+
+    async fn active_checks(&self) -> sqlx::Result<Vec<CheckRow>> {
+        sqlx::query_as("SELECT * FROM checks WHERE is_active = 1")
+            .fetch_all(self)
+            .await
+    }
+
+    async fn results(&self, check_id: &str, limit: Option<i64>) -> sqlx::Result<Vec<ResultRow>> {
+        // SQLite treats a negative LIMIT as no limit
+        sqlx::query_as(
+            "SELECT * FROM check_results WHERE check_id = ? ORDER BY checked_at_ms DESC, id DESC LIMIT ?",
+        )
+        .bind(check_id)
+        .bind(limit.unwrap_or(-1))
+        .fetch_all(self)
+        .await
+    }
+
+    async fn results_after(
+        &self,
+        check_id: &str,
+        after: Option<(DateTime<Utc>, i64)>,
+        limit: i64,
+    ) -> sqlx::Result<Vec<ResultRow>> {
+        match after {
+            // The cursor goes through the same conversion as the generated column so it
+            // matches the last row exactly
+            Some((checked_at, id)) => {
+                sqlx::query_as(
+                    "SELECT * FROM check_results WHERE check_id = ? \
+                     AND (checked_at_ms, id) > (CAST(round((julianday(?) - 2440587.5) * 86400000) AS INTEGER), ?) \
+                     ORDER BY checked_at_ms, id LIMIT ?",
+                )
+                .bind(check_id)
+                .bind(checked_at)
+                .bind(id)
+                .bind(limit)
+                .fetch_all(self)
+                .await
+            }
+            None => sqlx::query_as(
+                "SELECT * FROM check_results WHERE check_id = ? ORDER BY checked_at_ms, id LIMIT ?",
+            )
+            .bind(check_id)
+            .bind(limit)
+            .fetch_all(self)
+            .await,
+        }
+    }
+
+    async fn incidents(
+        &self,
+        check_id: &str,
+        limit: Option<i64>,
+    ) -> sqlx::Result<Vec<IncidentRow>> {
+        sqlx::query_as(
+            "SELECT * FROM incidents WHERE check_id = ? ORDER BY started_at DESC LIMIT ?",
+        )
+        .bind(check_id)
+        .bind(limit.unwrap_or(-1))
+        .fetch_all(self)
+        .await
+    }
+}
+
+/// AES-256-GCM cipher. The master key comes from `SECRETS_KEY` (base64, 32 bytes) or from
+/// the file named by `SECRETS_KEY_FILE`, e.g. one mounted by a KMS / secret manager.
+#[derive(Clone)]
+pub(crate) struct SecretCipher {
+    pub(crate) aead: Aes256Gcm,
+}
+
+impl SecretCipher {
+    pub(crate) fn from_env() -> anyhow::Result<Option<Self>> {
+        let encoded = match (env::var("SECRETS_KEY"), env::var("SECRETS_KEY_FILE")) {
+            (Ok(value), _) => value,
+            (Err(_), Ok(path)) => std::fs::read_to_string(path)?,
+            _ => return Ok(None),
+        };
+        let key = BASE64.decode(encoded.trim())?;
+        if key.len() != 32 {
+            anyhow::bail!("SECRETS_KEY must decode to 32 bytes");
+        }
+        Ok(Some(SecretCipher::from_key(&key)))
+    }
+
+    pub(crate) fn from_key(key: &[u8]) -> Self {
+        SecretCipher {
+            aead: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key)),
+        }
+    }
+
+    pub(crate) fn encrypt(&self, plaintext: &[u8]) -> String {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .aead
+            .encrypt(&nonce, plaintext)
+            .expect("AES-GCM encryption failed");
+        let mut out = nonce.to_vec();
+        out.extend_from_slice(&ciphertext);
+        BASE64.encode(out)
+    }
+
+    pub(crate) fn decrypt(&self, encoded: &str) -> Result<Vec<u8>, String> {
+        let raw = BASE64.decode(encoded).map_err(|e| e.to_string())?;
+        if raw.len() < 12 {
+            return Err("secreto cifrado inválido".into());
+        }
+        let (nonce, ciphertext) = raw.split_at(12);
+        self.aead
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| "no se pudo descifrar el secreto".to_string())
+    }
+}
+
+pub(crate) const MIGRATIONS: &[(&str, &str)] = &[
+    ("001_init", include_str!("../migrations/001_init.sql")),
+    (
+        "002_content_change",
+        include_str!("../migrations/002_content_change.sql"),
+    ),
+    (
+        "003_probe_dns",
+        include_str!("../migrations/003_probe_dns.sql"),
+    ),
+    (
+        "004_probe_proxy",
+        include_str!("../migrations/004_probe_proxy.sql"),
+    ),
+    (
+        "005_probe_mtls",
+        include_str!("../migrations/005_probe_mtls.sql"),
+    ),
+    ("006_secrets", include_str!("../migrations/006_secrets.sql")),
+    ("007_agents", include_str!("../migrations/007_agents.sql")),
+    ("008_quorum", include_str!("../migrations/008_quorum.sql")),
+    ("009_leases", include_str!("../migrations/009_leases.sql")),
+    ("010_jitter", include_str!("../migrations/010_jitter.sql")),
+    ("011_rollups", include_str!("../migrations/011_rollups.sql")),
+    (
+        "012_incidents",
+        include_str!("../migrations/012_incidents.sql"),
+    ),
+    (
+        "013_incident_ack",
+        include_str!("../migrations/013_incident_ack.sql"),
+    ),
+    (
+        "014_incident_stats",
+        include_str!("../migrations/014_incident_stats.sql"),
+    ),
+    (
+        "015_notification_channels",
+        include_str!("../migrations/015_notification_channels.sql"),
+    ),
+    (
+        "016_quiet_hours",
+        include_str!("../migrations/016_quiet_hours.sql"),
+    ),
+    (
+        "017_alert_digest",
+        include_str!("../migrations/017_alert_digest.sql"),
+    ),
+    (
+        "018_check_dependencies",
+        include_str!("../migrations/018_check_dependencies.sql"),
+    ),
+    (
+        "019_verified_emails",
+        include_str!("../migrations/019_verified_emails.sql"),
+    ),
+    ("020_users", include_str!("../migrations/020_users.sql")),
+    ("021_orgs", include_str!("../migrations/021_orgs.sql")),
+    (
+        "022_audit_log",
+        include_str!("../migrations/022_audit_log.sql"),
+    ),
+    ("023_plans", include_str!("../migrations/023_plans.sql")),
+    ("024_billing", include_str!("../migrations/024_billing.sql")),
+    (
+        "025_idempotency_keys",
+        include_str!("../migrations/025_idempotency_keys.sql"),
+    ),
+    (
+        "026_check_versions",
+        include_str!("../migrations/026_check_versions.sql"),
+    ),
+    (
+        "027_notifications_sent",
+        include_str!("../migrations/027_notifications_sent.sql"),
+    ),
+    (
+        "028_latency_baselines",
+        include_str!("../migrations/028_latency_baselines.sql"),
+    ),
+    (
+        "029_maintenance_windows",
+        include_str!("../migrations/029_maintenance_windows.sql"),
+    ),
+    (
+        "030_status_pages",
+        include_str!("../migrations/030_status_pages.sql"),
+    ),
+    (
+        "031_incident_updates",
+        include_str!("../migrations/031_incident_updates.sql"),
+    ),
+    (
+        "032_check_notifications",
+        include_str!("../migrations/032_check_notifications.sql"),
+    ),
+    (
+        "033_severity",
+        include_str!("../migrations/033_severity.sql"),
+    ),
+    (
+        "034_response_size",
+        include_str!("../migrations/034_response_size.sql"),
+    ),
+    (
+        "035_probe_headers",
+        include_str!("../migrations/035_probe_headers.sql"),
+    ),
+    ("036_backoff", include_str!("../migrations/036_backoff.sql")),
+    (
+        "037_accepted_statuses",
+        include_str!("../migrations/037_accepted_statuses.sql"),
+    ),
+    (
+        "038_check_groups",
+        include_str!("../migrations/038_check_groups.sql"),
+    ),
+    (
+        "039_http_version",
+        include_str!("../migrations/039_http_version.sql"),
+    ),
+    (
+        "040_connect_to",
+        include_str!("../migrations/040_connect_to.sql"),
+    ),
+    (
+        "041_error_kind",
+        include_str!("../migrations/041_error_kind.sql"),
+    ),
+    ("042_oncall", include_str!("../migrations/042_oncall.sql")),
+    (
+        "043_notification_outbox",
+        include_str!("../migrations/043_notification_outbox.sql"),
+    ),
+    (
+        "044_channel_secrets",
+        include_str!("../migrations/044_channel_secrets.sql"),
+    ),
+    (
+        "045_annotations",
+        include_str!("../migrations/045_annotations.sql"),
+    ),
+    (
+        "046_results_epoch",
+        include_str!("../migrations/046_results_epoch.sql"),
+    ),
+    ("047_cron", include_str!("../migrations/047_cron.sql")),
+    (
+        "048_auth_enabled",
+        include_str!("../migrations/048_auth_enabled.sql"),
+    ),
+    (
+        "049_outbox_check",
+        include_str!("../migrations/049_outbox_check.sql"),
+    ),
+    (
+        "050_ingest_sources",
+        include_str!("../migrations/050_ingest_sources.sql"),
+    ),
+    (
+        "051_rollup_errors",
+        include_str!("../migrations/051_rollup_errors.sql"),
+    ),
+    (
+        "052_outbox_recipients",
+        include_str!("../migrations/052_outbox_recipients.sql"),
+    ),
+];
+
+/// Fails on a corrupt file or on rows pointing at missing parents, so a damaged database stops
+/// the service at startup rather than failing requests at random later.
+pub(crate) async fn check_integrity(db: &mut SqliteConnection) -> anyhow::Result<()> {
+    let problems: Vec<String> = sqlx::query_scalar("PRAGMA integrity_check(20)")
+        .fetch_all(&mut *db)
+        .await
+        .context("the file is not a readable SQLite database; restore the latest backup")?;
+    if problems != ["ok"] {
+        anyhow::bail!(
+            "PRAGMA integrity_check found corruption:\n  {}\nrestore the latest backup (BACKUP_DIR) or salvage the rows with `sqlite3 <file> .recover`",
+            problems.join("\n  ")
+        );
+    }
+
+    let orphans: Vec<(String, Option<i64>, String)> =
+        sqlx::query_as("SELECT \"table\", rowid, parent FROM pragma_foreign_key_check")
+            .fetch_all(&mut *db)
+            .await?;
+    if !orphans.is_empty() {
+        let mut by_table: BTreeMap<(String, String), Vec<i64>> = BTreeMap::new();
+        for (table, rowid, parent) in orphans {
+            by_table.entry((table, parent)).or_default().extend(rowid);
+        }
+        let details: Vec<String> = by_table
+            .into_iter()
+            .map(|((table, parent), rowids)| {
+                let sample: Vec<String> = rowids.iter().take(10).map(i64::to_string).collect();
+                format!(
+                    "{} rows of {table} reference missing {parent} rows (rowid {})",
+                    rowids.len(),
+                    sample.join(", ")
+                )
+            })
+            .collect();
+        anyhow::bail!(
+            "PRAGMA foreign_key_check failed:\n  {}\ndelete or repair those rows, e.g. `DELETE FROM <table> WHERE rowid IN (...)`, then start again",
+            details.join("\n  ")
+        );
+    }
+    Ok(())
+}
+
+/// Indexes the hot paths depend on. Migrations create them, but one dropped by hand (or lost
+/// to a `.recover`) is recreated at startup instead of turning every lookup into a scan.
+pub(crate) const REQUIRED_INDEXES: &[(&str, &str)] = &[
+    (
+        "idx_results_check_epoch",
+        "CREATE INDEX idx_results_check_epoch ON check_results(check_id, checked_at_ms)",
+    ),
+    (
+        "idx_results_check_errors",
+        "CREATE INDEX idx_results_check_errors ON check_results(check_id, error_kind, checked_at_ms) WHERE error_kind IS NOT NULL",
+    ),
+    (
+        "idx_checks_org",
+        "CREATE INDEX idx_checks_org ON checks(org_id)",
+    ),
+    (
+        "idx_incidents_check_time",
+        "CREATE INDEX idx_incidents_check_time ON incidents(check_id, started_at)",
+    ),
+    (
+        "idx_memberships_user",
+        "CREATE INDEX idx_memberships_user ON memberships(user_id)",
+    ),
+    (
+        "idx_audit_log_org_created",
+        "CREATE INDEX idx_audit_log_org_created ON audit_log(org_id, created_at)",
+    ),
+    (
+        "idx_notification_outbox_due",
+        "CREATE INDEX idx_notification_outbox_due ON notification_outbox(status, next_attempt_at)",
+    ),
+    (
+        "idx_annotations_check_at",
+        "CREATE INDEX idx_annotations_check_at ON annotations(check_id, at)",
+    ),
+];
+
+pub(crate) async fn ensure_indexes(db: &mut SqliteConnection) -> anyhow::Result<()> {
+    for (name, sql) in REQUIRED_INDEXES {
+        let exists: Option<String> =
+            sqlx::query_scalar("SELECT name FROM sqlite_master WHERE type = 'index' AND name = ?")
+                .bind(name)
+                .fetch_optional(&mut *db)
+                .await?;
+        if exists.is_none() {
+            tracing::warn!("Index {name} was missing, creating it");
+            sqlx::query(sql)
+                .execute(&mut *db)
+                .await
+                .with_context(|| format!("creating the missing index {name}"))?;
+        }
+    }
+    Ok(())
+}
+
+pub(crate) async fn run_migrations(db: &mut SqliteConnection) -> anyhow::Result<()> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (version TEXT PRIMARY KEY, applied_at TEXT NOT NULL)",
+    )
+    .execute(&mut *db)
+    .await?;
+
+    for (version, sql) in MIGRATIONS {
+        let applied: Option<String> =
+            sqlx::query_scalar("SELECT version FROM schema_migrations WHERE version = ?")
+                .bind(version)
+                .fetch_optional(&mut *db)
+                .await?;
+        if applied.is_some() {
+            continue;
+        }
+
+        let mut tx = db.begin().await?;
+        for stmt in sql.split(';') {
+            let stmt = stmt.trim();
+            if !stmt.is_empty() {
+                sqlx::query(stmt).execute(&mut *tx).await?;
+            }
+        }
+        sqlx::query("INSERT INTO schema_migrations (version, applied_at) VALUES (?, ?)")
+            .bind(version)
+            .bind(Utc::now())
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        info!("Applied migration {version}");
+    }
+    Ok(())
+}
+
+/// Whether an API key was ever created. Until then the API is open, as there's nobody to
+/// authenticate; afterwards it never reopens.
+pub(crate) async fn auth_enabled(db: &Db) -> Result<bool, sqlx::Error> {
+    let (enabled,): (bool,) = sqlx::query_as("SELECT auth_enabled FROM instance_settings")
+        .fetch_one(db)
+        .await?;
+    Ok(enabled)
+}
+
+pub(crate) async fn org_plan(db: &Db, org_id: &str) -> Result<&'static Plan, sqlx::Error> {
+    let (plan,): (String,) = sqlx::query_as("SELECT plan FROM orgs WHERE id = ?")
+        .bind(org_id)
+        .fetch_one(db)
+        .await?;
+    Ok(Plan::named(&plan))
+}
+
+pub(crate) async fn count_checks(db: &Db, org_id: &str) -> Result<i64, sqlx::Error> {
+    let (checks,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM checks WHERE org_id = ?")
+        .bind(org_id)
+        .fetch_one(db)
+        .await?;
+    Ok(checks)
+}
+
+/// The first upstream check (direct or transitive) that is currently DOWN.
+pub(crate) async fn down_upstream(
+    db: &Db,
+    check_id: &str,
+) -> Result<Option<CheckRow>, sqlx::Error> {
+    sqlx::query_as::<_, CheckRow>(
+        r#"
+        WITH RECURSIVE up(id) AS (
+          SELECT depends_on_id FROM check_dependencies WHERE check_id = ?
+          UNION
+          SELECT d.depends_on_id FROM check_dependencies d JOIN up ON d.check_id = up.id
+        )
+        SELECT checks.* FROM checks JOIN up ON checks.id = up.id
+        WHERE checks.last_status = 'DOWN'
+        LIMIT 1
+        "#,
+    )
+    .bind(check_id)
+    .fetch_optional(db)
+    .await
+}
+
+pub(crate) async fn is_email_verified(db: &Db, email: &str) -> Result<bool, sqlx::Error> {
+    let row: Option<(String,)> =
+        sqlx::query_as("SELECT email FROM verified_emails WHERE email = ?")
+            .bind(email)
+            .fetch_optional(db)
+            .await?;
+    Ok(row.is_some())
+}
+
+/// One audit log row. `before` and `after` are JSON snapshots of the entity; `actor` is the
+/// user's email, `anonymous` in open mode or `telegram` for bot commands.
+pub(crate) struct AuditEntry<'a> {
+    pub(crate) org_id: &'a str,
+    pub(crate) actor_id: Option<&'a str>,
+    pub(crate) actor: &'a str,
+    pub(crate) action: &'a str,
+    pub(crate) entity_type: &'a str,
+    pub(crate) entity_id: &'a str,
+    pub(crate) before: Option<serde_json::Value>,
+    pub(crate) after: Option<serde_json::Value>,
+}
+
+pub(crate) fn snapshot(entity: &impl Serialize) -> Option<serde_json::Value> {
+    serde_json::to_value(entity).ok()
+}
+
+pub(crate) async fn record_audit<'e, E>(
+    executor: E,
+    entry: AuditEntry<'_>,
+) -> Result<(), sqlx::Error>
+where
+    E: sqlx::Executor<'e, Database = Sqlite>,
+{
+    sqlx::query(
+        "INSERT INTO audit_log (org_id, actor_id, actor, action, entity_type, entity_id, before, after, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(entry.org_id)
+    .bind(entry.actor_id)
+    .bind(entry.actor)
+    .bind(entry.action)
+    .bind(entry.entity_type)
+    .bind(entry.entity_id)
+    .bind(entry.before.map(sqlx::types::Json))
+    .bind(entry.after.map(sqlx::types::Json))
+    .bind(Utc::now())
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Envelope encryption: every secret gets a fresh data key, which is itself encrypted
+/// ("wrapped") with the master key. Rotating the master key only re-wraps data keys.
+pub(crate) async fn store_secret(
+    db: &Db,
+    master: &SecretCipher,
+    org_id: &str,
+    name: &str,
+    value: &str,
+) -> Result<String, sqlx::Error> {
+    let data_key = Aes256Gcm::generate_key(&mut OsRng);
+    let ciphertext = SecretCipher::from_key(&data_key).encrypt(value.as_bytes());
+    let wrapped_key = master.encrypt(&data_key);
+    let id = Uuid::new_v4().to_string();
+
+    sqlx::query(
+        "INSERT INTO secrets (id, name, wrapped_key, ciphertext, created_at, org_id) VALUES (?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&id)
+    .bind(name)
+    .bind(wrapped_key)
+    .bind(ciphertext)
+    .bind(Utc::now())
+    .bind(org_id)
+    .execute(db)
+    .await?;
+
+    Ok(id)
+}
+
+/// Checks can only reference secrets of their own organization.
+pub(crate) async fn ensure_org_secret(
+    state: &AppState,
+    caller: &Caller,
+    id: &str,
+) -> Result<(), (StatusCode, String)> {
+    let row: Option<(String,)> =
+        sqlx::query_as("SELECT id FROM secrets WHERE id = ? AND org_id = ?")
+            .bind(id)
+            .bind(&caller.org_id)
+            .fetch_optional(&state.db)
+            .await
+            .map_err(internal_error)?;
+    row.map(|_| ())
+        .ok_or((StatusCode::BAD_REQUEST, format!("secreto {id} no existe")))
+}
+
+pub(crate) async fn load_secret(
+    state: &AppState,
+    id: &str,
+) -> Result<String, (StatusCode, String)> {
+    let master = require_cipher(state)?;
+    let row: Option<(String, String)> =
+        sqlx::query_as("SELECT wrapped_key, ciphertext FROM secrets WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&state.db)
+            .await
+            .map_err(internal_error)?;
+    let (wrapped_key, ciphertext) =
+        row.ok_or((StatusCode::BAD_REQUEST, format!("secreto {id} no existe")))?;
+
+    let decrypt = || -> Result<String, String> {
+        let data_key = master.decrypt(&wrapped_key)?;
+        let value = SecretCipher::from_key(&data_key).decrypt(&ciphertext)?;
+        String::from_utf8(value).map_err(|e| e.to_string())
+    };
+    decrypt().map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))
+}
+
+/// Newest first.
+pub(crate) async fn check_annotations(
+    db: &Db,
+    check_id: &str,
+) -> Result<Vec<AnnotationRow>, sqlx::Error> {
+    sqlx::query_as::<_, AnnotationRow>(
+        "SELECT * FROM annotations WHERE check_id = ? ORDER BY at DESC",
+    )
+    .bind(check_id)
+    .fetch_all(db)
+    .await
+}
+
+/// The check's own maintenance windows and those of its whole organization.
+pub(crate) async fn maintenance_windows(
+    db: &Db,
+    check_id: &str,
+) -> Result<Vec<MaintenanceWindowRow>, sqlx::Error> {
+    sqlx::query_as::<_, MaintenanceWindowRow>(
+        r#"
+        SELECT * FROM maintenance_windows
+        WHERE org_id = (SELECT org_id FROM checks WHERE id = ?1) AND (check_id IS NULL OR check_id = ?1)
+        "#,
+    )
+    .bind(check_id)
+    .fetch_all(db)
+    .await
+}
+
+pub(crate) async fn in_maintenance(
+    db: &Db,
+    check_id: &str,
+    at: DateTime<Utc>,
+) -> Result<bool, sqlx::Error> {
+    Ok(maintenance_windows(db, check_id)
+        .await?
+        .iter()
+        .any(|w| w.covers(at)))
+}
+
+pub(crate) async fn probe_defaults(db: &Db, org_id: &str) -> Result<ProbeDefaults, sqlx::Error> {
+    let (user_agent, headers): (Option<String>, Option<Json<BTreeMap<String, String>>>) =
+        sqlx::query_as("SELECT probe_user_agent, probe_headers FROM orgs WHERE id = ?")
+            .bind(org_id)
+            .fetch_optional(db)
+            .await?
+            .unwrap_or_default();
+    Ok(ProbeDefaults {
+        user_agent,
+        headers: headers.map(|h| h.0).unwrap_or_default(),
+    })
+}
+
+/// Ordered by name.
+pub(crate) async fn group_members(
+    db: &Db,
+    group_id: &str,
+) -> Result<Vec<GroupMember>, sqlx::Error> {
+    sqlx::query_as(
+        r#"
+        SELECT c.id, c.name, c.last_status AS status FROM checks c
+        JOIN check_group_members m ON m.check_id = c.id
+        WHERE m.group_id = ? ORDER BY c.name
+        "#,
+    )
+    .bind(group_id)
+    .fetch_all(db)
+    .await
+}
+
+pub(crate) async fn org_status_pages(
+    db: &Db,
+    org_id: &str,
+) -> Result<Vec<StatusPageRow>, sqlx::Error> {
+    let mut pages = sqlx::query_as::<_, StatusPageRow>(
+        "SELECT * FROM status_pages WHERE org_id = ? ORDER BY created_at",
+    )
+    .bind(org_id)
+    .fetch_all(db)
+    .await?;
+    for page in &mut pages {
+        page.check_ids = status_page_check_ids(db, &page.id).await?;
+    }
+    Ok(pages)
+}
+
+pub(crate) async fn status_page_check_ids(
+    db: &Db,
+    status_page_id: &str,
+) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT check_id FROM status_page_checks WHERE status_page_id = ? ORDER BY check_id",
+    )
+    .bind(status_page_id)
+    .fetch_all(db)
+    .await
+}
+
+/// Raises the checks probed more often than the server and their plan allow, e.g. after
+/// `MIN_INTERVAL_SECONDS` went up or an organization moved to a smaller plan. Windows that
+/// must span an interval grow with it.
+pub(crate) async fn clamp_check_intervals(state: &AppState) -> anyhow::Result<()> {
+    let orgs: Vec<(String, String)> = sqlx::query_as(
+        "SELECT id, plan FROM orgs WHERE id IN (SELECT DISTINCT org_id FROM checks)",
+    )
+    .fetch_all(&state.db)
+    .await?;
+    for (org_id, plan) in orgs {
+        let floor = Plan::named(&plan).interval_floor(state.min_interval_seconds);
+        let clamped: Vec<(String, i64)> = sqlx::query_as(
+            "SELECT name, interval_seconds FROM checks WHERE org_id = ? AND interval_seconds < ?",
+        )
+        .bind(&org_id)
+        .bind(floor)
+        .fetch_all(&state.db)
+        .await?;
+        for (name, interval_seconds) in &clamped {
+            tracing::warn!(
+                "Check {name} of {org_id} ran every {interval_seconds}s, below the minimum of {floor}s: raised to {floor}s"
+            );
+        }
+        sqlx::query(
+            r#"
+            UPDATE checks SET interval_seconds = ?1,
+              quorum_window_seconds = MAX(quorum_window_seconds, ?1),
+              backoff_max_seconds = MAX(backoff_max_seconds, ?1), version = version + 1
+            WHERE org_id = ?2 AND interval_seconds < ?1
+            "#,
+        )
+        .bind(floor)
+        .bind(&org_id)
+        .execute(&state.db)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Client keys used to be encrypted inline on `checks`; move them into `secrets`.
+pub(crate) async fn migrate_legacy_client_keys(state: &AppState) -> anyhow::Result<()> {
+    let legacy: Vec<(String, String, String, String)> = sqlx::query_as(
+        "SELECT id, org_id, name, client_key_encrypted FROM checks WHERE client_key_encrypted IS NOT NULL",
+    )
+    .fetch_all(&state.db)
+    .await?;
+    if legacy.is_empty() {
+        return Ok(());
+    }
+    let Some(master) = &state.cipher else {
+        tracing::warn!(
+            "{} checks have legacy client keys but SECRETS_KEY is not set",
+            legacy.len()
+        );
+        return Ok(());
+    };
+
+    for (check_id, org_id, name, encrypted) in legacy {
+        let key = String::from_utf8(master.decrypt(&encrypted).map_err(anyhow::Error::msg)?)?;
+        let secret_id = store_secret(
+            &state.db,
+            master,
+            &org_id,
+            &format!("{name} client key"),
+            &key,
+        )
+        .await?;
+        sqlx::query(
+            "UPDATE checks SET client_key_secret_id = ?, client_key_encrypted = NULL WHERE id = ?",
+        )
+        .bind(&secret_id)
+        .bind(&check_id)
+        .execute(&state.db)
+        .await?;
+        info!("Moved client key of check {check_id} to secret {secret_id}");
+    }
+    Ok(())
+}
+
+/// A user with their role in `org_id`, if they are a member.
+pub(crate) async fn find_member(
+    state: &AppState,
+    org_id: &str,
+    user_id: &str,
+) -> Result<Option<UserRow>, (StatusCode, String)> {
+    load_member(&state.db, org_id, user_id)
+        .await
+        .map_err(internal_error)
+}
+
+/// [`find_member`] within a transaction.
+pub(crate) async fn load_member<'e, E>(
+    executor: E,
+    org_id: &str,
+    user_id: &str,
+) -> Result<Option<UserRow>, sqlx::Error>
+where
+    E: sqlx::Executor<'e, Database = Sqlite>,
+{
+    sqlx::query_as::<_, UserRow>(
+        r#"
+        SELECT users.id, users.email, users.name, memberships.role, users.created_at
+        FROM users JOIN memberships ON memberships.user_id = users.id
+        WHERE memberships.org_id = ? AND users.id = ?
+        "#,
+    )
+    .bind(org_id)
+    .bind(user_id)
+    .fetch_optional(executor)
+    .await
+}
+
+/// The id of the user with that email, created with `name` if there is none.
+pub(crate) async fn upsert_user(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    email: &str,
+    name: &str,
+) -> Result<String, sqlx::Error> {
+    let existing: Option<(String,)> = sqlx::query_as("SELECT id FROM users WHERE email = ?")
+        .bind(email)
+        .fetch_optional(&mut **tx)
+        .await?;
+    if let Some((id,)) = existing {
+        return Ok(id);
+    }
+
+    let id = Uuid::new_v4().to_string();
+    sqlx::query("INSERT INTO users (id, email, name, created_at) VALUES (?, ?, ?, ?)")
+        .bind(&id)
+        .bind(email)
+        .bind(name)
+        .bind(Utc::now())
+        .execute(&mut **tx)
+        .await?;
+    Ok(id)
+}
+
+/// Turns authentication on, see [`auth_enabled`].
+pub(crate) async fn insert_api_key(
+    conn: &mut SqliteConnection,
+    user_id: &str,
+    name: &str,
+) -> Result<CreateApiKeyResponse, sqlx::Error> {
+    let id = Uuid::new_v4().to_string();
+    let token = generate_token();
+
+    sqlx::query(
+        "INSERT INTO api_keys (id, user_id, name, token_hash, created_at) VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(&id)
+    .bind(user_id)
+    .bind(name)
+    .bind(hash_token(&token))
+    .bind(Utc::now())
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query("UPDATE instance_settings SET auth_enabled = 1")
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(CreateApiKeyResponse { id, token })
+}
+
+/// Where database snapshots go: `BACKUP_DIR` (`data/backups`) keeps the newest `BACKUP_KEEP`
+/// (7), and with S3 configured each one is also uploaded under `BACKUP_S3_PREFIX`.
+/// `BACKUP_INTERVAL_HOURS` takes one on a schedule.
+pub(crate) struct Backups {
+    pub(crate) dir: std::path::PathBuf,
+    pub(crate) keep: usize,
+    pub(crate) s3_prefix: String,
+    pub(crate) interval_hours: Option<u64>,
+    pub(crate) running: tokio::sync::Mutex<()>,
+}
+
+impl Backups {
+    pub(crate) fn from_env() -> Self {
+        Backups {
+            dir: env::var("BACKUP_DIR")
+                .unwrap_or_else(|_| "data/backups".to_string())
+                .into(),
+            keep: env::var("BACKUP_KEEP")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(7),
+            s3_prefix: env::var("BACKUP_S3_PREFIX").unwrap_or_else(|_| "backups/".to_string()),
+            interval_hours: env::var("BACKUP_INTERVAL_HOURS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|hours| *hours > 0),
+            running: tokio::sync::Mutex::new(()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct Backup {
+    pub(crate) file: String,
+    pub(crate) bytes: u64,
+    pub(crate) s3_key: Option<String>,
+    pub(crate) created_at: DateTime<Utc>,
+}
+
+/// `VACUUM INTO` copies a consistent snapshot while the service keeps writing, without
+/// touching the WAL of the live database. Snapshots are named after the millisecond they were
+/// taken, or the next free one, so that back-to-back backups don't collide and still sort.
+pub(crate) async fn create_backup(state: &AppState) -> anyhow::Result<Backup> {
+    let backups = &state.backups;
+    let _running = backups.running.lock().await;
+    tokio::fs::create_dir_all(&backups.dir).await?;
+
+    let now = Utc::now();
+    let mut taken_at = now;
+    let (name, path) = loop {
+        let name = format!("uptime-{}.db", taken_at.format("%Y%m%dT%H%M%S%3fZ"));
+        let path = backups.dir.join(&name);
+        if !tokio::fs::try_exists(&path).await? {
+            break (name, path);
+        }
+        taken_at += chrono::Duration::milliseconds(1);
+    };
+    sqlx::query("VACUUM INTO ?")
+        .bind(path.to_string_lossy().to_string())
+        .execute(&state.db)
+        .await?;
+    let bytes = tokio::fs::metadata(&path).await?.len();
+
+    let s3_key = match &state.s3 {
+        Some(s3) => {
+            let key = format!("{}{name}", backups.s3_prefix);
+            s3.put_object(
+                &key,
+                tokio::fs::read(&path).await?,
+                "application/vnd.sqlite3",
+            )
+            .await?;
+            Some(key)
+        }
+        None => None,
+    };
+
+    let mut snapshots = Vec::new();
+    let mut entries = tokio::fs::read_dir(&backups.dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let file = entry.file_name().to_string_lossy().to_string();
+        if file.starts_with("uptime-") && file.ends_with(".db") {
+            snapshots.push(file);
+        }
+    }
+    snapshots.sort();
+    let excess = snapshots.len().saturating_sub(backups.keep.max(1));
+    for old in &snapshots[..excess] {
+        tokio::fs::remove_file(backups.dir.join(old)).await?;
+    }
+
+    Ok(Backup {
+        file: path.to_string_lossy().to_string(),
+        bytes,
+        s3_key,
+        created_at: now,
+    })
+}
+
+pub(crate) async fn backup_loop(state: Arc<AppState>, hours: u64) {
+    loop {
+        sleep(Duration::from_secs(hours * 3600)).await;
+        match create_backup(&state).await {
+            Ok(backup) => info!("Scheduled backup {} ({} bytes)", backup.file, backup.bytes),
+            Err(e) => error!("Error creating scheduled backup: {e}"),
+        }
+    }
+}
+
+pub(crate) async fn flush_writes(db: &Db, batch: &[PendingWrite]) -> Result<(), sqlx::Error> {
+    let mut rollups: HashMap<(&str, String), RollupDelta> = HashMap::new();
+    let mut updates: HashMap<&str, &PendingWrite> = HashMap::new();
+    for write in batch {
+        let r = &write.result;
+        rollups
+            .entry((r.check_id.as_str(), rollup_bucket(r.checked_at)))
+            .or_default()
+            .add(
+                &r.status,
+                r.latency_ms,
+                r.response_bytes,
+                r.error_kind,
+                r.planned,
+            );
+        updates.insert(write.update.check_id.as_str(), write);
+    }
+
+    let mut tx = db.begin().await?;
+    for r in batch.iter().filter(|w| w.persist).map(|w| &w.result) {
+        sqlx::query(
+            "INSERT INTO check_results (check_id, checked_at, status, http_status, latency_ms, error, content_hash, response_bytes, protocol, error_kind) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&r.check_id)
+        .bind(r.checked_at)
+        .bind(&r.status)
+        .bind(r.http_status)
+        .bind(r.latency_ms)
+        .bind(r.error.as_deref())
+        .bind(r.content_hash.as_deref())
+        .bind(r.response_bytes)
+        .bind(r.protocol)
+        .bind(r.error_kind)
+        .execute(&mut *tx)
+        .await?;
+    }
+    for ((check_id, bucket), delta) in &rollups {
+        delta.apply(&mut tx, check_id, bucket).await?;
+    }
+    for r in batch
+        .iter()
+        .map(|w| &w.result)
+        .filter(|r| r.status == "DOWN")
+    {
+        let cause = failure_cause(r.error.as_deref(), r.http_status);
+        record_incident_failure(&mut *tx, &r.check_id, cause.as_deref(), r.latency_ms).await?;
+    }
+    for write in updates.values() {
+        let u = &write.update;
+        sqlx::query(
+            r#"
+            UPDATE checks SET last_checked_at = ?, next_run_at = ?, content_hash = COALESCE(?, content_hash),
+              last_probe_status = ?, samples_since_persist = ?, last_status = COALESCE(?, last_status),
+              down_probes = ?, leased_by = NULL, leased_until = NULL
+            WHERE id = ? AND leased_by = ?
+            "#,
+        )
+        .bind(u.last_checked_at)
+        .bind(u.next_run_at)
+        .bind(u.content_hash.as_deref())
+        .bind(&u.last_probe_status)
+        .bind(u.samples_since_persist)
+        .bind(u.last_status.as_deref())
+        .bind(u.down_probes)
+        .bind(&u.check_id)
+        .bind(&write.leased_by)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await
+}
+
+/// Counts a failed probe against the check's open incident, if any.
+pub(crate) async fn record_incident_failure<'e, E>(
+    executor: E,
+    check_id: &str,
+    error: Option<&str>,
+    latency_ms: Option<i64>,
+) -> Result<(), sqlx::Error>
+where
+    E: sqlx::Executor<'e, Database = Sqlite>,
+{
+    sqlx::query(
+        r#"
+        UPDATE incidents SET failed_probes = failed_probes + 1, last_error = COALESCE(?1, last_error),
+          max_latency_ms = COALESCE(MAX(max_latency_ms, ?2), max_latency_ms, ?2)
+        WHERE check_id = ?3 AND resolved_at IS NULL
+        "#,
+    )
+    .bind(error)
+    .bind(latency_ms)
+    .bind(check_id)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
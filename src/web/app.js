@@ -7,6 +7,33 @@ const healthValue = document.getElementById("healthValue");
 let checksCache = [];
 let selectedId = null;
 
+// Once the server has API keys, requests need one; it is asked for once and remembered.
+// Users in several organizations pick one by storing its id as "orgId".
+async function apiFetch(url, options = {}) {
+  const send = () => {
+    const headers = { ...(options.headers || {}) };
+    const apiKey = localStorage.getItem("apiKey");
+    if (apiKey) {
+      headers.Authorization = `Bearer ${apiKey}`;
+    }
+    const orgId = localStorage.getItem("orgId");
+    if (orgId) {
+      headers["X-Org-Id"] = orgId;
+    }
+    return fetch(url, { ...options, headers });
+  };
+
+  let response = await send();
+  if (response.status === 401) {
+    const apiKey = prompt("API key");
+    if (apiKey) {
+      localStorage.setItem("apiKey", apiKey.trim());
+      response = await send();
+    }
+  }
+  return response;
+}
+
 async function fetchHealth() {
   try {
     const response = await fetch("/health");
@@ -22,7 +49,7 @@ async function fetchHealth() {
 }
 
 async function loadChecks() {
-  const response = await fetch("/checks");
+  const response = await apiFetch("/checks");
   if (!response.ok) {
     throw new Error("No se pudieron cargar los checks");
   }
@@ -74,7 +101,7 @@ async function selectCheck(id) {
 
 async function loadResults(id) {
   results.innerHTML = "Cargando...";
-  const response = await fetch(`/checks/${id}/results`);
+  const response = await apiFetch(`/checks/${id}/results`);
   if (!response.ok) {
     results.innerHTML = "No se pudieron cargar los resultados.";
     return;
@@ -105,6 +132,9 @@ function renderResults(list) {
         <strong> Latencia:</strong> ${result.latency_ms ?? "-"} ms
       </div>
       <div>${result.error ? `Error: ${result.error}` : ""}</div>
+      ${(result.annotations ?? [])
+        .map((note) => `<div class="annotation">📝 ${note.text} — ${note.author}</div>`)
+        .join("")}
     `;
 
     results.appendChild(item);
@@ -125,7 +155,7 @@ form.addEventListener("submit", async (event) => {
   };
 
   try {
-    const response = await fetch("/checks", {
+    const response = await apiFetch("/checks", {
       method: "POST",
       headers: {
         "Content-Type": "application/json",
@@ -0,0 +1,2107 @@
+mod common;
+
+use axum::http::{Method, StatusCode};
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use common::{wait_until, TestApp};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use wiremock::matchers::method;
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+#[tokio::test]
+async fn health_and_readiness() {
+    let app = TestApp::new().await;
+
+    assert_eq!(app.get("/health", None).await.status, StatusCode::OK);
+    let ready = app.get("/readyz", None).await;
+    assert_eq!(ready.status, StatusCode::OK, "{}", ready.body);
+    assert_eq!(ready.body["database"]["ok"], true);
+}
+
+#[tokio::test]
+async fn readiness_reports_what_is_degraded() {
+    let app = TestApp::new().await;
+    let app = &app;
+    wait_until(|| async move { app.get("/healthz", None).await.status == StatusCode::OK }).await;
+    let live = app.get("/healthz", None).await;
+    assert_eq!(live.body["ok"], true);
+    assert!(live.body["detail"]
+        .as_str()
+        .unwrap()
+        .starts_with("last scheduler pass"));
+
+    let version: (String,) = sqlx::query_as("SELECT MAX(version) FROM schema_migrations")
+        .fetch_one(&app.db)
+        .await
+        .unwrap();
+    sqlx::query("DELETE FROM schema_migrations WHERE version = ?")
+        .bind(&version.0)
+        .execute(&app.db)
+        .await
+        .unwrap();
+    let ready = app.get("/readyz", None).await;
+    assert_eq!(ready.status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(ready.body["status"], "degraded");
+    assert_eq!(ready.body["database"]["ok"], true);
+    assert_eq!(ready.body["migrations"]["ok"], false);
+    assert_eq!(
+        ready.body["migrations"]["detail"],
+        format!("missing {}", version.0)
+    );
+    // Liveness only watches the scheduler
+    assert_eq!(app.get("/healthz", None).await.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn creates_lists_and_deletes_checks() {
+    let app = TestApp::new().await;
+
+    let payload =
+        json!({ "name": "example", "url": "https://example.com", "interval_seconds": 60 });
+    let created = app
+        .request(
+            Method::POST,
+            "/checks",
+            None,
+            &[("idempotency-key", "create-example")],
+            Some(payload.clone()),
+        )
+        .await;
+    assert_eq!(created.status, StatusCode::CREATED, "{}", created.body);
+    let id = created.body["id"].as_str().unwrap().to_string();
+    // An alert about it and a digest that also covers other checks wait in the outbox
+    for (outbox_id, check_id) in [("alert", Some(&id)), ("digest", None)] {
+        sqlx::query("INSERT INTO notification_outbox (id, org_id, text, body, next_attempt_at, created_at, check_id) VALUES (?, 'default', 'DOWN', '{}', ?, ?, ?)")
+            .bind(outbox_id)
+            .bind(Utc::now())
+            .bind(Utc::now())
+            .bind(check_id)
+            .execute(&app.db)
+            .await
+            .unwrap();
+    }
+    let check = app.get(&format!("/checks/{id}"), None).await;
+    assert_eq!(check.status, StatusCode::OK);
+    assert_eq!(check.body["url"], "https://example.com");
+    assert_eq!(check.body["is_active"], 1);
+    assert_eq!(
+        check.headers["etag"],
+        format!("\"{}\"", check.body["version"])
+    );
+
+    let list = app.get("/checks", None).await;
+    assert_eq!(list.body.as_array().unwrap().len(), 1);
+
+    let version = check.body["version"].to_string();
+    let deleted = app
+        .request(
+            Method::DELETE,
+            &format!("/checks/{id}"),
+            None,
+            &[("if-match", &version)],
+            None,
+        )
+        .await;
+    assert_eq!(deleted.status, StatusCode::NO_CONTENT, "{}", deleted.body);
+    let missing = app.get(&format!("/checks/{id}"), None).await;
+    assert_eq!(missing.status, StatusCode::NOT_FOUND);
+
+    let outbox: Vec<String> = sqlx::query_scalar("SELECT id FROM notification_outbox")
+        .fetch_all(&app.db)
+        .await
+        .unwrap();
+    assert_eq!(outbox, ["digest"]);
+    // The key no longer replays the deleted check
+    let recreated = app
+        .request(
+            Method::POST,
+            "/checks",
+            None,
+            &[("idempotency-key", "create-example")],
+            Some(payload),
+        )
+        .await;
+    assert_eq!(recreated.status, StatusCode::CREATED, "{}", recreated.body);
+    assert_ne!(recreated.body["id"], id.as_str());
+}
+
+#[tokio::test]
+async fn rejects_invalid_checks() {
+    let app = TestApp::new().await;
+
+    let bad_url = app
+        .post(
+            "/checks",
+            None,
+            json!({ "name": "ftp", "url": "ftp://example.com", "interval_seconds": 60 }),
+        )
+        .await;
+    assert_eq!(bad_url.status, StatusCode::BAD_REQUEST);
+
+    let too_often = app
+        .post(
+            "/checks",
+            None,
+            json!({ "name": "fast", "url": "https://example.com", "interval_seconds": 5 }),
+        )
+        .await;
+    assert_eq!(too_often.status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn updates_need_the_current_version() {
+    let app = TestApp::new().await;
+    let id = app.create_check(None, "https://example.com").await;
+    let path = format!("/checks/{id}");
+    let rename = Some(json!({ "name": "renamed" }));
+
+    let unconditional = app
+        .request(Method::PATCH, &path, None, &[], rename.clone())
+        .await;
+    assert_eq!(unconditional.status, StatusCode::PRECONDITION_REQUIRED);
+
+    let updated = app
+        .request(
+            Method::PATCH,
+            &path,
+            None,
+            &[("if-match", "\"1\"")],
+            rename.clone(),
+        )
+        .await;
+    assert_eq!(updated.status, StatusCode::OK, "{}", updated.body);
+    assert_eq!(updated.body["name"], "renamed");
+    assert_eq!(updated.body["version"], 2);
+
+    let stale = app
+        .request(Method::PATCH, &path, None, &[("if-match", "\"1\"")], rename)
+        .await;
+    assert_eq!(stale.status, StatusCode::PRECONDITION_FAILED);
+}
+
+#[tokio::test]
+async fn updates_are_validated_as_a_whole_and_clear_optional_fields() {
+    let app = TestApp::new().await;
+    let created = app
+        .post(
+            "/checks",
+            None,
+            json!({
+                "name": "api",
+                "url": "https://example.com",
+                "interval_seconds": 120,
+                "jitter_seconds": 100,
+                "alert_template": "{{ check.name }} is {{ status }}",
+                "accepted_statuses": "401",
+                "http_version": "2",
+                "connect_to": "192.0.2.10"
+            }),
+        )
+        .await;
+    assert_eq!(created.status, StatusCode::CREATED, "{}", created.body);
+    let path = format!("/checks/{}", created.body["id"].as_str().unwrap());
+    let patch = |body: Value| {
+        let path = &path;
+        let app = &app;
+        async move {
+            app.request(Method::PATCH, path, None, &[("if-match", "*")], Some(body))
+                .await
+        }
+    };
+
+    // The stored jitter no longer fits the new interval
+    let shorter = patch(json!({ "interval_seconds": 90 })).await;
+    assert_eq!(shorter.status, StatusCode::BAD_REQUEST);
+    let backoff = patch(json!({ "interval_seconds": 300, "backoff_max_seconds": 200 })).await;
+    assert_eq!(backoff.status, StatusCode::BAD_REQUEST);
+    let invalid = patch(json!({ "http_version": "3" })).await;
+    assert_eq!(invalid.status, StatusCode::BAD_REQUEST);
+
+    let cleared = patch(json!({
+        "alert_template": "",
+        "accepted_statuses": "",
+        "http_version": "",
+        "connect_to": ""
+    }))
+    .await;
+    assert_eq!(cleared.status, StatusCode::OK, "{}", cleared.body);
+    for field in [
+        "alert_template",
+        "accepted_statuses",
+        "http_version",
+        "connect_to",
+    ] {
+        assert_eq!(cleared.body[field], Value::Null, "{field}");
+    }
+    assert_eq!(cleared.body["interval_seconds"], 120);
+    assert_eq!(cleared.body["jitter_seconds"], 100);
+}
+
+#[tokio::test]
+async fn deletes_use_the_etag_of_the_check_read() {
+    let app = TestApp::new().await;
+    let id = app.create_check(None, "https://example.com").await;
+    let path = format!("/checks/{id}");
+
+    let read = app.get(&path, None).await;
+    let etag = read.headers["etag"].to_str().unwrap().to_string();
+    assert_eq!(etag, "\"1\"");
+    let renamed = app
+        .request(
+            Method::PATCH,
+            &path,
+            None,
+            &[("if-match", &etag)],
+            Some(json!({ "name": "renamed" })),
+        )
+        .await;
+    assert_eq!(renamed.headers["etag"], "\"2\"");
+
+    let unconditional = app.request(Method::DELETE, &path, None, &[], None).await;
+    assert_eq!(unconditional.status, StatusCode::PRECONDITION_REQUIRED);
+    // Deleting what was read before someone else's rename
+    let stale = app
+        .request(Method::DELETE, &path, None, &[("if-match", &etag)], None)
+        .await;
+    assert_eq!(stale.status, StatusCode::PRECONDITION_FAILED);
+    assert_eq!(app.get(&path, None).await.status, StatusCode::OK);
+
+    let current = app
+        .request(Method::DELETE, &path, None, &[("if-match", "\"2\"")], None)
+        .await;
+    assert_eq!(current.status, StatusCode::NO_CONTENT);
+    assert_eq!(app.get(&path, None).await.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn idempotency_key_replays_the_created_check() {
+    let app = TestApp::new().await;
+    let body = json!({ "name": "home", "url": "https://example.com", "interval_seconds": 60 });
+    let key = [("idempotency-key", "create-home")];
+
+    let first = app
+        .request(Method::POST, "/checks", None, &key, Some(body.clone()))
+        .await;
+    let retry = app
+        .request(Method::POST, "/checks", None, &key, Some(body))
+        .await;
+    assert_eq!(first.status, StatusCode::CREATED);
+    assert_eq!(first.body["id"], retry.body["id"]);
+    assert_eq!(
+        app.get("/checks", None)
+            .await
+            .body
+            .as_array()
+            .unwrap()
+            .len(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn idempotency_keys_are_tied_to_their_request_until_they_expire() {
+    let app = TestApp::new().await;
+    let key = [("idempotency-key", "create-api")];
+    let body = |url: &str| json!({ "name": "api", "url": url, "interval_seconds": 60 });
+
+    // A failed request leaves the key free for the corrected retry
+    let invalid = app
+        .request(Method::POST, "/checks", None, &key, Some(body("not a url")))
+        .await;
+    assert_eq!(invalid.status, StatusCode::BAD_REQUEST);
+    let first = app
+        .request(
+            Method::POST,
+            "/checks",
+            None,
+            &key,
+            Some(body("https://example.com")),
+        )
+        .await;
+    assert_eq!(first.status, StatusCode::CREATED, "{}", first.body);
+
+    let other = app
+        .request(
+            Method::POST,
+            "/checks",
+            None,
+            &key,
+            Some(body("https://api.example.com")),
+        )
+        .await;
+    assert_eq!(other.status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    sqlx::query("UPDATE idempotency_keys SET created_at = ?")
+        .bind(Utc::now() - Duration::days(2))
+        .execute(&app.db)
+        .await
+        .unwrap();
+    let after_expiry = app
+        .request(
+            Method::POST,
+            "/checks",
+            None,
+            &key,
+            Some(body("https://example.com")),
+        )
+        .await;
+    assert_eq!(after_expiry.status, StatusCode::CREATED);
+    assert_ne!(after_expiry.body["id"], first.body["id"]);
+}
+
+#[tokio::test]
+async fn first_api_key_turns_authentication_on_for_good() {
+    let app = TestApp::new().await;
+    assert_eq!(app.get("/checks", None).await.status, StatusCode::OK);
+
+    let admin = app.user_token(None, "admin@example.com", "admin").await;
+    assert_eq!(
+        app.get("/checks", None).await.status,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        app.get("/checks", Some("not-a-key")).await.status,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        app.get("/checks", Some(&admin)).await.status,
+        StatusCode::OK
+    );
+
+    // The last admin can't give up their only key, and losing every key doesn't reopen the API
+    let users = app.get("/users", Some(&admin)).await;
+    let admin_id = users.body[0]["id"].as_str().unwrap().to_string();
+    let keys = app
+        .get(&format!("/users/{admin_id}/api-keys"), Some(&admin))
+        .await;
+    let key_id = keys.body[0]["id"].as_str().unwrap().to_string();
+    let path = format!("/api-keys/{key_id}");
+    let last = app
+        .request(Method::DELETE, &path, Some(&admin), &[], None)
+        .await;
+    assert_eq!(last.status, StatusCode::CONFLICT, "{}", last.body);
+    let spare = app
+        .post(
+            &format!("/users/{admin_id}/api-keys"),
+            Some(&admin),
+            json!({ "name": "spare" }),
+        )
+        .await;
+    let spare = spare.body["token"].as_str().unwrap();
+    let rotated = app
+        .request(Method::DELETE, &path, Some(spare), &[], None)
+        .await;
+    assert_eq!(rotated.status, StatusCode::NO_CONTENT, "{}", rotated.body);
+    sqlx::query("DELETE FROM api_keys")
+        .execute(&app.db)
+        .await
+        .unwrap();
+    assert_eq!(
+        app.get("/checks", None).await.status,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[tokio::test]
+async fn rotating_bogus_tokens_share_the_ip_rate_limit() {
+    let app = TestApp::new().await;
+    let admin = app.user_token(None, "admin@example.com", "admin").await;
+
+    // The default burst is 100 requests
+    let mut limited = None;
+    for n in 0..101 {
+        let response = app.get("/checks", Some(&format!("bogus-{n}"))).await;
+        if response.status == StatusCode::TOO_MANY_REQUESTS {
+            limited = Some(n);
+            break;
+        }
+        assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    }
+    assert!(limited.is_some(), "bogus tokens were never limited");
+    assert!(app
+        .get("/checks", None)
+        .await
+        .headers
+        .contains_key("retry-after"));
+
+    // A real key keeps its own bucket
+    assert_eq!(
+        app.get("/checks", Some(&admin)).await.status,
+        StatusCode::OK
+    );
+}
+
+#[tokio::test]
+async fn rate_limit_works_without_connection_info() {
+    use axum::body::Body;
+    use tower::ServiceExt;
+
+    // As when an embedder serves the router without `into_make_service_with_connect_info`
+    let app = TestApp::new().await;
+    let router = uptime_saas::start(app.db.clone(), app.clock.clone())
+        .await
+        .unwrap();
+    let request = || {
+        axum::http::Request::get("/checks")
+            .body(Body::empty())
+            .unwrap()
+    };
+    let response = router.clone().oneshot(request()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Every such client shares one bucket
+    let mut limited = false;
+    for _ in 0..100 {
+        let response = router.clone().oneshot(request()).await.unwrap();
+        if response.status() == StatusCode::TOO_MANY_REQUESTS {
+            limited = true;
+            break;
+        }
+    }
+    assert!(limited);
+}
+
+#[tokio::test]
+async fn roles_limit_what_members_can_do() {
+    let app = TestApp::new().await;
+    let admin = app.user_token(None, "admin@example.com", "admin").await;
+    let editor = app
+        .user_token(Some(&admin), "editor@example.com", "editor")
+        .await;
+    let viewer = app
+        .user_token(Some(&admin), "viewer@example.com", "viewer")
+        .await;
+    let check = json!({ "name": "home", "url": "https://example.com", "interval_seconds": 60 });
+
+    let by_viewer = app.post("/checks", Some(&viewer), check.clone()).await;
+    assert_eq!(by_viewer.status, StatusCode::FORBIDDEN);
+    assert_eq!(
+        app.get("/checks", Some(&viewer)).await.status,
+        StatusCode::OK
+    );
+
+    let by_editor = app.post("/checks", Some(&editor), check).await;
+    assert_eq!(by_editor.status, StatusCode::CREATED);
+    assert_eq!(
+        app.get("/users", Some(&editor)).await.status,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(app.get("/users", Some(&admin)).await.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn only_admins_manage_channels_secrets_and_roles() {
+    let app = TestApp::new().await;
+    let admin = app.user_token(None, "admin@example.com", "admin").await;
+    let editor = app
+        .user_token(Some(&admin), "editor@example.com", "editor")
+        .await;
+    let id = app.create_check(Some(&editor), "https://example.com").await;
+    let channel =
+        json!({ "name": "hook", "kind": "webhook", "target": "https://hooks.example.com" });
+
+    for (path, body) in [
+        ("/channels", channel.clone()),
+        ("/secrets", json!({ "name": "token", "value": "x" })),
+        (
+            "/users",
+            json!({ "email": "new@example.com", "name": "new", "role": "admin" }),
+        ),
+    ] {
+        let by_editor = app.post(path, Some(&editor), body).await;
+        assert_eq!(by_editor.status, StatusCode::FORBIDDEN, "{path}");
+    }
+    for path in ["/channels", "/secrets", "/audit-log"] {
+        let by_editor = app.get(path, Some(&editor)).await;
+        assert_eq!(by_editor.status, StatusCode::FORBIDDEN, "{path}");
+    }
+    let by_admin = app.post("/channels", Some(&admin), channel).await;
+    assert_eq!(by_admin.status, StatusCode::CREATED, "{}", by_admin.body);
+
+    // Demoting the editor takes effect on their very next request
+    let users = app.get("/users", Some(&admin)).await;
+    let editor_id = users
+        .body
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|u| u["email"] == "editor@example.com")
+        .unwrap()["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let invalid = app
+        .request(
+            Method::PATCH,
+            &format!("/users/{editor_id}"),
+            Some(&admin),
+            &[],
+            Some(json!({ "role": "owner" })),
+        )
+        .await;
+    assert_eq!(invalid.status, StatusCode::BAD_REQUEST);
+    let demoted = app
+        .request(
+            Method::PATCH,
+            &format!("/users/{editor_id}"),
+            Some(&admin),
+            &[],
+            Some(json!({ "role": "viewer" })),
+        )
+        .await;
+    assert_eq!(demoted.status, StatusCode::OK, "{}", demoted.body);
+    let deleted = app
+        .request(
+            Method::DELETE,
+            &format!("/checks/{id}"),
+            Some(&editor),
+            &[("if-match", "\"1\"")],
+            None,
+        )
+        .await;
+    assert_eq!(deleted.status, StatusCode::FORBIDDEN);
+    let read = app
+        .get(&format!("/checks/{id}/results"), Some(&editor))
+        .await;
+    assert_eq!(read.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn the_audit_log_records_who_changed_what() {
+    let app = TestApp::new().await;
+    let admin = app.user_token(None, "admin@example.com", "admin").await;
+    let editor = app
+        .user_token(Some(&admin), "editor@example.com", "editor")
+        .await;
+    let id = app.create_check(Some(&editor), "https://example.com").await;
+    let updated = app
+        .request(
+            Method::PATCH,
+            &format!("/checks/{id}"),
+            Some(&editor),
+            &[("if-match", "\"1\"")],
+            Some(json!({ "interval_seconds": 300 })),
+        )
+        .await;
+    assert_eq!(updated.status, StatusCode::OK, "{}", updated.body);
+    let channel = app
+        .post(
+            "/channels",
+            Some(&admin),
+            json!({ "name": "hook", "kind": "webhook", "target": "https://hooks.example.com" }),
+        )
+        .await;
+    assert_eq!(channel.status, StatusCode::CREATED, "{}", channel.body);
+
+    let changes = app
+        .get(
+            &format!("/audit-log?entity_type=check&entity_id={id}"),
+            Some(&admin),
+        )
+        .await;
+    assert_eq!(changes.status, StatusCode::OK, "{}", changes.body);
+    let changes = changes.body.as_array().unwrap();
+    let actions: Vec<_> = changes.iter().map(|c| c["action"].clone()).collect();
+    assert_eq!(actions, [json!("update"), json!("create")]);
+    // Who changed the interval?
+    assert_eq!(changes[0]["actor"], "editor@example.com");
+    assert_eq!(changes[0]["before"]["interval_seconds"], 60);
+    assert_eq!(changes[0]["after"]["interval_seconds"], 300);
+    assert_eq!(changes[1]["before"], Value::Null);
+
+    let by_admin = app
+        .get("/audit-log?actor=admin@example.com", Some(&admin))
+        .await;
+    let entities: Vec<_> = by_admin
+        .body
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| c["entity_type"].as_str().unwrap())
+        .collect();
+    assert!(entities.contains(&"channel"), "{entities:?}");
+    assert!(!entities.contains(&"check"));
+
+    // Dependencies, members and keys are audited like checks
+    let upstream = app
+        .create_check(Some(&editor), "https://db.example.com")
+        .await;
+    let dependencies = app
+        .request(
+            Method::PUT,
+            &format!("/checks/{id}/dependencies"),
+            Some(&editor),
+            &[],
+            Some(json!({ "depends_on": [upstream] })),
+        )
+        .await;
+    assert_eq!(dependencies.status, StatusCode::OK, "{}", dependencies.body);
+    let users = app.get("/users", Some(&admin)).await;
+    let editor_id = users.body[1]["id"].as_str().unwrap().to_string();
+    let key = app
+        .post(
+            &format!("/users/{editor_id}/api-keys"),
+            Some(&admin),
+            json!({ "name": "ci" }),
+        )
+        .await;
+    let key_id = key.body["id"].as_str().unwrap();
+    app.request(
+        Method::DELETE,
+        &format!("/api-keys/{key_id}"),
+        Some(&admin),
+        &[],
+        None,
+    )
+    .await;
+    app.request(
+        Method::PATCH,
+        &format!("/users/{editor_id}"),
+        Some(&admin),
+        &[],
+        Some(json!({ "role": "viewer" })),
+    )
+    .await;
+
+    let changes = app
+        .get(
+            &format!("/audit-log?entity_type=check_dependencies&entity_id={id}"),
+            Some(&admin),
+        )
+        .await;
+    assert_eq!(changes.body[0]["actor"], "editor@example.com");
+    assert_eq!(changes.body[0]["before"]["depends_on"], json!([]));
+    assert_eq!(changes.body[0]["after"]["depends_on"], json!([upstream]));
+    let by_admin = app
+        .get("/audit-log?actor=admin@example.com", Some(&admin))
+        .await;
+    let actions: Vec<_> = by_admin
+        .body
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| {
+            format!(
+                "{} {}",
+                c["action"].as_str().unwrap(),
+                c["entity_type"].as_str().unwrap()
+            )
+        })
+        .collect();
+    assert_eq!(
+        actions[..5],
+        [
+            "update user",
+            "delete api_key",
+            "create api_key",
+            "create channel",
+            "create api_key"
+        ]
+    );
+    assert!(actions.contains(&"create user".to_string()), "{actions:?}");
+    assert_eq!(by_admin.body[0]["before"]["role"], "editor");
+    assert_eq!(by_admin.body[0]["after"]["role"], "viewer");
+    assert!(by_admin.body[1]["before"].get("token_hash").is_none());
+}
+
+#[tokio::test]
+async fn organizations_only_see_their_own_checks() {
+    let app = TestApp::new().await;
+    let admin = app.user_token(None, "admin@example.com", "admin").await;
+    let default_check = app.create_check(Some(&admin), "https://example.com").await;
+
+    let org = app
+        .post("/orgs", Some(&admin), json!({ "name": "Acme" }))
+        .await;
+    assert_eq!(org.status, StatusCode::CREATED, "{}", org.body);
+    let org_id = org.body["id"].as_str().unwrap();
+
+    // Members of several organizations have to pick one
+    let ambiguous = app.get("/checks", Some(&admin)).await;
+    assert_eq!(ambiguous.status, StatusCode::BAD_REQUEST);
+
+    let acme = [("x-org-id", org_id)];
+    let created = app
+        .request(
+            Method::POST,
+            "/checks",
+            Some(&admin),
+            &acme,
+            Some(json!({ "name": "acme", "url": "https://acme.test", "interval_seconds": 300 })),
+        )
+        .await;
+    assert_eq!(created.status, StatusCode::CREATED, "{}", created.body);
+
+    let listed = app
+        .request(Method::GET, "/checks", Some(&admin), &acme, None)
+        .await;
+    let listed = listed.body.as_array().unwrap();
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0]["url"], "https://acme.test");
+
+    let foreign = app
+        .request(
+            Method::GET,
+            &format!("/checks/{default_check}"),
+            Some(&admin),
+            &acme,
+            None,
+        )
+        .await;
+    assert_eq!(foreign.status, StatusCode::NOT_FOUND);
+
+    let outsider = app
+        .request(
+            Method::GET,
+            "/checks",
+            Some(&admin),
+            &[("x-org-id", "no-such-org")],
+            None,
+        )
+        .await;
+    assert_eq!(outsider.status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn existing_users_are_invited_rather_than_added() {
+    let app = TestApp::new().await;
+    let admin = app.user_token(None, "admin@example.com", "admin").await;
+    let bob = app
+        .user_token(Some(&admin), "bob@example.com", "editor")
+        .await;
+    let org = app
+        .post("/orgs", Some(&admin), json!({ "name": "Acme" }))
+        .await;
+    let acme = [("x-org-id", org.body["id"].as_str().unwrap())];
+
+    let added = app
+        .request(
+            Method::POST,
+            "/users",
+            Some(&admin),
+            &acme,
+            Some(json!({ "email": "Bob@example.com", "name": "Bob", "role": "viewer" })),
+        )
+        .await;
+    assert_eq!(added.status, StatusCode::ACCEPTED, "{}", added.body);
+    let token = added.body["token"].as_str().unwrap().to_string();
+    let members = app
+        .request(Method::GET, "/users", Some(&admin), &acme, None)
+        .await;
+    assert_eq!(members.body.as_array().unwrap().len(), 1);
+    let outsider = app
+        .request(Method::GET, "/checks", Some(&bob), &acme, None)
+        .await;
+    assert_eq!(outsider.status, StatusCode::FORBIDDEN);
+
+    let accepted = app
+        .post("/invitations/accept", None, json!({ "token": token }))
+        .await;
+    assert_eq!(accepted.status, StatusCode::OK, "{}", accepted.body);
+    let member = app
+        .request(Method::GET, "/checks", Some(&bob), &acme, None)
+        .await;
+    assert_eq!(member.status, StatusCode::OK);
+    let again = app
+        .request(
+            Method::POST,
+            "/users",
+            Some(&admin),
+            &acme,
+            Some(json!({ "email": "bob@example.com", "name": "Bob", "role": "viewer" })),
+        )
+        .await;
+    assert_eq!(again.status, StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn graphql_is_scoped_to_the_organization_and_bounded() {
+    let app = TestApp::new().await;
+    let admin = app.user_token(None, "admin@example.com", "admin").await;
+    let default_check = app.create_check(Some(&admin), "https://example.com").await;
+    let org = app
+        .post("/orgs", Some(&admin), json!({ "name": "Acme" }))
+        .await;
+    let acme = [("x-org-id", org.body["id"].as_str().unwrap())];
+    let created = app
+        .request(
+            Method::POST,
+            "/checks",
+            Some(&admin),
+            &acme,
+            Some(json!({ "name": "acme", "url": "https://acme.test", "interval_seconds": 300 })),
+        )
+        .await;
+    assert_eq!(created.status, StatusCode::CREATED, "{}", created.body);
+    let graphql = |query: String| {
+        app.request(
+            Method::POST,
+            "/graphql",
+            Some(&admin),
+            &acme,
+            Some(json!({ "query": query })),
+        )
+    };
+
+    let anonymous = app
+        .post("/graphql", None, json!({ "query": "{ checks { id } }" }))
+        .await;
+    assert_eq!(anonymous.status, StatusCode::UNAUTHORIZED);
+
+    let listed = graphql("{ checks { url results { status } } }".to_string()).await;
+    assert_eq!(listed.status, StatusCode::OK);
+    assert_eq!(
+        listed.body["data"]["checks"],
+        json!([{ "url": "https://acme.test", "results": [] }])
+    );
+    let foreign = graphql(format!("{{ check(id: \"{default_check}\") {{ url }} }}")).await;
+    assert_eq!(
+        foreign.body["data"]["check"],
+        Value::Null,
+        "{}",
+        foreign.body
+    );
+
+    // A negative LIMIT would mean no limit at all to SQLite
+    for limit in [-1, 0, 101] {
+        let unbounded = graphql(format!(
+            "{{ checks {{ results(limit: {limit}) {{ status }} }} }}"
+        ))
+        .await;
+        let message = unbounded.body["errors"][0]["message"].as_str().unwrap();
+        assert!(message.contains("limit"), "{message}");
+    }
+    let fan_out = graphql(
+        "{ incidents(limit: 100) { check { results(limit: 100) { incident { id } } } } }"
+            .to_string(),
+    )
+    .await;
+    assert_eq!(fan_out.body["data"], Value::Null, "{}", fan_out.body);
+    assert!(fan_out.body["errors"][0]["message"]
+        .as_str()
+        .unwrap()
+        .contains("complex"));
+    let nested = graphql(
+        "{ incidents(limit: 1) { check { incidents(limit: 1) { check { incidents(limit: 1) { check { incidents(limit: 1) { check { id } } } } } } } } }"
+            .to_string(),
+    )
+    .await;
+    assert_eq!(nested.body["data"], Value::Null, "{}", nested.body);
+    assert!(nested.body["errors"][0]["message"]
+        .as_str()
+        .unwrap()
+        .contains("nested too deep"));
+}
+
+#[tokio::test]
+async fn only_instance_operators_manage_agents() {
+    let app = TestApp::new().await;
+    let admin = app.user_token(None, "admin@example.com", "admin").await;
+    let org = app
+        .post("/orgs", Some(&admin), json!({ "name": "Acme" }))
+        .await;
+    let acme = [("x-org-id", org.body["id"].as_str().unwrap())];
+    let agent = json!({ "name": "eu-1", "region": "eu" });
+
+    // Anyone can create an organization and be its admin, but agents see every tenant
+    let by_tenant = app
+        .request(
+            Method::POST,
+            "/agents",
+            Some(&admin),
+            &acme,
+            Some(agent.clone()),
+        )
+        .await;
+    assert_eq!(by_tenant.status, StatusCode::FORBIDDEN);
+    let listed = app
+        .request(Method::GET, "/agents", Some(&admin), &acme, None)
+        .await;
+    assert_eq!(listed.status, StatusCode::FORBIDDEN);
+
+    let default_org = [("x-org-id", "default")];
+    let created = app
+        .request(
+            Method::POST,
+            "/agents",
+            Some(&admin),
+            &default_org,
+            Some(agent),
+        )
+        .await;
+    assert_eq!(created.status, StatusCode::CREATED, "{}", created.body);
+    let path = format!("/agents/{}", created.body["id"].as_str().unwrap());
+    let deleted = app
+        .request(Method::DELETE, &path, Some(&admin), &acme, None)
+        .await;
+    assert_eq!(deleted.status, StatusCode::FORBIDDEN);
+    let deleted = app
+        .request(Method::DELETE, &path, Some(&admin), &default_org, None)
+        .await;
+    assert_eq!(deleted.status, StatusCode::NO_CONTENT);
+}
+
+#[tokio::test]
+async fn admin_stats_total_the_whole_instance() {
+    let target = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&target)
+        .await;
+    let hooks = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&hooks)
+        .await;
+    let app = TestApp::new().await;
+    let channel = app
+        .post(
+            "/channels",
+            None,
+            json!({ "name": "hook", "kind": "webhook", "target": hooks.uri() }),
+        )
+        .await;
+    assert_eq!(channel.status, StatusCode::CREATED, "{}", channel.body);
+    let down = app.create_check(None, &target.uri()).await;
+    let paused = app.create_check(None, "https://paused.example.com").await;
+    let paused = app
+        .request(
+            Method::PATCH,
+            &format!("/checks/{paused}"),
+            None,
+            &[("if-match", "\"1\"")],
+            Some(json!({ "is_active": false })),
+        )
+        .await;
+    assert_eq!(paused.status, StatusCode::OK, "{}", paused.body);
+    app.run_check(&down).await;
+    // The result and the sent alert are written in the background
+    let app = &app;
+    wait_until(|| async move {
+        let stats = app.get("/admin/stats", None).await.body;
+        stats["results"] == 1 && stats["notifications_last_24h"] == 1
+    })
+    .await;
+
+    let stats = app.get("/admin/stats", None).await;
+    assert_eq!(stats.body["checks"], 2);
+    assert_eq!(stats.body["active_checks"], 1);
+    assert_eq!(stats.body["results"], 1);
+    assert_eq!(stats.body["open_incidents"], 1);
+    assert_eq!(stats.body["notifications_last_24h"], 1);
+    assert!(stats.body["database_bytes"].as_i64().unwrap() > 0);
+}
+
+#[tokio::test]
+async fn daily_stats_follow_the_requested_timezone() {
+    let app = TestApp::new().await;
+    let id = app.create_check(None, "https://example.com").await;
+    // 23:00 and 00:00 UTC fall on the same morning in Tokyo
+    let evening = (app.clock.now() - Duration::days(2))
+        .date_naive()
+        .and_hms_opt(23, 0, 0)
+        .unwrap()
+        .and_utc();
+    for (start, up_samples) in [(evening, 60), (evening + Duration::hours(1), 30)] {
+        sqlx::query(
+            "INSERT INTO check_rollups (check_id, bucket_start, samples, up_samples, latency_sum, latency_samples) VALUES (?, ?, 60, ?, 0, 0)",
+        )
+        .bind(&id)
+        .bind(start)
+        .bind(up_samples)
+        .execute(&app.db)
+        .await
+        .unwrap();
+    }
+
+    let utc = app
+        .get(&format!("/checks/{id}/stats?period=7d"), None)
+        .await;
+    assert_eq!(utc.status, StatusCode::OK, "{}", utc.body);
+    assert_eq!(utc.body["timezone"], "UTC");
+    assert_eq!(utc.body["uptime_percent"], 75.0);
+    let daily = utc.body["daily"].as_array().unwrap();
+    assert_eq!(daily.len(), 2);
+    assert_eq!(daily[0]["date"], evening.date_naive().to_string());
+    assert_eq!(daily[0]["uptime_percent"], 100.0);
+    assert_eq!(daily[1]["uptime_percent"], 50.0);
+
+    let tokyo = app
+        .get(
+            &format!("/checks/{id}/stats?period=7d&timezone=Asia/Tokyo"),
+            None,
+        )
+        .await;
+    assert_eq!(tokyo.body["timezone"], "Asia/Tokyo");
+    let daily = tokyo.body["daily"].as_array().unwrap();
+    assert_eq!(daily.len(), 1);
+    assert_eq!(
+        daily[0]["date"],
+        (evening + Duration::days(1)).date_naive().to_string()
+    );
+    assert_eq!(daily[0]["samples"], 120);
+    assert_eq!(daily[0]["uptime_percent"], 75.0);
+
+    let unknown = app
+        .get(&format!("/checks/{id}/stats?timezone=Mars/Olympus"), None)
+        .await;
+    assert_eq!(unknown.status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn stats_cover_the_whole_hours_of_the_period() {
+    let app = TestApp::new().await;
+    let id = app.create_check(None, "https://example.com").await;
+    if app.clock.now().timestamp_subsec_nanos() == 0 && app.clock.now().timestamp() % 3600 == 0 {
+        app.clock.advance(Duration::seconds(1));
+    }
+    let hour = |at: DateTime<Utc>| at.duration_trunc(Duration::hours(1)).unwrap();
+    // The hour the period starts in is only partly inside it
+    let before = hour(app.clock.now() - Duration::hours(24));
+    let current = hour(app.clock.now());
+    for (start, up_samples, kind, errors) in [(before, 0, "timeout", 60), (current, 58, "dns", 2)] {
+        sqlx::query(
+            "INSERT INTO check_rollups (check_id, bucket_start, samples, up_samples, latency_sum, latency_samples) VALUES (?, ?, 60, ?, 0, 0)",
+        )
+        .bind(&id)
+        .bind(start.format("%Y-%m-%dT%H:00:00+00:00").to_string())
+        .bind(up_samples)
+        .execute(&app.db)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO check_rollup_errors (check_id, bucket_start, error_kind, samples) VALUES (?, ?, ?, ?)",
+        )
+        .bind(&id)
+        .bind(start.format("%Y-%m-%dT%H:00:00+00:00").to_string())
+        .bind(kind)
+        .bind(errors)
+        .execute(&app.db)
+        .await
+        .unwrap();
+    }
+
+    let stats = app
+        .get(&format!("/checks/{id}/stats?period=24h"), None)
+        .await;
+    assert_eq!(stats.status, StatusCode::OK, "{}", stats.body);
+    assert_eq!(stats.body["samples"], 60);
+    assert_eq!(stats.body["errors_by_kind"], json!({ "dns": 2 }));
+    let since: DateTime<Utc> = stats.body["since"].as_str().unwrap().parse().unwrap();
+    assert_eq!(since, before + Duration::hours(1));
+
+    for (period, status) in [
+        ("30m", StatusCode::BAD_REQUEST),
+        ("90m", StatusCode::BAD_REQUEST),
+        ("120m", StatusCode::OK),
+    ] {
+        let stats = app
+            .get(&format!("/checks/{id}/stats?period={period}"), None)
+            .await;
+        assert_eq!(stats.status, status, "{period}: {}", stats.body);
+    }
+}
+
+#[tokio::test]
+async fn timestamps_are_returned_in_utc() {
+    let app = TestApp::new().await;
+    let id = app.create_check(None, "https://example.com").await;
+
+    let check = app.get(&format!("/checks/{id}"), None).await;
+    let next_run_at = check.body["next_run_at"].as_str().unwrap();
+    assert!(next_run_at.ends_with('Z'), "{next_run_at}");
+    assert!(DateTime::parse_from_rfc3339(next_run_at).is_ok());
+}
+
+#[tokio::test]
+async fn results_are_ordered_by_instant_whatever_their_format() {
+    let app = TestApp::new().await;
+    let id = app.create_check(None, "https://example.com").await;
+    // As text these sort "T12:30+02:00", "T11:00Z", " 10:45"
+    for checked_at in [
+        "2026-03-01T12:30:00+02:00",
+        "2026-03-01T11:00:00Z",
+        "2026-03-01 10:45:00.5+00:00",
+    ] {
+        sqlx::query("INSERT INTO check_results (check_id, checked_at, status) VALUES (?, ?, 'UP')")
+            .bind(&id)
+            .bind(checked_at)
+            .execute(&app.db)
+            .await
+            .unwrap();
+    }
+
+    let results = app.get(&format!("/checks/{id}/results"), None).await;
+    let times: Vec<&str> = results
+        .body
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| r["checked_at"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        times,
+        [
+            "2026-03-01T11:00:00Z",
+            "2026-03-01T10:45:00.500Z",
+            "2026-03-01T10:30:00Z"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn maintenance_windows_are_published_as_a_calendar() {
+    let app = TestApp::new().await;
+    let id = app.create_check(None, "https://example.com").await;
+    let starts_at = app.clock.now() + Duration::days(1);
+    let window = json!({
+        "title": "Database upgrade",
+        "check_id": id,
+        "starts_at": starts_at,
+        "ends_at": starts_at + Duration::hours(2),
+        "rrule": "freq=weekly;byday=sa,su",
+    });
+
+    let created = app.post("/maintenance", None, window.clone()).await;
+    assert_eq!(created.status, StatusCode::CREATED, "{}", created.body);
+    assert_eq!(created.body["rrule"], "FREQ=WEEKLY;BYDAY=SA,SU");
+
+    let holidays = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nBEGIN:VEVENT\r\nUID:new-year\r\nDTSTART;VALUE=DATE:20270101\r\nSUMMARY:New Year\\, closed\r\nRRULE:FREQ=YEARLY\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+    let imported = app
+        .post_text("/maintenance/import?timezone=Europe/Madrid", None, holidays)
+        .await;
+    assert_eq!(imported.status, StatusCode::CREATED, "{}", imported.body);
+    assert_eq!(imported.body["imported"], 1);
+    let listed = app.get("/maintenance", None).await;
+    let listed = listed.body.as_array().unwrap();
+    assert_eq!(listed.len(), 2);
+    let new_year = listed.iter().find(|w| w["title"] == "New Year, closed");
+    assert_eq!(new_year.unwrap()["starts_at"], "2026-12-31T23:00:00Z");
+
+    let feed = app.get("/maintenance.ics", None).await;
+    assert_eq!(feed.status, StatusCode::OK);
+    assert!(feed.headers["content-type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/calendar"));
+    let ics = feed.body.as_str().unwrap();
+    assert_eq!(ics.matches("BEGIN:VEVENT").count(), 2);
+    assert!(ics.contains("RRULE:FREQ=WEEKLY;BYDAY=SA,SU\r\n"), "{ics}");
+    assert!(ics.contains("SUMMARY:New Year\\, closed\r\n"), "{ics}");
+
+    let mut hourly = window;
+    hourly["rrule"] = json!("FREQ=HOURLY");
+    let bad_rule = app.post("/maintenance", None, hourly).await;
+    assert_eq!(bad_rule.status, StatusCode::BAD_REQUEST);
+    let broken = app
+        .post_text(
+            "/maintenance/import",
+            None,
+            "BEGIN:VEVENT\nSUMMARY:x\nEND:VEVENT",
+        )
+        .await;
+    assert_eq!(broken.status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn status_page_feed_lists_incidents_publicly() {
+    let app = TestApp::new().await;
+    let admin = app.user_token(None, "admin@example.com", "admin").await;
+    let id = app.create_check(Some(&admin), "https://example.com").await;
+    let old = app.clock.now() - Duration::hours(3);
+    for (incident, started_at, resolved_at) in [
+        ("old", old, Some(old + Duration::minutes(5))),
+        ("new", old + Duration::hours(1), None),
+    ] {
+        sqlx::query(
+            "INSERT INTO incidents (id, check_id, started_at, resolved_at, failed_probes, last_error) VALUES (?, ?, ?, ?, 3, 'HTTP 503')",
+        )
+        .bind(incident)
+        .bind(&id)
+        .bind(started_at)
+        .bind(resolved_at)
+        .execute(&app.db)
+        .await
+        .unwrap();
+    }
+
+    let page = json!({ "slug": "acme", "title": "Acme <status>", "check_ids": [id] });
+    let created = app.post("/status-pages", Some(&admin), page.clone()).await;
+    assert_eq!(created.status, StatusCode::CREATED, "{}", created.body);
+    let taken = app.post("/status-pages", Some(&admin), page).await;
+    assert_eq!(taken.status, StatusCode::CONFLICT);
+    let bad_slug = app
+        .post(
+            "/status-pages",
+            Some(&admin),
+            json!({ "slug": "Not A Slug", "title": "x", "check_ids": [] }),
+        )
+        .await;
+    assert_eq!(bad_slug.status, StatusCode::BAD_REQUEST);
+
+    // Feed readers have no API key
+    let feed = app.get("/status-pages/acme/feed.atom", None).await;
+    assert_eq!(feed.status, StatusCode::OK, "{}", feed.body);
+    assert!(feed.headers["content-type"]
+        .to_str()
+        .unwrap()
+        .starts_with("application/atom+xml"));
+    let xml = feed.body.as_str().unwrap();
+    assert!(xml.contains("<title>Acme &lt;status&gt;</title>"), "{xml}");
+    assert_eq!(xml.matches("<entry>").count(), 3);
+    let down = xml.find("incident-new-opened").unwrap();
+    let recovered = xml.find("incident-old-resolved").unwrap();
+    let opened = xml.find("incident-old-opened").unwrap();
+    assert!(down < recovered && recovered < opened, "{xml}");
+    assert!(xml.contains("https://example.com recovered"), "{xml}");
+
+    // The page the feed links to is public too
+    let link = xml
+        .split("<link href=\"")
+        .nth(1)
+        .and_then(|rest| rest.split('"').next())
+        .unwrap();
+    let path = link.strip_prefix("http://localhost:8080").unwrap();
+    let html = app.get(path, None).await;
+    assert_eq!(html.status, StatusCode::OK, "{}", html.body);
+    assert!(html.headers["content-type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/html"));
+    let html = html.body.as_str().unwrap();
+    assert!(html.contains("<h1>Acme &lt;status&gt;</h1>"), "{html}");
+    assert!(html.contains("https://example.com recovered"), "{html}");
+
+    let missing = app.get("/status-pages/nobody/feed.atom", None).await;
+    assert_eq!(missing.status, StatusCode::NOT_FOUND);
+    let missing = app.get("/status-pages/nobody", None).await;
+    assert_eq!(missing.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn operators_post_updates_to_the_status_page_timeline() {
+    let app = TestApp::new().await;
+    let id = app.create_check(None, "https://example.com").await;
+    sqlx::query("INSERT INTO incidents (id, check_id, started_at) VALUES ('outage', ?, ?)")
+        .bind(&id)
+        .bind(app.clock.now())
+        .execute(&app.db)
+        .await
+        .unwrap();
+    let page = json!({ "slug": "acme", "title": "Acme", "check_ids": [id] });
+    assert_eq!(
+        app.post("/status-pages", None, page).await.status,
+        StatusCode::CREATED
+    );
+
+    let unknown_status = app
+        .post(
+            "/incidents/outage/updates",
+            None,
+            json!({ "status": "panicking", "message": "help" }),
+        )
+        .await;
+    assert_eq!(unknown_status.status, StatusCode::BAD_REQUEST);
+    let missing = app
+        .post(
+            "/incidents/nope/updates",
+            None,
+            json!({ "status": "identified", "message": "x" }),
+        )
+        .await;
+    assert_eq!(missing.status, StatusCode::NOT_FOUND);
+
+    app.clock.advance(Duration::minutes(10));
+    let posted = app
+        .post(
+            "/incidents/outage/updates",
+            None,
+            json!({ "status": "identified", "message": "A bad deploy; rolling back" }),
+        )
+        .await;
+    assert_eq!(posted.status, StatusCode::CREATED, "{}", posted.body);
+    let updates = app.get("/incidents/outage/updates", None).await;
+    assert_eq!(updates.body.as_array().unwrap().len(), 1);
+    assert_eq!(updates.body[0]["status"], "identified");
+
+    let feed = app.get("/status-pages/acme/feed.atom", None).await;
+    let xml = feed.body.as_str().unwrap();
+    assert_eq!(xml.matches("<entry>").count(), 2);
+    let update = xml.find("<title>https://example.com: Identified</title>");
+    assert!(
+        update.unwrap() < xml.find("incident-outage-opened").unwrap(),
+        "{xml}"
+    );
+    assert!(
+        xml.contains("<summary>A bad deploy; rolling back</summary>"),
+        "{xml}"
+    );
+}
+
+#[tokio::test]
+async fn latency_is_broken_down_by_agent_region() {
+    let app = TestApp::new().await;
+    let id = app.create_check(None, "https://example.com").await;
+    let now = app.clock.now();
+    for (region, samples) in [
+        ("eu", [("UP", 100), ("UP", 120)]),
+        ("us", [("UP", 300), ("DOWN", 900)]),
+    ] {
+        let agent = app
+            .post("/agents", None, json!({ "name": region, "region": region }))
+            .await;
+        assert_eq!(agent.status, StatusCode::CREATED, "{}", agent.body);
+        let results: Vec<Value> = samples
+            .iter()
+            .map(|(status, latency_ms)| {
+                json!({ "check_id": id, "checked_at": now, "status": status, "latency_ms": latency_ms })
+            })
+            .collect();
+        let sent = app
+            .post(
+                "/agent/results",
+                agent.body["token"].as_str(),
+                json!({ "results": results }),
+            )
+            .await;
+        assert!(sent.status.is_success(), "{}", sent.body);
+    }
+
+    let invalid = app
+        .get(&format!("/checks/{id}/latency-by-region?period=soon"), None)
+        .await;
+    assert_eq!(invalid.status, StatusCode::BAD_REQUEST);
+    let by_region = app
+        .get(&format!("/checks/{id}/latency-by-region?period=1h"), None)
+        .await;
+    assert_eq!(by_region.status, StatusCode::OK, "{}", by_region.body);
+    let regions = &by_region.body["regions"];
+    assert_eq!(regions[0]["region"], "eu");
+    assert_eq!(regions[0]["avg_latency_ms"], 110.0);
+    assert_eq!(regions[0]["uptime_percent"], 100.0);
+    assert_eq!(regions[1]["region"], "us");
+    assert_eq!(regions[1]["max_latency_ms"], 900);
+    assert_eq!(regions[1]["uptime_percent"], 50.0);
+}
+
+#[tokio::test]
+async fn agents_only_report_results_for_checks_they_are_assigned() {
+    let app = TestApp::new().await;
+    let eu_check = app.create_check(None, "https://example.com").await;
+    let us_only = app
+        .post(
+            "/checks",
+            None,
+            json!({ "name": "us", "url": "https://us.example.com", "interval_seconds": 60, "regions": "us" }),
+        )
+        .await;
+    assert_eq!(us_only.status, StatusCode::CREATED, "{}", us_only.body);
+    let paused = app.create_check(None, "https://paused.example.com").await;
+    sqlx::query("UPDATE checks SET is_active = 0 WHERE id = ?")
+        .bind(&paused)
+        .execute(&app.db)
+        .await
+        .unwrap();
+    let agent = app
+        .post("/agents", None, json!({ "name": "eu-1", "region": "eu" }))
+        .await;
+    let token = agent.body["token"].as_str();
+    let now = app.clock.now();
+
+    for foreign in [
+        us_only.body["id"].as_str().unwrap(),
+        &paused,
+        "no-such-check",
+    ] {
+        let results = json!({ "results": [
+            { "check_id": eu_check, "checked_at": now, "status": "UP" },
+            { "check_id": foreign, "checked_at": now, "status": "DOWN" },
+        ] });
+        let sent = app.post("/agent/results", token, results).await;
+        assert_eq!(sent.status, StatusCode::FORBIDDEN, "{foreign}");
+    }
+    let (stored,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM check_results")
+        .fetch_one(&app.db)
+        .await
+        .unwrap();
+    assert_eq!(stored, 0);
+
+    let own = json!({ "results": [{ "check_id": eu_check, "checked_at": now, "status": "UP" }] });
+    let sent = app.post("/agent/results", token, own).await;
+    assert!(sent.status.is_success(), "{}", sent.body);
+}
+
+#[tokio::test]
+async fn agent_results_need_a_known_status_and_a_recent_timestamp() {
+    let app = TestApp::new().await;
+    let check = app.create_check(None, "https://example.com").await;
+    let agent = app
+        .post("/agents", None, json!({ "name": "eu-1", "region": "eu" }))
+        .await;
+    let token = agent.body["token"].as_str();
+    let now = app.clock.now();
+
+    for (checked_at, status) in [
+        (now, "FLAPPING"),
+        (now - Duration::hours(2), "UP"),
+        (now + Duration::minutes(10), "UP"),
+    ] {
+        let results = json!({ "results": [
+            { "check_id": check, "checked_at": checked_at, "status": status },
+        ] });
+        let sent = app.post("/agent/results", token, results).await;
+        assert_eq!(
+            sent.status,
+            StatusCode::BAD_REQUEST,
+            "{status} {checked_at}"
+        );
+    }
+    let (stored,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM check_results")
+        .fetch_one(&app.db)
+        .await
+        .unwrap();
+    assert_eq!(stored, 0);
+
+    let results = json!({ "results": [
+        { "check_id": check, "checked_at": now - Duration::seconds(30), "status": "DEGRADED" },
+    ] });
+    let sent = app.post("/agent/results", token, results).await;
+    assert!(sent.status.is_success(), "{}", sent.body);
+}
+
+#[tokio::test]
+async fn results_of_external_monitors_are_ingested() {
+    let app = TestApp::new().await;
+    let id = app.create_check(None, "https://example.com").await;
+    let mut tokens = HashMap::new();
+    for source in ["uptimerobot", "pingdom", "generic"] {
+        let created = app
+            .post("/ingest/sources", None, json!({ "source": source }))
+            .await;
+        assert_eq!(created.status, StatusCode::CREATED, "{}", created.body);
+        assert_eq!(created.body["source"], source);
+        tokens.insert(source, created.body["token"].as_str().unwrap().to_string());
+    }
+    let unknown_source = app
+        .post("/ingest/sources", None, json!({ "source": "nagios" }))
+        .await;
+    assert_eq!(unknown_source.status, StatusCode::BAD_REQUEST);
+    let sources = app.get("/ingest/sources", None).await;
+    assert_eq!(sources.body.as_array().unwrap().len(), 3);
+    assert!(sources.body[0].get("token_hash").is_none());
+
+    let uptimerobot = json!({ "monitorURL": "https://example.com", "alertType": "1", "alertDetails": "Connection Timeout", "alertDateTime": 1700000000 });
+    // An API key is not enough: the webhook needs its source's token
+    let anonymous = app
+        .post("/ingest/external", None, uptimerobot.clone())
+        .await;
+    assert_eq!(anonymous.status, StatusCode::UNAUTHORIZED);
+    let down = app
+        .request(
+            Method::POST,
+            "/ingest/external",
+            None,
+            &[("x-ingest-token", tokens["uptimerobot"].as_str())],
+            Some(uptimerobot),
+        )
+        .await;
+    assert_eq!(down.status, StatusCode::ACCEPTED, "{}", down.body);
+    let up = app
+        .post(
+            &format!(
+                "/ingest/external?token={}&check_id={id}",
+                tokens["pingdom"]
+            ),
+            None,
+            json!({ "current_state": "UP", "previous_state": "DOWN", "state_changed_timestamp": 1700000600 }),
+        )
+        .await;
+    assert_eq!(up.status, StatusCode::ACCEPTED, "{}", up.body);
+
+    // The token decides the payload's format
+    let wrong_format = app
+        .post(
+            &format!("/ingest/external?token={}", tokens["pingdom"]),
+            None,
+            json!({ "url": "https://example.com", "status": "UP" }),
+        )
+        .await;
+    assert_eq!(wrong_format.status, StatusCode::BAD_REQUEST);
+    let generic = format!("/ingest/external?token={}", tokens["generic"]);
+    let unknown_url = app
+        .post(
+            &generic,
+            None,
+            json!({ "url": "https://elsewhere.example", "status": "UP" }),
+        )
+        .await;
+    assert_eq!(unknown_url.status, StatusCode::NOT_FOUND);
+    let recent = app
+        .post(
+            &generic,
+            None,
+            json!({ "url": "https://example.com", "status": "DOWN", "latency_ms": 5000 }),
+        )
+        .await;
+    assert_eq!(recent.status, StatusCode::ACCEPTED, "{}", recent.body);
+
+    let results = app.get(&format!("/checks/{id}/results"), None).await;
+    let results: Vec<_> = results
+        .body
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| {
+            (
+                r["status"].clone(),
+                r["location"].clone(),
+                r["error"].clone(),
+                r["external"].clone(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        results,
+        [
+            (json!("DOWN"), json!("generic"), Value::Null, json!(true)),
+            (json!("UP"), json!("pingdom"), Value::Null, json!(true)),
+            (
+                json!("DOWN"),
+                json!("uptimerobot"),
+                json!("Connection Timeout"),
+                json!(true)
+            ),
+        ]
+    );
+    // History only: the stats still come from the check's own probes
+    let stats = app.get(&format!("/checks/{id}/stats"), None).await;
+    assert_eq!(stats.body["samples"], 0, "{}", stats.body);
+    let regions = app
+        .get(&format!("/checks/{id}/latency-by-region"), None)
+        .await;
+    assert_eq!(regions.body["regions"], json!([]), "{}", regions.body);
+
+    let source_id = sources.body[2]["id"].as_str().unwrap();
+    let deleted = app
+        .request(
+            Method::DELETE,
+            &format!("/ingest/sources/{source_id}"),
+            None,
+            &[],
+            None,
+        )
+        .await;
+    assert_eq!(deleted.status, StatusCode::NO_CONTENT);
+    let revoked = app
+        .post(
+            &generic,
+            None,
+            json!({ "url": "https://example.com", "status": "UP" }),
+        )
+        .await;
+    assert_eq!(revoked.status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn results_export_streams_the_whole_history_oldest_first() {
+    let app = TestApp::new().await;
+    let id = app.create_check(None, "https://example.com").await;
+    // Inserted newest first and spanning several pages
+    let now = app.clock.now();
+    for i in 0..1234 {
+        sqlx::query(
+            "INSERT INTO check_results (check_id, checked_at, status, latency_ms) VALUES (?, ?, 'UP', ?)",
+        )
+        .bind(&id)
+        .bind(now - Duration::seconds(i))
+        .bind(i)
+        .execute(&app.db)
+        .await
+        .unwrap();
+    }
+
+    let export = app.get(&format!("/checks/{id}/results.ndjson"), None).await;
+    assert_eq!(export.status, StatusCode::OK);
+    assert_eq!(export.headers["content-type"], "application/x-ndjson");
+    let latencies: Vec<i64> = export
+        .body
+        .as_str()
+        .unwrap()
+        .lines()
+        .map(|line| {
+            serde_json::from_str::<Value>(line).unwrap()["latency_ms"]
+                .as_i64()
+                .unwrap()
+        })
+        .collect();
+    assert_eq!(latencies, (0..1234).rev().collect::<Vec<_>>());
+
+    let unknown = app.get("/checks/nope/results.ndjson", None).await;
+    assert_eq!(unknown.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn apply_converges_on_the_declared_configuration() {
+    let app = TestApp::new().await;
+    let legacy = app.create_check(None, "https://legacy.example.com").await;
+    let spec = json!({
+        "checks": [
+            { "name": "web", "url": "https://example.com", "interval_seconds": 60 },
+            { "name": "api", "url": "https://api.example.com", "interval_seconds": 30, "severity": "warning" },
+        ],
+        "channels": [
+            { "name": "ops", "kind": "webhook", "target": "https://hooks.example.com/ops" },
+        ],
+        "status_pages": [
+            { "slug": "public", "title": "Example", "checks": ["web", "api"] },
+        ],
+    });
+    let summary = |plan: &Value| -> Vec<String> {
+        plan["changes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| format!("{} {} {}", c["action"], c["kind"], c["key"]).replace('"', ""))
+            .collect()
+    };
+
+    let dry_run = app.post("/apply?dry_run=true", None, spec.clone()).await;
+    assert_eq!(dry_run.status, StatusCode::OK, "{}", dry_run.body);
+    assert_eq!(
+        summary(&dry_run.body),
+        [
+            "create check web",
+            "create check api",
+            "delete check https://legacy.example.com",
+            "create channel ops",
+            "create status_page public",
+        ]
+    );
+    let checks = app.get("/checks", None).await;
+    assert_eq!(checks.body.as_array().unwrap().len(), 1);
+
+    let applied = app.post("/apply", None, spec.clone()).await;
+    assert_eq!(applied.status, StatusCode::OK, "{}", applied.body);
+    assert_eq!(summary(&applied.body), summary(&dry_run.body));
+    let missing = app.get(&format!("/checks/{legacy}"), None).await;
+    assert_eq!(missing.status, StatusCode::NOT_FOUND);
+    let pages = app.get("/status-pages", None).await;
+    assert_eq!(pages.body[0]["check_ids"].as_array().unwrap().len(), 2);
+
+    let again = app.post("/apply", None, spec.clone()).await;
+    assert_eq!(again.body["changes"], json!([]));
+
+    let mut changed = spec.clone();
+    changed["checks"][1]["interval_seconds"] = json!(120);
+    changed["status_pages"][0]["checks"] = json!(["web"]);
+    let updated = app.post("/apply", None, changed).await;
+    assert_eq!(updated.status, StatusCode::OK, "{}", updated.body);
+    assert_eq!(
+        summary(&updated.body),
+        ["update check api", "update status_page public"]
+    );
+    assert_eq!(
+        updated.body["changes"][0]["fields"],
+        json!(["interval_seconds"])
+    );
+
+    let mut invalid = spec.clone();
+    invalid["checks"][0]["url"] = json!("ftp://example.com");
+    invalid["status_pages"] = json!([]);
+    let rejected = app.post("/apply", None, invalid).await;
+    assert_eq!(rejected.status, StatusCode::BAD_REQUEST);
+    let pages = app.get("/status-pages", None).await;
+    assert_eq!(pages.body.as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn intervals_below_the_limits_are_raised_at_startup() {
+    let app = TestApp::new().await;
+    let id = app.create_check(None, "https://example.com").await;
+    let too_often = app
+        .request(
+            Method::PATCH,
+            &format!("/checks/{id}"),
+            None,
+            &[("if-match", "\"1\"")],
+            Some(json!({ "interval_seconds": 5 })),
+        )
+        .await;
+    assert_eq!(too_often.status, StatusCode::BAD_REQUEST);
+
+    // Moved to a plan that doesn't allow its interval
+    sqlx::query("UPDATE orgs SET plan = 'free' WHERE id = 'default'")
+        .execute(&app.db)
+        .await
+        .unwrap();
+    sqlx::query("UPDATE checks SET quorum_window_seconds = 120 WHERE id = ?")
+        .bind(&id)
+        .execute(&app.db)
+        .await
+        .unwrap();
+    let _restarted = uptime_saas::start(app.db.clone(), app.clock.clone())
+        .await
+        .unwrap();
+
+    let check = app.get(&format!("/checks/{id}"), None).await;
+    assert_eq!(check.body["interval_seconds"], 300);
+    assert_eq!(check.body["quorum_window_seconds"], 300);
+    assert_eq!(check.body["version"], 2);
+}
+
+#[tokio::test]
+async fn annotations_give_context_to_results_and_status_pages() {
+    let app = TestApp::new().await;
+    let id = app.create_check(None, "https://example.com").await;
+    let now = app.clock.now();
+    for minutes in [20, 10, 0] {
+        sqlx::query("INSERT INTO check_results (check_id, checked_at, status) VALUES (?, ?, 'UP')")
+            .bind(&id)
+            .bind(now - Duration::minutes(minutes))
+            .execute(&app.db)
+            .await
+            .unwrap();
+    }
+    let results = app.get(&format!("/checks/{id}/results"), None).await;
+    let oldest = results.body[2]["id"].as_i64().unwrap();
+
+    let deploy = app
+        .post(
+            &format!("/checks/{id}/annotations"),
+            None,
+            json!({ "text": "deploy v2.3", "result_id": oldest }),
+        )
+        .await;
+    assert_eq!(deploy.status, StatusCode::CREATED, "{}", deploy.body);
+    assert_eq!(deploy.body["at"], results.body[2]["checked_at"]);
+    let outage = app
+        .post(
+            &format!("/checks/{id}/annotations"),
+            None,
+            json!({
+                "text": "ISP outage",
+                "at": now - Duration::minutes(15),
+                "ends_at": now,
+                "public": true,
+            }),
+        )
+        .await;
+    assert_eq!(outage.status, StatusCode::CREATED, "{}", outage.body);
+    let foreign = app
+        .post(
+            &format!("/checks/{id}/annotations"),
+            None,
+            json!({ "text": "?", "result_id": oldest + 100 }),
+        )
+        .await;
+    assert_eq!(foreign.status, StatusCode::BAD_REQUEST);
+
+    let results = app.get(&format!("/checks/{id}/results"), None).await;
+    let notes: Vec<Vec<&str>> = results
+        .body
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| {
+            r["annotations"]
+                .as_array()
+                .map(|a| a.iter().map(|n| n["text"].as_str().unwrap()).collect())
+                .unwrap_or_default()
+        })
+        .collect();
+    assert_eq!(
+        notes,
+        vec![vec!["ISP outage"], vec!["ISP outage"], vec!["deploy v2.3"]]
+    );
+
+    let page = json!({ "slug": "notes", "title": "Notes", "check_ids": [id] });
+    let created = app.post("/status-pages", None, page).await;
+    assert_eq!(created.status, StatusCode::CREATED, "{}", created.body);
+    let feed = app.get("/status-pages/notes/feed.atom", None).await;
+    let xml = feed.body.as_str().unwrap();
+    assert!(xml.contains("ISP outage"), "{xml}");
+    assert!(!xml.contains("deploy v2.3"), "{xml}");
+
+    let deploy_id = deploy.body["id"].as_str().unwrap();
+    let deleted = app
+        .request(
+            Method::DELETE,
+            &format!("/annotations/{deploy_id}"),
+            None,
+            &[],
+            None,
+        )
+        .await;
+    assert_eq!(deleted.status, StatusCode::NO_CONTENT);
+    let annotations = app.get(&format!("/checks/{id}/annotations"), None).await;
+    assert_eq!(annotations.body.as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn grafana_datasource_serves_latency_and_uptime_series() {
+    let app = TestApp::new().await;
+    let id = app.create_check(None, "https://example.com").await;
+    let hour = DateTime::parse_from_rfc3339("2026-03-01T10:00:00Z")
+        .unwrap()
+        .to_utc();
+    for (seconds, status, latency) in [(5, "UP", 100), (30, "DOWN", 300), (70, "UP", 50)] {
+        sqlx::query(
+            "INSERT INTO check_results (check_id, checked_at, status, latency_ms) VALUES (?, ?, ?, ?)",
+        )
+        .bind(&id)
+        .bind(hour + Duration::seconds(seconds))
+        .bind(status)
+        .bind(latency)
+        .execute(&app.db)
+        .await
+        .unwrap();
+    }
+    sqlx::query(
+        "INSERT INTO check_rollups (check_id, bucket_start, samples, up_samples, latency_sum, latency_samples) VALUES (?, '2026-03-01T10:00:00+00:00', 4, 3, 400, 4)",
+    )
+    .bind(&id)
+    .execute(&app.db)
+    .await
+    .unwrap();
+
+    assert_eq!(app.get("/grafana", None).await.status, StatusCode::OK);
+    let search = app
+        .post("/grafana/search", None, json!({ "target": "EXAMPLE" }))
+        .await;
+    assert_eq!(
+        search.body,
+        json!([
+            { "text": "https://example.com latency_ms", "value": format!("{id}:latency_ms") },
+            { "text": "https://example.com uptime_percent", "value": format!("{id}:uptime_percent") },
+        ])
+    );
+
+    let range = json!({ "from": "2026-03-01T10:00:00Z", "to": "2026-03-01T10:05:00Z" });
+    let query = app
+        .post(
+            "/grafana/query",
+            None,
+            json!({
+                "range": range,
+                "intervalMs": 60000,
+                "targets": [
+                    { "target": format!("{id}:latency_ms"), "refId": "A" },
+                    { "target": format!("{id}:uptime_percent"), "refId": "B" },
+                ],
+            }),
+        )
+        .await;
+    assert_eq!(query.status, StatusCode::OK, "{}", query.body);
+    let minute = hour.timestamp_millis();
+    assert_eq!(
+        query.body[0]["datapoints"],
+        json!([[200.0, minute], [50.0, minute + 60_000]])
+    );
+    assert_eq!(
+        query.body[1]["datapoints"],
+        json!([[50.0, minute], [100.0, minute + 60_000]])
+    );
+
+    // Hourly and coarser buckets come from the rollups
+    let hourly = app
+        .post(
+            "/grafana/query",
+            None,
+            json!({
+                "range": { "from": "2026-03-01T00:00:00Z", "to": "2026-03-02T00:00:00Z" },
+                "intervalMs": 60000,
+                "maxDataPoints": 24,
+                "targets": [{ "target": format!("{id}:uptime_percent") }],
+            }),
+        )
+        .await;
+    assert_eq!(hourly.body[0]["datapoints"], json!([[75.0, minute]]));
+
+    let unknown = app
+        .post(
+            "/grafana/query",
+            None,
+            json!({ "range": range, "targets": [{ "target": "nope:latency_ms" }] }),
+        )
+        .await;
+    assert_eq!(unknown.status, StatusCode::BAD_REQUEST);
+
+    let note = app
+        .post(
+            &format!("/checks/{id}/annotations"),
+            None,
+            json!({ "text": "deploy", "at": "2026-03-01T10:01:00Z" }),
+        )
+        .await;
+    assert_eq!(note.status, StatusCode::CREATED, "{}", note.body);
+    let annotations = app
+        .post(
+            "/grafana/annotations",
+            None,
+            json!({ "range": range, "annotation": { "name": "deploys", "query": "" } }),
+        )
+        .await;
+    assert_eq!(annotations.body[0]["text"], "deploy");
+    assert_eq!(annotations.body[0]["time"], minute + 60_000);
+    assert_eq!(annotations.body[0]["annotation"]["name"], "deploys");
+}
+
+#[tokio::test]
+async fn startup_recreates_missing_indexes_and_refuses_a_damaged_database() {
+    let dir = std::env::temp_dir().join(format!("uptime-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let url = format!("sqlite://{}", dir.join("uptime.db").display());
+    let index_exists = |db: uptime_saas::store::Db| async move {
+        let name: Option<String> = sqlx::query_scalar(
+            "SELECT name FROM sqlite_master WHERE name = 'idx_results_check_epoch'",
+        )
+        .fetch_optional(&db)
+        .await
+        .unwrap();
+        db.close().await;
+        name.is_some()
+    };
+
+    let db = uptime_saas::store::connect(&url).await.unwrap();
+    sqlx::query("DROP INDEX idx_results_check_epoch")
+        .execute(&db)
+        .await
+        .unwrap();
+    assert!(!index_exists(db).await);
+    let db = uptime_saas::store::connect(&url).await.unwrap();
+    assert!(index_exists(db.clone()).await);
+
+    // A result whose check is gone, written with foreign keys off
+    let db = uptime_saas::store::connect(&url).await.unwrap();
+    let mut conn = db.acquire().await.unwrap();
+    sqlx::query("PRAGMA foreign_keys = OFF")
+        .execute(&mut *conn)
+        .await
+        .unwrap();
+    sqlx::query(
+        "INSERT INTO check_results (check_id, checked_at, status) VALUES ('gone', '2026-03-01T10:00:00Z', 'UP')",
+    )
+    .execute(&mut *conn)
+    .await
+    .unwrap();
+    drop(conn);
+    db.close().await;
+    let err = uptime_saas::store::connect(&url).await.unwrap_err();
+    let message = format!("{err:#}");
+    assert!(message.contains("refusing to start"), "{message}");
+    assert!(
+        message.contains("1 rows of check_results reference missing checks rows"),
+        "{message}"
+    );
+
+    let garbage = dir.join("garbage.db");
+    std::fs::write(&garbage, vec![b'x'; 8192]).unwrap();
+    let err = uptime_saas::store::connect(&format!("sqlite://{}", garbage.display()))
+        .await
+        .unwrap_err();
+    assert!(format!("{err:#}").contains("restore"), "{err:#}");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn plans_limit_checks_intervals_and_history() {
+    let app = TestApp::new().await;
+    sqlx::query("UPDATE orgs SET plan = 'free' WHERE id = 'default'")
+        .execute(&app.db)
+        .await
+        .unwrap();
+
+    let too_often = app
+        .post(
+            "/checks",
+            None,
+            json!({ "name": "api", "url": "https://example.com", "interval_seconds": 60 }),
+        )
+        .await;
+    assert_eq!(too_often.status, StatusCode::PAYMENT_REQUIRED);
+    assert!(
+        too_often
+            .body
+            .as_str()
+            .unwrap()
+            .contains("mejora al plan pro"),
+        "{}",
+        too_often.body
+    );
+
+    let mut ids = Vec::new();
+    for n in 0..5 {
+        let created = app
+            .post(
+                "/checks",
+                None,
+                json!({ "name": format!("api {n}"), "url": "https://example.com", "interval_seconds": 300 }),
+            )
+            .await;
+        assert_eq!(created.status, StatusCode::CREATED, "{}", created.body);
+        ids.push(created.body["id"].as_str().unwrap().to_string());
+    }
+    let sixth = app
+        .post(
+            "/checks",
+            None,
+            json!({ "name": "api 5", "url": "https://example.com", "interval_seconds": 300 }),
+        )
+        .await;
+    assert_eq!(sixth.status, StatusCode::PAYMENT_REQUIRED);
+    assert!(sixth.body.as_str().unwrap().contains("máximo 5 checks"));
+
+    let usage = app.get("/plan", None).await;
+    assert_eq!(usage.body["plan"]["name"], "free");
+    assert_eq!(usage.body["checks"], 5);
+
+    // History older than the plan's 7 days goes on the next retention pass
+    for days in [8, 1] {
+        sqlx::query("INSERT INTO check_results (check_id, checked_at, status) VALUES (?, ?, 'UP')")
+            .bind(&ids[0])
+            .bind(Utc::now() - Duration::days(days))
+            .execute(&app.db)
+            .await
+            .unwrap();
+    }
+    let _restarted = app.another_instance().await;
+    let app = &app;
+    let id = &ids[0];
+    wait_until(|| async move {
+        app.get(&format!("/checks/{id}/results"), None)
+            .await
+            .body
+            .as_array()
+            .unwrap()
+            .len()
+            == 1
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn responses_carry_a_request_id() {
+    let app = TestApp::new().await;
+
+    let first = app.get("/checks", None).await;
+    let second = app.get("/checks/missing", None).await;
+    assert_eq!(second.status, StatusCode::NOT_FOUND);
+    let ids: Vec<&str> = [&first, &second]
+        .iter()
+        .map(|response| response.headers["x-request-id"].to_str().unwrap())
+        .collect();
+    assert_eq!(ids[0].len(), 36, "{ids:?}");
+    assert_ne!(ids[0], ids[1]);
+
+    // An id set by a proxy in front is kept
+    let forwarded = app
+        .request(
+            Method::GET,
+            "/checks",
+            None,
+            &[("x-request-id", "from-the-proxy")],
+            None,
+        )
+        .await;
+    assert_eq!(forwarded.headers["x-request-id"], "from-the-proxy");
+}
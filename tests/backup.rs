@@ -0,0 +1,203 @@
+mod common;
+
+use axum::http::StatusCode;
+use chrono::{Duration, Utc};
+use common::{wait_until, TestApp};
+use flate2::read::GzDecoder;
+use std::io::Read;
+use tokio::sync::{Mutex, MutexGuard};
+use wiremock::matchers::{header_exists, method, path_regex};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+/// The service reads its S3 settings from the environment, shared by every test here.
+static ENV: Mutex<()> = Mutex::const_new(());
+
+/// An S3 endpoint for the `backups-bucket` bucket, configured until the guard is dropped.
+async fn s3_server() -> (MutexGuard<'static, ()>, MockServer) {
+    let guard = ENV.lock().await;
+    let s3 = MockServer::start().await;
+    std::env::set_var("S3_BUCKET", "backups-bucket");
+    std::env::set_var("S3_ENDPOINT", s3.uri());
+    std::env::set_var("S3_ACCESS_KEY_ID", "AKID");
+    std::env::set_var("S3_SECRET_ACCESS_KEY", "secret");
+    (guard, s3)
+}
+
+#[tokio::test]
+async fn backups_are_consistent_snapshots_kept_locally_and_on_s3() {
+    let dir = std::env::temp_dir().join(format!("uptime-backups-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for old in ["uptime-20240101T000000Z.db", "uptime-20240102T000000Z.db"] {
+        std::fs::write(dir.join(old), b"").unwrap();
+    }
+    let (_env, s3) = s3_server().await;
+    Mock::given(method("PUT"))
+        .and(path_regex(
+            r"^/backups-bucket/snapshots/uptime-\d{8}T\d{9}Z\.db$",
+        ))
+        .and(header_exists("authorization"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&s3)
+        .await;
+    std::env::set_var("BACKUP_DIR", &dir);
+    std::env::set_var("BACKUP_KEEP", "2");
+    std::env::set_var("BACKUP_S3_PREFIX", "snapshots/");
+    let app = TestApp::with_database(&format!("sqlite://{}", dir.join("live.db").display())).await;
+    let id = app.create_check(None, "https://example.com").await;
+
+    // Back to back, within the same second
+    let first = app.post("/admin/backup", None, serde_json::json!({})).await;
+    assert_eq!(first.status, StatusCode::CREATED, "{}", first.body);
+    let backup = app.post("/admin/backup", None, serde_json::json!({})).await;
+    assert_eq!(backup.status, StatusCode::CREATED, "{}", backup.body);
+    let file = backup.body["file"].as_str().unwrap();
+    assert_ne!(first.body["file"], backup.body["file"]);
+    assert!(backup.body["s3_key"]
+        .as_str()
+        .unwrap()
+        .starts_with("snapshots/uptime-"));
+    assert_eq!(
+        backup.body["bytes"].as_u64().unwrap(),
+        std::fs::metadata(file).unwrap().len()
+    );
+
+    // The older snapshots made room for the new ones
+    let mut kept: Vec<String> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+        .filter(|name| name.starts_with("uptime-"))
+        .collect();
+    kept.sort();
+    let name = |backup: &serde_json::Value| {
+        let file = std::path::PathBuf::from(backup["file"].as_str().unwrap());
+        file.file_name().unwrap().to_string_lossy().to_string()
+    };
+    assert_eq!(kept, [name(&first.body), name(&backup.body)]);
+
+    let snapshot = sqlx::SqlitePool::connect(&format!("sqlite://{file}?mode=ro"))
+        .await
+        .unwrap();
+    let (url,): (String,) = sqlx::query_as("SELECT url FROM checks WHERE id = ?")
+        .bind(&id)
+        .fetch_one(&snapshot)
+        .await
+        .unwrap();
+    assert_eq!(url, "https://example.com");
+    snapshot.close().await;
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn aged_results_are_pruned_only_once_archived() {
+    let (_env, s3) = s3_server().await;
+    let archive = || Mock::given(method("PUT")).and(path_regex(r"^/backups-bucket/archive/"));
+    archive()
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .mount(&s3)
+        .await;
+    // Slow enough for a result to be written while the upload is in flight
+    archive()
+        .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(1)))
+        .mount(&s3)
+        .await;
+    std::env::set_var("RESULTS_ARCHIVE_PREFIX", "archive/");
+    // One result per object
+    std::env::set_var("RESULTS_ARCHIVE_BATCH", "1");
+    let app = TestApp::new().await;
+    let id = app.create_check(None, "https://example.com").await;
+    let idle = app.create_check(None, "https://idle.example.com").await;
+    sqlx::query("UPDATE orgs SET plan = 'free' WHERE id = 'default'")
+        .execute(&app.db)
+        .await
+        .unwrap();
+    // An organization with nothing to archive still has its rollups pruned
+    sqlx::query("INSERT INTO orgs (id, name, created_at, plan) VALUES ('idle', 'Idle', ?, 'free')")
+        .bind(Utc::now())
+        .execute(&app.db)
+        .await
+        .unwrap();
+    sqlx::query("UPDATE checks SET org_id = 'idle' WHERE id = ?")
+        .bind(&idle)
+        .execute(&app.db)
+        .await
+        .unwrap();
+    sqlx::query("INSERT INTO check_rollups (check_id, bucket_start, samples, up_samples, latency_sum, latency_samples) VALUES (?, ?, 1, 1, 0, 0)")
+        .bind(&idle)
+        .bind(Utc::now() - Duration::days(30))
+        .execute(&app.db)
+        .await
+        .unwrap();
+    let insert = |days: i64| {
+        let (app, id) = (&app, &id);
+        async move {
+            sqlx::query(
+                "INSERT INTO check_results (check_id, checked_at, status) VALUES (?, ?, 'UP') RETURNING id",
+            )
+            .bind(id)
+            .bind(Utc::now() - Duration::days(days))
+            .fetch_one(&app.db)
+            .await
+            .map(|row| sqlx::Row::get::<i64, _>(&row, "id"))
+            .unwrap()
+        }
+    };
+    let aged = [insert(9).await, insert(8).await];
+    let recent = insert(1).await;
+    let ids = || async {
+        sqlx::query_scalar::<_, i64>("SELECT id FROM check_results ORDER BY id")
+            .fetch_all(&app.db)
+            .await
+            .unwrap()
+    };
+    let uploads = || async {
+        s3.received_requests()
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|request| request.method.as_str() == "PUT")
+            .collect::<Vec<_>>()
+    };
+
+    // The first upload fails, so the retention pass at startup deletes nothing
+    let _failed = app.another_instance().await;
+    wait_until(|| async { uploads().await.len() == 1 }).await;
+    assert_eq!(ids().await, vec![aged[0], aged[1], recent]);
+
+    let _archived = app.another_instance().await;
+    wait_until(|| async { uploads().await.len() == 2 }).await;
+    let late = insert(8).await;
+    wait_until(|| async { ids().await.len() == 2 }).await;
+    // The late result was not in the archive, so it waits for the next pass
+    assert_eq!(ids().await, vec![recent, late]);
+    std::env::remove_var("RESULTS_ARCHIVE_PREFIX");
+    std::env::remove_var("RESULTS_ARCHIVE_BATCH");
+    let (rollups,): (i64,) =
+        sqlx::query_as("SELECT COUNT(*) FROM check_rollups WHERE check_id = ?")
+            .bind(&idle)
+            .fetch_one(&app.db)
+            .await
+            .unwrap();
+    assert_eq!(rollups, 0);
+
+    let uploads = uploads().await;
+    assert_eq!(uploads.len(), 3);
+    for (upload, id) in uploads[1..].iter().zip(aged) {
+        assert_eq!(
+            upload.url.path(),
+            format!("/backups-bucket/archive/default/results-{id}-{id}.jsonl.gz")
+        );
+        let mut lines = String::new();
+        GzDecoder::new(upload.body.as_slice())
+            .read_to_string(&mut lines)
+            .unwrap();
+        let archived: Vec<serde_json::Value> = lines
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(archived.len(), 1);
+        assert_eq!(archived[0]["id"], id);
+        assert_eq!(archived[0]["status"], "UP");
+    }
+}
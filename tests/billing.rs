@@ -0,0 +1,128 @@
+mod common;
+
+use axum::http::{Method, StatusCode};
+use chrono::Utc;
+use common::{TestApp, TestResponse};
+use hmac::{Hmac, Mac};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use wiremock::matchers::{body_string_contains, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+/// Posts `event` to the webhook with a `Stripe-Signature` made at `timestamp`.
+async fn send_event(app: &TestApp, secret: &str, timestamp: i64, event: Value) -> TestResponse {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(format!("{timestamp}.{event}").as_bytes());
+    let signature: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    app.request(
+        Method::POST,
+        "/billing/webhook",
+        None,
+        &[("stripe-signature", &format!("t={timestamp},v1={signature}"))],
+        Some(event),
+    )
+    .await
+}
+
+async fn plan(app: &TestApp) -> Value {
+    app.get("/plan", None).await.body["plan"]["name"].clone()
+}
+
+#[tokio::test]
+async fn subscriptions_reported_by_stripe_change_the_plan() {
+    let stripe = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/customers"))
+        .and(body_string_contains("metadata%5Borg_id%5D=default"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "id": "cus_1" })))
+        .expect(1)
+        .mount(&stripe)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/v1/checkout/sessions"))
+        .and(body_string_contains("customer=cus_1"))
+        .and(body_string_contains(
+            "line_items%5B0%5D%5Bprice%5D=price_pro",
+        ))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!({ "id": "cs_1", "url": "https://checkout.stripe.com/cs_1" })),
+        )
+        .mount(&stripe)
+        .await;
+    std::env::set_var("STRIPE_API_URL", stripe.uri());
+    std::env::set_var("STRIPE_SECRET_KEY", "sk_test");
+    std::env::set_var("STRIPE_WEBHOOK_SECRET", "whsec_test");
+    std::env::set_var("STRIPE_PRICE_PRO", "price_pro");
+    std::env::set_var("STRIPE_PRICE_BUSINESS", "price_business");
+    let app = TestApp::new().await;
+    sqlx::query("UPDATE orgs SET plan = 'free' WHERE id = 'default'")
+        .execute(&app.db)
+        .await
+        .unwrap();
+
+    let unsold = app
+        .post("/billing/checkout", None, json!({ "plan": "unlimited" }))
+        .await;
+    assert_eq!(unsold.status, StatusCode::BAD_REQUEST);
+    let checkout = app
+        .post("/billing/checkout", None, json!({ "plan": "pro" }))
+        .await;
+    assert_eq!(checkout.status, StatusCode::OK, "{}", checkout.body);
+    assert_eq!(checkout.body["url"], "https://checkout.stripe.com/cs_1");
+    // Paying happens on Stripe, the plan waits for the webhook
+    assert_eq!(plan(&app).await, "free");
+
+    let completed = json!({
+        "id": "evt_1",
+        "type": "checkout.session.completed",
+        "data": { "object": {
+            "client_reference_id": "default",
+            "subscription": "sub_1",
+            "metadata": { "plan": "pro" },
+        } },
+    });
+    let now = Utc::now().timestamp();
+    let forged = send_event(&app, "whsec_other", now, completed.clone()).await;
+    assert_eq!(forged.status, StatusCode::BAD_REQUEST);
+    let replayed = send_event(&app, "whsec_test", now - 3600, completed.clone()).await;
+    assert_eq!(replayed.status, StatusCode::BAD_REQUEST);
+    assert_eq!(plan(&app).await, "free");
+    let accepted = send_event(&app, "whsec_test", now, completed).await;
+    assert_eq!(accepted.status, StatusCode::OK, "{}", accepted.body);
+    assert_eq!(plan(&app).await, "pro");
+
+    let subscription = |status: &str, price: &str| {
+        json!({
+            "id": "evt_2",
+            "type": "customer.subscription.updated",
+            "data": { "object": {
+                "id": "sub_1",
+                "status": status,
+                "metadata": { "org_id": "default" },
+                "items": { "data": [{ "price": { "id": price } }] },
+            } },
+        })
+    };
+    send_event(
+        &app,
+        "whsec_test",
+        now,
+        subscription("active", "price_business"),
+    )
+    .await;
+    assert_eq!(plan(&app).await, "business");
+    send_event(
+        &app,
+        "whsec_test",
+        now,
+        subscription("canceled", "price_business"),
+    )
+    .await;
+    assert_eq!(plan(&app).await, "free");
+}
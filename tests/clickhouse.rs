@@ -0,0 +1,71 @@
+#![cfg(feature = "clickhouse")]
+
+mod common;
+
+use common::{wait_until, TestApp};
+use serde_json::{json, Value};
+use wiremock::matchers::{method, path, query_param_contains};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+#[tokio::test]
+async fn results_are_written_to_and_stats_read_from_clickhouse() {
+    let clickhouse = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(query_param_contains("query", "SELECT count()"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(
+            json!({ "samples": 10, "up_samples": 9, "avg_latency_ms": 12.5, "max_latency_ms": 40 })
+                .to_string(),
+        ))
+        .mount(&clickhouse)
+        .await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&clickhouse)
+        .await;
+    std::env::set_var("CLICKHOUSE_URL", clickhouse.uri());
+    let app = TestApp::new().await;
+    let target = MockServer::start().await;
+    Mock::given(path("/ok"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&target)
+        .await;
+
+    let id = app
+        .create_check(None, &format!("{}/ok", target.uri()))
+        .await;
+    app.run_check(&id).await;
+
+    let inserted = || async {
+        clickhouse
+            .received_requests()
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|r| {
+                r.url
+                    .query_pairs()
+                    .any(|(k, v)| k == "query" && v.starts_with("INSERT"))
+            })
+            .flat_map(|r| {
+                String::from_utf8(r.body)
+                    .unwrap()
+                    .lines()
+                    .map(|line| serde_json::from_str::<Value>(line).unwrap())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>()
+    };
+    wait_until(|| async { !inserted().await.is_empty() }).await;
+    let rows = inserted().await;
+    assert_eq!(rows[0]["check_id"], id.as_str());
+    assert_eq!(rows[0]["status"], "UP");
+    assert_eq!(rows[0]["http_status"], 200);
+
+    // SQLite only holds the one sample, the figures come from ClickHouse
+    let stats = app
+        .get(&format!("/checks/{id}/stats?period=24h"), None)
+        .await;
+    assert_eq!(stats.body["samples"], 10, "{}", stats.body);
+    assert_eq!(stats.body["uptime_percent"], 90.0);
+    assert_eq!(stats.body["max_latency_ms"], 40);
+}
@@ -0,0 +1,229 @@
+#![allow(dead_code)]
+
+use axum::body::{to_bytes, Body};
+use axum::extract::connect_info::MockConnectInfo;
+use axum::http::{HeaderMap, Method, Request, StatusCode};
+use axum::Router;
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tower::ServiceExt;
+use uptime_saas::scheduler::Clock;
+use uptime_saas::store::{self, Db};
+
+/// Held while an app starts, as the service reads its settings from the environment then:
+/// the variables set for one app are never seen by another.
+static ENV: Mutex<()> = Mutex::const_new(());
+
+/// The whole service on an in-memory database, with the worker running on a manual clock.
+pub struct TestApp {
+    pub router: Router,
+    pub db: Db,
+    pub clock: Clock,
+}
+
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Value,
+}
+
+impl TestApp {
+    pub async fn new() -> Self {
+        Self::with_database("sqlite::memory:").await
+    }
+
+    /// The same, on the database at `url`, for features that need a file such as backups.
+    pub async fn with_database(url: &str) -> Self {
+        let _env = ENV.lock().await;
+        Self::start(url).await
+    }
+
+    /// A new app that also reads `vars` from its environment.
+    pub async fn with_env(vars: &[(&str, &str)]) -> Self {
+        let _env = ENV.lock().await;
+        for (name, value) in vars {
+            std::env::set_var(name, value);
+        }
+        let app = Self::start("sqlite::memory:").await;
+        for (name, _) in vars {
+            std::env::remove_var(name);
+        }
+        app
+    }
+
+    async fn start(url: &str) -> Self {
+        let db = store::connect(url).await.unwrap();
+        let clock = Clock::manual(Utc::now());
+        let router = uptime_saas::start(db.clone(), clock.clone())
+            .await
+            .unwrap()
+            .layer(MockConnectInfo(SocketAddr::from(([127, 0, 0, 1], 0))));
+        TestApp { router, db, clock }
+    }
+
+    /// Another instance of the service on the same database and clock, as when scaling out.
+    pub async fn another_instance(&self) -> Self {
+        let _env = ENV.lock().await;
+        let router = uptime_saas::start(self.db.clone(), self.clock.clone())
+            .await
+            .unwrap()
+            .layer(MockConnectInfo(SocketAddr::from(([127, 0, 0, 1], 0))));
+        TestApp {
+            router,
+            db: self.db.clone(),
+            clock: self.clock.clone(),
+        }
+    }
+
+    pub async fn request(
+        &self,
+        method: Method,
+        path: &str,
+        token: Option<&str>,
+        headers: &[(&str, &str)],
+        body: Option<Value>,
+    ) -> TestResponse {
+        let mut request = Request::builder().method(method).uri(path);
+        if let Some(token) = token {
+            request = request.header("authorization", format!("Bearer {token}"));
+        }
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        let request = match body {
+            Some(body) => request
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string())),
+            None => request.body(Body::empty()),
+        }
+        .unwrap();
+        self.send(request).await
+    }
+
+    /// Posts `text` as it is, for bodies that aren't JSON such as calendars.
+    pub async fn post_text(&self, path: &str, token: Option<&str>, text: &str) -> TestResponse {
+        let mut request = Request::builder().method(Method::POST).uri(path);
+        if let Some(token) = token {
+            request = request.header("authorization", format!("Bearer {token}"));
+        }
+        self.send(request.body(Body::from(text.to_string())).unwrap())
+            .await
+    }
+
+    async fn send(&self, request: Request<Body>) -> TestResponse {
+        let response = self.router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let headers = response.headers().clone();
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        // Errors are plain text
+        let body = serde_json::from_slice(&bytes)
+            .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&bytes).into_owned()));
+        TestResponse {
+            status,
+            headers,
+            body,
+        }
+    }
+
+    pub async fn get(&self, path: &str, token: Option<&str>) -> TestResponse {
+        self.request(Method::GET, path, token, &[], None).await
+    }
+
+    pub async fn post(&self, path: &str, token: Option<&str>, body: Value) -> TestResponse {
+        self.request(Method::POST, path, token, &[], Some(body))
+            .await
+    }
+
+    /// Creates a check and returns its id.
+    pub async fn create_check(&self, token: Option<&str>, url: &str) -> String {
+        let response = self
+            .post(
+                "/checks",
+                token,
+                json!({ "name": url, "url": url, "interval_seconds": 60 }),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::CREATED, "{}", response.body);
+        response.body["id"].as_str().unwrap().to_string()
+    }
+
+    /// Adds a user with `role` to the caller's organization and returns an API key for them.
+    /// The first key turns authentication on, so it should be an admin's.
+    pub async fn user_token(&self, admin: Option<&str>, email: &str, role: &str) -> String {
+        let user = self
+            .post(
+                "/users",
+                admin,
+                json!({ "email": email, "name": email, "role": role }),
+            )
+            .await;
+        assert_eq!(user.status, StatusCode::CREATED, "{}", user.body);
+        let user_id = user.body["id"].as_str().unwrap();
+        let key = self
+            .post(
+                &format!("/users/{user_id}/api-keys"),
+                admin,
+                json!({ "name": "tests" }),
+            )
+            .await;
+        assert_eq!(key.status, StatusCode::CREATED, "{}", key.body);
+        key.body["token"].as_str().unwrap().to_string()
+    }
+
+    /// Moves the clock forward and waits for the scheduler pass at the new time to finish
+    /// every probe it started.
+    pub async fn advance(&self, by: chrono::Duration) {
+        self.clock.advance(by);
+        let now = self.clock.now();
+        wait_until(|| async {
+            let worker = self.get("/admin/worker", None).await.body;
+            let pass = worker["last_scheduler_pass_at"]
+                .as_str()
+                .and_then(|at| DateTime::parse_from_rfc3339(at).ok());
+            // The pass time is kept in milliseconds
+            pass.is_some_and(|at| now.signed_duration_since(at) < chrono::Duration::milliseconds(1))
+                && worker["queue_depth"] == 0
+                && worker["jobs_in_flight"] == 0
+        })
+        .await;
+    }
+
+    /// Moves the clock one 60s interval forward, past the next run of checks created by
+    /// [`TestApp::create_check`], and waits until `id` has been probed.
+    pub async fn run_check(&self, id: &str) {
+        let before = self.last_checked_at(id).await;
+        self.advance(chrono::Duration::seconds(60)).await;
+        assert_ne!(
+            self.last_checked_at(id).await,
+            before,
+            "check was not probed"
+        );
+    }
+
+    async fn last_checked_at(&self, id: &str) -> Option<String> {
+        sqlx::query_scalar("SELECT last_checked_at FROM checks WHERE id = ?")
+            .bind(id)
+            .fetch_one(&self.db)
+            .await
+            .unwrap()
+    }
+}
+
+/// Polls `condition` for up to five seconds.
+pub async fn wait_until<F, Fut>(mut condition: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = bool>,
+{
+    for _ in 0..100 {
+        if condition().await {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("condition not met within 5s");
+}
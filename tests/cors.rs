@@ -0,0 +1,78 @@
+mod common;
+
+use axum::http::{Method, StatusCode};
+use common::TestApp;
+
+#[tokio::test]
+async fn browsers_on_allowed_origins_may_call_the_api() {
+    std::env::set_var(
+        "CORS_ALLOWED_ORIGINS",
+        "https://dashboard.example.com, https://admin.example.com",
+    );
+    let app = TestApp::new().await;
+
+    let preflight = app
+        .request(
+            Method::OPTIONS,
+            "/checks",
+            None,
+            &[
+                ("origin", "https://dashboard.example.com"),
+                ("access-control-request-method", "PATCH"),
+                ("access-control-request-headers", "authorization,if-match"),
+            ],
+            None,
+        )
+        .await;
+    assert_eq!(preflight.status, StatusCode::OK);
+    let header = |name: &str| {
+        preflight
+            .headers
+            .get(name)
+            .map(|value| value.to_str().unwrap().to_string())
+    };
+    assert_eq!(
+        header("access-control-allow-origin").as_deref(),
+        Some("https://dashboard.example.com")
+    );
+    assert!(header("access-control-allow-methods")
+        .unwrap()
+        .contains("PATCH"));
+    assert!(header("access-control-allow-headers")
+        .unwrap()
+        .contains("if-match"));
+
+    let listed = app
+        .request(
+            Method::GET,
+            "/checks",
+            None,
+            &[("origin", "https://admin.example.com")],
+            None,
+        )
+        .await;
+    assert_eq!(listed.status, StatusCode::OK);
+    assert_eq!(
+        listed.headers["access-control-allow-origin"],
+        "https://admin.example.com"
+    );
+    // Scripts may read the request id to report errors
+    assert!(listed.headers["access-control-expose-headers"]
+        .to_str()
+        .unwrap()
+        .contains("x-request-id"));
+
+    let elsewhere = app
+        .request(
+            Method::GET,
+            "/checks",
+            None,
+            &[("origin", "https://evil.example.com")],
+            None,
+        )
+        .await;
+    assert!(elsewhere
+        .headers
+        .get("access-control-allow-origin")
+        .is_none());
+}
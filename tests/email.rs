@@ -0,0 +1,136 @@
+mod common;
+
+use axum::http::StatusCode;
+use common::{wait_until, TestApp};
+use serde_json::json;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use wiremock::matchers::path;
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+/// An SMTP server that accepts every message and keeps `(recipient, data)` of each.
+async fn fake_smtp() -> (String, Arc<Mutex<Vec<(String, String)>>>) {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("smtp://{}", listener.local_addr().unwrap());
+    let mailbox = Arc::new(Mutex::new(vec![]));
+    let delivered = mailbox.clone();
+    tokio::spawn(async move {
+        while let Ok((socket, _)) = listener.accept().await {
+            let mailbox = mailbox.clone();
+            tokio::spawn(async move {
+                let (read, mut write) = socket.into_split();
+                let mut read = BufReader::new(read);
+                write.write_all(b"220 test ESMTP\r\n").await.ok();
+                let (mut recipient, mut data, mut in_data) = (String::new(), String::new(), false);
+                let mut line = String::new();
+                while read.read_line(&mut line).await.is_ok_and(|n| n > 0) {
+                    let reply: &[u8] = if in_data {
+                        if line == ".\r\n" {
+                            in_data = false;
+                            mailbox
+                                .lock()
+                                .unwrap()
+                                .push((recipient.clone(), std::mem::take(&mut data)));
+                            b"250 queued\r\n"
+                        } else {
+                            data.push_str(&line);
+                            b""
+                        }
+                    } else {
+                        let command = line.to_ascii_uppercase();
+                        if command.starts_with("EHLO") || command.starts_with("HELO") {
+                            b"250 test\r\n"
+                        } else if command.starts_with("RCPT TO:") {
+                            recipient = line[8..].trim().trim_matches(['<', '>']).to_string();
+                            b"250 ok\r\n"
+                        } else if command.starts_with("DATA") {
+                            in_data = true;
+                            b"354 go ahead\r\n"
+                        } else if command.starts_with("QUIT") {
+                            write.write_all(b"221 bye\r\n").await.ok();
+                            break;
+                        } else {
+                            b"250 ok\r\n"
+                        }
+                    };
+                    write.write_all(reply).await.ok();
+                    line.clear();
+                }
+            });
+        }
+    });
+    (url, delivered)
+}
+
+/// Enough of quoted-printable decoding for the URLs in a message.
+fn quoted_printable(data: &str) -> String {
+    data.replace("=\r\n", "").replace("=3D", "=")
+}
+
+#[tokio::test]
+async fn alerts_only_reach_email_addresses_that_were_verified() {
+    let (url, mailbox) = fake_smtp().await;
+    std::env::set_var("SMTP_URL", url);
+    std::env::set_var("SMTP_FROM", "uptime@example.com");
+    std::env::set_var("PUBLIC_URL", "https://uptime.example.com");
+    let app = TestApp::new().await;
+    let target = MockServer::start().await;
+    Mock::given(path("/"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .mount(&target)
+        .await;
+    Mock::given(path("/"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&target)
+        .await;
+    let mails = || mailbox.lock().unwrap().clone();
+
+    let created = app
+        .post(
+            "/checks",
+            None,
+            json!({ "name": "site", "url": target.uri(), "interval_seconds": 60, "alert_email": "ops@example.com" }),
+        )
+        .await;
+    assert_eq!(created.status, StatusCode::CREATED, "{}", created.body);
+    let id = created.body["id"].as_str().unwrap();
+    wait_until(|| async { mails().len() == 1 }).await;
+    let (to, verification) = mails()[0].clone();
+    assert_eq!(to, "ops@example.com");
+    assert!(verification.contains("Subject: Confirm your alert email address"));
+    let link = quoted_printable(&verification)
+        .lines()
+        .find_map(|line| line.strip_prefix("https://uptime.example.com"))
+        .unwrap()
+        .to_string();
+
+    // Going DOWN isn't mailed before the address is confirmed
+    app.run_check(id).await;
+    let check = app.get(&format!("/checks/{id}"), None).await;
+    assert_eq!(check.body["last_status"], "DOWN");
+    let queued = app.get("/admin/notifications", None).await;
+    let queued = queued.body.as_array().unwrap();
+    assert_eq!(queued.len(), 1, "{queued:?}");
+    assert_eq!(queued[0]["email"], "ops@example.com");
+    assert_eq!(queued[0]["last_error"], "ops@example.com is not verified");
+
+    let forged = app
+        .get(
+            "/verify-email?token=b3BzQGV4YW1wbGUuY29t.9999999999.c2lnbmF0dXJl",
+            None,
+        )
+        .await;
+    assert_eq!(forged.status, StatusCode::BAD_REQUEST);
+    let verified = app.get(&link, None).await;
+    assert_eq!(verified.status, StatusCode::OK, "{}", verified.body);
+
+    app.run_check(id).await;
+    wait_until(|| async { mails().len() == 2 }).await;
+    let (to, recovery) = mails()[1].clone();
+    assert_eq!(to, "ops@example.com");
+    assert!(
+        quoted_printable(&recovery).contains("Down for 1m 0s"),
+        "{recovery}"
+    );
+}
@@ -0,0 +1,91 @@
+#![cfg(feature = "nats")]
+
+mod common;
+
+use common::TestApp;
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc;
+use wiremock::matchers::path;
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+/// Just enough of a NATS server to accept a client and pass on what it publishes.
+async fn fake_nats() -> (String, mpsc::UnboundedReceiver<(String, Value)>) {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("nats://{}", listener.local_addr().unwrap());
+    let (published, received) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Ok((socket, _)) = listener.accept().await {
+            let published = published.clone();
+            tokio::spawn(async move {
+                let (read, mut write) = socket.into_split();
+                let info = r#"{"server_id":"test","server_name":"test","version":"2.10.0","proto":1,"host":"127.0.0.1","port":4222,"max_payload":1048576,"headers":true}"#;
+                write
+                    .write_all(format!("INFO {info}\r\n").as_bytes())
+                    .await
+                    .ok();
+                let mut read = BufReader::new(read);
+                let mut line = String::new();
+                while read.read_line(&mut line).await.is_ok_and(|n| n > 0) {
+                    let words: Vec<&str> = line.split_whitespace().collect();
+                    match words.first().copied() {
+                        Some("PING") => {
+                            write.write_all(b"PONG\r\n").await.ok();
+                        }
+                        // PUB <subject> [reply-to] <bytes>, HPUB <subject> [reply-to] <header bytes> <bytes>
+                        Some(verb @ ("PUB" | "HPUB")) => {
+                            let total: usize = words.last().unwrap().parse().unwrap();
+                            let mut message = vec![0; total + 2];
+                            read.read_exact(&mut message).await.unwrap();
+                            let headers = match verb {
+                                "HPUB" => words[words.len() - 2].parse().unwrap(),
+                                _ => 0,
+                            };
+                            let payload = serde_json::from_slice(&message[headers..total]).unwrap();
+                            published.send((words[1].to_string(), payload)).ok();
+                        }
+                        _ => {}
+                    }
+                    line.clear();
+                }
+            });
+        }
+    });
+    (url, received)
+}
+
+#[tokio::test]
+async fn status_changes_and_incidents_are_published_to_nats() {
+    let (url, mut published) = fake_nats().await;
+    std::env::set_var("NATS_URL", url);
+    let app = TestApp::new().await;
+    let target = MockServer::start().await;
+    Mock::given(path("/down"))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&target)
+        .await;
+
+    let id = app
+        .create_check(None, &format!("{}/down", target.uri()))
+        .await;
+    app.run_check(&id).await;
+
+    let mut events = vec![];
+    while events.len() < 2 {
+        let event = tokio::time::timeout(std::time::Duration::from_secs(5), published.recv())
+            .await
+            .expect("no event published within 5s")
+            .unwrap();
+        events.push(event);
+    }
+    events.sort_by(|a, b| a.0.cmp(&b.0));
+    let (subjects, payloads): (Vec<_>, Vec<_>) = events.into_iter().unzip();
+    assert_eq!(
+        subjects,
+        ["uptime.incident_opened", "uptime.status_changed"]
+    );
+    assert_eq!(payloads[0]["type"], "incident_opened");
+    assert_eq!(payloads[0]["check_id"], id.as_str());
+    assert_eq!(payloads[1]["status"], "DOWN");
+    assert_eq!(payloads[1]["previous"], "UNKNOWN");
+}
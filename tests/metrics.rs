@@ -0,0 +1,58 @@
+mod common;
+
+use axum::http::{Method, StatusCode};
+use common::TestApp;
+use serde_json::json;
+
+#[tokio::test]
+async fn metrics_need_the_scrape_token_or_an_operator_key() {
+    std::env::set_var("METRICS_TOKEN", "scrape-token");
+    let app = TestApp::new().await;
+    let operator = app.user_token(None, "ops@example.com", "admin").await;
+    let org = app
+        .post("/orgs", Some(&operator), json!({ "name": "Acme" }))
+        .await;
+    let org_id = org.body["id"].as_str().unwrap();
+    let invited = app
+        .request(
+            Method::POST,
+            "/invitations",
+            Some(&operator),
+            &[("x-org-id", org_id)],
+            Some(json!({ "email": "acme@example.com", "role": "admin" })),
+        )
+        .await;
+    let accepted = app
+        .post(
+            "/invitations/accept",
+            None,
+            json!({ "token": invited.body["token"] }),
+        )
+        .await;
+    let tenant = accepted.body["api_key"]["token"].as_str().unwrap();
+
+    for (token, status) in [
+        (None, StatusCode::UNAUTHORIZED),
+        (Some("not-the-token"), StatusCode::UNAUTHORIZED),
+        (Some(tenant), StatusCode::FORBIDDEN),
+        (Some("scrape-token"), StatusCode::OK),
+    ] {
+        let metrics = app.get("/metrics", token).await;
+        assert_eq!(metrics.status, status, "{token:?}");
+    }
+    let by_operator = app
+        .request(
+            Method::GET,
+            "/metrics",
+            Some(&operator),
+            &[("x-org-id", "default")],
+            None,
+        )
+        .await;
+    assert_eq!(by_operator.status, StatusCode::OK);
+    assert!(by_operator
+        .body
+        .as_str()
+        .unwrap()
+        .contains("uptime_probes_total"));
+}
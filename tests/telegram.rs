@@ -0,0 +1,134 @@
+mod common;
+
+use common::{wait_until, TestApp};
+use serde_json::{json, Value};
+use wiremock::matchers::{method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+#[tokio::test]
+async fn alert_chats_manage_their_own_checks_through_bot_commands() {
+    let telegram = MockServer::start().await;
+    Mock::given(path("/bottest-token/getUpdates"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!({ "ok": true, "result": [] }))
+                .set_delay(std::time::Duration::from_millis(100)),
+        )
+        .mount(&telegram)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/bottest-token/sendMessage"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "ok": true })))
+        .mount(&telegram)
+        .await;
+    std::env::set_var("TELEGRAM_API_URL", telegram.uri());
+    std::env::set_var("TELEGRAM_BOT_TOKEN", "test-token");
+    std::env::set_var("TELEGRAM_CHAT_ID", "42");
+    let app = TestApp::new().await;
+    let api = app.create_check(None, "https://api.example.com").await;
+    let db = app.create_check(None, "https://db.example.com").await;
+    sqlx::query("UPDATE checks SET name = 'api' WHERE id = ?")
+        .bind(&api)
+        .execute(&app.db)
+        .await
+        .unwrap();
+    sqlx::query("UPDATE checks SET name = 'db', last_status = 'DOWN' WHERE id = ?")
+        .bind(&db)
+        .execute(&app.db)
+        .await
+        .unwrap();
+
+    // Acme gets its alerts in chat 7 through the instance's bot, and has a check called api too
+    let acme_api = app.create_check(None, "https://api.acme.example.com").await;
+    sqlx::query("INSERT INTO orgs (id, name, created_at) VALUES ('acme', 'Acme', '2024-01-01T00:00:00+00:00')")
+        .execute(&app.db)
+        .await
+        .unwrap();
+    sqlx::query(
+        "UPDATE checks SET name = 'api', org_id = 'acme', last_status = 'DOWN' WHERE id = ?",
+    )
+    .bind(&acme_api)
+    .execute(&app.db)
+    .await
+    .unwrap();
+    sqlx::query("INSERT INTO notification_channels (id, name, kind, target, created_at, org_id) VALUES ('acme-tg', 'ops', 'telegram', '7', '2024-01-01T00:00:00+00:00', 'acme')")
+        .execute(&app.db)
+        .await
+        .unwrap();
+    for url in ["https://a.example.com", "https://b.example.com"] {
+        let dup = app.create_check(None, url).await;
+        sqlx::query("UPDATE checks SET name = 'web' WHERE id = ?")
+            .bind(&dup)
+            .execute(&app.db)
+            .await
+            .unwrap();
+    }
+
+    let message = |update_id: i64, chat: i64, text: &str| json!({ "update_id": update_id, "message": { "chat": { "id": chat }, "text": text } });
+    Mock::given(path("/bottest-token/getUpdates"))
+        .and(query_param("offset", "0"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "ok": true,
+            "result": [
+                message(1, 99, "/pause api"),
+                message(2, 42, "/help"),
+                message(3, 42, "/pause@uptime_bot API"),
+                message(4, 42, "/status"),
+                message(5, 42, "/uptime nothing 7d"),
+                message(6, 42, "/ack web"),
+                message(7, 7, "/status"),
+            ],
+        })))
+        .with_priority(1)
+        .mount(&telegram)
+        .await;
+
+    let replies = || async {
+        telegram
+            .received_requests()
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|r| r.url.path().ends_with("/sendMessage"))
+            .map(|r| r.body_json::<Value>().unwrap())
+            .collect::<Vec<_>>()
+    };
+    wait_until(|| async { replies().await.len() == 6 }).await;
+    let replies = replies().await;
+    let chats: Vec<&str> = replies
+        .iter()
+        .map(|r| r["chat_id"].as_str().unwrap())
+        .collect();
+    assert_eq!(chats, ["42", "42", "42", "42", "42", "7"]);
+    let texts: Vec<&str> = replies
+        .iter()
+        .map(|r| r["text"].as_str().unwrap())
+        .collect();
+    // Commands chats without alerts send are ignored, unknown ones get the list
+    assert!(texts[0].starts_with("Commands:"), "{texts:?}");
+    assert_eq!(texts[1], "⏸ Paused api");
+    assert_eq!(texts[2], "1 DOWN\n🔴 db https://db.example.com");
+    assert_eq!(texts[3], "No check named \"nothing\"");
+    assert!(
+        texts[4].starts_with("Several checks are named \"web\""),
+        "{texts:?}"
+    );
+    // Each chat only sees and manages its own organization's checks
+    assert_eq!(texts[5], "1 DOWN\n🔴 api https://api.acme.example.com");
+    let check = app.get(&format!("/checks/{api}"), None).await;
+    assert_eq!(check.body["is_active"], 0);
+    let (acme_active,): (i64,) = sqlx::query_as("SELECT is_active FROM checks WHERE id = ?")
+        .bind(&acme_api)
+        .fetch_one(&app.db)
+        .await
+        .unwrap();
+    assert_eq!(acme_active, 1);
+    // The follow-up poll confirms the updates
+    let polled_past = telegram
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .any(|r| r.url.query_pairs().any(|(k, v)| k == "offset" && v == "8"));
+    assert!(polled_past);
+}
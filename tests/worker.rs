@@ -0,0 +1,2347 @@
+mod common;
+
+use axum::http::{Method, StatusCode};
+use chrono::Duration;
+use common::{wait_until, TestApp};
+use serde_json::{json, Value};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+async fn webhook_channel(app: &TestApp, hooks: &MockServer) {
+    Mock::given(method("POST"))
+        .and(path("/hook"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(hooks)
+        .await;
+    let channel = app
+        .post(
+            "/channels",
+            None,
+            json!({ "name": "hook", "kind": "webhook", "target": format!("{}/hook", hooks.uri()) }),
+        )
+        .await;
+    assert_eq!(channel.status, StatusCode::CREATED, "{}", channel.body);
+}
+
+/// Turns secret storage on for the apps started from now on, the same key for all of them.
+fn enable_secrets() {
+    std::env::set_var(
+        "SECRETS_KEY",
+        "MDEyMzQ1Njc4OTAxMjM0NTY3ODkwMTIzNDU2Nzg5MDE=",
+    );
+}
+
+/// The `alert` of every webhook delivered so far.
+async fn alerts(hooks: &MockServer) -> Vec<Value> {
+    hooks
+        .received_requests()
+        .await
+        .unwrap_or_default()
+        .iter()
+        .map(|request| request.body_json::<Value>().unwrap()["alert"].clone())
+        .collect()
+}
+
+#[tokio::test]
+async fn records_results_of_a_healthy_target() {
+    let app = TestApp::new().await;
+    let target = MockServer::start().await;
+    Mock::given(path("/ok"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&target)
+        .await;
+
+    let id = app
+        .create_check(None, &format!("{}/ok", target.uri()))
+        .await;
+    app.run_check(&id).await;
+
+    let results = app.get(&format!("/checks/{id}/results"), None).await;
+    let results = results.body.as_array().unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0]["status"], "UP");
+    assert_eq!(results[0]["http_status"], 200);
+    let check = app.get(&format!("/checks/{id}"), None).await;
+    assert_eq!(check.body["last_status"], "UP");
+}
+
+#[tokio::test]
+async fn probes_again_once_the_interval_has_passed() {
+    let app = TestApp::new().await;
+    let target = MockServer::start().await;
+    Mock::given(path("/ok"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&target)
+        .await;
+    let id = app
+        .create_check(None, &format!("{}/ok", target.uri()))
+        .await;
+    let results = || async {
+        let results = app.get(&format!("/checks/{id}/results"), None).await;
+        results.body.as_array().unwrap().len()
+    };
+
+    app.run_check(&id).await;
+    assert_eq!(results().await, 1);
+    app.advance(Duration::seconds(59)).await;
+    assert_eq!(results().await, 1);
+    app.advance(Duration::seconds(1)).await;
+    assert_eq!(results().await, 2);
+
+    // Paused checks are left alone
+    let paused = app
+        .request(
+            Method::PATCH,
+            &format!("/checks/{id}"),
+            None,
+            &[("if-match", "*")],
+            Some(json!({ "is_active": false })),
+        )
+        .await;
+    assert_eq!(paused.status, StatusCode::OK, "{}", paused.body);
+    app.advance(Duration::seconds(600)).await;
+    assert_eq!(results().await, 2);
+}
+
+#[tokio::test]
+async fn error_statuses_and_refused_connections_are_down() {
+    let app = TestApp::new().await;
+    let target = MockServer::start().await;
+    Mock::given(path("/broken"))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&target)
+        .await;
+
+    let broken = app
+        .create_check(None, &format!("{}/broken", target.uri()))
+        .await;
+    // Nothing listens on port 9 of localhost
+    let refused = app.create_check(None, "http://127.0.0.1:9/").await;
+    app.run_check(&broken).await;
+    app.run_check(&refused).await;
+
+    let broken = app.get(&format!("/checks/{broken}/results"), None).await;
+    assert_eq!(broken.body[0]["status"], "DOWN");
+    assert_eq!(broken.body[0]["http_status"], 503);
+    let refused = app.get(&format!("/checks/{refused}/results"), None).await;
+    assert_eq!(refused.body[0]["status"], "DOWN");
+    assert_eq!(refused.body[0]["http_status"], Value::Null);
+    assert!(refused.body[0]["error"].is_string());
+}
+
+#[tokio::test]
+async fn outage_opens_an_incident_and_alerts_until_recovery() {
+    let app = TestApp::new().await;
+    let hooks = MockServer::start().await;
+    webhook_channel(&app, &hooks).await;
+    let target = MockServer::start().await;
+    Mock::given(path("/flaky"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(2)
+        .mount(&target)
+        .await;
+    Mock::given(path("/flaky"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&target)
+        .await;
+
+    let id = app
+        .create_check(None, &format!("{}/flaky", target.uri()))
+        .await;
+    app.run_check(&id).await;
+    app.run_check(&id).await;
+
+    let incidents = app.get(&format!("/checks/{id}/incidents"), None).await;
+    let incidents = incidents.body.as_array().unwrap();
+    assert_eq!(incidents.len(), 1);
+    assert_eq!(incidents[0]["resolved_at"], Value::Null);
+    assert_eq!(incidents[0]["failed_probes"], 2);
+    // Staying DOWN is not a new transition
+    wait_until(|| async { alerts(&hooks).await.len() == 1 }).await;
+    let down = &alerts(&hooks).await[0];
+    assert_eq!(down["event"], "status_change");
+    assert_eq!(down["status"], "DOWN");
+
+    app.run_check(&id).await;
+    wait_until(|| async { alerts(&hooks).await.len() == 2 }).await;
+    let recovery = &alerts(&hooks).await[1];
+    assert_eq!(recovery["event"], "recovery");
+    assert_eq!(recovery["status"], "UP");
+    assert_eq!(recovery["failed_probes"], 2);
+    let incidents = app.get(&format!("/checks/{id}/incidents"), None).await;
+    assert!(incidents.body[0]["resolved_at"].is_string());
+}
+
+#[tokio::test]
+async fn content_change_checks_alert_when_the_page_changes() {
+    let app = TestApp::new().await;
+    let hooks = MockServer::start().await;
+    webhook_channel(&app, &hooks).await;
+    let target = MockServer::start().await;
+    Mock::given(path("/page"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("<p>v1</p>"))
+        .up_to_n_times(2)
+        .mount(&target)
+        .await;
+    Mock::given(path("/page"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("<p>v2</p>"))
+        .mount(&target)
+        .await;
+
+    let url = format!("{}/page", target.uri());
+    let created = app
+        .post(
+            "/checks",
+            None,
+            json!({ "name": "page", "url": url, "interval_seconds": 60, "check_type": "content_change" }),
+        )
+        .await;
+    assert_eq!(created.status, StatusCode::CREATED, "{}", created.body);
+    let id = created.body["id"].as_str().unwrap();
+
+    // The first probe only reports UP; the unchanged second one stays quiet
+    app.run_check(id).await;
+    app.run_check(id).await;
+    wait_until(|| async { alerts(&hooks).await.len() == 1 }).await;
+    app.run_check(id).await;
+    wait_until(|| async { alerts(&hooks).await.len() == 2 }).await;
+    assert_eq!(alerts(&hooks).await[1]["event"], "content_change");
+}
+
+#[tokio::test]
+async fn content_selectors_ignore_changes_outside_the_selected_elements() {
+    let app = TestApp::new().await;
+    let hooks = MockServer::start().await;
+    webhook_channel(&app, &hooks).await;
+    let target = MockServer::start().await;
+    for (body, times) in [
+        ("<header>12:00</header><main>v1</main>", 1),
+        ("<header>12:01</header><main>v1</main>", 1),
+        ("<header>12:02</header><main>v2</main>", 1),
+    ] {
+        Mock::given(path("/page"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(body))
+            .up_to_n_times(times)
+            .mount(&target)
+            .await;
+    }
+
+    let url = format!("{}/page", target.uri());
+    let created = app
+        .post(
+            "/checks",
+            None,
+            json!({
+                "name": "page",
+                "url": url,
+                "interval_seconds": 60,
+                "check_type": "content_change",
+                "content_selector": "main",
+            }),
+        )
+        .await;
+    assert_eq!(created.status, StatusCode::CREATED, "{}", created.body);
+    let id = created.body["id"].as_str().unwrap();
+
+    // Only the third probe changes what's inside <main>
+    for _ in 0..3 {
+        app.run_check(id).await;
+    }
+    wait_until(|| async { alerts(&hooks).await.len() >= 2 }).await;
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    let alerts = alerts(&hooks).await;
+    assert_eq!(alerts.len(), 2, "{alerts:?}");
+    assert_eq!(alerts[1]["event"], "content_change");
+}
+
+#[tokio::test]
+async fn slow_responses_raise_a_latency_anomaly() {
+    let app = TestApp::new().await;
+    let hooks = MockServer::start().await;
+    webhook_channel(&app, &hooks).await;
+    let target = MockServer::start().await;
+    Mock::given(path("/api"))
+        .respond_with(ResponseTemplate::new(200))
+        .up_to_n_times(20)
+        .mount(&target)
+        .await;
+    Mock::given(path("/api"))
+        .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_millis(300)))
+        .up_to_n_times(3)
+        .mount(&target)
+        .await;
+    Mock::given(path("/api"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&target)
+        .await;
+    // Nudging the clock wakes the analyzer without making any check due
+    let latency_alerts = || async {
+        app.clock.advance(Duration::milliseconds(1));
+        alerts(&hooks)
+            .await
+            .into_iter()
+            .filter(|alert| alert["event"].as_str().unwrap().starts_with("latency_"))
+            .collect::<Vec<_>>()
+    };
+
+    let id = app
+        .create_check(None, &format!("{}/api", target.uri()))
+        .await;
+    // The baseline needs a few samples before anything counts as slow
+    for _ in 0..22 {
+        app.run_check(&id).await;
+    }
+    assert!(latency_alerts().await.is_empty());
+
+    app.run_check(&id).await;
+    wait_until(|| async { latency_alerts().await.len() == 1 }).await;
+    let anomaly = &latency_alerts().await[0];
+    assert_eq!(anomaly["event"], "latency_anomaly");
+    assert!(anomaly["latency_ms"].as_i64().unwrap() >= 300);
+    assert!(anomaly["baseline_ms"].as_i64().unwrap() < 300);
+
+    app.run_check(&id).await;
+    wait_until(|| async { latency_alerts().await.len() == 2 }).await;
+    assert_eq!(latency_alerts().await[1]["event"], "latency_normal");
+}
+
+#[tokio::test]
+async fn planned_downtime_is_left_out_of_uptime_and_alerts() {
+    let app = TestApp::new().await;
+    let hooks = MockServer::start().await;
+    webhook_channel(&app, &hooks).await;
+    let target = MockServer::start().await;
+    Mock::given(path("/down"))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&target)
+        .await;
+    let id = app
+        .create_check(None, &format!("{}/down", target.uri()))
+        .await;
+    let now = app.clock.now();
+    let window = app
+        .post(
+            "/maintenance",
+            None,
+            json!({ "title": "deploy", "starts_at": now, "ends_at": now + Duration::hours(1) }),
+        )
+        .await;
+    assert_eq!(window.status, StatusCode::CREATED, "{}", window.body);
+
+    app.run_check(&id).await;
+    let results = app.get(&format!("/checks/{id}/results"), None).await;
+    assert_eq!(results.body[0]["status"], "DOWN");
+    let stats = app.get(&format!("/checks/{id}/stats"), None).await;
+    assert_eq!(stats.body["planned_samples"], 1);
+    assert_eq!(stats.body["samples"], 0);
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    assert!(alerts(&hooks).await.is_empty());
+}
+
+#[tokio::test]
+async fn alerts_only_reach_the_channels_a_check_is_routed_to() {
+    let app = TestApp::new().await;
+    let (routed, other) = (MockServer::start().await, MockServer::start().await);
+    webhook_channel(&app, &routed).await;
+    webhook_channel(&app, &other).await;
+    let channels = app.get("/channels", None).await;
+    let routed_id = channels
+        .body
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|c| c["target"].as_str().unwrap().starts_with(&routed.uri()))
+        .unwrap()["id"]
+        .clone();
+    let target = MockServer::start().await;
+    Mock::given(path("/down"))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&target)
+        .await;
+    let id = app
+        .create_check(None, &format!("{}/down", target.uri()))
+        .await;
+
+    let unknown = app
+        .request(
+            Method::PUT,
+            &format!("/checks/{id}/notifications"),
+            None,
+            &[],
+            Some(json!({ "channel_ids": ["nope"] })),
+        )
+        .await;
+    assert_eq!(unknown.status, StatusCode::BAD_REQUEST);
+    let set = app
+        .request(
+            Method::PUT,
+            &format!("/checks/{id}/notifications"),
+            None,
+            &[],
+            Some(json!({ "channel_ids": [routed_id] })),
+        )
+        .await;
+    assert_eq!(set.status, StatusCode::OK, "{}", set.body);
+    let listed = app.get(&format!("/checks/{id}/notifications"), None).await;
+    assert_eq!(listed.body["channel_ids"], json!([routed_id]));
+
+    app.run_check(&id).await;
+    wait_until(|| async { alerts(&routed).await.len() == 1 }).await;
+    assert!(alerts(&other).await.is_empty());
+}
+
+#[tokio::test]
+async fn channels_skip_alerts_below_their_minimum_severity() {
+    let app = TestApp::new().await;
+    let (everything, pager) = (MockServer::start().await, MockServer::start().await);
+    webhook_channel(&app, &everything).await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&pager)
+        .await;
+    let critical_only = app
+        .post(
+            "/channels",
+            None,
+            json!({ "name": "pager", "kind": "webhook", "target": pager.uri(), "min_severity": "critical" }),
+        )
+        .await;
+    assert_eq!(
+        critical_only.status,
+        StatusCode::CREATED,
+        "{}",
+        critical_only.body
+    );
+    let target = MockServer::start().await;
+    Mock::given(path("/down"))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&target)
+        .await;
+    let url = format!("{}/down", target.uri());
+    let created = app
+        .post(
+            "/checks",
+            None,
+            json!({ "name": "batch", "url": url, "interval_seconds": 60, "severity": "warning" }),
+        )
+        .await;
+    assert_eq!(created.status, StatusCode::CREATED, "{}", created.body);
+    let id = created.body["id"].as_str().unwrap();
+
+    app.run_check(id).await;
+    wait_until(|| async { alerts(&everything).await.len() == 1 }).await;
+    assert_eq!(alerts(&everything).await[0]["severity"], "warning");
+    assert!(alerts(&pager).await.is_empty());
+    let incidents = app.get(&format!("/checks/{id}/incidents"), None).await;
+    assert_eq!(incidents.body[0]["severity"], "warning");
+}
+
+#[tokio::test]
+async fn bodies_smaller_than_the_minimum_size_are_down() {
+    let app = TestApp::new().await;
+    let target = MockServer::start().await;
+    Mock::given(path("/page"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("<html>hello</html>"))
+        .mount(&target)
+        .await;
+    Mock::given(path("/empty"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&target)
+        .await;
+
+    let mut ids = Vec::new();
+    for page in ["page", "empty"] {
+        let created = app
+            .post(
+                "/checks",
+                None,
+                json!({ "name": page, "url": format!("{}/{page}", target.uri()), "interval_seconds": 60, "min_response_bytes": 10 }),
+            )
+            .await;
+        assert_eq!(created.status, StatusCode::CREATED, "{}", created.body);
+        let id = created.body["id"].as_str().unwrap().to_string();
+        app.run_check(&id).await;
+        ids.push(id);
+    }
+
+    let page = app.get(&format!("/checks/{}/results", ids[0]), None).await;
+    assert_eq!(page.body[0]["status"], "UP");
+    assert_eq!(page.body[0]["response_bytes"], 18);
+    let stats = app.get(&format!("/checks/{}/stats", ids[0]), None).await;
+    assert_eq!(stats.body["avg_response_bytes"], 18.0);
+    let empty = app.get(&format!("/checks/{}/results", ids[1]), None).await;
+    assert_eq!(empty.body[0]["status"], "DOWN");
+    assert_eq!(empty.body[0]["http_status"], 200);
+    assert_eq!(empty.body[0]["response_bytes"], 0);
+    assert_eq!(
+        empty.body[0]["error"],
+        "response body of 0 bytes is smaller than 10"
+    );
+
+    let negative = app
+        .post(
+            "/checks",
+            None,
+            json!({ "name": "bad", "url": target.uri(), "interval_seconds": 60, "min_response_bytes": -1 }),
+        )
+        .await;
+    assert_eq!(negative.status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn probes_send_the_organization_headers_unless_the_check_overrides_them() {
+    let app = TestApp::new().await;
+    let target = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&target)
+        .await;
+
+    let invalid = app
+        .request(
+            Method::PUT,
+            "/probe-defaults",
+            None,
+            &[],
+            Some(json!({ "headers": { "bad header": "x" } })),
+        )
+        .await;
+    assert_eq!(invalid.status, StatusCode::BAD_REQUEST);
+    let set = app
+        .request(
+            Method::PUT,
+            "/probe-defaults",
+            None,
+            &[],
+            Some(json!({
+                "user_agent": "AcmeMonitor/1.0 (+id 42)",
+                "headers": { "X-Monitor": "acme", "X-Team": "ops" }
+            })),
+        )
+        .await;
+    assert_eq!(set.status, StatusCode::OK, "{}", set.body);
+    assert_eq!(set.body["headers"]["x-monitor"], "acme");
+
+    let inherited = app
+        .create_check(None, &format!("{}/inherited", target.uri()))
+        .await;
+    let created = app
+        .post(
+            "/checks",
+            None,
+            json!({
+                "name": "own", "url": format!("{}/own", target.uri()), "interval_seconds": 60,
+                "user_agent": "Probe/2", "headers": { "X-Team": "web" }
+            }),
+        )
+        .await;
+    assert_eq!(created.status, StatusCode::CREATED, "{}", created.body);
+    app.run_check(&inherited).await;
+    app.run_check(created.body["id"].as_str().unwrap()).await;
+
+    let requests = target.received_requests().await.unwrap();
+    let header = |path: &str, name: &str| {
+        let request = requests.iter().find(|r| r.url.path() == path).unwrap();
+        request.headers[name].to_str().unwrap().to_string()
+    };
+    assert_eq!(
+        header("/inherited", "user-agent"),
+        "AcmeMonitor/1.0 (+id 42)"
+    );
+    assert_eq!(header("/inherited", "x-team"), "ops");
+    assert_eq!(header("/own", "user-agent"), "Probe/2");
+    assert_eq!(header("/own", "x-team"), "web");
+    assert_eq!(header("/own", "x-monitor"), "acme");
+}
+
+#[tokio::test]
+async fn checks_that_stay_down_back_off_until_they_recover() {
+    let app = TestApp::new().await;
+    let target = MockServer::start().await;
+    Mock::given(path("/flaky"))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&target)
+        .await;
+    let created = app
+        .post(
+            "/checks",
+            None,
+            json!({
+                "name": "flaky", "url": format!("{}/flaky", target.uri()),
+                "interval_seconds": 60, "backoff_max_seconds": 200
+            }),
+        )
+        .await;
+    assert_eq!(created.status, StatusCode::CREATED, "{}", created.body);
+    let id = created.body["id"].as_str().unwrap();
+    // Seconds from the last probe to the next one
+    let wait = || async {
+        let check = app.get(&format!("/checks/{id}"), None).await.body;
+        let at = |field: &str| {
+            chrono::DateTime::parse_from_rfc3339(check[field].as_str().unwrap()).unwrap()
+        };
+        (at("next_run_at") - at("last_checked_at")).num_seconds()
+    };
+
+    app.run_check(id).await;
+    assert_eq!(wait().await, 60);
+    app.run_check(id).await;
+    assert_eq!(wait().await, 120);
+    app.advance(Duration::seconds(120)).await;
+    assert_eq!(wait().await, 200);
+    assert_eq!(
+        app.get(&format!("/checks/{id}"), None).await.body["down_probes"],
+        3
+    );
+
+    target.reset().await;
+    Mock::given(path("/flaky"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&target)
+        .await;
+    app.advance(Duration::seconds(200)).await;
+    assert_eq!(wait().await, 60);
+    let results = app.get(&format!("/checks/{id}/results"), None).await;
+    assert_eq!(results.body.as_array().unwrap().len(), 4);
+    assert_eq!(results.body[0]["status"], "UP");
+}
+
+#[tokio::test]
+async fn accepted_statuses_count_as_up() {
+    let app = TestApp::new().await;
+    let target = MockServer::start().await;
+    Mock::given(path("/private"))
+        .respond_with(ResponseTemplate::new(401))
+        .mount(&target)
+        .await;
+    let url = format!("{}/private", target.uri());
+
+    let invalid = app
+        .post(
+            "/checks",
+            None,
+            json!({ "name": "bad", "url": url, "interval_seconds": 60, "accepted_statuses": "401,abc" }),
+        )
+        .await;
+    assert_eq!(invalid.status, StatusCode::BAD_REQUEST);
+    let created = app
+        .post(
+            "/checks",
+            None,
+            json!({ "name": "private", "url": url, "interval_seconds": 60, "accepted_statuses": "401, 403" }),
+        )
+        .await;
+    assert_eq!(created.status, StatusCode::CREATED, "{}", created.body);
+    let id = created.body["id"].as_str().unwrap();
+    app.run_check(id).await;
+
+    let results = app.get(&format!("/checks/{id}/results"), None).await;
+    assert_eq!(results.body[0]["status"], "UP");
+    assert_eq!(results.body[0]["http_status"], 401);
+}
+
+#[tokio::test]
+async fn groups_alert_once_most_of_their_members_are_down() {
+    let app = TestApp::new().await;
+    let hooks = MockServer::start().await;
+    webhook_channel(&app, &hooks).await;
+    let target = MockServer::start().await;
+    Mock::given(path("/ok"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&target)
+        .await;
+    Mock::given(path("/down"))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&target)
+        .await;
+    let up = app
+        .create_check(None, &format!("{}/ok", target.uri()))
+        .await;
+    let first = app
+        .create_check(None, &format!("{}/down", target.uri()))
+        .await;
+    let second = app
+        .create_check(None, &format!("{}/down?node=2", target.uri()))
+        .await;
+
+    let created = app
+        .post(
+            "/groups",
+            None,
+            json!({ "name": "EU cluster", "check_ids": [up, first, second], "alert_threshold_percent": 50 }),
+        )
+        .await;
+    assert_eq!(created.status, StatusCode::CREATED, "{}", created.body);
+    let group = created.body["id"].as_str().unwrap();
+
+    app.run_check(&first).await;
+    wait_until(|| async { !alerts(&hooks).await.is_empty() }).await;
+    let sent = alerts(&hooks).await;
+    assert_eq!(sent.len(), 1, "{sent:?}");
+    assert_eq!(sent[0]["event"], "group_degraded");
+    assert_eq!(sent[0]["group"], "EU cluster");
+    assert_eq!(sent[0]["down_checks"], 2);
+    assert_eq!(sent[0]["total_checks"], 3);
+
+    let status = app.get(&format!("/groups/{group}/status"), None).await;
+    assert_eq!(status.status, StatusCode::OK, "{}", status.body);
+    assert_eq!(status.body["status"], "DOWN");
+    assert_eq!(status.body["down_checks"], 2);
+    assert_eq!(status.body["degraded"], true);
+    let uptime = status.body["uptime_percent"].as_f64().unwrap();
+    assert!((uptime - 100.0 / 3.0).abs() < 0.01, "{uptime}");
+    let missing = app.get("/groups/nope/status", None).await;
+    assert_eq!(missing.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn checks_can_require_http2() {
+    let app = TestApp::new().await;
+    let target = MockServer::start().await;
+    Mock::given(path("/"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&target)
+        .await;
+    // Answers anything, the HTTP/2 connection preface included, as HTTP/1.1
+    let http1 = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let http1_url = format!("http://{}/", http1.local_addr().unwrap());
+    tokio::spawn(async move {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        while let Ok((mut socket, _)) = http1.accept().await {
+            let mut buf = [0; 16];
+            socket.read_exact(&mut buf).await.ok();
+            socket
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .await
+                .ok();
+        }
+    });
+
+    let invalid = app
+        .post(
+            "/checks",
+            None,
+            json!({ "name": "bad", "url": target.uri(), "interval_seconds": 60, "http_version": "3" }),
+        )
+        .await;
+    assert_eq!(invalid.status, StatusCode::BAD_REQUEST);
+
+    let mut protocols = vec![];
+    for (name, url, version) in [
+        ("default", target.uri(), None),
+        ("h2", target.uri(), Some("2")),
+        ("h1", http1_url.clone(), Some("1.1")),
+        ("h1-as-h2", http1_url, Some("2")),
+    ] {
+        let created = app
+            .post(
+                "/checks",
+                None,
+                json!({ "name": name, "url": url, "interval_seconds": 60, "http_version": version }),
+            )
+            .await;
+        assert_eq!(created.status, StatusCode::CREATED, "{}", created.body);
+        let id = created.body["id"].as_str().unwrap().to_string();
+        app.run_check(&id).await;
+        let results = app.get(&format!("/checks/{id}/results"), None).await;
+        protocols.push((
+            results.body[0]["status"].clone(),
+            results.body[0]["protocol"].clone(),
+        ));
+    }
+    assert_eq!(
+        protocols,
+        [
+            (json!("UP"), json!("HTTP/1.1")),
+            (json!("UP"), json!("HTTP/2")),
+            (json!("UP"), json!("HTTP/1.1")),
+            (json!("DOWN"), Value::Null),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn checks_pinned_to_an_ip_keep_the_url_host() {
+    let app = TestApp::new().await;
+    let origin = MockServer::start().await;
+    Mock::given(path("/"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&origin)
+        .await;
+    let port = origin.address().port();
+    let url = format!("http://new-origin.invalid:{port}/");
+
+    let invalid = app
+        .post(
+            "/checks",
+            None,
+            json!({ "name": "bad", "url": url, "interval_seconds": 60, "connect_to": "new-origin" }),
+        )
+        .await;
+    assert_eq!(invalid.status, StatusCode::BAD_REQUEST);
+    let created = app
+        .post(
+            "/checks",
+            None,
+            json!({ "name": "new origin", "url": url, "interval_seconds": 60, "connect_to": "127.0.0.1" }),
+        )
+        .await;
+    assert_eq!(created.status, StatusCode::CREATED, "{}", created.body);
+    let id = created.body["id"].as_str().unwrap();
+    app.run_check(id).await;
+
+    let results = app.get(&format!("/checks/{id}/results"), None).await;
+    assert_eq!(results.body[0]["status"], "UP", "{}", results.body);
+    let requests = origin.received_requests().await.unwrap();
+    assert_eq!(
+        requests[0].headers["host"],
+        format!("new-origin.invalid:{port}").as_str()
+    );
+}
+
+#[tokio::test]
+async fn failed_probes_are_classified_by_error_kind() {
+    let app = TestApp::new().await;
+    let hooks = MockServer::start().await;
+    webhook_channel(&app, &hooks).await;
+    let target = MockServer::start().await;
+    Mock::given(path("/unavailable"))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&target)
+        .await;
+    Mock::given(path("/tiny"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("ok"))
+        .mount(&target)
+        .await;
+    // Nothing listens on the port once the listener is dropped
+    let closed = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+
+    let mut ids = vec![];
+    let mut kinds = vec![];
+    for (name, url, min_response_bytes) in [
+        ("unavailable", format!("{}/unavailable", target.uri()), None),
+        ("tiny", format!("{}/tiny", target.uri()), Some(100)),
+        ("refused", format!("http://{closed}/"), None),
+        (
+            "unresolvable",
+            "http://unresolvable.invalid/".to_string(),
+            None,
+        ),
+    ] {
+        let created = app
+            .post(
+                "/checks",
+                None,
+                json!({ "name": name, "url": url, "interval_seconds": 60, "min_response_bytes": min_response_bytes }),
+            )
+            .await;
+        assert_eq!(created.status, StatusCode::CREATED, "{}", created.body);
+        let id = created.body["id"].as_str().unwrap().to_string();
+        app.run_check(&id).await;
+        let results = app.get(&format!("/checks/{id}/results"), None).await;
+        kinds.push(results.body[0]["error_kind"].clone());
+        ids.push(id);
+    }
+    assert_eq!(
+        kinds,
+        [
+            json!("http_5xx"),
+            json!("assertion"),
+            json!("connect"),
+            json!("dns")
+        ]
+    );
+
+    wait_until(|| async { alerts(&hooks).await.len() == 4 }).await;
+    let unavailable = alerts(&hooks)
+        .await
+        .into_iter()
+        .find(|a| a["check"]["name"] == "unavailable")
+        .unwrap();
+    assert_eq!(unavailable["error_kind"], "http_5xx");
+
+    // Every later run_check probed the first check again
+    let stats = app.get(&format!("/checks/{}/stats", ids[0]), None).await;
+    assert_eq!(stats.body["errors_by_kind"], json!({ "http_5xx": 4 }));
+    let by_kind = |kind: &str| format!("/checks/{}/results?error_kind={kind}", ids[0]);
+    let server_errors = app.get(&by_kind("http_5xx"), None).await;
+    assert_eq!(server_errors.body.as_array().unwrap().len(), 4);
+    let dns_errors = app.get(&by_kind("dns"), None).await;
+    assert_eq!(dns_errors.body.as_array().unwrap().len(), 0);
+    let unknown = app.get(&by_kind("gremlins"), None).await;
+    assert_eq!(unknown.status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn alerts_page_whoever_is_on_call() {
+    let app = TestApp::new().await;
+    let hooks = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&hooks)
+        .await;
+    let mut users = vec![];
+    for name in ["alice", "bob"] {
+        let user = app
+            .post(
+                "/users",
+                None,
+                json!({ "email": format!("{name}@example.com"), "name": name, "role": "editor" }),
+            )
+            .await;
+        assert_eq!(user.status, StatusCode::CREATED, "{}", user.body);
+        let user_id = user.body["id"].as_str().unwrap().to_string();
+        let channel = app
+            .post(
+                "/channels",
+                None,
+                json!({ "name": name, "kind": "webhook", "target": format!("{}/{name}", hooks.uri()) }),
+            )
+            .await;
+        let own = app
+            .request(
+                Method::PUT,
+                &format!("/users/{user_id}/channels"),
+                None,
+                &[],
+                Some(json!({ "channel_ids": [channel.body["id"]] })),
+            )
+            .await;
+        assert_eq!(own.status, StatusCode::OK, "{}", own.body);
+        users.push(user_id);
+    }
+
+    let schedule = app
+        .post(
+            "/oncall/schedules",
+            None,
+            json!({ "name": "backend", "rotation_start": app.clock.now() - Duration::days(1), "user_ids": users }),
+        )
+        .await;
+    assert_eq!(schedule.status, StatusCode::CREATED, "{}", schedule.body);
+    let schedule_id = schedule.body["id"].as_str().unwrap();
+    let pager = app
+        .post(
+            "/channels",
+            None,
+            json!({ "name": "pager", "kind": "oncall", "target": schedule_id }),
+        )
+        .await;
+    assert_eq!(pager.status, StatusCode::CREATED, "{}", pager.body);
+
+    let now = app.get("/oncall/now", None).await;
+    assert_eq!(now.body[0]["user"]["id"], users[0]);
+    assert_eq!(now.body[0]["override_id"], Value::Null);
+
+    // Bob takes over the rest of Alice's day
+    let covering = app
+        .post(
+            &format!("/oncall/schedules/{schedule_id}/overrides"),
+            None,
+            json!({ "user_id": users[1], "starts_at": app.clock.now() - Duration::hours(1), "ends_at": app.clock.now() + Duration::days(1) }),
+        )
+        .await;
+    assert_eq!(covering.status, StatusCode::CREATED, "{}", covering.body);
+    let now = app.get("/oncall/now", None).await;
+    assert_eq!(now.body[0]["user"]["id"], users[1]);
+    assert_eq!(now.body[0]["override_id"], covering.body["id"]);
+
+    let target = MockServer::start().await;
+    Mock::given(path("/down"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&target)
+        .await;
+    let id = app
+        .create_check(None, &format!("{}/down", target.uri()))
+        .await;
+    let routed = app
+        .request(
+            Method::PUT,
+            &format!("/checks/{id}/notifications"),
+            None,
+            &[],
+            Some(json!({ "channel_ids": [pager.body["id"]] })),
+        )
+        .await;
+    assert_eq!(routed.status, StatusCode::OK, "{}", routed.body);
+    app.run_check(&id).await;
+
+    wait_until(|| async { !alerts(&hooks).await.is_empty() }).await;
+    let paged: Vec<String> = hooks
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|r| r.url.path().to_string())
+        .collect();
+    assert_eq!(paged, ["/bob"]);
+
+    // Without channels Bob is emailed, which this instance can't do: the page stays queued
+    let cleared = app
+        .request(
+            Method::PUT,
+            &format!("/users/{}/channels", users[1]),
+            None,
+            &[],
+            Some(json!({ "channel_ids": [] })),
+        )
+        .await;
+    assert_eq!(cleared.status, StatusCode::OK, "{}", cleared.body);
+    target.reset().await;
+    Mock::given(path("/down"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&target)
+        .await;
+    app.run_check(&id).await;
+    wait_until(|| async {
+        let queued = app.get("/admin/notifications", None).await;
+        queued.body.as_array().is_some_and(|q| !q.is_empty())
+    })
+    .await;
+    let queued = app.get("/admin/notifications", None).await;
+    assert_eq!(queued.body[0]["status"], "pending");
+    assert_eq!(queued.body[0]["channel_id"], pager.body["id"]);
+    let error = queued.body[0]["last_error"].as_str().unwrap();
+    assert!(error.contains("bob has no channels"), "{error}");
+    assert!(error.contains("email is not configured"), "{error}");
+
+    let in_use = app
+        .request(
+            Method::DELETE,
+            &format!("/oncall/schedules/{schedule_id}"),
+            None,
+            &[],
+            None,
+        )
+        .await;
+    assert_eq!(in_use.status, StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn probe_results_are_appended_to_the_result_log() {
+    let log = std::env::temp_dir().join(format!("results-{}.jsonl", uuid::Uuid::new_v4()));
+    let app = TestApp::with_env(&[("RESULT_LOG_PATH", log.to_str().unwrap())]).await;
+    let target = MockServer::start().await;
+    Mock::given(path("/ok"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&target)
+        .await;
+
+    let id = app
+        .create_check(None, &format!("{}/ok", target.uri()))
+        .await;
+    app.run_check(&id).await;
+    app.run_check(&id).await;
+
+    let logged: Vec<Value> = std::fs::read_to_string(&log)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .filter(|result: &Value| result["check_id"] == id.as_str())
+        .collect();
+    std::fs::remove_file(&log).ok();
+    assert_eq!(logged.len(), 2);
+    assert_eq!(logged[0]["status"], "UP");
+    assert_eq!(logged[0]["http_status"], 200);
+    assert!(logged[0]["checked_at"].as_str().unwrap() < logged[1]["checked_at"].as_str().unwrap());
+}
+
+#[tokio::test]
+async fn failed_notifications_are_retried_then_dead_lettered() {
+    let app = TestApp::new().await;
+    let hooks = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/hook"))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&hooks)
+        .await;
+    let channel = app
+        .post(
+            "/channels",
+            None,
+            json!({ "name": "hook", "kind": "webhook", "target": format!("{}/hook", hooks.uri()) }),
+        )
+        .await;
+    assert_eq!(channel.status, StatusCode::CREATED, "{}", channel.body);
+    let target = MockServer::start().await;
+    Mock::given(path("/down"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&target)
+        .await;
+
+    let id = app
+        .create_check(None, &format!("{}/down", target.uri()))
+        .await;
+    app.run_check(&id).await;
+    let queued = app.get("/admin/notifications", None).await;
+    let queued = queued.body.as_array().unwrap();
+    assert_eq!(queued.len(), 1);
+    assert_eq!(queued[0]["status"], "pending");
+    assert_eq!(queued[0]["attempts"], 1);
+    assert!(queued[0]["last_error"].as_str().unwrap().contains("503"));
+    let notification = queued[0]["id"].as_str().unwrap().to_string();
+
+    // Each retry waits longer; an hour is past any of them
+    for attempts in 2..=6 {
+        app.clock.advance(Duration::hours(1));
+        wait_until(|| async {
+            let queued = app.get("/admin/notifications", None).await;
+            queued.body[0]["attempts"] == attempts
+        })
+        .await;
+    }
+    let dead = app.get("/admin/notifications?status=dead", None).await;
+    assert_eq!(dead.body.as_array().unwrap().len(), 1);
+    assert_eq!(dead.body[0]["id"], notification.as_str());
+    app.clock.advance(Duration::hours(1));
+    tokio::time::sleep(std::time::Duration::from_millis(1500)).await;
+    assert_eq!(hooks.received_requests().await.unwrap().len(), 6);
+
+    hooks.reset().await;
+    Mock::given(method("POST"))
+        .and(path("/hook"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&hooks)
+        .await;
+    let retried = app
+        .post(
+            &format!("/admin/notifications/{notification}/retry"),
+            None,
+            json!({}),
+        )
+        .await;
+    assert_eq!(retried.status, StatusCode::OK, "{}", retried.body);
+    assert_eq!(retried.body["status"], "sent");
+    assert_eq!(retried.body["attempts"], 1);
+    assert_eq!(alerts(&hooks).await.len(), 1);
+    let again = app
+        .post(
+            &format!("/admin/notifications/{notification}/retry"),
+            None,
+            json!({}),
+        )
+        .await;
+    assert_eq!(again.status, StatusCode::CONFLICT);
+    let queued = app.get("/admin/notifications", None).await;
+    assert_eq!(queued.body, json!([]));
+
+    // Other organizations only see their own outbox
+    let operator = app.user_token(None, "ops@example.com", "admin").await;
+    let org = app
+        .post("/orgs", Some(&operator), json!({ "name": "Acme" }))
+        .await;
+    let invited = app
+        .request(
+            Method::POST,
+            "/invitations",
+            Some(&operator),
+            &[("x-org-id", org.body["id"].as_str().unwrap())],
+            Some(json!({ "email": "acme@example.com", "role": "admin" })),
+        )
+        .await;
+    let accepted = app
+        .post(
+            "/invitations/accept",
+            None,
+            json!({ "token": invited.body["token"] }),
+        )
+        .await;
+    let tenant = accepted.body["api_key"]["token"].as_str().unwrap();
+    let theirs = app
+        .get("/admin/notifications?status=sent", Some(tenant))
+        .await;
+    assert_eq!(theirs.body, json!([]));
+    let everyone = app
+        .get("/admin/notifications?status=sent&all=true", Some(tenant))
+        .await;
+    assert_eq!(everyone.status, StatusCode::FORBIDDEN);
+    let retried = app
+        .request(
+            Method::POST,
+            &format!("/admin/notifications/{notification}/retry"),
+            Some(tenant),
+            &[],
+            Some(json!({})),
+        )
+        .await;
+    assert_eq!(retried.status, StatusCode::NOT_FOUND);
+    let everyone = app
+        .request(
+            Method::GET,
+            "/admin/notifications?status=sent&all=true",
+            Some(&operator),
+            &[("x-org-id", "default")],
+            None,
+        )
+        .await;
+    assert_eq!(everyone.body[0]["id"], notification.as_str());
+}
+
+#[tokio::test]
+async fn channels_can_be_sent_a_test_message() {
+    let app = TestApp::new().await;
+    let hooks = MockServer::start().await;
+    webhook_channel(&app, &hooks).await;
+    let channels = app.get("/channels", None).await;
+    let id = channels.body[0]["id"].as_str().unwrap().to_string();
+
+    let sent = app
+        .post(&format!("/channels/{id}/test"), None, json!({}))
+        .await;
+    assert_eq!(sent.status, StatusCode::NO_CONTENT, "{}", sent.body);
+    let received = hooks.received_requests().await.unwrap();
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].body_json::<Value>().unwrap()["event"], "test");
+
+    hooks.reset().await;
+    let failed = app
+        .post(&format!("/channels/{id}/test"), None, json!({}))
+        .await;
+    assert_eq!(failed.status, StatusCode::BAD_GATEWAY);
+
+    // Without TELEGRAM_BOT_TOKEN every Telegram channel brings its own bot
+    let telegram = app
+        .post(
+            "/channels",
+            None,
+            json!({ "name": "tg", "kind": "telegram", "target": "12345" }),
+        )
+        .await;
+    assert_eq!(telegram.status, StatusCode::BAD_REQUEST);
+    let slack = app
+        .post(
+            "/channels",
+            None,
+            json!({ "name": "slack", "kind": "slack", "target": "https://hooks.slack.com/x", "secret": "token" }),
+        )
+        .await;
+    assert_eq!(slack.status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn cron_checks_run_at_their_times_in_their_timezone() {
+    let app = TestApp::new().await;
+    let target = MockServer::start().await;
+    Mock::given(path("/ok"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&target)
+        .await;
+    let url = format!("{}/ok", target.uri());
+    let madrid: chrono_tz::Tz = "Europe/Madrid".parse().unwrap();
+    let next_run_at = |check: &Value| {
+        chrono::DateTime::parse_from_rfc3339(check["next_run_at"].as_str().unwrap())
+            .unwrap()
+            .to_utc()
+    };
+    let at_half_past_nine_on_a_weekday = |at: chrono::DateTime<chrono::Utc>| {
+        use chrono::{Datelike, Timelike};
+        let local = at.with_timezone(&madrid);
+        assert_eq!((local.hour(), local.minute()), (9, 30), "{local}");
+        assert!(local.weekday().number_from_monday() <= 5, "{local}");
+    };
+
+    for (cron, timezone) in [
+        ("61 * * * *", "UTC"),
+        ("* * *", "UTC"),
+        ("0 0 30 2 *", "UTC"),
+        ("0 9 * * *", "Mars/Base"),
+    ] {
+        let rejected = app
+            .post(
+                "/checks",
+                None,
+                json!({ "name": "bad", "url": url, "interval_seconds": 60, "cron": cron, "timezone": timezone }),
+            )
+            .await;
+        assert_eq!(
+            rejected.status,
+            StatusCode::BAD_REQUEST,
+            "{cron} {timezone}"
+        );
+    }
+
+    let created = app
+        .post(
+            "/checks",
+            None,
+            json!({
+                "name": "office hours",
+                "url": url,
+                "interval_seconds": 60,
+                "cron": "30 9 * * MON-FRI",
+                "timezone": "Europe/Madrid",
+            }),
+        )
+        .await;
+    assert_eq!(created.status, StatusCode::CREATED, "{}", created.body);
+    let id = created.body["id"].as_str().unwrap().to_string();
+    let check = app.get(&format!("/checks/{id}"), None).await.body;
+    let first = next_run_at(&check);
+    at_half_past_nine_on_a_weekday(first);
+    assert!(first > app.clock.now() && first < app.clock.now() + Duration::days(4));
+
+    // Nothing runs before then
+    app.advance(first - app.clock.now() - Duration::seconds(1))
+        .await;
+    assert!(app.get(&format!("/checks/{id}"), None).await.body["last_checked_at"].is_null());
+    app.advance(Duration::seconds(1)).await;
+    let check = app.get(&format!("/checks/{id}"), None).await.body;
+    assert_eq!(check["last_status"], "UP");
+    let second = next_run_at(&check);
+    at_half_past_nine_on_a_weekday(second);
+    assert!(second - first >= Duration::hours(23), "{first} {second}");
+
+    // Going back to the interval reschedules it within one
+    let version = check["version"].to_string();
+    let updated = app
+        .request(
+            Method::PATCH,
+            &format!("/checks/{id}"),
+            None,
+            &[("if-match", &version)],
+            Some(json!({ "cron": "" })),
+        )
+        .await;
+    assert_eq!(updated.status, StatusCode::OK, "{}", updated.body);
+    assert!(updated.body["cron"].is_null());
+    assert!(next_run_at(&updated.body) <= app.clock.now() + Duration::seconds(60));
+}
+
+#[tokio::test]
+async fn checks_resolve_through_their_own_nameserver_and_ip_version() {
+    let app = TestApp::new().await;
+    let origin = MockServer::start().await;
+    Mock::given(path("/"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&origin)
+        .await;
+    // Answers A queries for any name with 127.0.0.1 and AAAA ones with nothing
+    let nameserver = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let resolver = nameserver.local_addr().unwrap().to_string();
+    tokio::spawn(async move {
+        let mut buf = [0; 512];
+        while let Ok((len, peer)) = nameserver.recv_from(&mut buf).await {
+            let query = &buf[..len];
+            let mut end = 12;
+            while query[end] != 0 {
+                end += query[end] as usize + 1;
+            }
+            let question = &query[12..end + 5];
+            let is_a = question[question.len() - 4..question.len() - 2] == [0, 1];
+            let mut reply = vec![
+                query[0], query[1], 0x81, 0x80, 0, 1, 0, is_a as u8, 0, 0, 0, 0,
+            ];
+            reply.extend_from_slice(question);
+            if is_a {
+                reply.extend_from_slice(&[0xc0, 12, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 127, 0, 0, 1]);
+            }
+            nameserver.send_to(&reply, peer).await.ok();
+        }
+    });
+    let url = format!("http://status.probe.test:{}/", origin.address().port());
+
+    for (field, value) in [
+        ("dns_resolver", json!("not-an-ip")),
+        ("ip_version", json!(5)),
+    ] {
+        let invalid = app
+            .post(
+                "/checks",
+                None,
+                json!({ "name": "bad", "url": url, "interval_seconds": 60, field: value }),
+            )
+            .await;
+        assert_eq!(invalid.status, StatusCode::BAD_REQUEST, "{field}");
+    }
+
+    let mut statuses = vec![];
+    for ip_version in [None, Some(4), Some(6)] {
+        let created = app
+            .post(
+                "/checks",
+                None,
+                json!({
+                    "name": "custom dns",
+                    "url": url,
+                    "interval_seconds": 60,
+                    "dns_resolver": resolver,
+                    "ip_version": ip_version,
+                }),
+            )
+            .await;
+        assert_eq!(created.status, StatusCode::CREATED, "{}", created.body);
+        let id = created.body["id"].as_str().unwrap().to_string();
+        app.run_check(&id).await;
+        let results = app.get(&format!("/checks/{id}/results"), None).await;
+        statuses.push(results.body[0]["status"].clone());
+    }
+    // The name only exists on that nameserver, and only as an IPv4 address
+    assert_eq!(statuses, [json!("UP"), json!("UP"), json!("DOWN")]);
+}
+
+#[tokio::test]
+async fn checks_can_probe_through_a_proxy() {
+    let app = TestApp::new().await;
+    let proxy = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&proxy)
+        .await;
+    let url = "http://internal.invalid/health";
+
+    let invalid = app
+        .post(
+            "/checks",
+            None,
+            json!({ "name": "bad", "url": url, "interval_seconds": 60, "proxy_url": "ftp://proxy" }),
+        )
+        .await;
+    assert_eq!(invalid.status, StatusCode::BAD_REQUEST);
+    let created = app
+        .post(
+            "/checks",
+            None,
+            json!({ "name": "behind proxy", "url": url, "interval_seconds": 60, "proxy_url": proxy.uri() }),
+        )
+        .await;
+    assert_eq!(created.status, StatusCode::CREATED, "{}", created.body);
+    let id = created.body["id"].as_str().unwrap();
+    app.run_check(id).await;
+
+    let results = app.get(&format!("/checks/{id}/results"), None).await;
+    assert_eq!(results.body[0]["status"], "UP", "{}", results.body);
+    // The name only resolves for the proxy, which is asked for the absolute URL
+    let requests = proxy.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].headers["host"], "internal.invalid");
+    assert_eq!(requests[0].url.path(), "/health");
+}
+
+#[tokio::test]
+async fn client_certificates_are_stored_with_their_key_encrypted() {
+    enable_secrets();
+    let app = TestApp::new().await;
+    let target = MockServer::start().await;
+    Mock::given(path("/internal"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&target)
+        .await;
+    let cert = include_str!("fixtures/client.pem");
+    let key = include_str!("fixtures/client.key");
+    let url = format!("{}/internal", target.uri());
+
+    let invalid = app
+        .post(
+            "/checks",
+            None,
+            json!({ "name": "bad", "url": url, "interval_seconds": 60, "client_cert": cert, "client_key": cert }),
+        )
+        .await;
+    assert_eq!(invalid.status, StatusCode::BAD_REQUEST);
+    let created = app
+        .post(
+            "/checks",
+            None,
+            json!({ "name": "mtls", "url": url, "interval_seconds": 60, "client_cert": cert, "client_key": key }),
+        )
+        .await;
+    assert_eq!(created.status, StatusCode::CREATED, "{}", created.body);
+    let id = created.body["id"].as_str().unwrap();
+
+    let secrets = app.get("/secrets", None).await;
+    assert_eq!(
+        secrets.body[0]["name"], "mtls client key",
+        "{}",
+        secrets.body
+    );
+    let (ciphertext,): (String,) = sqlx::query_as("SELECT ciphertext FROM secrets")
+        .fetch_one(&app.db)
+        .await
+        .unwrap();
+    let key_body = key.lines().nth(1).unwrap();
+    assert!(!ciphertext.contains(key_body));
+    // Still referenced by the check, which decrypts it to build its probe client
+    let deleted = app
+        .request(
+            Method::DELETE,
+            &format!("/secrets/{}", secrets.body[0]["id"].as_str().unwrap()),
+            None,
+            &[],
+            None,
+        )
+        .await;
+    assert_eq!(deleted.status, StatusCode::CONFLICT);
+    app.run_check(id).await;
+    let results = app.get(&format!("/checks/{id}/results"), None).await;
+    assert_eq!(results.body[0]["status"], "UP", "{}", results.body);
+    let reused = app
+        .post(
+            "/checks",
+            None,
+            json!({
+                "name": "same identity",
+                "url": url,
+                "interval_seconds": 60,
+                "client_cert": cert,
+                "client_key_secret_id": secrets.body[0]["id"],
+            }),
+        )
+        .await;
+    assert_eq!(reused.status, StatusCode::CREATED, "{}", reused.body);
+}
+
+#[tokio::test]
+async fn probes_and_channels_authenticate_with_encrypted_secrets() {
+    enable_secrets();
+    let app = TestApp::new().await;
+    let target = MockServer::start().await;
+    Mock::given(path("/private"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&target)
+        .await;
+    let hooks = MockServer::start().await;
+    Mock::given(path("/hook"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&hooks)
+        .await;
+
+    let secret = app
+        .post(
+            "/secrets",
+            None,
+            json!({ "name": "probe token", "value": "Bearer probe-token" }),
+        )
+        .await;
+    assert_eq!(secret.status, StatusCode::CREATED, "{}", secret.body);
+    let secret_id = secret.body["id"].as_str().unwrap();
+    let listed = app.get("/secrets", None).await;
+    assert_eq!(listed.body[0]["name"], "probe token");
+    assert!(!listed.body.to_string().contains("probe-token"));
+    let stored: Vec<(String,)> = sqlx::query_as("SELECT ciphertext FROM secrets")
+        .fetch_all(&app.db)
+        .await
+        .unwrap();
+    assert!(stored.iter().all(|(c,)| !c.contains("probe-token")));
+
+    let unknown = app
+        .post(
+            "/checks",
+            None,
+            json!({ "name": "bad", "url": target.uri(), "interval_seconds": 60, "auth_header_secret_id": "missing" }),
+        )
+        .await;
+    assert_eq!(unknown.status, StatusCode::BAD_REQUEST);
+    let created = app
+        .post(
+            "/checks",
+            None,
+            json!({
+                "name": "private",
+                "url": format!("{}/private", target.uri()),
+                "interval_seconds": 60,
+                "auth_header_secret_id": secret_id,
+            }),
+        )
+        .await;
+    assert_eq!(created.status, StatusCode::CREATED, "{}", created.body);
+    assert!(!created.body.to_string().contains("probe-token"));
+    app.run_check(created.body["id"].as_str().unwrap()).await;
+    let probed = target.received_requests().await.unwrap();
+    assert_eq!(probed[0].headers["authorization"], "Bearer probe-token");
+
+    let channel = app
+        .post(
+            "/channels",
+            None,
+            json!({
+                "name": "hook",
+                "kind": "webhook",
+                "target": format!("{}/hook", hooks.uri()),
+                "secret": "Bearer hook-token",
+            }),
+        )
+        .await;
+    assert_eq!(channel.status, StatusCode::CREATED, "{}", channel.body);
+    assert!(!channel.body.to_string().contains("hook-token"));
+    let sent = app
+        .post(
+            &format!("/channels/{}/test", channel.body["id"].as_str().unwrap()),
+            None,
+            json!({}),
+        )
+        .await;
+    assert_eq!(sent.status, StatusCode::NO_CONTENT, "{}", sent.body);
+    let delivered = hooks.received_requests().await.unwrap();
+    assert_eq!(delivered[0].headers["authorization"], "Bearer hook-token");
+}
+
+#[tokio::test]
+async fn checks_go_down_once_a_quorum_of_locations_fails() {
+    let app = TestApp::new().await;
+    let target = MockServer::start().await;
+    Mock::given(path("/"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&target)
+        .await;
+    let created = app
+        .post(
+            "/checks",
+            None,
+            json!({ "name": "quorum", "url": target.uri(), "interval_seconds": 60, "regions": "eu,us", "quorum": 2 }),
+        )
+        .await;
+    assert_eq!(created.status, StatusCode::CREATED, "{}", created.body);
+    let id = created.body["id"].as_str().unwrap();
+    let mut tokens = vec![];
+    for region in ["eu", "us"] {
+        let agent = app
+            .post("/agents", None, json!({ "name": region, "region": region }))
+            .await;
+        tokens.push(agent.body["token"].as_str().unwrap().to_string());
+    }
+    let (eu, us) = (Some(tokens[0].as_str()), Some(tokens[1].as_str()));
+    let app = &app;
+    let report = |token, status| async move {
+        let results = json!({ "results": [{ "check_id": id, "checked_at": app.clock.now(), "status": status }] });
+        let sent = app.post("/agent/results", token, results).await;
+        assert!(sent.status.is_success(), "{}", sent.body);
+        app.get(&format!("/checks/{id}"), None).await.body["last_status"].clone()
+    };
+
+    // The server's own probe is a location too
+    app.run_check(id).await;
+    // A single bad network path isn't an outage
+    assert_eq!(report(eu, "DOWN").await, "UP");
+    assert_eq!(report(us, "DOWN").await, "DOWN");
+    assert_eq!(report(eu, "UP").await, "UP");
+    assert_eq!(report(eu, "DOWN").await, "DOWN");
+
+    // Failures older than the window, twice the interval by default, no longer count
+    app.advance(Duration::seconds(121)).await;
+    assert_eq!(report(us, "DOWN").await, "UP");
+}
+
+#[tokio::test]
+async fn quorum_cannot_exceed_the_locations_probing_the_check() {
+    let app = TestApp::new().await;
+    let create = |regions: Option<&str>, quorum: i64| {
+        let mut check = json!({ "name": "quorum", "url": "https://example.com", "interval_seconds": 60, "quorum": quorum });
+        if let Some(regions) = regions {
+            check["regions"] = json!(regions);
+        }
+        let app = &app;
+        async move { app.post("/checks", None, check).await.status }
+    };
+
+    // Without agents only the server's own probe reports, so the check could never go DOWN
+    assert_eq!(create(None, 2).await, StatusCode::BAD_REQUEST);
+    assert_eq!(create(Some("eu"), 2).await, StatusCode::CREATED);
+    assert_eq!(create(Some("eu, eu"), 3).await, StatusCode::BAD_REQUEST);
+
+    // Checks without regions run wherever there is an agent
+    for (name, region) in [("eu-1", "eu"), ("eu-2", "eu"), ("us-1", "us")] {
+        let agent = app
+            .post("/agents", None, json!({ "name": name, "region": region }))
+            .await;
+        assert_eq!(agent.status, StatusCode::CREATED, "{}", agent.body);
+    }
+    assert_eq!(create(None, 3).await, StatusCode::CREATED);
+    assert_eq!(create(None, 4).await, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn instances_share_checks_without_double_probing_and_take_over_expired_leases() {
+    let app = TestApp::new().await;
+    let target = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&target)
+        .await;
+    let mut ids = vec![];
+    for n in 0..4 {
+        ids.push(
+            app.create_check(None, &format!("{}/{n}", target.uri()))
+                .await,
+        );
+    }
+    // Started after the checks exist, so both instances are due to probe all of them
+    let other = app.another_instance().await;
+    let (app, other) = (&app, &other);
+    let advance = |by| async move {
+        app.advance(by).await;
+        other.advance(chrono::Duration::zero()).await;
+    };
+    let probes = || async {
+        let mut probes = vec![0; ids.len()];
+        for request in target.received_requests().await.unwrap() {
+            probes[request.url.path()[1..].parse::<usize>().unwrap()] += 1;
+        }
+        probes
+    };
+
+    for _ in 0..3 {
+        advance(Duration::seconds(60)).await;
+    }
+    assert_eq!(probes().await, [3, 3, 3, 3]);
+
+    // An instance died while probing the first check: nobody probes it until its lease runs out
+    sqlx::query("UPDATE checks SET leased_by = 'crashed', leased_until = ? WHERE id = ?")
+        .bind(app.clock.now() + Duration::seconds(90))
+        .bind(&ids[0])
+        .execute(&app.db)
+        .await
+        .unwrap();
+    advance(Duration::seconds(60)).await;
+    assert_eq!(probes().await, [3, 4, 4, 4]);
+    advance(Duration::seconds(60)).await;
+    assert_eq!(probes().await, [4, 5, 5, 5]);
+}
+
+#[tokio::test]
+async fn busy_workers_take_the_most_overdue_checks_first() {
+    let app = TestApp::new().await;
+    let target = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_millis(500)))
+        .mount(&target)
+        .await;
+    let mut ids = vec![];
+    for n in 0..10 {
+        ids.push(
+            app.create_check(None, &format!("{}/{n}", target.uri()))
+                .await,
+        );
+    }
+    let mut queued: Vec<(chrono::DateTime<chrono::Utc>, String)> =
+        sqlx::query_as("SELECT next_run_at, id FROM checks")
+            .fetch_all(&app.db)
+            .await
+            .unwrap();
+    queued.sort();
+
+    // All ten fall due at once, for the eight workers there are
+    app.clock.advance(Duration::seconds(60));
+    wait_until(|| async {
+        let metrics = app.get("/metrics", None).await.body;
+        let metrics = metrics.as_str().unwrap();
+        metrics.contains("uptime_jobs_in_flight 8\n") && metrics.contains("uptime_queue_depth 2\n")
+    })
+    .await;
+    app.advance(Duration::zero()).await;
+
+    let probed: Vec<String> = target
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|request| ids[request.url.path()[1..].parse::<usize>().unwrap()].clone())
+        .collect();
+    let mut last = probed[8..].to_vec();
+    last.sort();
+    let mut latest: Vec<String> = queued[8..].iter().map(|(_, id)| id.clone()).collect();
+    latest.sort();
+    assert_eq!(last, latest);
+    let metrics = app.get("/metrics", None).await.body;
+    assert!(metrics
+        .as_str()
+        .unwrap()
+        .contains("uptime_jobs_completed_total 10\n"));
+}
+
+#[tokio::test]
+async fn checks_sharing_an_interval_are_spread_across_it() {
+    let app = TestApp::new().await;
+    let target = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&target)
+        .await;
+    let created_at = app.clock.now();
+    for n in 0..20 {
+        app.create_check(None, &format!("{}/{n}", target.uri()))
+            .await;
+    }
+    let next_runs = || async {
+        sqlx::query_scalar::<_, chrono::DateTime<chrono::Utc>>("SELECT next_run_at FROM checks")
+            .fetch_all(&app.db)
+            .await
+            .unwrap()
+    };
+    let first_runs = next_runs().await;
+    assert!(first_runs
+        .iter()
+        .all(|at| *at > created_at && *at <= created_at + Duration::seconds(60)));
+    let mut distinct = first_runs.clone();
+    distinct.sort();
+    distinct.dedup();
+    assert!(distinct.len() > 5, "{distinct:?}");
+
+    let too_much = app
+        .post(
+            "/checks",
+            None,
+            json!({ "name": "bad", "url": target.uri(), "interval_seconds": 60, "jitter_seconds": 61 }),
+        )
+        .await;
+    assert_eq!(too_much.status, StatusCode::BAD_REQUEST);
+    let jittered = app
+        .post(
+            "/checks",
+            None,
+            json!({ "name": "jittered", "url": target.uri(), "interval_seconds": 60, "jitter_seconds": 30 }),
+        )
+        .await;
+    assert_eq!(jittered.status, StatusCode::CREATED, "{}", jittered.body);
+    let id = jittered.body["id"].as_str().unwrap();
+    app.run_check(id).await;
+    let (checked_at, next_run_at): (chrono::DateTime<chrono::Utc>, chrono::DateTime<chrono::Utc>) =
+        sqlx::query_as("SELECT last_checked_at, next_run_at FROM checks WHERE id = ?")
+            .bind(id)
+            .fetch_one(&app.db)
+            .await
+            .unwrap();
+    let wait = next_run_at - checked_at;
+    assert!(
+        wait >= Duration::seconds(60) && wait <= Duration::seconds(90),
+        "{wait}"
+    );
+}
+
+#[tokio::test]
+async fn change_only_checks_keep_fewer_results_but_exact_stats() {
+    let app = TestApp::new().await;
+    let target = MockServer::start().await;
+    Mock::given(path("/"))
+        .respond_with(ResponseTemplate::new(200))
+        .up_to_n_times(4)
+        .mount(&target)
+        .await;
+    Mock::given(path("/"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&target)
+        .await;
+    let created = app
+        .post(
+            "/checks",
+            None,
+            json!({ "name": "quiet", "url": target.uri(), "interval_seconds": 60, "persist_mode": "changes", "persist_every": 3 }),
+        )
+        .await;
+    assert_eq!(created.status, StatusCode::CREATED, "{}", created.body);
+    let id = created.body["id"].as_str().unwrap();
+
+    for _ in 0..5 {
+        app.run_check(id).await;
+    }
+
+    // The first sample, the third identical one after it and the change to DOWN
+    let results = app.get(&format!("/checks/{id}/results"), None).await;
+    let statuses: Vec<_> = results
+        .body
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| r["status"].as_str().unwrap())
+        .collect();
+    assert_eq!(statuses, ["DOWN", "UP", "UP"]);
+    let stats = app
+        .get(&format!("/checks/{id}/stats?period=24h"), None)
+        .await;
+    assert_eq!(stats.body["samples"], 5, "{}", stats.body);
+    assert_eq!(stats.body["up_samples"], 4);
+    assert_eq!(stats.body["uptime_percent"], 80.0);
+}
+
+#[tokio::test]
+async fn a_cycle_of_probes_is_written_in_full() {
+    let app = TestApp::new().await;
+    let target = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&target)
+        .await;
+    for n in 0..40 {
+        app.create_check(None, &format!("{}/{n}", target.uri()))
+            .await;
+    }
+
+    // More probes than workers, all batched through the same writer
+    app.advance(Duration::seconds(60)).await;
+
+    let (results, checks): (i64, i64) =
+        sqlx::query_as("SELECT COUNT(*), COUNT(DISTINCT check_id) FROM check_results")
+            .fetch_one(&app.db)
+            .await
+            .unwrap();
+    assert_eq!((results, checks), (40, 40));
+    let (samples, up_samples): (i64, i64) =
+        sqlx::query_as("SELECT SUM(samples), SUM(up_samples) FROM check_rollups")
+            .fetch_one(&app.db)
+            .await
+            .unwrap();
+    assert_eq!((samples, up_samples), (40, 40));
+    let listed = app.get("/checks", None).await;
+    assert!(listed
+        .body
+        .as_array()
+        .unwrap()
+        .iter()
+        .all(|c| c["last_status"] == "UP" && c["last_checked_at"].is_string()));
+}
+
+#[tokio::test]
+async fn the_scheduler_follows_check_changes_from_memory() {
+    let app = TestApp::new().await;
+    let target = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&target)
+        .await;
+    let id = app
+        .create_check(None, &format!("{}/before", target.uri()))
+        .await;
+    let update = |changes: Value| {
+        let app = &app;
+        let id = &id;
+        async move {
+            let check = app.get(&format!("/checks/{id}"), None).await;
+            let version = check.body["version"].to_string();
+            let updated = app
+                .request(
+                    Method::PATCH,
+                    &format!("/checks/{id}"),
+                    None,
+                    &[("if-match", &version)],
+                    Some(changes),
+                )
+                .await;
+            assert_eq!(updated.status, StatusCode::OK, "{}", updated.body);
+        }
+    };
+    let last_path = || async {
+        let requests = target.received_requests().await.unwrap();
+        (
+            requests.len(),
+            requests.last().unwrap().url.path().to_string(),
+        )
+    };
+
+    app.run_check(&id).await;
+    assert_eq!(last_path().await, (1, "/before".to_string()));
+    update(json!({ "url": format!("{}/after", target.uri()) })).await;
+    app.run_check(&id).await;
+    assert_eq!(last_path().await, (2, "/after".to_string()));
+
+    // Writes that skip the API aren't seen until the check is next reloaded
+    sqlx::query("UPDATE checks SET url = 'http://127.0.0.1:9/' WHERE id = ?")
+        .bind(&id)
+        .execute(&app.db)
+        .await
+        .unwrap();
+    app.run_check(&id).await;
+    assert_eq!(last_path().await, (3, "/after".to_string()));
+
+    update(json!({ "url": format!("{}/after", target.uri()), "is_active": false })).await;
+    app.advance(Duration::seconds(120)).await;
+    assert_eq!(last_path().await.0, 3);
+    update(json!({ "is_active": true })).await;
+    app.advance(Duration::seconds(60)).await;
+    assert_eq!(last_path().await.0, 4);
+
+    let version = app.get(&format!("/checks/{id}"), None).await.body["version"].to_string();
+    let deleted = app
+        .request(
+            Method::DELETE,
+            &format!("/checks/{id}"),
+            None,
+            &[("if-match", &version)],
+            None,
+        )
+        .await;
+    assert_eq!(deleted.status, StatusCode::NO_CONTENT);
+    app.advance(Duration::seconds(120)).await;
+    assert_eq!(last_path().await.0, 4);
+}
+
+#[tokio::test]
+async fn recovery_alerts_summarize_the_outage() {
+    let app = TestApp::new().await;
+    let hooks = MockServer::start().await;
+    webhook_channel(&app, &hooks).await;
+    let target = MockServer::start().await;
+    Mock::given(path("/"))
+        .respond_with(ResponseTemplate::new(500).set_delay(std::time::Duration::from_millis(300)))
+        .up_to_n_times(1)
+        .mount(&target)
+        .await;
+    Mock::given(path("/"))
+        .respond_with(ResponseTemplate::new(503))
+        .up_to_n_times(2)
+        .mount(&target)
+        .await;
+    Mock::given(path("/"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&target)
+        .await;
+
+    let id = app.create_check(None, &target.uri()).await;
+    for _ in 0..4 {
+        app.run_check(&id).await;
+    }
+
+    wait_until(|| async { alerts(&hooks).await.len() == 2 }).await;
+    let recovery = &alerts(&hooks).await[1];
+    assert_eq!(recovery["event"], "recovery", "{recovery}");
+    assert_eq!(recovery["previous"], "DOWN");
+    assert_eq!(recovery["downtime"], "3m 0s");
+    assert_eq!(recovery["failed_probes"], 3);
+    assert_eq!(recovery["last_error"], "HTTP 503");
+    assert!(recovery["worst_latency_ms"].as_i64().unwrap() >= 300);
+}
+
+#[tokio::test]
+async fn alerts_are_rendered_with_check_or_channel_templates() {
+    let app = TestApp::new().await;
+    let hooks = MockServer::start().await;
+    Mock::given(path("/hook"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&hooks)
+        .await;
+    let channel = |template: &str| json!({ "name": "hook", "kind": "webhook", "target": format!("{}/hook", hooks.uri()), "template": template });
+    let invalid = app.post("/channels", None, channel("{{ check.name")).await;
+    assert_eq!(invalid.status, StatusCode::BAD_REQUEST);
+    let created = app
+        .post(
+            "/channels",
+            None,
+            channel("{{ check.name }} went {{ previous }} -> {{ status }}"),
+        )
+        .await;
+    assert_eq!(created.status, StatusCode::CREATED, "{}", created.body);
+    let target = MockServer::start().await;
+    Mock::given(path("/"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&target)
+        .await;
+
+    let plain = app
+        .post(
+            "/checks",
+            None,
+            json!({ "name": "plain", "url": target.uri(), "interval_seconds": 60 }),
+        )
+        .await;
+    assert_eq!(plain.status, StatusCode::CREATED, "{}", plain.body);
+    let own = app
+        .post(
+            "/checks",
+            None,
+            json!({
+                "name": "own",
+                "url": target.uri(),
+                "interval_seconds": 60,
+                "alert_template": "[{{ severity | upper }}] {{ check.name }}: HTTP {{ status }}",
+            }),
+        )
+        .await;
+    assert_eq!(own.status, StatusCode::CREATED, "{}", own.body);
+    app.advance(Duration::seconds(60)).await;
+
+    wait_until(|| async { hooks.received_requests().await.unwrap().len() == 2 }).await;
+    let mut texts: Vec<String> = hooks
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|r| {
+            r.body_json::<Value>().unwrap()["text"]
+                .as_str()
+                .unwrap()
+                .to_string()
+        })
+        .collect();
+    texts.sort();
+    assert_eq!(
+        texts,
+        ["[CRITICAL] own: HTTP DOWN", "plain went UNKNOWN -> DOWN"]
+    );
+}
+
+#[tokio::test]
+async fn quiet_hours_hold_alerts_and_report_what_is_still_down() {
+    let app = TestApp::new().await;
+    let hooks = MockServer::start().await;
+    Mock::given(path("/hook"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&hooks)
+        .await;
+    let now = app.clock.now();
+    let channel = app
+        .post(
+            "/channels",
+            None,
+            json!({
+                "name": "night",
+                "kind": "webhook",
+                "target": format!("{}/hook", hooks.uri()),
+                "quiet_start": (now - Duration::hours(1)).format("%H:%M").to_string(),
+                "quiet_end": (now + Duration::hours(1)).format("%H:%M").to_string(),
+                "timezone": "UTC",
+            }),
+        )
+        .await;
+    assert_eq!(channel.status, StatusCode::CREATED, "{}", channel.body);
+    let target = MockServer::start().await;
+    Mock::given(path("/down"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&target)
+        .await;
+    Mock::given(path("/blip"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .mount(&target)
+        .await;
+    Mock::given(path("/blip"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&target)
+        .await;
+    let down = app
+        .create_check(None, &format!("{}/down", target.uri()))
+        .await;
+    let blip = app
+        .create_check(None, &format!("{}/blip", target.uri()))
+        .await;
+    app.run_check(&down).await;
+    app.run_check(&blip).await;
+    assert!(hooks.received_requests().await.unwrap().is_empty());
+
+    // Past the window only the check that is still DOWN gets reported, in a single summary
+    app.advance(Duration::minutes(62)).await;
+    wait_until(|| async { !hooks.received_requests().await.unwrap().is_empty() }).await;
+    let received = hooks.received_requests().await.unwrap();
+    assert_eq!(received.len(), 1);
+    let summary = received[0].body_json::<Value>().unwrap();
+    assert_eq!(summary["digest"]["event"], "quiet_hours_summary");
+    assert_eq!(summary["digest"]["suppressed"], 3);
+    assert_eq!(summary["digest"]["down"], json!([down]));
+}
+
+#[tokio::test]
+async fn alert_storms_are_batched_into_digests_and_rate_limited() {
+    let app = TestApp::new().await;
+    let hooks = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&hooks)
+        .await;
+    for channel in [
+        json!({ "name": "digest", "kind": "webhook", "target": format!("{}/digest", hooks.uri()), "digest_seconds": 1 }),
+        json!({ "name": "limited", "kind": "webhook", "target": format!("{}/limited", hooks.uri()), "max_alerts_per_hour": 1 }),
+    ] {
+        let created = app.post("/channels", None, channel).await;
+        assert_eq!(created.status, StatusCode::CREATED, "{}", created.body);
+    }
+    let target = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&target)
+        .await;
+    for n in 0..5 {
+        app.create_check(None, &format!("{}/{n}", target.uri()))
+            .await;
+    }
+
+    // The datacenter goes away
+    app.advance(Duration::seconds(60)).await;
+    let received = |channel: &'static str| {
+        let hooks = &hooks;
+        async move {
+            hooks
+                .received_requests()
+                .await
+                .unwrap()
+                .into_iter()
+                .filter(|r| r.url.path() == format!("/{channel}"))
+                .map(|r| r.body_json::<Value>().unwrap())
+                .collect::<Vec<_>>()
+        }
+    };
+    let outbox = |status: &'static str| {
+        let app = &app;
+        async move {
+            let listed = app
+                .get(&format!("/admin/notifications?status={status}"), None)
+                .await;
+            listed.body.as_array().unwrap().clone()
+        }
+    };
+    wait_until(|| async { outbox("held").await.len() == 5 }).await;
+    assert!(received("digest").await.is_empty());
+
+    // The digest window runs on the service clock
+    app.clock.advance(Duration::seconds(1));
+    wait_until(|| async { !received("digest").await.is_empty() }).await;
+    let digests = received("digest").await;
+    assert_eq!(digests.len(), 1);
+    assert_eq!(digests[0]["digest"]["event"], "digest");
+    assert_eq!(digests[0]["digest"]["alerts"].as_array().unwrap().len(), 5);
+    assert!(digests[0]["text"]
+        .as_str()
+        .unwrap()
+        .starts_with("📦 5 alerts"));
+    let limited = received("limited").await;
+    assert_eq!(limited.len(), 1);
+    assert_eq!(limited[0]["alert"]["status"], "DOWN");
+
+    // What the limit held back is kept in the outbox
+    assert!(outbox("held").await.is_empty());
+    let dropped = outbox("dropped").await;
+    assert_eq!(dropped.len(), 4);
+    assert!(dropped[0]["last_error"]
+        .as_str()
+        .unwrap()
+        .contains("limit of 1 alerts per hour"));
+}
+
+#[tokio::test]
+async fn dependents_of_a_down_upstream_stay_quiet() {
+    let app = TestApp::new().await;
+    let hooks = MockServer::start().await;
+    webhook_channel(&app, &hooks).await;
+    let target = MockServer::start().await;
+    Mock::given(path("/balancer"))
+        .respond_with(ResponseTemplate::new(502))
+        .up_to_n_times(2)
+        .mount(&target)
+        .await;
+    Mock::given(path("/app"))
+        .respond_with(ResponseTemplate::new(200))
+        .up_to_n_times(1)
+        .mount(&target)
+        .await;
+    Mock::given(path("/app"))
+        .respond_with(ResponseTemplate::new(502))
+        .up_to_n_times(1)
+        .mount(&target)
+        .await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&target)
+        .await;
+    let balancer = app
+        .create_check(None, &format!("{}/balancer", target.uri()))
+        .await;
+    let dependent = app
+        .create_check(None, &format!("{}/app", target.uri()))
+        .await;
+    let depend = |id: String, upstream: String| {
+        let app = &app;
+        async move {
+            app.request(
+                Method::PUT,
+                &format!("/checks/{id}/dependencies"),
+                None,
+                &[],
+                Some(json!({ "depends_on": [upstream] })),
+            )
+            .await
+        }
+    };
+    let set = depend(dependent.clone(), balancer.clone()).await;
+    assert_eq!(set.status, StatusCode::OK, "{}", set.body);
+    let cycle = depend(balancer.clone(), dependent.clone()).await;
+    assert_eq!(cycle.status, StatusCode::BAD_REQUEST);
+
+    // The balancer fails first, then takes the app down with it, then both come back
+    for _ in 0..3 {
+        app.run_check(&balancer).await;
+    }
+
+    wait_until(|| async { alerts(&hooks).await.len() >= 3 }).await;
+    let alerted = |id: &str| {
+        let hooks = &hooks;
+        let id = id.to_string();
+        async move {
+            alerts(hooks)
+                .await
+                .iter()
+                .filter(|a| a["check"]["id"] == id.as_str())
+                .map(|a| {
+                    format!(
+                        "{} -> {}",
+                        a["previous"].as_str().unwrap(),
+                        a["status"].as_str().unwrap()
+                    )
+                })
+                .collect::<Vec<_>>()
+        }
+    };
+    assert_eq!(alerted(&balancer).await, ["UNKNOWN -> DOWN", "DOWN -> UP"]);
+    // Only its first result was alerted
+    assert_eq!(alerted(&dependent).await, ["UNKNOWN -> UP"]);
+    let incidents = app
+        .get(&format!("/checks/{dependent}/incidents"), None)
+        .await;
+    assert_eq!(incidents.body[0]["caused_by_check_id"], balancer.as_str());
+    assert!(incidents.body[0]["resolved_at"].is_string());
+}
+
+#[tokio::test]
+async fn the_worker_reports_its_own_health() {
+    let target = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&target)
+        .await;
+    enable_secrets();
+    let app = TestApp::new().await;
+    app.create_check(None, &target.uri()).await;
+    // Nothing listens there, so the probe gets no response at all
+    app.create_check(None, "http://127.0.0.1:1").await;
+    // And this one can't even be sent, its secret no longer decrypts
+    let secret = app
+        .post(
+            "/secrets",
+            None,
+            json!({ "name": "token", "value": "Bearer x" }),
+        )
+        .await;
+    let broken = app
+        .post(
+            "/checks",
+            None,
+            json!({ "name": "broken", "url": target.uri(), "interval_seconds": 60, "auth_header_secret_id": secret.body["id"] }),
+        )
+        .await;
+    assert_eq!(broken.status, StatusCode::CREATED, "{}", broken.body);
+    sqlx::query("UPDATE secrets SET ciphertext = 'broken'")
+        .execute(&app.db)
+        .await
+        .unwrap();
+    app.advance(Duration::seconds(60)).await;
+
+    let worker = app.get("/admin/worker", None).await.body;
+    assert_eq!(worker["stalled"], false);
+    let now_ms = app.clock.now().timestamp_millis();
+    let pass =
+        chrono::DateTime::parse_from_rfc3339(worker["last_scheduler_pass_at"].as_str().unwrap())
+            .unwrap();
+    assert_eq!(pass.timestamp_millis(), now_ms, "{worker}");
+    assert_eq!(worker["probes_total"], 3);
+    // An HTTP error is the target's problem and a broken check its owner's, not the worker's
+    assert_eq!(worker["probe_errors_total"], 1);
+    assert_eq!(worker["probe_config_errors_total"], 1);
+
+    let metrics = app.get("/metrics", None).await.body;
+    let metrics = metrics.as_str().unwrap();
+    assert!(metrics.contains("uptime_probes_total 3\n"), "{metrics}");
+    assert!(metrics.contains("uptime_probe_errors_total 1\n"));
+    assert!(metrics.contains("uptime_probe_config_errors_total 1\n"));
+    assert!(metrics.contains(&format!(
+        "uptime_scheduler_heartbeat_timestamp_seconds {:.3}\n",
+        now_ms as f64 / 1000.0
+    )));
+}